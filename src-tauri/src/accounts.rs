@@ -0,0 +1,188 @@
+//! Rejestr kont Gmail. Każde konto ma własny katalog `accounts/<id>/`
+//! z bazą cache i zaszyfrowanymi tokenami, więc konta nie mieszają się ze sobą.
+
+use crate::auth::{TokenStore, TokenVault};
+use crate::client::GmailClient;
+use crate::config::ApiConfig;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `account_id` oznaczający połączony widok wszystkich skrzynek
+pub const ALL_ACCOUNTS: &str = "all";
+
+pub const CACHE_FILE: &str = "nexdeck_cache.sqlite3";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "addedAt")]
+    pub added_at: i64,
+}
+
+pub struct AccountRegistry {
+    base_dir: PathBuf,
+    accounts: Vec<AccountInfo>,
+}
+
+impl AccountRegistry {
+    pub fn empty(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            accounts: Vec::new(),
+        }
+    }
+
+    pub fn load(base_dir: &Path) -> Result<Self> {
+        let path = base_dir.join("accounts.json");
+        let accounts = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).context("Failed to parse accounts.json")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            base_dir: base_dir.to_path_buf(),
+            accounts,
+        })
+    }
+
+    pub fn list(&self) -> &[AccountInfo] {
+        &self.accounts
+    }
+
+    pub fn get(&self, id: &str) -> Option<&AccountInfo> {
+        self.accounts.iter().find(|a| a.id == id)
+    }
+
+    /// Pierwsze dodane konto - używane, gdy komenda nie poda `account_id`
+    pub fn default_account(&self) -> Option<&AccountInfo> {
+        self.accounts.first()
+    }
+
+    /// Dodaje konto (ponowne logowanie na to samo konto nic nie zmienia). Konto szukane po adresie,
+    /// bo konta dodane przed zmianą `account_id_for` mają identyfikatory w starym formacie.
+    pub fn add(&mut self, email: &str) -> Result<AccountInfo> {
+        let email_key = normalize_email(email);
+        if let Some(existing) = self.accounts.iter().find(|a| normalize_email(&a.email) == email_key) {
+            return Ok(existing.clone());
+        }
+        let id = account_id_for(email);
+        let account = AccountInfo {
+            id,
+            email: email.to_string(),
+            added_at: chrono::Utc::now().timestamp_millis(),
+        };
+        self.accounts.push(account.clone());
+        self.save()?;
+        fs::create_dir_all(self.account_dir(&account.id))?;
+        Ok(account)
+    }
+
+    /// Usuwa konto razem z jego cache i tokenami
+    pub fn remove(&mut self, id: &str) -> Result<bool> {
+        let before = self.accounts.len();
+        self.accounts.retain(|a| a.id != id);
+        if self.accounts.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        let dir = self.account_dir(id);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        Ok(true)
+    }
+
+    pub fn account_dir(&self, id: &str) -> PathBuf {
+        self.base_dir.join("accounts").join(id)
+    }
+
+    pub fn token_store(&self, id: &str, config: &ApiConfig) -> TokenStore {
+        TokenStore::new(config, Some(TokenVault::new(&self.account_dir(id))))
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.base_dir)?;
+        let path = self.base_dir.join("accounts.json");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.accounts)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Dane sprzed obsługi wielu kont (tokeny i cache bezpośrednio w katalogu danych)
+    /// przenosi do katalogu konta, ustalając adres e-mail z profilu Gmail.
+    pub async fn adopt_legacy_account(&mut self, config: &ApiConfig) -> Result<Option<AccountInfo>> {
+        let legacy = TokenStore::new(config, Some(TokenVault::new(&self.base_dir)));
+        if !self.accounts.is_empty() || legacy.get_token().await?.is_none() {
+            return Ok(None);
+        }
+
+        let profile = GmailClient::new(legacy, config).get_profile().await?;
+        let account = self.add(&profile.email)?;
+        let dir = self.account_dir(&account.id);
+
+        for name in ["oauth.key", "oauth_tokens.bin", CACHE_FILE, "nexdeck_cache.sqlite3-wal", "nexdeck_cache.sqlite3-shm"] {
+            let from = self.base_dir.join(name);
+            if from.exists() {
+                fs::rename(&from, dir.join(name))?;
+            }
+        }
+        eprintln!("📦 Moved existing session to account {}", account.email);
+        Ok(Some(account))
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// sha256 (hex) adresu małymi literami - bezpieczne jako nazwa katalogu i różne dla różnych adresów
+/// (`jan.kowalski@` i `jan_kowalski@` to dwa konta)
+pub fn account_id_for(email: &str) -> String {
+    Sha256::digest(normalize_email(email).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similar_addresses_get_separate_accounts() {
+        let base = std::env::temp_dir().join(format!("nexdeck-accounts-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let mut registry = AccountRegistry::empty(&base);
+
+        let emails = ["jan.kowalski@x.com", "jan-kowalski@x.com", "jan_kowalski@x.com"];
+        let accounts: Vec<AccountInfo> = emails.iter().map(|e| registry.add(e).unwrap()).collect();
+        for (i, account) in accounts.iter().enumerate() {
+            assert_eq!(account.email, emails[i]);
+            assert!(registry.account_dir(&account.id).is_dir());
+            for other in &accounts[i + 1..] {
+                assert_ne!(account.id, other.id);
+                assert_ne!(registry.account_dir(&account.id), registry.account_dir(&other.id));
+            }
+        }
+        assert_eq!(fs::read_dir(base.join("accounts")).unwrap().count(), 3);
+
+        // Ponowne logowanie (inna wielkość liter) trafia do tego samego konta
+        assert_eq!(registry.add(" Jan.Kowalski@X.com").unwrap().id, accounts[0].id);
+        assert_eq!(AccountRegistry::load(&base).unwrap().list().len(), 3);
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn legacy_ids_are_kept() {
+        let base = std::env::temp_dir().join(format!("nexdeck-accounts-legacy-{}", std::process::id()));
+        let mut registry = AccountRegistry::empty(&base);
+        registry.accounts.push(AccountInfo { id: "jan_x_com".into(), email: "jan@x.com".into(), added_at: 1 });
+        assert_eq!(registry.add("jan@x.com").unwrap().id, "jan_x_com");
+        assert_eq!(registry.list().len(), 1);
+    }
+}
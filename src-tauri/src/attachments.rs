@@ -0,0 +1,300 @@
+//! Lokalny magazyn załączników. Plik trafia do katalogu `attachments` obok bazy cache
+//! pod nazwą sha256 treści (+ rozszerzenie), opis do tabeli `attachments`. Ponowne
+//! otwarcie nie pobiera pliku z Gmaila; po przekroczeniu limitu znikają najdawniej używane.
+
+use crate::cache::Cache;
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+
+/// Górny limit rozmiaru magazynu (na konto)
+pub const STORE_LIMIT_BYTES: i64 = 512 * 1024 * 1024;
+
+/// Wynik `download_attachment_rust`
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentFile {
+    pub path: String,
+    pub filename: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub size: i64,
+    pub sha256: String,
+    /// Plik był już w magazynie - bez pobierania
+    pub cached: bool,
+}
+
+/// Postęp pobierania (zdarzenie `attachment-download-progress`)
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    #[serde(rename = "attachmentId")]
+    pub attachment_id: String,
+    pub received: u64,
+    pub total: u64,
+}
+
+/// Nazwa pliku w magazynie: sha256 + rozszerzenie z nazwy załącznika (dla programu, który go otworzy)
+pub fn store_file_name(sha256: &str, filename: &str) -> String {
+    let ext: String = filename
+        .rsplit_once('.')
+        .map(|(_, e)| e.chars().filter(|c| c.is_ascii_alphanumeric()).take(10).collect::<String>())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if ext.is_empty() {
+        sha256.to_string()
+    } else {
+        format!("{}.{}", sha256, ext)
+    }
+}
+
+/// Usuwa najdawniej używane pliki, aż magazyn zmieści się w `limit`. `keep` (właśnie
+/// otwierany plik) zostaje nawet wtedy, gdy sam przekracza limit.
+pub fn evict(cache: &Cache, limit: i64, keep: &str) -> Result<usize> {
+    let files = cache.attachment_files()?;
+    let mut total: i64 = files.iter().map(|(_, size)| size).sum();
+    let mut removed = 0;
+    for (file_name, size) in files {
+        if total <= limit {
+            break;
+        }
+        if file_name == keep {
+            continue;
+        }
+        match std::fs::remove_file(cache.attachments_dir().join(&file_name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("⚠️ Failed to evict attachment {}: {}", file_name, e);
+                continue;
+            }
+        }
+        cache.remove_attachment_file(&file_name)?;
+        total -= size;
+        removed += 1;
+    }
+    if removed > 0 {
+        eprintln!("🧹 Attachment store: evicted {} file(s), {} bytes left", removed, total);
+    }
+    Ok(removed)
+}
+
+enum FieldState {
+    Key,
+    Colon,
+    Value,
+    Done,
+}
+
+/// Dekoduje pole `"data"` (base64url) z odpowiedzi messages.attachments.get kawałek po
+/// kawałku - duży załącznik nie musi mieścić się w pamięci w całości.
+pub struct Base64FieldDecoder {
+    key: &'static [u8],
+    matched: usize,
+    state: FieldState,
+    pending: Vec<u8>,
+}
+
+impl Default for Base64FieldDecoder {
+    fn default() -> Self {
+        Self { key: b"\"data\"", matched: 0, state: FieldState::Key, pending: Vec::new() }
+    }
+}
+
+impl Base64FieldDecoder {
+    pub fn feed(&mut self, chunk: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        for &b in chunk {
+            match self.state {
+                FieldState::Key => {
+                    if b == self.key[self.matched] {
+                        self.matched += 1;
+                        if self.matched == self.key.len() {
+                            self.state = FieldState::Colon;
+                        }
+                    } else {
+                        self.matched = usize::from(b == self.key[0]);
+                    }
+                }
+                FieldState::Colon => match b {
+                    b'"' => self.state = FieldState::Value,
+                    b':' => {}
+                    _ if b.is_ascii_whitespace() => {}
+                    _ => {
+                        self.matched = 0;
+                        self.state = FieldState::Key;
+                    }
+                },
+                FieldState::Value => match b {
+                    b'"' => self.state = FieldState::Done,
+                    // Standardowy alfabet (gdyby się trafił) -> base64url; `\/` z JSON-a to też `/`
+                    b'+' => self.pending.push(b'-'),
+                    b'/' => self.pending.push(b'_'),
+                    b'=' | b'\\' => {}
+                    _ if b.is_ascii_whitespace() => {}
+                    _ => self.pending.push(b),
+                },
+                FieldState::Done => break,
+            }
+        }
+        let whole = self.pending.len() / 4 * 4;
+        if whole > 0 {
+            out.extend(general_purpose::URL_SAFE_NO_PAD.decode(&self.pending[..whole])?);
+            self.pending.drain(..whole);
+        }
+        Ok(())
+    }
+
+    pub fn finish(self, out: &mut Vec<u8>) -> Result<()> {
+        if !matches!(self.state, FieldState::Done) {
+            anyhow::bail!("Attachment response has no data");
+        }
+        out.extend(general_purpose::URL_SAFE_NO_PAD.decode(&self.pending)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::tests::test_cache;
+    use crate::cache::StoredAttachment;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 256) as u8).collect()
+    }
+
+    /// Odpowiedź podana dekoderowi w kawałkach po `size` bajtów
+    fn decode_chunked(json: &[u8], size: usize) -> Result<Vec<u8>> {
+        let mut decoder = Base64FieldDecoder::default();
+        let mut out = Vec::new();
+        for chunk in json.chunks(size) {
+            decoder.feed(chunk, &mut out)?;
+        }
+        decoder.finish(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decodes_data_split_across_chunks() {
+        for len in [0, 1, 2, 3, 299, 300] {
+            let data = payload(len);
+            let json = format!(
+                "{{\"size\": {}, \"data\": \"{}\", \"attachmentId\": \"att-1\"}}",
+                len,
+                general_purpose::URL_SAFE_NO_PAD.encode(&data)
+            );
+            // Od pojedynczych bajtów (klucz i każdy znak base64 na granicy) po całość naraz
+            for size in (1..=13).chain([64, json.len()]) {
+                assert_eq!(decode_chunked(json.as_bytes(), size).unwrap(), data, "len {}, chunk {}", len, size);
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_standard_alphabet_escapes_and_missing_padding() {
+        // Bajty dające '+' i '/' w standardowym base64
+        let data: Vec<u8> = [0xfb, 0xff, 0xbf, 0xfe, 0x3f].repeat(20);
+        let standard = general_purpose::STANDARD.encode(&data);
+        assert!(standard.contains('/') && standard.contains('+') && standard.ends_with('='));
+
+        // JSON może escapować '/' jako `\/`
+        let escaped = format!("{{\"data\":\"{}\"}}", standard.replace('/', "\\/"));
+        let padded_url = format!("{{\"data\" : \"{}\"}}", general_purpose::URL_SAFE.encode(&data));
+        let unpadded = format!("{{\"data\":\n  \"{}\"}}", general_purpose::URL_SAFE_NO_PAD.encode(&data));
+        for json in [escaped, padded_url, unpadded] {
+            for size in [1, 2, 3, 5, 1000] {
+                assert_eq!(decode_chunked(json.as_bytes(), size).unwrap(), data, "{}", json);
+            }
+        }
+    }
+
+    #[test]
+    fn finds_the_data_key_only() {
+        let data = payload(40);
+        let encoded = general_purpose::URL_SAFE_NO_PAD.encode(&data);
+        // "data" jako wartość innego pola i końcówka innego klucza nie są polem danych
+        let json = format!("{{\"note\": \"data\", \"metadata\": \"x\", \"ddata\": 1, \"data\": \"{}\"}}", encoded);
+        for size in [1, 4, 1000] {
+            assert_eq!(decode_chunked(json.as_bytes(), size).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn missing_data_is_an_error() {
+        for json in [
+            "{\"size\": 0}",
+            "{\"error\": {\"code\": 404, \"message\": \"Not Found\"}}",
+            "",
+            // Urwana odpowiedź
+            "{\"data\": \"AAEC",
+        ] {
+            let err = decode_chunked(json.as_bytes(), 3).unwrap_err();
+            assert!(err.to_string().contains("no data"), "{}: {}", json, err);
+        }
+        // Niepoprawny base64 (jeden znak na końcu)
+        assert!(decode_chunked(b"{\"data\": \"AAECA\"}", 100).is_err());
+    }
+
+    /// Plik w magazynie i wpis wiadomości, ostatnio używany w chwili `used`
+    fn stored(cache: &Cache, message_id: &str, file_name: &str, size: usize, used: i64) {
+        std::fs::create_dir_all(cache.attachments_dir()).unwrap();
+        std::fs::write(cache.attachments_dir().join(file_name), vec![0u8; size]).unwrap();
+        cache
+            .put_attachment(&StoredAttachment {
+                message_id: message_id.to_string(),
+                attachment_id: format!("att-{}", file_name),
+                filename: file_name.to_string(),
+                mime_type: "application/octet-stream".to_string(),
+                sha256: file_name.to_string(),
+                file_name: file_name.to_string(),
+                size: size as i64,
+                fetched_at: used,
+            })
+            .unwrap();
+    }
+
+    fn files(cache: &Cache) -> Vec<String> {
+        let mut names: Vec<String> = cache.attachment_files().unwrap().into_iter().map(|(f, _)| f).collect();
+        names.sort();
+        for name in &names {
+            assert!(cache.attachments_dir().join(name).exists(), "{} zniknął z dysku", name);
+        }
+        names
+    }
+
+    #[test]
+    fn evicts_least_recently_used_down_to_limit() {
+        let cache = test_cache();
+        stored(&cache, "m1", "a.pdf", 100, 1);
+        stored(&cache, "m2", "b.pdf", 100, 2);
+        stored(&cache, "m3", "c.pdf", 100, 3);
+        // Ten sam plik w drugiej wiadomości liczy się raz
+        stored(&cache, "m4", "c.pdf", 100, 3);
+        stored(&cache, "m5", "d.pdf", 100, 4);
+        // Otwarcie odświeża kolejność: a.pdf jest teraz najnowszy
+        cache.find_attachment("m1", "att-a.pdf", 10).unwrap().unwrap();
+
+        assert_eq!(evict(&cache, 400, "").unwrap(), 0);
+        assert_eq!(evict(&cache, 250, "").unwrap(), 2);
+        assert_eq!(files(&cache), vec!["a.pdf", "d.pdf"]);
+        assert!(!cache.attachments_dir().join("b.pdf").exists());
+        assert!(cache.find_attachment("m4", "att-c.pdf", 11).unwrap().is_none());
+    }
+
+    #[test]
+    fn eviction_keeps_the_opened_file_and_skips_missing_ones() {
+        let cache = test_cache();
+        stored(&cache, "m1", "a.pdf", 100, 1);
+        stored(&cache, "m2", "b.pdf", 100, 2);
+        stored(&cache, "m3", "big.iso", 500, 3);
+        // Plik usunięty ręcznie z dysku - wpis i tak znika
+        std::fs::remove_file(cache.attachments_dir().join("b.pdf")).unwrap();
+
+        // Najstarszy a.pdf jest otwierany, więc zostaje; reszta znika, choć limit nadal przekroczony
+        assert_eq!(evict(&cache, 50, "a.pdf").unwrap(), 2);
+        assert_eq!(files(&cache), vec!["a.pdf"]);
+        assert_eq!(evict(&cache, 0, "a.pdf").unwrap(), 0);
+        assert_eq!(evict(&cache, 0, "").unwrap(), 1);
+        assert!(files(&cache).is_empty());
+    }
+}
@@ -0,0 +1,701 @@
+//! Logowanie OAuth 2.0 dla aplikacji desktopowej (RFC 8252): PKCE + przekierowanie na loopback.
+//! Refresh token trafia do zaszyfrowanego pliku obok bazy cache (klucz w pęku kluczy systemu);
+//! `TokenStore` dostarcza aktualny access token klientom Gmail.
+
+use crate::config::ApiConfig;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use rand::RngCore;
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, RwLock};
+
+pub const GMAIL_SCOPES: &[&str] = &[
+    "https://www.googleapis.com/auth/gmail.modify",
+    "https://www.googleapis.com/auth/gmail.send",
+    "https://www.googleapis.com/auth/userinfo.email",
+];
+
+/// Odświeżamy trochę przed faktycznym wygaśnięciem
+const EXPIRY_MARGIN_MS: i64 = 60_000;
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// ms od epoki, wyliczone z `expires_in`
+    pub expires_at: i64,
+}
+
+impl OAuthTokens {
+    pub fn is_fresh(&self) -> bool {
+        chrono::Utc::now().timestamp_millis() + EXPIRY_MARGIN_MS < self.expires_at
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Para PKCE: `code_verifier` zostaje u nas, `code_challenge` (S256) idzie w URL logowania
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn generate() -> Self {
+        let verifier = random_urlsafe(32);
+        let challenge = general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self { verifier, challenge }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthClient {
+    http: HttpClient,
+    config: ApiConfig,
+}
+
+impl AuthClient {
+    pub fn new(config: &ApiConfig) -> Self {
+        Self {
+            http: HttpClient::new(),
+            config: config.clone(),
+        }
+    }
+
+    pub fn authorization_url(&self, redirect_uri: &str, pkce: &Pkce, state: &str) -> Result<String> {
+        let scope = GMAIL_SCOPES.join(" ");
+        let url = reqwest::Url::parse_with_params(
+            &self.config.oauth_auth_url,
+            &[
+                ("client_id", self.config.oauth_client_id.as_str()),
+                ("redirect_uri", redirect_uri),
+                ("response_type", "code"),
+                ("scope", scope.as_str()),
+                ("code_challenge", pkce.challenge.as_str()),
+                ("code_challenge_method", "S256"),
+                ("state", state),
+                // offline + consent: Google zwraca refresh_token
+                ("access_type", "offline"),
+                ("prompt", "consent"),
+            ],
+        )
+        .context("Invalid OAuth authorization URL")?;
+        Ok(url.into())
+    }
+
+    /// Pełny flow logowania: nasłuch na 127.0.0.1, przeglądarka, wymiana kodu na tokeny.
+    /// `open_url` dostaje gotowy URL (otwarcie przeglądarki zostawiamy wywołującemu).
+    pub async fn login<F>(&self, open_url: F) -> Result<OAuthTokens>
+    where
+        F: FnOnce(&str) -> Result<()>,
+    {
+        if self.config.oauth_client_id.is_empty() {
+            anyhow::bail!("OAuth client id not configured (NEXDECK_GOOGLE_CLIENT_ID)");
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.context("Failed to bind loopback listener")?;
+        let redirect_uri = format!("http://127.0.0.1:{}", listener.local_addr()?.port());
+        let pkce = Pkce::generate();
+        let state = random_urlsafe(16);
+
+        open_url(&self.authorization_url(&redirect_uri, &pkce, &state)?)?;
+        eprintln!("🔐 Waiting for OAuth redirect on {}", redirect_uri);
+
+        let code = tokio::time::timeout(LOGIN_TIMEOUT, wait_for_code(&listener, &state))
+            .await
+            .context("Timed out waiting for OAuth login")??;
+
+        self.exchange_code(&code, &pkce.verifier, &redirect_uri).await
+    }
+
+    pub async fn exchange_code(&self, code: &str, verifier: &str, redirect_uri: &str) -> Result<OAuthTokens> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("code_verifier", verifier),
+            ("redirect_uri", redirect_uri),
+            ("client_id", self.config.oauth_client_id.as_str()),
+        ];
+        if let Some(secret) = &self.config.oauth_client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let resp = self.request_token(&form).await.context("OAuth code exchange failed")?;
+        Ok(tokens_from_response(resp, None))
+    }
+
+    /// Nowy access token. Google zwykle nie zwraca nowego refresh tokenu - zostaje stary.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<OAuthTokens> {
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", self.config.oauth_client_id.as_str()),
+        ];
+        if let Some(secret) = &self.config.oauth_client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let resp = self.request_token(&form).await.context("OAuth token refresh failed")?;
+        Ok(tokens_from_response(resp, Some(refresh_token)))
+    }
+
+    async fn request_token(&self, form: &[(&str, &str)]) -> Result<TokenResponse> {
+        let resp = self.http.post(&self.config.oauth_token_url).form(form).send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(match serde_json::from_str::<TokenErrorResponse>(&text) {
+                Ok(err) => match err.error_description {
+                    Some(desc) => anyhow::anyhow!("{} ({}): {}", err.error, status, desc),
+                    None => anyhow::anyhow!("{} ({})", err.error, status),
+                },
+                Err(_) => anyhow::anyhow!("Token endpoint returned {}: {}", status, text),
+            });
+        }
+        resp.json().await.context("Failed to parse token response")
+    }
+}
+
+fn tokens_from_response(resp: TokenResponse, previous_refresh: Option<&str>) -> OAuthTokens {
+    OAuthTokens {
+        access_token: resp.access_token,
+        refresh_token: resp.refresh_token.or_else(|| previous_refresh.map(String::from)),
+        expires_at: chrono::Utc::now().timestamp_millis() + resp.expires_in * 1000,
+    }
+}
+
+/// Czeka na `GET /?code=...&state=...` z przeglądarki. Inne żądania (favicon) dostają 404.
+async fn wait_for_code(listener: &TcpListener, expected_state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buf = vec![0u8; 8192];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let target = request
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or("/");
+
+        let params: Vec<(String, String)> = reqwest::Url::parse(&format!("http://localhost{}", target))
+            .map(|u| u.query_pairs().into_owned().collect())
+            .unwrap_or_default();
+        let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+
+        let (status, message, result) = match (param("code"), param("error")) {
+            (_, Some(error)) => ("400 Bad Request", "Logowanie anulowane.", Some(Err(anyhow::anyhow!("OAuth error: {}", error)))),
+            (Some(code), None) if param("state").as_deref() == Some(expected_state) => {
+                ("200 OK", "Zalogowano. Możesz wrócić do NexDeck.", Some(Ok(code)))
+            }
+            (Some(_), None) => ("400 Bad Request", "Nieprawidłowy parametr state.", Some(Err(anyhow::anyhow!("OAuth state mismatch")))),
+            (None, None) => ("404 Not Found", "", None),
+        };
+
+        let body = format!("<!doctype html><html><body><p>{}</p></body></html>", message);
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+
+        if let Some(result) = result {
+            return result;
+        }
+    }
+}
+
+/// Otwiera URL w domyślnej przeglądarce systemu
+pub fn open_in_browser(url: &str) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut c = std::process::Command::new("rundll32");
+        c.args(["url.dll,FileProtocolHandler", url]);
+        c
+    };
+    #[cfg(target_os = "macos")]
+    let mut cmd = {
+        let mut c = std::process::Command::new("open");
+        c.arg(url);
+        c
+    };
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut cmd = {
+        let mut c = std::process::Command::new("xdg-open");
+        c.arg(url);
+        c
+    };
+    cmd.spawn().context("Failed to open browser")?;
+    Ok(())
+}
+
+fn random_urlsafe(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buf);
+    general_purpose::URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// Tokeny OAuth: access token w pamięci, refresh token w zaszyfrowanym `TokenVault`.
+/// Access token odświeżany wg `expires_in` z odpowiedzi serwera albo po 401.
+/// Klony dzielą stan, więc wszystkie `GmailClient` (sync w tle, prefetch) widzą nowy token.
+#[derive(Clone)]
+pub struct TokenStore {
+    pub tokens: Arc<RwLock<Option<OAuthTokens>>>,
+    pub auth: AuthClient,
+    pub vault: Option<TokenVault>,
+    /// Jedno odświeżanie naraz - równoległe 401 czekają na wynik pierwszego
+    refresh_lock: Arc<Mutex<()>>,
+}
+
+impl TokenStore {
+    /// Bez `vault` tokeny żyją tylko w pamięci
+    pub fn new(config: &ApiConfig, vault: Option<TokenVault>) -> Self {
+        Self {
+            tokens: Arc::new(RwLock::new(None)),
+            auth: AuthClient::new(config),
+            vault,
+            refresh_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn get_token(&self) -> Result<Option<String>> {
+        if let Some(tokens) = self.tokens.read().await.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(Some(tokens.access_token.clone()));
+        }
+
+        let _guard = self.refresh_lock.lock().await;
+        // Ktoś mógł odświeżyć, gdy czekaliśmy na lock
+        if let Some(tokens) = self.tokens.read().await.as_ref().filter(|t| t.is_fresh()) {
+            return Ok(Some(tokens.access_token.clone()));
+        }
+        if let Some(stored) = self.load_stored().await? {
+            if stored.is_fresh() {
+                let access = stored.access_token.clone();
+                *self.tokens.write().await = Some(stored);
+                return Ok(Some(access));
+            }
+        }
+        match self.refresh_token().await? {
+            Some(rt) => self.refresh_with(&rt).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Serwer odrzucił `stale` (401) - odśwież, chyba że inny wątek już to zrobił
+    pub async fn refresh_after_unauthorized(&self, stale: &str) -> Result<String> {
+        let _guard = self.refresh_lock.lock().await;
+        if let Some(tokens) = self.tokens.read().await.as_ref() {
+            if tokens.access_token != stale && tokens.is_fresh() {
+                return Ok(tokens.access_token.clone());
+            }
+        }
+        let rt = self
+            .refresh_token()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Access token rejected and no refresh token available - please log in again"))?;
+        self.refresh_with(&rt).await
+    }
+
+    /// Tokeny z logowania / odświeżenia - zapisywane też w vault
+    pub async fn set_tokens(&self, tokens: OAuthTokens) -> Result<()> {
+        if let (Some(vault), Some(_)) = (&self.vault, &tokens.refresh_token) {
+            vault.save(&tokens)?;
+        }
+        *self.tokens.write().await = Some(tokens);
+        Ok(())
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        *self.tokens.write().await = None;
+        if let Some(vault) = &self.vault {
+            vault.clear()?;
+        }
+        Ok(())
+    }
+
+    async fn load_stored(&self) -> Result<Option<OAuthTokens>> {
+        if self.tokens.read().await.is_some() {
+            return Ok(None);
+        }
+        Ok(self.vault.as_ref().map(|v| v.load()).transpose()?.flatten())
+    }
+
+    async fn refresh_token(&self) -> Result<Option<String>> {
+        if let Some(rt) = self.tokens.read().await.as_ref().and_then(|t| t.refresh_token.clone()) {
+            return Ok(Some(rt));
+        }
+        Ok(self
+            .vault
+            .as_ref()
+            .map(|v| v.load())
+            .transpose()?
+            .flatten()
+            .and_then(|t| t.refresh_token))
+    }
+
+    async fn refresh_with(&self, refresh_token: &str) -> Result<String> {
+        eprintln!("🔑 Refreshing access token...");
+        let tokens = self.auth.refresh(refresh_token).await?;
+        let access = tokens.access_token.clone();
+        self.set_tokens(tokens).await?;
+        Ok(access)
+    }
+}
+
+/// Nazwa usługi, pod którą klucze vaultów leżą w pęku kluczy systemu
+const KEYRING_SERVICE: &str = "NexDeck";
+/// Nagłówek pliku tokenów: magic + id klucza w pęku kluczy (base64url) + nonce + szyfrogram
+const VAULT_MAGIC: &[u8] = b"NXV2";
+const KEY_ID_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Magazyn kluczy szyfrujących vault. Domyślnie pęk kluczy systemu (`OsKeyring`).
+pub trait KeyStore: Send + Sync {
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+    fn set(&self, name: &str, key: &[u8]) -> Result<()>;
+    fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// Keychain (macOS), Credential Manager (Windows), Secret Service (Linux)
+pub struct OsKeyring;
+
+impl KeyStore for OsKeyring {
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match keyring::Entry::new(KEYRING_SERVICE, name)?.get_secret() {
+            Ok(key) => Ok(Some(key)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).context("Failed to read token key from the system keychain"),
+        }
+    }
+
+    fn set(&self, name: &str, key: &[u8]) -> Result<()> {
+        keyring::Entry::new(KEYRING_SERVICE, name)?
+            .set_secret(key)
+            .context("Failed to store token key in the system keychain")
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        match keyring::Entry::new(KEYRING_SERVICE, name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e).context("Failed to remove token key from the system keychain"),
+        }
+    }
+}
+
+/// Tokeny zaszyfrowane AES-256-GCM. Na dysku leży tylko szyfrogram; klucz jest w pęku kluczy
+/// systemu, a plik zapamiętuje jedynie jego id - przeniesienie pliku (np. do katalogu konta)
+/// nie gubi klucza, a sam plik (np. w kopii zapasowej) nic nie zdradza.
+#[derive(Clone)]
+pub struct TokenVault {
+    tokens_path: PathBuf,
+    /// Klucz w pliku obok tokenów z wcześniejszych wersji - przenoszony do pęku kluczy przy odczycie
+    legacy_key_path: PathBuf,
+    keys: Arc<dyn KeyStore>,
+}
+
+impl TokenVault {
+    pub fn new(dir: &Path) -> Self {
+        Self::with_key_store(dir, Arc::new(OsKeyring))
+    }
+
+    pub fn with_key_store(dir: &Path, keys: Arc<dyn KeyStore>) -> Self {
+        Self {
+            tokens_path: dir.join("oauth_tokens.bin"),
+            legacy_key_path: dir.join("oauth.key"),
+            keys,
+        }
+    }
+
+    pub fn load(&self) -> Result<Option<OAuthTokens>> {
+        if !self.tokens_path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&self.tokens_path)?;
+
+        let Some(rest) = data.strip_prefix(VAULT_MAGIC) else {
+            return self.load_legacy(&data);
+        };
+        if rest.len() < KEY_ID_LEN + NONCE_LEN {
+            anyhow::bail!("Token store is corrupted");
+        }
+        let (key_id, sealed) = rest.split_at(KEY_ID_LEN);
+        let key_id = std::str::from_utf8(key_id).context("Token store is corrupted")?;
+        let Some(key) = self.keys.get(&key_name(key_id))? else {
+            // Klucz usunięty z pęku kluczy - pliku i tak nie da się odszyfrować
+            eprintln!("⚠️ Token key missing from the system keychain, please log in again");
+            let _ = fs::remove_file(&self.tokens_path);
+            return Ok(None);
+        };
+        Ok(Some(open_sealed(&key, sealed)?))
+    }
+
+    pub fn save(&self, tokens: &OAuthTokens) -> Result<()> {
+        // Ten sam klucz przy kolejnych zapisach - nowy tylko, gdy vault jest pusty
+        let existing = self.key_id()?.and_then(|id| match self.keys.get(&key_name(&id)) {
+            Ok(Some(key)) if key.len() == 32 => Some(Ok((id, key))),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        });
+        let (key_id, key) = match existing.transpose()? {
+            Some(pair) => pair,
+            None => {
+                let id = random_urlsafe(KEY_ID_LEN * 3 / 4);
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                self.keys.set(&key_name(&id), &key)?;
+                (id, key)
+            }
+        };
+
+        let mut data = VAULT_MAGIC.to_vec();
+        data.extend_from_slice(key_id.as_bytes());
+        data.extend(seal(&key, &serde_json::to_vec(tokens)?)?);
+        write_private(&self.tokens_path, &data)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        if let Some(id) = self.key_id()? {
+            self.keys.delete(&key_name(&id))?;
+        }
+        if self.tokens_path.exists() {
+            fs::remove_file(&self.tokens_path)?;
+        }
+        if self.legacy_key_path.exists() {
+            fs::remove_file(&self.legacy_key_path)?;
+        }
+        Ok(())
+    }
+
+    /// Id klucza z nagłówka pliku tokenów (None dla braku pliku i starego formatu)
+    fn key_id(&self) -> Result<Option<String>> {
+        let data = match fs::read(&self.tokens_path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(data
+            .strip_prefix(VAULT_MAGIC)
+            .and_then(|rest| rest.get(..KEY_ID_LEN))
+            .and_then(|id| std::str::from_utf8(id).ok())
+            .map(String::from))
+    }
+
+    /// Stary format (nonce + szyfrogram, klucz w `oauth.key`): odszyfruj i zapisz ponownie
+    /// z kluczem w pęku kluczy, po czym usuń plik z kluczem.
+    fn load_legacy(&self, data: &[u8]) -> Result<Option<OAuthTokens>> {
+        let key = match fs::read(&self.legacy_key_path) {
+            Ok(key) if key.len() == 32 => key,
+            _ => return Ok(None),
+        };
+        let tokens: OAuthTokens = open_sealed(&key, data)?;
+        // Stary plik nie ma id klucza, więc zapis wygeneruje nowy klucz w pęku kluczy
+        self.save(&tokens)?;
+        fs::remove_file(&self.legacy_key_path)?;
+        eprintln!("🔐 Moved token key to the system keychain");
+        Ok(Some(tokens))
+    }
+}
+
+fn key_name(key_id: &str) -> String {
+    format!("oauth-vault-{}", key_id)
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid token store key"))
+}
+
+/// nonce + szyfrogram
+fn seal(key: &[u8], plain: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher(key)?
+        .encrypt(Nonce::from_slice(&nonce), plain)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt token store"))?;
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+fn open_sealed(key: &[u8], data: &[u8]) -> Result<OAuthTokens> {
+    if data.len() < NONCE_LEN {
+        anyhow::bail!("Token store is corrupted");
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plain = cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt token store"))?;
+    Ok(serde_json::from_slice(&plain)?)
+}
+
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        std::io::Write::write_all(&mut file, data)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex as StdMutex;
+
+    /// Pęk kluczy w pamięci zamiast systemowego
+    #[derive(Default)]
+    struct MemoryKeys(StdMutex<HashMap<String, Vec<u8>>>);
+
+    impl KeyStore for MemoryKeys {
+        fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.lock().unwrap().get(name).cloned())
+        }
+        fn set(&self, name: &str, key: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().insert(name.to_string(), key.to_vec());
+            Ok(())
+        }
+        fn delete(&self, name: &str) -> Result<()> {
+            self.0.lock().unwrap().remove(name);
+            Ok(())
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "nexdeck-vault-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tokens(refresh: &str) -> OAuthTokens {
+        OAuthTokens {
+            access_token: "access".to_string(),
+            refresh_token: Some(refresh.to_string()),
+            expires_at: 42,
+        }
+    }
+
+    #[test]
+    fn key_lives_in_keychain_not_on_disk() {
+        let dir = temp_dir();
+        let keys = Arc::new(MemoryKeys::default());
+        let vault = TokenVault::with_key_store(&dir, keys.clone());
+
+        vault.save(&tokens("refresh-1")).unwrap();
+
+        let names: Vec<String> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        assert_eq!(names, vec!["oauth_tokens.bin"]);
+        let stored = keys.0.lock().unwrap().clone();
+        assert_eq!(stored.len(), 1);
+        let (name, key) = stored.into_iter().next().unwrap();
+        assert!(name.starts_with("oauth-vault-"));
+
+        let data = fs::read(dir.join("oauth_tokens.bin")).unwrap();
+        assert!(data.starts_with(VAULT_MAGIC));
+        assert!(!data.windows(key.len()).any(|w| w == key.as_slice()));
+        assert!(!String::from_utf8_lossy(&data).contains("refresh-1"));
+
+        let loaded = vault.load().unwrap().unwrap();
+        assert_eq!(loaded.refresh_token.as_deref(), Some("refresh-1"));
+        assert_eq!(loaded.expires_at, 42);
+    }
+
+    #[test]
+    fn resave_keeps_key_and_clear_removes_it() {
+        let dir = temp_dir();
+        let keys = Arc::new(MemoryKeys::default());
+        let vault = TokenVault::with_key_store(&dir, keys.clone());
+
+        vault.save(&tokens("refresh-1")).unwrap();
+        vault.save(&tokens("refresh-2")).unwrap();
+        assert_eq!(keys.0.lock().unwrap().len(), 1);
+        assert_eq!(vault.load().unwrap().unwrap().refresh_token.as_deref(), Some("refresh-2"));
+
+        vault.clear().unwrap();
+        assert!(keys.0.lock().unwrap().is_empty());
+        assert!(!dir.join("oauth_tokens.bin").exists());
+        assert!(vault.load().unwrap().is_none());
+    }
+
+    #[test]
+    fn moved_file_still_finds_its_key() {
+        let keys = Arc::new(MemoryKeys::default());
+        let (from, to) = (temp_dir(), temp_dir());
+        TokenVault::with_key_store(&from, keys.clone()).save(&tokens("refresh-1")).unwrap();
+        fs::rename(from.join("oauth_tokens.bin"), to.join("oauth_tokens.bin")).unwrap();
+
+        let loaded = TokenVault::with_key_store(&to, keys).load().unwrap().unwrap();
+        assert_eq!(loaded.refresh_token.as_deref(), Some("refresh-1"));
+    }
+
+    #[test]
+    fn missing_key_means_logged_out() {
+        let dir = temp_dir();
+        let keys = Arc::new(MemoryKeys::default());
+        let vault = TokenVault::with_key_store(&dir, keys.clone());
+        vault.save(&tokens("refresh-1")).unwrap();
+        keys.0.lock().unwrap().clear();
+
+        assert!(vault.load().unwrap().is_none());
+        assert!(!dir.join("oauth_tokens.bin").exists());
+    }
+
+    #[test]
+    fn legacy_key_file_is_moved_to_keychain() {
+        let dir = temp_dir();
+        let legacy_key = [7u8; 32];
+        fs::write(dir.join("oauth.key"), legacy_key).unwrap();
+        let sealed = seal(&legacy_key, &serde_json::to_vec(&tokens("old-refresh")).unwrap()).unwrap();
+        fs::write(dir.join("oauth_tokens.bin"), sealed).unwrap();
+
+        let keys = Arc::new(MemoryKeys::default());
+        let vault = TokenVault::with_key_store(&dir, keys.clone());
+        assert_eq!(vault.load().unwrap().unwrap().refresh_token.as_deref(), Some("old-refresh"));
+
+        assert!(!dir.join("oauth.key").exists());
+        assert_eq!(keys.0.lock().unwrap().len(), 1);
+        assert!(fs::read(dir.join("oauth_tokens.bin")).unwrap().starts_with(VAULT_MAGIC));
+        // Drugi odczyt już z nowego formatu
+        assert_eq!(vault.load().unwrap().unwrap().refresh_token.as_deref(), Some("old-refresh"));
+    }
+}
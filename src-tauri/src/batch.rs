@@ -0,0 +1,209 @@
+//! multipart/mixed dla Gmail batch API (`POST /batch/gmail/v1`).
+//! Każda część to osadzone żądanie / odpowiedź HTTP (`Content-Type: application/http`).
+
+use anyhow::{Context, Result};
+
+/// Maksymalna liczba pod-żądań w jednym batchu (limit Gmaila)
+pub const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    pub content_id: String,
+    pub method: &'static str,
+    /// Ścieżka z query, np. `/gmail/v1/users/me/messages/abc?format=metadata`
+    pub path: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchResponse {
+    /// Content-ID z żądania (bez prefiksu `response-`)
+    pub content_id: String,
+    pub status: u16,
+    pub body: String,
+}
+
+pub fn new_boundary() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("batch_nexdeck_{}", nanos)
+}
+
+pub fn build_body(boundary: &str, requests: &[BatchRequest]) -> String {
+    let mut body = String::new();
+    for req in requests {
+        body.push_str(&format!("--{}\r\n", boundary));
+        body.push_str("Content-Type: application/http\r\n");
+        body.push_str(&format!("Content-ID: <{}>\r\n\r\n", req.content_id));
+        body.push_str(&format!("{} {}\r\n\r\n", req.method, req.path));
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+    body
+}
+
+/// `multipart/mixed; boundary=batch_xyz` -> `batch_xyz`
+pub fn boundary_from_content_type(content_type: &str) -> Option<String> {
+    content_type
+        .split(';')
+        .map(|p| p.trim())
+        .find_map(|p| p.strip_prefix("boundary="))
+        .map(|b| b.trim_matches('"').to_string())
+}
+
+pub fn parse_response(content_type: &str, body: &str) -> Result<Vec<BatchResponse>> {
+    let boundary = boundary_from_content_type(content_type)
+        .with_context(|| format!("Batch response without boundary: {}", content_type))?;
+
+    let mut responses = Vec::new();
+    for (headers, part_body) in split_parts(body, &boundary) {
+        let content_id = header_value(headers, "Content-ID")
+            .map(|v| v.trim_matches(|c| c == '<' || c == '>').to_string())
+            .unwrap_or_default();
+        let content_id = content_id
+            .strip_prefix("response-")
+            .unwrap_or(&content_id)
+            .to_string();
+
+        // Osadzona odpowiedź: "HTTP/1.1 200 OK", nagłówki, pusta linia, JSON
+        let (http_head, http_body) = split_head(part_body);
+        let status = http_head
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .with_context(|| format!("Malformed batch part for {}", content_id))?;
+
+        responses.push(BatchResponse {
+            content_id,
+            status,
+            body: http_body.trim().to_string(),
+        });
+    }
+    Ok(responses)
+}
+
+/// Dzieli body multipart na (nagłówki części, treść części)
+pub fn split_parts<'a>(body: &'a str, boundary: &str) -> Vec<(&'a str, &'a str)> {
+    let delimiter = format!("--{}", boundary);
+    body.split(delimiter.as_str())
+        .skip(1)
+        .take_while(|chunk| !chunk.starts_with("--"))
+        .map(|chunk| split_head(chunk.trim_start_matches(['\r', '\n'])))
+        .collect()
+}
+
+/// Oddziela blok nagłówków od treści (CRLF CRLF albo LF LF)
+pub fn split_head(s: &str) -> (&str, &str) {
+    if let Some(i) = s.find("\r\n\r\n") {
+        (&s[..i], &s[i + 4..])
+    } else if let Some(i) = s.find("\n\n") {
+        (&s[..i], &s[i + 2..])
+    } else {
+        (s, "")
+    }
+}
+
+pub fn header_value<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::GmailMessage;
+
+    /// Odpowiedź `messages.get` z batcha Gmaila (CRLF, części nie po kolei, 404 i 429 wśród 200)
+    const RESPONSE: &str = include_str!("../tests/fixtures/batch/messages_get.http");
+    const CONTENT_TYPE: &str = "multipart/mixed; boundary=batch_Ub6P4ZpOFxo_AAKmMxJ1wWI";
+
+    fn statuses(responses: &[BatchResponse]) -> Vec<(&str, u16)> {
+        responses.iter().map(|r| (r.content_id.as_str(), r.status)).collect()
+    }
+
+    #[test]
+    fn builds_one_part_per_request() {
+        let requests = [
+            BatchRequest { content_id: "item-0".into(), method: "GET", path: "/gmail/v1/users/me/messages/a?format=full".into() },
+            BatchRequest { content_id: "item-1".into(), method: "GET", path: "/gmail/v1/users/me/messages/b".into() },
+        ];
+        let body = build_body("batch_x", &requests);
+        assert_eq!(
+            body,
+            "--batch_x\r\nContent-Type: application/http\r\nContent-ID: <item-0>\r\n\r\n\
+             GET /gmail/v1/users/me/messages/a?format=full\r\n\r\n\
+             --batch_x\r\nContent-Type: application/http\r\nContent-ID: <item-1>\r\n\r\n\
+             GET /gmail/v1/users/me/messages/b\r\n\r\n\
+             --batch_x--\r\n"
+        );
+        assert_eq!(build_body("batch_x", &[]), "--batch_x--\r\n");
+    }
+
+    #[test]
+    fn parses_gmail_response_with_crlf_and_lf() {
+        assert!(RESPONSE.contains("\r\n"));
+        for body in [RESPONSE.to_string(), RESPONSE.replace("\r\n", "\n")] {
+            let responses = parse_response(CONTENT_TYPE, &body).unwrap();
+            // `<response-item-N>` wraca jako `item-N`, w kolejności z odpowiedzi
+            assert_eq!(statuses(&responses), [("item-0", 200), ("item-2", 429), ("item-1", 404), ("item-3", 200)]);
+
+            let first: GmailMessage = serde_json::from_str(&responses[0].body).unwrap();
+            assert_eq!(first.id, "18c1f0a2b3c4d5e6");
+            assert_eq!(first.snippet, "Cześć, w załączniku umowa do podpisu.");
+            let last: GmailMessage = serde_json::from_str(&responses[3].body).unwrap();
+            assert_eq!(last.label_ids, ["SENT"]);
+
+            let limited: serde_json::Value = serde_json::from_str(&responses[1].body).unwrap();
+            assert_eq!(limited["error"]["errors"][0]["reason"], "rateLimitExceeded");
+            let missing: serde_json::Value = serde_json::from_str(&responses[2].body).unwrap();
+            assert_eq!(missing["error"]["status"], "NOT_FOUND");
+        }
+    }
+
+    #[test]
+    fn reads_quoted_and_parameterized_boundaries() {
+        for content_type in [
+            "multipart/mixed; boundary=batch_Ub6P4ZpOFxo_AAKmMxJ1wWI",
+            "multipart/mixed; boundary=\"batch_Ub6P4ZpOFxo_AAKmMxJ1wWI\"",
+            "multipart/mixed;boundary=\"batch_Ub6P4ZpOFxo_AAKmMxJ1wWI\"; charset=UTF-8",
+        ] {
+            assert_eq!(boundary_from_content_type(content_type).as_deref(), Some("batch_Ub6P4ZpOFxo_AAKmMxJ1wWI"));
+            assert_eq!(parse_response(content_type, RESPONSE).unwrap().len(), 4);
+        }
+        assert!(parse_response("application/json; charset=UTF-8", RESPONSE).is_err());
+    }
+
+    #[test]
+    fn request_built_here_round_trips_through_the_parser_helpers() {
+        let requests: Vec<BatchRequest> = (0..3)
+            .map(|i| BatchRequest {
+                content_id: format!("item-{}", i),
+                method: "GET",
+                path: format!("/gmail/v1/users/me/messages/m{}?format=metadata", i),
+            })
+            .collect();
+        let body = build_body("batch_rt", &requests);
+        let parts: Vec<(Option<&str>, &str)> = split_parts(&body, "batch_rt")
+            .into_iter()
+            .map(|(headers, part)| (header_value(headers, "content-id"), split_head(part).0))
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                (Some("<item-0>"), "GET /gmail/v1/users/me/messages/m0?format=metadata"),
+                (Some("<item-1>"), "GET /gmail/v1/users/me/messages/m1?format=metadata"),
+                (Some("<item-2>"), "GET /gmail/v1/users/me/messages/m2?format=metadata"),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_part_is_an_error() {
+        let body = "--b\r\nContent-Type: application/http\r\nContent-ID: <response-item-0>\r\n\r\ngarbage\r\n--b--\r\n";
+        let err = parse_response("multipart/mixed; boundary=b", body).unwrap_err();
+        assert!(err.to_string().contains("item-0"), "{}", err);
+    }
+}
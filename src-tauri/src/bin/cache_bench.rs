@@ -0,0 +1,23 @@
+//! `cargo run --release --features cache-bench --bin cache_bench -- [--messages 500000] [cache.sqlite3]`
+
+use std::path::PathBuf;
+
+fn main() -> anyhow::Result<()> {
+    let mut count: i64 = 500_000;
+    let mut path = std::env::temp_dir().join("nexdeck_cache_bench.sqlite3");
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--messages" => {
+                count = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| anyhow::anyhow!("--messages needs a number"))?;
+            }
+            p => path = PathBuf::from(p),
+        }
+    }
+
+    nexdeck_lib::cache_bench::run(count, &path).map(|_| ())
+}
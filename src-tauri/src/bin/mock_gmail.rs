@@ -0,0 +1,40 @@
+//! `cargo run --features mock-gmail --bin mock_gmail -- [--port 3999] [mailbox.json]`
+
+use nexdeck_lib::mock_gmail::{MailboxScript, MockGmailServer, MockMailbox};
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut port: u16 = 3999;
+    let mut script_path: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                port = args
+                    .next()
+                    .and_then(|p| p.parse().ok())
+                    .ok_or_else(|| anyhow::anyhow!("--port needs a number"))?;
+            }
+            path => script_path = Some(path.to_string()),
+        }
+    }
+
+    let mailbox = match script_path {
+        Some(path) => {
+            let script: MailboxScript = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+            MockMailbox::from_script(script)
+        }
+        None => MockMailbox::new(),
+    };
+
+    let server = MockGmailServer::start(SocketAddr::from(([127, 0, 0, 1], port)), mailbox).await?;
+    eprintln!("🧪 Mock Gmail listening on http://{}", server.addr);
+    eprintln!("   NEXDECK_GMAIL_API_BASE={}", server.gmail_api_base());
+    eprintln!("   NEXDECK_OAUTH_AUTH_URL={}", server.oauth_auth_url());
+    eprintln!("   NEXDECK_OAUTH_TOKEN_URL={}", server.oauth_token_url());
+
+    server.wait().await;
+    Ok(())
+}
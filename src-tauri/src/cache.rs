@@ -1,11 +1,18 @@
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
 use anyhow::Result;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use std::fs;
 use dirs_next;
+use crate::outbox::MailOp;
+use crate::mime::looks_like_html;
+use crate::parser::{decode_address_header, decode_header, html_to_text, safe_body};
+use crate::sanitize::SANITIZER_VERSION;
+use crate::search::SearchQuery;
+use crate::types::{EmailData, EmailMessage, EmailThread, GmailHeader, GmailLabel, GmailMessage, LabelColor};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CachedMessage {
@@ -18,8 +25,118 @@ pub struct CachedMessage {
     pub synced_history_id: Option<i64>,
 }
 
+impl CachedMessage {
+    pub fn from_gmail(full: &GmailMessage, synced_history_id: Option<i64>) -> Self {
+        Self {
+            message_id: full.id.clone(),
+            thread_id: full.thread_id.clone(),
+            headers_json: serde_json::to_string(&full.payload.headers).unwrap_or_default(),
+            label_ids_json: serde_json::to_string(&full.label_ids).unwrap_or_default(),
+            snippet: full.snippet.clone(),
+            internal_date: full.internal_date.as_deref().and_then(|s| s.parse::<i64>().ok()).unwrap_or(0),
+            synced_history_id,
+        }
+    }
+}
+
+/// Sparsowana treść wiadomości (body + załączniki), niezmienna po pobraniu
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedBody {
+    pub message_id: String,
+    pub body: String,
+    pub body_text: String,
+    pub attachments_json: String,
+    pub inline_images_json: String,
+    pub fetched_at: i64,
+}
+
+/// Wiadomość `m` ma załączniki według zapisanych wierszy (body z listą załączników albo pobrany
+/// plik), a nie według zgadywania z Content-Type
+const HAS_ATTACHMENT: &str = "(EXISTS (SELECT 1 FROM bodies b, json_each(b.attachments_json) WHERE b.message_id = m.message_id)
+      OR EXISTS (SELECT 1 FROM attachments a WHERE a.message_id = m.message_id))";
+
+/// Wiersz listy wątków - agregaty policzone w SQL
+#[derive(Debug, Clone)]
+pub struct ThreadRow {
+    pub thread_id: String,
+    pub last_activity: i64,
+    pub message_count: usize,
+    pub unread: bool,
+    pub has_attachment: bool,
+}
+
+pub const OP_PENDING: &str = "pending";
+pub const OP_FAILED: &str = "failed";
+/// Kolejka poddała się po zbyt wielu błędach przejściowych
+pub const OP_DEAD_LETTER: &str = "dead_letter";
+
+/// Zmiana użytkownika w kolejce `pending_ops` (outbox)
+#[derive(Debug, Serialize, Clone)]
+pub struct PendingOp {
+    pub id: i64,
+    pub op: MailOp,
+    /// Stan wiadomości sprzed zmiany - przywracany, gdy Gmail ją odrzuci
+    #[serde(skip)]
+    pub snapshots: Vec<CachedMessage>,
+    pub status: String,
+    pub attempts: i64,
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: i64,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    /// Ustawiane przez komendy przy połączonym widoku kont (nie jest zapisywane)
+    #[serde(rename = "accountId", skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+}
+
+/// Wersja robocza z tabeli `drafts` - to ją edytuje composer, także offline
+#[derive(Debug, Serialize, Clone)]
+pub struct LocalDraft {
+    #[serde(rename = "localId")]
+    pub local_id: i64,
+    /// Id w Gmailu - brak, dopóki wersja nie została tam utworzona
+    #[serde(rename = "draftId")]
+    pub draft_id: Option<String>,
+    #[serde(rename = "messageId")]
+    pub message_id: Option<String>,
+    #[serde(rename = "threadId")]
+    pub thread_id: Option<String>,
+    pub data: EmailData,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+    /// Aktualna treść jest już zapisana w Gmailu
+    pub synced: bool,
+}
+
+/// Załącznik pobrany do katalogu `attachments` obok bazy (plik nazwany sha256 treści)
+#[derive(Debug, Clone)]
+pub struct StoredAttachment {
+    pub message_id: String,
+    pub attachment_id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub sha256: String,
+    pub file_name: String,
+    pub size: i64,
+    pub fetched_at: i64,
+}
+
+/// Katalog danych aplikacji (baza cache, tokeny)
+pub fn data_dir() -> Result<PathBuf> {
+    let mut base = dirs_next::data_local_dir()
+        .or_else(|| dirs_next::data_dir())
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    base.push("NexDeck");
+    fs::create_dir_all(&base)?;
+    Ok(base)
+}
+
 pub struct Cache {
     pool: Pool<SqliteConnectionManager>,
+    attachments_dir: PathBuf,
+    outgoing_dir: PathBuf,
 }
 
 impl Cache {
@@ -27,11 +144,7 @@ impl Cache {
         let db_path = match path {
             Some(p) => p,
             None => {
-                let mut base = dirs_next::data_local_dir()
-                    .or_else(|| dirs_next::data_dir())
-                    .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
-                base.push("NexDeck");
-                fs::create_dir_all(&base)?;
+                let mut base = data_dir()?;
                 base.push("nexdeck_cache.sqlite3");
                 base
             }
@@ -41,31 +154,25 @@ impl Cache {
             fs::create_dir_all(parent)?;
         }
 
+        let attachments_dir = db_path.with_file_name("attachments");
+        let outgoing_dir = db_path.with_file_name("outgoing");
         let manager = SqliteConnectionManager::file(db_path);
         let pool = Pool::builder().build(manager)?;
         {
-            let conn = pool.get()?;
-            conn.execute_batch(
-                "BEGIN;
-                CREATE TABLE IF NOT EXISTS messages (
-                    message_id TEXT PRIMARY KEY,
-                    thread_id TEXT NOT NULL,
-                    headers_json TEXT NOT NULL,
-                    label_ids_json TEXT NOT NULL,
-                    snippet TEXT,
-                    internal_date INTEGER NOT NULL,
-                    synced_history_id INTEGER
-                );
-                CREATE INDEX IF NOT EXISTS idx_thread_id ON messages(thread_id);
-                CREATE INDEX IF NOT EXISTS idx_internal_date ON messages(internal_date DESC);
-                CREATE TABLE IF NOT EXISTS meta (
-                    key TEXT PRIMARY KEY,
-                    value TEXT
-                );
-                COMMIT;",
-            )?;
+            let mut conn = pool.get()?;
+            crate::migrations::migrate(&mut conn)?;
         }
-        Ok(Self { pool })
+        Ok(Self { pool, attachments_dir, outgoing_dir })
+    }
+
+    /// Katalog pobranych załączników - osobny dla każdej bazy (konta)
+    pub fn attachments_dir(&self) -> &Path {
+        &self.attachments_dir
+    }
+
+    /// Gotowe wiadomości z załącznikami (.eml) czekające na wysłanie albo w trakcie uploadu
+    pub fn outgoing_dir(&self) -> &Path {
+        &self.outgoing_dir
     }
 
     fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
@@ -95,89 +202,450 @@ impl Cache {
                 msg.synced_history_id
             ],
         )?;
+        index_message(&conn, msg)?;
         Ok(())
     }
 
     pub fn delete_message(&self, message_id: &str) -> Result<()> {
         let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM messages_fts WHERE rowid = (SELECT rowid FROM messages WHERE message_id = ?1)",
+            params![message_id],
+        )?;
         conn.execute("DELETE FROM messages WHERE message_id = ?1", params![message_id])?;
+        conn.execute("DELETE FROM bodies WHERE message_id = ?1", params![message_id])?;
         Ok(())
     }
 
-    // ✅ Sortowanie WĄTKÓW po lastActivity (max internalDate w wątku)
-    pub fn load_threads_sorted(&self) -> Result<Vec<(String, Vec<CachedMessage>, i64)>> {
-        let conn = self.conn()?;
-        
-        // Znajdź wszystkie wątki z ich lastActivity
-        let mut stmt = conn.prepare(
-            "SELECT thread_id, MAX(internal_date) as last_activity
-             FROM messages
-             GROUP BY thread_id
-             ORDER BY last_activity DESC"
-        )?;
-        
-        let thread_activities: Vec<(String, i64)> = stmt.query_map([], |row| {
-            Ok((row.get(0)?, row.get(1)?))
-        })?.collect::<Result<Vec<_>, _>>()?;
-        
-        // Dla każdego wątku pobierz wszystkie wiadomości
-        let mut threads = Vec::new();
-        for (thread_id, last_activity) in thread_activities {
-            let mut msg_stmt = conn.prepare(
-                "SELECT message_id, thread_id, headers_json, label_ids_json, snippet, internal_date, synced_history_id
-                 FROM messages
-                 WHERE thread_id = ?1
-                 ORDER BY internal_date ASC" // wewnątrz wątku chronologicznie
-            )?;
-            
-            let messages: Vec<CachedMessage> = msg_stmt.query_map([&thread_id], |row| {
-                Ok(CachedMessage {
-                    message_id: row.get(0)?,
-                    thread_id: row.get(1)?,
-                    headers_json: row.get(2)?,
-                    label_ids_json: row.get(3)?,
-                    snippet: row.get(4)?,
-                    internal_date: row.get(5)?,
-                    synced_history_id: row.get(6)?,
-                })
-            })?.collect::<Result<Vec<_>, _>>()?;
-            
-            threads.push((thread_id, messages, last_activity));
-        }
-        
-        Ok(threads)
-    }
-
-    pub fn load_all_messages(&self) -> Result<Vec<CachedMessage>> {
+    pub fn load_message(&self, message_id: &str) -> Result<Option<CachedMessage>> {
         let conn = self.conn()?;
         let mut stmt = conn.prepare(
             "SELECT message_id, thread_id, headers_json, label_ids_json, snippet, internal_date, synced_history_id
              FROM messages
-             ORDER BY internal_date DESC"
-        )?;
-        let rows = stmt.query_map([], |row| {
-            Ok(CachedMessage {
-                message_id: row.get(0)?,
-                thread_id: row.get(1)?,
-                headers_json: row.get(2)?,
-                label_ids_json: row.get(3)?,
-                snippet: row.get(4)?,
-                internal_date: row.get(5)?,
-                synced_history_id: row.get(6)?,
+             WHERE message_id = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![message_id], map_cached_row)?;
+        match rows.next() {
+            Some(r) => Ok(Some(r?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Nakłada zmianę etykiet z rekordu historii na label_ids_json.
+    /// Zwraca false, jeśli wiadomości nie ma w cache (trzeba ją pobrać).
+    pub fn apply_label_changes(
+        &self,
+        message_id: &str,
+        added: &[String],
+        removed: &[String],
+        synced_history_id: Option<i64>,
+    ) -> Result<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT label_ids_json FROM messages WHERE message_id = ?1",
+                params![message_id],
+                |r| r.get(0),
+            )
+            .optional()?;
+        let Some(current) = current else {
+            return Ok(false);
+        };
+
+        let mut labels: Vec<String> = serde_json::from_str(&current).unwrap_or_default();
+        labels.retain(|l| !removed.contains(l));
+        for label in added {
+            if !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        }
+
+        tx.execute(
+            "UPDATE messages SET label_ids_json = ?2, synced_history_id = COALESCE(?3, synced_history_id)
+             WHERE message_id = ?1",
+            params![message_id, serde_json::to_string(&labels)?, synced_history_id],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn has_message(&self, message_id: &str) -> Result<bool> {
+        let conn = self.conn()?;
+        let n: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE message_id = ?1",
+            params![message_id],
+            |r| r.get(0),
+        )?;
+        Ok(n > 0)
+    }
+
+    /// Zapisuje sparsowane body i przeindeksowuje wiadomość (treść trafia do FTS)
+    pub fn put_body(&self, msg: &EmailMessage) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO bodies (message_id, body, body_text, attachments_json, inline_images_json, fetched_at, sanitizer_version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(message_id) DO UPDATE SET
+               body=excluded.body,
+               body_text=excluded.body_text,
+               attachments_json=excluded.attachments_json,
+               inline_images_json=excluded.inline_images_json,
+               fetched_at=excluded.fetched_at,
+               sanitizer_version=excluded.sanitizer_version
+            ;",
+            params![
+                msg.id,
+                msg.body,
+                html_to_text(&msg.body),
+                serde_json::to_string(&msg.attachments)?,
+                serde_json::to_string(&msg.inline_images)?,
+                chrono::Utc::now().timestamp_millis(),
+                SANITIZER_VERSION
+            ],
+        )?;
+        drop(conn);
+
+        if let Some(cached) = self.load_message(&msg.id)? {
+            index_message(&*self.conn()?, &cached)?;
+        }
+        Ok(())
+    }
+
+    /// Body z cache. Zapisane starszą wersją sanityzacji (albo sprzed niej) jest sanityzowane
+    /// ponownie i nadpisywane; typ części nie był zapisywany, więc HTML rozpoznajemy po tagach.
+    pub fn get_body(&self, message_id: &str) -> Result<Option<CachedBody>> {
+        let conn = self.conn()?;
+        let row = conn
+            .query_row(
+                "SELECT message_id, body, body_text, attachments_json, inline_images_json, fetched_at, sanitizer_version
+                 FROM bodies
+                 WHERE message_id = ?1",
+                params![message_id],
+                |row| {
+                    let body = CachedBody {
+                        message_id: row.get(0)?,
+                        body: row.get(1)?,
+                        body_text: row.get(2)?,
+                        attachments_json: row.get(3)?,
+                        inline_images_json: row.get(4)?,
+                        fetched_at: row.get(5)?,
+                    };
+                    Ok((body, row.get::<_, i64>(6)?))
+                },
+            )
+            .optional()?;
+        let Some((mut body, version)) = row else {
+            return Ok(None);
+        };
+        if version >= SANITIZER_VERSION {
+            return Ok(Some(body));
+        }
+
+        let html = looks_like_html(&body.body);
+        body.body = safe_body(std::mem::take(&mut body.body), html);
+        body.body_text = html_to_text(&body.body);
+        conn.execute(
+            "UPDATE bodies SET body = ?2, body_text = ?3, sanitizer_version = ?4 WHERE message_id = ?1",
+            params![message_id, body.body, body.body_text, SANITIZER_VERSION],
+        )?;
+        drop(conn);
+
+        if let Some(cached) = self.load_message(message_id)? {
+            index_message(&*self.conn()?, &cached)?;
+        }
+        Ok(Some(body))
+    }
+
+    pub fn has_body(&self, message_id: &str) -> Result<bool> {
+        let conn = self.conn()?;
+        let n: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM bodies WHERE message_id = ?1",
+            params![message_id],
+            |r| r.get(0),
+        )?;
+        Ok(n > 0)
+    }
+
+    /// Usuwa body wiadomości, których nie ma już w messages (np. po pełnym resyncu)
+    pub fn prune_orphan_bodies(&self) -> Result<usize> {
+        let conn = self.conn()?;
+        let removed = conn.execute(
+            "DELETE FROM bodies WHERE message_id NOT IN (SELECT message_id FROM messages)",
+            [],
+        )?;
+        Ok(removed)
+    }
+
+    /// Full-text search + filtry, sortowane po internal_date DESC
+    pub fn search_messages(&self, query: &SearchQuery, limit: usize, offset: usize) -> Result<Vec<CachedMessage>> {
+        let conn = self.conn()?;
+
+        let mut sql = String::from(
+            "SELECT m.message_id, m.thread_id, m.headers_json, m.label_ids_json, m.snippet, m.internal_date, m.synced_history_id
+             FROM messages m",
+        );
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        if let Some(expr) = query.fts_match() {
+            sql.push_str(" JOIN messages_fts ON messages_fts.rowid = m.rowid");
+            conditions.push("messages_fts MATCH ?".to_string());
+            values.push(expr.into());
+        }
+        if let Some(expr) = query.fts_exclude() {
+            conditions.push("m.rowid NOT IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH ?)".to_string());
+            values.push(expr.into());
+        }
+
+        if query.has_attachment {
+            conditions.push(HAS_ATTACHMENT.to_string());
+        }
+        if query.excludes_attachment() {
+            conditions.push(format!("NOT {}", HAS_ATTACHMENT));
+        }
+
+        for label in &query.labels {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM message_labels l WHERE l.message_id = m.message_id AND UPPER(l.label_id) = UPPER(?))"
+                    .to_string(),
+            );
+            values.push(label.clone().into());
+        }
+        for label in query.excluded_labels() {
+            conditions.push(
+                "NOT EXISTS (SELECT 1 FROM message_labels l WHERE l.message_id = m.message_id AND UPPER(l.label_id) = UPPER(?))"
+                    .to_string(),
+            );
+            values.push(label.clone().into());
+        }
+
+        match query.unread {
+            Some(true) => conditions.push(
+                "EXISTS (SELECT 1 FROM message_labels l WHERE l.message_id = m.message_id AND l.label_id = 'UNREAD')".to_string(),
+            ),
+            Some(false) => conditions.push(
+                "NOT EXISTS (SELECT 1 FROM message_labels l WHERE l.message_id = m.message_id AND l.label_id = 'UNREAD')".to_string(),
+            ),
+            None => {}
+        }
+
+        if let Some(after) = query.after {
+            conditions.push("m.internal_date >= ?".to_string());
+            values.push(after.into());
+        }
+        if let Some(before) = query.before {
+            conditions.push("m.internal_date < ?".to_string());
+            values.push(before.into());
+        }
+
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY m.internal_date DESC LIMIT ? OFFSET ?");
+        values.push((limit as i64).into());
+        values.push((offset as i64).into());
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), map_cached_row)?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Strona wątków z etykiet `label_ids` po kluczu (last_activity, thread_id), najnowsze pierwsze.
+    /// Wątek trafia na listę, jeśli choć jedna wiadomość pasuje do etykiety (te same reguły
+    /// co lista wiadomości: bez kosza, a wersje robocze bez wysłanych i spamu).
+    /// Kolejność i przynależność bierzemy z thread_labels (zakres klucza na etykietę, jak w
+    /// `load_label_page`), agregaty liczymy tylko dla wątków strony.
+    pub fn load_thread_page(&self, label_ids: &[&str], after: Option<&PageKey>, limit: usize) -> Result<Vec<ThreadRow>> {
+        let conn = self.conn()?;
+        let filter = LabelFilter::new(label_ids);
+        let (date, id) = match after {
+            Some(key) => (key.internal_date, key.id.as_str()),
+            None => (i64::MAX, ""),
+        };
+
+        // '' = wszystkie wiadomości poza koszem. Przy kilku etykietach wątek stoi na pozycji swojej
+        // najnowszej daty z nich wszystkich: z zakresu etykiety bierzemy tylko wiersze z tą datą,
+        // więc wątek nie wraca na kolejnej stronie z innej etykiety i żaden nie wypada między stronami.
+        let keys = if filter.labels.is_empty() { vec![String::new()] } else { filter.labels.clone() };
+        let latest_only = if keys.len() > 1 {
+            format!(
+                "AND t.last_date = (SELECT MAX(x.last_date) FROM thread_labels x
+                                    WHERE x.thread_id = t.thread_id AND x.label_id IN ({}))",
+                vec!["?"; keys.len()].join(", ")
+            )
+        } else {
+            String::new()
+        };
+        let mut recent = conn.prepare(&format!(
+            "SELECT t.thread_id, t.last_date FROM thread_labels t
+             WHERE t.label_id = ? AND (t.last_date, t.thread_id) < (?, ?) {}
+             ORDER BY t.last_date DESC, t.thread_id DESC
+             LIMIT ?",
+            latest_only
+        ))?;
+        let mut page: Vec<(String, i64)> = Vec::new();
+        for key in &keys {
+            let mut values: Vec<rusqlite::types::Value> = vec![key.clone().into(), date.into(), id.to_string().into()];
+            if keys.len() > 1 {
+                values.extend(keys.iter().map(|k| k.clone().into()));
+            }
+            values.push((limit as i64).into());
+            let rows = recent.query_map(rusqlite::params_from_iter(values), |r| Ok((r.get(0)?, r.get(1)?)))?;
+            for row in rows {
+                let (thread_id, last_date): (String, i64) = row?;
+                if !page.iter().any(|(t, _)| *t == thread_id) {
+                    page.push((thread_id, last_date));
+                }
+            }
+        }
+        if keys.len() > 1 {
+            page.sort_by(|a, b| (b.1, &b.0).cmp(&(a.1, &a.0)));
+            page.truncate(limit);
+        }
+        if page.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let sql = format!(
+            "SELECT m.thread_id,
+                    COUNT(*),
+                    MAX(EXISTS (SELECT 1 FROM message_labels u WHERE u.message_id = m.message_id AND u.label_id = 'UNREAD')),
+                    MAX({})
+             FROM messages m
+             WHERE m.thread_id IN ({}) AND {}
+             GROUP BY m.thread_id",
+            HAS_ATTACHMENT,
+            vec!["?"; page.len()].join(", "),
+            filter.visible("m"),
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut stats: HashMap<String, (usize, bool, bool)> = stmt
+            .query_map(rusqlite::params_from_iter(page.iter().map(|(t, _)| t)), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    (row.get::<_, i64>(1)? as usize, row.get::<_, i64>(2)? != 0, row.get::<_, i64>(3)? != 0),
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        // Data z indeksu (nie z agregatu), żeby klucz następnej strony zgadzał się z thread_labels
+        Ok(page
+            .into_iter()
+            .map(|(thread_id, last_activity)| {
+                let (message_count, unread, has_attachment) = stats.remove(&thread_id).unwrap_or_default();
+                ThreadRow { thread_id, last_activity, message_count, unread, has_attachment }
             })
-        })?;
+            .collect())
+    }
+
+    /// Wiadomości podanych wątków, chronologicznie
+    pub fn load_thread_messages(&self, thread_ids: &[String], include_trash: bool) -> Result<Vec<CachedMessage>> {
+        if thread_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn()?;
+        let placeholders = vec!["?"; thread_ids.len()].join(", ");
+        let trash = if include_trash { "1" } else { "NOT EXISTS (SELECT 1 FROM message_labels l WHERE l.message_id = m.message_id AND l.label_id = 'TRASH')" };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT message_id, thread_id, headers_json, label_ids_json, snippet, internal_date, synced_history_id
+             FROM messages m
+             WHERE thread_id IN ({}) AND {}
+             ORDER BY internal_date ASC",
+            placeholders, trash
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(thread_ids), map_cached_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Strona wiadomości etykiet(y) po kluczu (internal_date, message_id), najnowsze pierwsze.
+    /// Każda etykieta to zakres klucza głównego message_labels; kilka etykiet scalamy w Rust.
+    pub fn load_label_page(&self, label_ids: &[&str], after: Option<&PageKey>, limit: usize) -> Result<Vec<CachedMessage>> {
+        let conn = self.conn()?;
+        let filter = LabelFilter::new(label_ids);
+        let (date, id) = match after {
+            Some(key) => (key.internal_date, key.id.as_str()),
+            None => (i64::MAX, ""),
+        };
 
-        let mut messages = Vec::new();
-        for r in rows {
-            messages.push(r?);
+        if filter.labels.is_empty() {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT m.message_id, m.thread_id, m.headers_json, m.label_ids_json, m.snippet, m.internal_date, m.synced_history_id
+                 FROM messages m
+                 WHERE (m.internal_date, m.message_id) < (?1, ?2) AND {}
+                 ORDER BY m.internal_date DESC, m.message_id DESC
+                 LIMIT ?3",
+                filter.visible("m")
+            ))?;
+            let rows = stmt.query_map(params![date, id, limit as i64], map_cached_row)?;
+            return Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?);
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT m.message_id, m.thread_id, m.headers_json, m.label_ids_json, m.snippet, m.internal_date, m.synced_history_id
+             FROM message_labels ml
+             JOIN messages m ON m.message_id = ml.message_id
+             WHERE ml.label_id = ?1 AND (ml.internal_date, ml.message_id) < (?2, ?3) AND {}
+             ORDER BY ml.internal_date DESC, ml.message_id DESC
+             LIMIT ?4",
+            filter.not_excluded("ml")
+        ))?;
+        let mut messages: Vec<CachedMessage> = Vec::new();
+        for label in &filter.labels {
+            let rows = stmt.query_map(params![label, date, id, limit as i64], map_cached_row)?;
+            for row in rows {
+                let m = row?;
+                if !messages.iter().any(|x| x.message_id == m.message_id) {
+                    messages.push(m);
+                }
+            }
+        }
+        if filter.labels.len() > 1 {
+            messages.sort_by(|a, b| (b.internal_date, &b.message_id).cmp(&(a.internal_date, &a.message_id)));
+            messages.truncate(limit);
         }
-        
         Ok(messages)
     }
 
+    /// (etykieta, wszystkie, nieprzeczytane) - z pominięciem wersji roboczych w koszu/wysłanych/spamie
+    pub fn label_counts(&self) -> Result<Vec<(String, i64, i64)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT label_id, COUNT(*), SUM(unread) FROM message_labels WHERE label_id <> 'DRAFT' GROUP BY label_id
+             UNION ALL
+             SELECT 'DRAFT', COUNT(*), COALESCE(SUM(ml.unread), 0) FROM message_labels ml
+             WHERE ml.label_id = 'DRAFT' AND NOT EXISTS (
+                 SELECT 1 FROM message_labels x WHERE x.message_id = ml.message_id AND x.label_id IN ('TRASH', 'SENT', 'SPAM'))
+             HAVING COUNT(*) > 0",
+        )?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// (wszystkie, nieprzeczytane) wiadomości odebrane od `since_ms`
+    pub fn count_since(&self, since_ms: i64) -> Result<(i64, i64)> {
+        let conn = self.conn()?;
+        let total: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE internal_date >= ?1",
+            params![since_ms],
+            |r| r.get(0),
+        )?;
+        let unread: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM message_labels WHERE label_id = 'UNREAD' AND internal_date >= ?1",
+            params![since_ms],
+            |r| r.get(0),
+        )?;
+        Ok((total, unread))
+    }
+
     pub fn clear_all_messages(&self) -> Result<()> {
         let conn = self.conn()?;
+        conn.execute("DELETE FROM message_labels", [])?;
         conn.execute("DELETE FROM messages", [])?;
+        conn.execute("DELETE FROM messages_fts", [])?;
         eprintln!("🗑️  Cleared all messages from cache");
         Ok(())
     }
@@ -208,4 +676,824 @@ impl Cache {
         conn.execute("DELETE FROM meta WHERE key = ?1", params![key])?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Podmienia listę etykiet na aktualną z Gmaila (usunięte gdzie indziej znikają)
+    pub fn replace_labels(&self, labels: &[GmailLabel]) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM labels", [])?;
+        for label in labels {
+            insert_label(&tx, label)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn upsert_label(&self, label: &GmailLabel) -> Result<()> {
+        insert_label(&*self.conn()?, label)
+    }
+
+    /// Usuwa etykietę i zdejmuje ją z wiadomości (jak Gmail); message_labels poprawia trigger
+    pub fn delete_label(&self, label_id: &str) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE messages
+             SET label_ids_json = (SELECT json_group_array(value) FROM json_each(messages.label_ids_json) WHERE value <> ?1)
+             WHERE message_id IN (SELECT message_id FROM message_labels WHERE label_id = ?1)",
+            params![label_id],
+        )?;
+        tx.execute("DELETE FROM labels WHERE label_id = ?1", params![label_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Etykiety posortowane po nazwie (rodzic przed dziećmi)
+    pub fn load_labels(&self) -> Result<Vec<GmailLabel>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT label_id, name, type, message_list_visibility, label_list_visibility, text_color, background_color
+             FROM labels
+             ORDER BY name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            let text_color: Option<String> = r.get(5)?;
+            let background_color: Option<String> = r.get(6)?;
+            Ok(GmailLabel {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                label_type: r.get(2)?,
+                message_list_visibility: r.get(3)?,
+                label_list_visibility: r.get(4)?,
+                color: text_color.zip(background_color).map(|(text_color, background_color)| LabelColor {
+                    text_color,
+                    background_color,
+                }),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Dopisuje zmianę na koniec kolejki
+    pub fn enqueue_op(&self, op: &MailOp, snapshots: &[CachedMessage]) -> Result<i64> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO pending_ops (op_json, snapshots_json, status, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                serde_json::to_string(op)?,
+                serde_json::to_string(snapshots)?,
+                OP_PENDING,
+                chrono::Utc::now().timestamp_millis()
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Najstarsza oczekująca zmiana - kolejka jest wykonywana ściśle po kolei
+    pub fn next_pending_op(&self) -> Result<Option<PendingOp>> {
+        let conn = self.conn()?;
+        let op = conn
+            .query_row(
+                &format!("{} WHERE status = ?1 ORDER BY id LIMIT 1", PENDING_OP_SELECT),
+                params![OP_PENDING],
+                map_op_row,
+            )
+            .optional()?;
+        Ok(op)
+    }
+
+    pub fn get_op(&self, id: i64) -> Result<Option<PendingOp>> {
+        let conn = self.conn()?;
+        let op = conn
+            .query_row(&format!("{} WHERE id = ?1", PENDING_OP_SELECT), params![id], map_op_row)
+            .optional()?;
+        Ok(op)
+    }
+
+    /// Cała kolejka (oczekujące i odrzucone), od najstarszych
+    pub fn list_ops(&self) -> Result<Vec<PendingOp>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!("{} ORDER BY id", PENDING_OP_SELECT))?;
+        let rows = stmt.query_map([], map_op_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn remove_op(&self, id: i64) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM pending_ops WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Błąd przejściowy - kolejna próba nie wcześniej niż `next_attempt_at` (ms).
+    /// `counted = false` (brak sieci) nie zwiększa licznika prób.
+    pub fn reschedule_op(&self, id: i64, next_attempt_at: i64, error: &str, counted: bool) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE pending_ops SET attempts = attempts + ?4, next_attempt_at = ?2, last_error = ?3 WHERE id = ?1",
+            params![id, next_attempt_at, error, counted as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Koniec prób (`OP_FAILED` albo `OP_DEAD_LETTER`) - zmiana zostaje w kolejce tylko do wglądu
+    /// (nie blokuje kolejnych)
+    pub fn fail_op(&self, id: i64, status: &str, error: &str) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE pending_ops SET attempts = attempts + 1, status = ?2, last_error = ?3 WHERE id = ?1",
+            params![id, status, error],
+        )?;
+        Ok(())
+    }
+
+    /// Sieć wróciła - oczekujące zmiany nie muszą czekać do końca backoffu
+    pub fn reset_op_backoff(&self) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE pending_ops SET next_attempt_at = 0 WHERE status = ?1 AND next_attempt_at > 0",
+            params![OP_PENDING],
+        )?;
+        Ok(())
+    }
+
+    /// Ponowienie odrzuconej (albo porzuconej) zmiany na żądanie użytkownika
+    pub fn retry_op(&self, id: i64) -> Result<bool> {
+        let conn = self.conn()?;
+        let n = conn.execute(
+            "UPDATE pending_ops SET status = ?2, attempts = 0, next_attempt_at = 0 WHERE id = ?1 AND status IN (?3, ?4)",
+            params![id, OP_PENDING, OP_FAILED, OP_DEAD_LETTER],
+        )?;
+        Ok(n > 0)
+    }
+
+    /// Zapis z edytora: nowa wersja albo zmiana istniejącej, zawsze do wysłania (dirty)
+    pub fn save_draft(&self, local_id: Option<i64>, data: &EmailData, thread_id: Option<&str>) -> Result<LocalDraft> {
+        let conn = self.conn()?;
+        let data_json = serde_json::to_string(data)?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let local_id = match local_id {
+            Some(id) => {
+                let n = conn.execute(
+                    // updated_at rośnie przy każdym zapisie - po nim rozpoznajemy wysłaną treść
+                    "UPDATE drafts SET data_json = ?2, thread_id = COALESCE(?3, thread_id),
+                                       updated_at = MAX(?4, updated_at + 1), dirty = 1
+                     WHERE local_id = ?1",
+                    params![id, data_json, thread_id, now],
+                )?;
+                if n == 0 {
+                    anyhow::bail!("Draft {} not found", id);
+                }
+                id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO drafts (thread_id, data_json, updated_at, dirty) VALUES (?1, ?2, ?3, 1)",
+                    params![thread_id, data_json, now],
+                )?;
+                conn.last_insert_rowid()
+            }
+        };
+        drop(conn);
+        self.get_draft(local_id)?
+            .ok_or_else(|| anyhow::anyhow!("Draft {} not found", local_id))
+    }
+
+    pub fn get_draft(&self, local_id: i64) -> Result<Option<LocalDraft>> {
+        let conn = self.conn()?;
+        let draft = conn
+            .query_row(&format!("{} WHERE local_id = ?1", DRAFT_SELECT), params![local_id], map_draft_row)
+            .optional()?;
+        Ok(draft)
+    }
+
+    /// Najnowsze pierwsze
+    pub fn list_drafts(&self) -> Result<Vec<LocalDraft>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!("{} ORDER BY updated_at DESC, local_id DESC", DRAFT_SELECT))?;
+        let rows = stmt.query_map([], map_draft_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Po zapisie w Gmailu. `pushed_at` = updated_at wysłanej treści - jeśli w międzyczasie
+    /// przyszła nowsza edycja, wersja zostaje dirty i wyjdzie przy następnym zapisie.
+    pub fn mark_draft_synced(&self, local_id: i64, draft_id: &str, message_id: &str, pushed_at: i64) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE drafts SET draft_id = ?2, message_id = ?3, dirty = CASE WHEN updated_at = ?4 THEN 0 ELSE dirty END
+             WHERE local_id = ?1",
+            params![local_id, draft_id, message_id, pushed_at],
+        )?;
+        Ok(())
+    }
+
+    /// Wersja z Gmaila (utworzona albo zmieniona w innym kliencie) - bez lokalnych zmian
+    pub fn store_remote_draft(
+        &self,
+        local_id: Option<i64>,
+        draft_id: &str,
+        message_id: &str,
+        thread_id: Option<&str>,
+        data: &EmailData,
+    ) -> Result<()> {
+        let conn = self.conn()?;
+        let data_json = serde_json::to_string(data)?;
+        let now = chrono::Utc::now().timestamp_millis();
+        match local_id {
+            Some(id) => conn.execute(
+                "UPDATE drafts SET message_id = ?2, thread_id = ?3, data_json = ?4, updated_at = ?5
+                 WHERE local_id = ?1 AND dirty = 0",
+                params![id, message_id, thread_id, data_json, now],
+            )?,
+            None => conn.execute(
+                "INSERT INTO drafts (draft_id, message_id, thread_id, data_json, updated_at, dirty)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 0)",
+                params![draft_id, message_id, thread_id, data_json, now],
+            )?,
+        };
+        Ok(())
+    }
+
+    pub fn remove_draft(&self, local_id: i64) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM drafts WHERE local_id = ?1", params![local_id])?;
+        Ok(())
+    }
+
+    /// Pobrany załącznik; odczyt odświeża `last_used_at` (kolejność usuwania przy limicie)
+    pub fn find_attachment(&self, message_id: &str, attachment_id: &str, now: i64) -> Result<Option<StoredAttachment>> {
+        let conn = self.conn()?;
+        let found = conn
+            .query_row(
+                "UPDATE attachments SET last_used_at = ?3 WHERE message_id = ?1 AND attachment_id = ?2
+                 RETURNING message_id, attachment_id, filename, mime_type, sha256, file_name, size, fetched_at",
+                params![message_id, attachment_id, now],
+                |row| {
+                    Ok(StoredAttachment {
+                        message_id: row.get(0)?,
+                        attachment_id: row.get(1)?,
+                        filename: row.get(2)?,
+                        mime_type: row.get(3)?,
+                        sha256: row.get(4)?,
+                        file_name: row.get(5)?,
+                        size: row.get(6)?,
+                        fetched_at: row.get(7)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(found)
+    }
+
+    pub fn put_attachment(&self, a: &StoredAttachment) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO attachments
+               (message_id, attachment_id, filename, mime_type, sha256, file_name, size, fetched_at, last_used_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+            params![a.message_id, a.attachment_id, a.filename, a.mime_type, a.sha256, a.file_name, a.size, a.fetched_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_attachment(&self, message_id: &str, attachment_id: &str) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM attachments WHERE message_id = ?1 AND attachment_id = ?2",
+            params![message_id, attachment_id],
+        )?;
+        Ok(())
+    }
+
+    /// Pliki magazynu (nazwa, rozmiar), od najdawniej używanego
+    pub fn attachment_files(&self) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT file_name, MAX(size) FROM attachments GROUP BY file_name ORDER BY MAX(last_used_at)",
+        )?;
+        let files = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(files)
+    }
+
+    /// Usuwa wpisy wszystkich wiadomości wskazujących na ten plik
+    pub fn remove_attachment_file(&self, file_name: &str) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM attachments WHERE file_name = ?1", params![file_name])?;
+        Ok(())
+    }
+
+    /// Czy nadawca ma włączone "zawsze ładuj obrazki"
+    pub fn sender_images_allowed(&self, email: &str) -> Result<bool> {
+        let conn = self.conn()?;
+        let allowed = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM image_senders WHERE email = ?1)",
+            params![email.to_lowercase()],
+            |r| r.get(0),
+        )?;
+        Ok(allowed)
+    }
+
+    pub fn set_sender_images_allowed(&self, email: &str, allowed: bool) -> Result<()> {
+        let conn = self.conn()?;
+        if allowed {
+            conn.execute(
+                "INSERT OR IGNORE INTO image_senders (email, added_at) VALUES (?1, ?2)",
+                params![email.to_lowercase(), chrono::Utc::now().timestamp_millis()],
+            )?;
+        } else {
+            conn.execute("DELETE FROM image_senders WHERE email = ?1", params![email.to_lowercase()])?;
+        }
+        Ok(())
+    }
+
+    /// Nadawcy z włączonymi obrazkami, od ostatnio dodanego
+    pub fn image_senders(&self) -> Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT email FROM image_senders ORDER BY added_at DESC")?;
+        let senders = stmt.query_map([], |r| r.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(senders)
+    }
+
+    /// Czy identyczna zmiana już czeka w kolejce (np. kolejny autozapis tej samej wersji)
+    pub fn has_pending_op(&self, op: &MailOp) -> Result<bool> {
+        let conn = self.conn()?;
+        let exists = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pending_ops WHERE status = ?1 AND op_json = ?2)",
+            params![OP_PENDING, serde_json::to_string(op)?],
+            |r| r.get(0),
+        )?;
+        Ok(exists)
+    }
+}
+
+/// Filtr etykiet w SQL nad message_labels. Etykiety systemowe (INBOX, SENT...) bez względu
+/// na wielkość liter - każda w wersji oryginalnej i wielkimi literami; id etykiet użytkownika
+/// (`Label_123`) Gmail rozróżnia, więc pasują dokładnie.
+struct LabelFilter {
+    labels: Vec<String>,
+    excluded: Vec<&'static str>,
+}
+
+impl LabelFilter {
+    fn new(label_ids: &[&str]) -> Self {
+        let mut labels: Vec<String> = Vec::new();
+        for label in label_ids.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            for variant in [label.to_string(), label.to_uppercase()] {
+                if !labels.contains(&variant) {
+                    labels.push(variant);
+                }
+            }
+        }
+        // Kosz tylko w widoku kosza; wersje robocze bez wysłanych i spamu
+        let mut excluded = Vec::new();
+        if !labels.iter().any(|l| l == "TRASH") {
+            excluded.push("TRASH");
+        }
+        if labels.iter().any(|l| l == "DRAFT") {
+            for label in ["SENT", "SPAM", "TRASH"] {
+                if !excluded.contains(&label) {
+                    excluded.push(label);
+                }
+            }
+        }
+        Self { labels, excluded }
+    }
+
+    fn has_any(alias: &str, labels: &str) -> String {
+        format!(
+            "EXISTS (SELECT 1 FROM message_labels l WHERE l.message_id = {}.message_id AND l.label_id IN ({}))",
+            alias, labels
+        )
+    }
+
+    fn quoted(labels: &[&str]) -> String {
+        labels.iter().map(|l| format!("'{}'", l)).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Wiadomość bez etykiet wykluczonych z widoku (`alias` ma kolumnę message_id)
+    fn not_excluded(&self, alias: &str) -> String {
+        if self.excluded.is_empty() {
+            "1".to_string()
+        } else {
+            format!("NOT {}", Self::has_any(alias, &Self::quoted(&self.excluded)))
+        }
+    }
+
+    /// Wiadomość, która może być pokazana w widoku (kosz tylko w widoku kosza)
+    fn visible(&self, alias: &str) -> String {
+        if self.labels.iter().any(|l| l == "TRASH") {
+            "1".to_string()
+        } else {
+            format!("NOT {}", Self::has_any(alias, "'TRASH'"))
+        }
+    }
+}
+
+/// Token strony: klucz ostatniej wiadomości (albo wątku) poprzedniej strony
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageKey {
+    pub internal_date: i64,
+    /// message_id, a na liście wątków thread_id
+    pub id: String,
+}
+
+impl PageKey {
+    pub fn of(m: &CachedMessage) -> Self {
+        Self {
+            internal_date: m.internal_date,
+            id: m.message_id.clone(),
+        }
+    }
+
+    pub fn of_thread(t: &EmailThread) -> Self {
+        Self {
+            internal_date: t.last_activity,
+            id: t.thread_id.clone(),
+        }
+    }
+
+    /// `"<internal_date>:<id>"`; stare tokeny (przesunięcie) = pierwsza strona
+    pub fn parse(token: &str) -> Option<Self> {
+        let (date, id) = token.split_once(':')?;
+        Some(Self {
+            internal_date: date.parse().ok()?,
+            id: id.to_string(),
+        })
+    }
+
+    pub fn token(&self) -> String {
+        format!("{}:{}", self.internal_date, self.id)
+    }
+}
+
+const PENDING_OP_SELECT: &str =
+    "SELECT id, op_json, snapshots_json, status, attempts, next_attempt_at, last_error, created_at FROM pending_ops";
+
+const DRAFT_SELECT: &str =
+    "SELECT local_id, draft_id, message_id, thread_id, data_json, updated_at, dirty FROM drafts";
+
+fn map_draft_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<LocalDraft> {
+    let data_json: String = row.get(4)?;
+    Ok(LocalDraft {
+        local_id: row.get(0)?,
+        draft_id: row.get(1)?,
+        message_id: row.get(2)?,
+        thread_id: row.get(3)?,
+        data: serde_json::from_str(&data_json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e)))?,
+        updated_at: row.get(5)?,
+        synced: row.get::<_, i64>(6)? == 0,
+    })
+}
+
+fn map_op_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<PendingOp> {
+    let json_col = |idx: usize, e: serde_json::Error| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    };
+    let op_json: String = row.get(1)?;
+    let snapshots_json: String = row.get(2)?;
+    Ok(PendingOp {
+        id: row.get(0)?,
+        op: serde_json::from_str(&op_json).map_err(|e| json_col(1, e))?,
+        snapshots: serde_json::from_str(&snapshots_json).map_err(|e| json_col(2, e))?,
+        status: row.get(3)?,
+        attempts: row.get(4)?,
+        next_attempt_at: row.get(5)?,
+        last_error: row.get(6)?,
+        created_at: row.get(7)?,
+        account_id: None,
+    })
+}
+
+fn map_cached_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<CachedMessage> {
+    Ok(CachedMessage {
+        message_id: row.get(0)?,
+        thread_id: row.get(1)?,
+        headers_json: row.get(2)?,
+        label_ids_json: row.get(3)?,
+        snippet: row.get(4)?,
+        internal_date: row.get(5)?,
+        synced_history_id: row.get(6)?,
+    })
+}
+
+fn insert_label(conn: &rusqlite::Connection, label: &GmailLabel) -> Result<()> {
+    let color = label.color.as_ref();
+    conn.execute(
+        "INSERT OR REPLACE INTO labels
+             (label_id, name, type, message_list_visibility, label_list_visibility, text_color, background_color)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            label.id,
+            label.name,
+            label.label_type,
+            label.message_list_visibility,
+            label.label_list_visibility,
+            color.map(|c| &c.text_color),
+            color.map(|c| &c.background_color)
+        ],
+    )?;
+    Ok(())
+}
+
+/// (Re)indeksuje jedną wiadomość w messages_fts na podstawie zapisanych nagłówków
+fn index_message(conn: &rusqlite::Connection, msg: &CachedMessage) -> Result<()> {
+    let headers: Vec<GmailHeader> = serde_json::from_str(&msg.headers_json).unwrap_or_default();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let recipients = [header("To"), header("Cc")]
+        .iter()
+        .map(|h| decode_address_header(h))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let has_attachment = header("Content-Type").to_ascii_lowercase().starts_with("multipart/mixed");
+
+    // Jeśli body jest już w cache - indeksuj treść i użyj prawdziwej listy załączników
+    conn.execute(
+        "INSERT OR REPLACE INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+         SELECT m.rowid, ?2, ?3, ?4, ?5, COALESCE(b.body_text, ''), COALESCE(b.attachments_json <> '[]', ?6)
+         FROM messages m
+         LEFT JOIN bodies b ON b.message_id = m.message_id
+         WHERE m.message_id = ?1",
+        params![
+            msg.message_id,
+            decode_header(&header("Subject")),
+            decode_address_header(&header("From")),
+            recipients,
+            msg.snippet,
+            has_attachment
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::types::EmailAttachment;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Świeża baza w katalogu tymczasowym (osobna dla każdego testu)
+    pub(crate) fn test_cache() -> Cache {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "nexdeck-cache-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        Cache::new(Some(dir.join("cache.sqlite3"))).unwrap()
+    }
+
+    pub(crate) fn message(id: &str, from: &str, subject: &str, content_type: &str, labels: &[&str], date: i64) -> CachedMessage {
+        CachedMessage {
+            message_id: id.to_string(),
+            thread_id: format!("t-{}", id),
+            headers_json: serde_json::json!([
+                { "name": "From", "value": from },
+                { "name": "Subject", "value": subject },
+                { "name": "Content-Type", "value": content_type },
+            ])
+            .to_string(),
+            label_ids_json: serde_json::to_string(labels).unwrap(),
+            snippet: subject.to_string(),
+            internal_date: date,
+            synced_history_id: None,
+        }
+    }
+
+    fn body(id: &str, attachments: Vec<EmailAttachment>) -> EmailMessage {
+        EmailMessage {
+            id: id.to_string(),
+            thread_id: format!("t-{}", id),
+            label_ids: Vec::new(),
+            from: String::new(),
+            to: String::new(),
+            subject: String::new(),
+            date: String::new(),
+            snippet: String::new(),
+            body: "<p>treść</p>".to_string(),
+            unread: false,
+            has_attachment: !attachments.is_empty(),
+            attachments,
+            inline_images: Vec::new(),
+            internal_date: None,
+            account_id: None,
+            addresses: Default::default(),
+            remote_content: Default::default(),
+        }
+    }
+
+    fn search(cache: &Cache, q: &str) -> Vec<String> {
+        let query = SearchQuery::parse(q);
+        let mut ids: Vec<String> =
+            cache.search_messages(&query, 50, 0).unwrap().into_iter().map(|m| m.message_id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn has_attachment_uses_stored_attachment_rows() {
+        let cache = test_cache();
+        // multipart/mixed bez załączników (np. podpis w osobnej części) i zwykła wiadomość z plikiem
+        cache.upsert_message(&message("a", "jan@example.com", "raport", "multipart/mixed; boundary=x", &["INBOX"], 1)).unwrap();
+        cache.upsert_message(&message("b", "ola@example.com", "faktura", "text/plain", &["INBOX"], 2)).unwrap();
+        cache.upsert_message(&message("c", "ola@example.com", "zdjęcia", "multipart/mixed; boundary=y", &["INBOX"], 3)).unwrap();
+        cache.put_body(&body("a", Vec::new())).unwrap();
+        cache
+            .put_body(&body(
+                "b",
+                vec![EmailAttachment { id: "att1".into(), filename: "f.pdf".into(), size: 10, mime_type: "application/pdf".into() }],
+            ))
+            .unwrap();
+        // Bez body, ale z pobranym plikiem w magazynie załączników
+        cache
+            .put_attachment(&StoredAttachment {
+                message_id: "c".into(),
+                attachment_id: "att2".into(),
+                filename: "a.jpg".into(),
+                mime_type: "image/jpeg".into(),
+                sha256: "00".into(),
+                file_name: "00".into(),
+                size: 1,
+                fetched_at: 0,
+            })
+            .unwrap();
+
+        assert_eq!(search(&cache, "has:attachment"), vec!["b", "c"]);
+        assert_eq!(search(&cache, "-has:attachment"), vec!["a"]);
+        assert_eq!(search(&cache, "from:ola has:attachment faktura"), vec!["b"]);
+    }
+
+    #[test]
+    fn negated_terms_and_labels_exclude_messages() {
+        let cache = test_cache();
+        cache.upsert_message(&message("a", "jan@example.com", "raport kwartalny", "text/plain", &["INBOX"], 1)).unwrap();
+        cache.upsert_message(&message("b", "bot@example.com", "raport dzienny", "text/plain", &["INBOX"], 2)).unwrap();
+        cache.upsert_message(&message("c", "ola@example.com", "raport roczny", "text/plain", &["SPAM"], 3)).unwrap();
+
+        assert_eq!(search(&cache, "raport -dzienny"), vec!["a", "c"]);
+        assert_eq!(search(&cache, "raport -from:bot@example.com -label:spam"), vec!["a"]);
+        // Samo zaprzeczenie, bez pozytywnego tekstu
+        assert_eq!(search(&cache, "-kwartalny"), vec!["b", "c"]);
+    }
+
+    fn in_thread(thread: &str, id: &str, labels: &[&str], date: i64) -> CachedMessage {
+        CachedMessage { thread_id: thread.to_string(), ..message(id, "a@x.pl", id, "text/plain", labels, date) }
+    }
+
+    /// Wszystkie strony listy wątków po kluczu ostatniego wątku
+    fn thread_pages(cache: &Cache, labels: &[&str], size: usize) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+        let mut after: Option<PageKey> = None;
+        loop {
+            let page = cache.load_thread_page(labels, after.as_ref(), size).unwrap();
+            let Some(last) = page.last() else { break };
+            after = Some(PageKey { internal_date: last.last_activity, id: last.thread_id.clone() });
+            pages.push(page.into_iter().map(|t| t.thread_id).collect());
+        }
+        pages
+    }
+
+    #[test]
+    fn thread_pages_follow_latest_visible_message() {
+        let cache = test_cache();
+        cache.upsert_message(&in_thread("t1", "a1", &["INBOX"], 10)).unwrap();
+        cache.upsert_message(&in_thread("t1", "a2", &["SENT"], 60)).unwrap();
+        cache.upsert_message(&in_thread("t2", "b1", &["INBOX", "UNREAD"], 50)).unwrap();
+        cache.upsert_message(&in_thread("t3", "c1", &["INBOX"], 40)).unwrap();
+        cache.upsert_message(&in_thread("t3", "c2", &["INBOX", "TRASH"], 70)).unwrap();
+        cache.upsert_message(&in_thread("t4", "d1", &["SENT"], 30)).unwrap();
+        cache.upsert_message(&in_thread("t5", "e1", &["INBOX"], 50)).unwrap();
+
+        // t1 po dacie odpowiedzi z SENT, t3 bez wiadomości z kosza, t5/t2 z tą samą datą po thread_id
+        assert_eq!(thread_pages(&cache, &["INBOX"], 2), vec![vec!["t1", "t5"], vec!["t2", "t3"]]);
+        assert_eq!(thread_pages(&cache, &[], 3), vec![vec!["t1", "t5", "t2"], vec!["t3", "t4"]]);
+        assert_eq!(thread_pages(&cache, &["TRASH"], 5), vec![vec!["t3"]]);
+
+        let first = cache.load_thread_page(&["INBOX"], None, 3).unwrap();
+        let t2 = first.iter().find(|t| t.thread_id == "t2").unwrap();
+        assert_eq!((t2.message_count, t2.unread), (1, true));
+        let t1 = first.iter().find(|t| t.thread_id == "t1").unwrap();
+        assert_eq!((t1.last_activity, t1.message_count, t1.unread), (60, 2, false));
+
+        // Ponowny zapis tej samej wiadomości (synchronizacja) nie zmienia listy
+        cache.upsert_message(&in_thread("t1", "a2", &["SENT"], 60)).unwrap();
+        assert_eq!(thread_pages(&cache, &["INBOX"], 2), vec![vec!["t1", "t5"], vec!["t2", "t3"]]);
+
+        // Nowa wiadomość przesuwa wątek na początek, usunięcie - z powrotem
+        cache.upsert_message(&in_thread("t3", "c3", &["INBOX"], 80)).unwrap();
+        assert_eq!(thread_pages(&cache, &["INBOX"], 5), vec![vec!["t3", "t1", "t5", "t2"]]);
+        cache.delete_message("c3").unwrap();
+        cache.apply_label_changes("a1", &[], &["INBOX".to_string()], None).unwrap();
+        assert_eq!(thread_pages(&cache, &["INBOX"], 5), vec![vec!["t5", "t2", "t3"]]);
+    }
+
+    #[test]
+    fn multi_label_thread_pages_have_no_duplicates_or_gaps() {
+        let cache = test_cache();
+        // t1: w koszu najnowsza wiadomość (100), w odebranych starsza (10)
+        cache.upsert_message(&in_thread("t1", "a1", &["INBOX"], 10)).unwrap();
+        cache.upsert_message(&in_thread("t1", "a2", &["INBOX", "TRASH"], 100)).unwrap();
+        cache.upsert_message(&in_thread("t2", "b1", &["INBOX"], 50)).unwrap();
+        cache.upsert_message(&in_thread("t3", "c1", &["TRASH"], 70)).unwrap();
+        cache.upsert_message(&in_thread("t4", "d1", &["INBOX"], 5)).unwrap();
+
+        let expected = vec!["t1", "t3", "t2", "t4"];
+        for labels in [&["TRASH", "INBOX"][..], &["INBOX", "TRASH"][..], &["inbox", "trash"][..]] {
+            for size in 1..=4 {
+                let pages = thread_pages(&cache, labels, size);
+                assert_eq!(pages.concat(), expected, "{:?}, strona {}", labels, size);
+            }
+        }
+        let first = cache.load_thread_page(&["INBOX", "TRASH"], None, 1).unwrap();
+        assert_eq!((first[0].last_activity, first[0].message_count), (100, 2));
+    }
+
+    #[test]
+    fn thread_attachment_flag_uses_stored_attachments() {
+        let cache = test_cache();
+        // multipart/mixed z samym obrazkiem inline i multipart/related z prawdziwym załącznikiem
+        let mut inline = in_thread("t1", "a1", &["INBOX"], 10);
+        inline.headers_json = message("a1", "a@x.pl", "a1", "multipart/mixed; boundary=x", &[], 10).headers_json;
+        cache.upsert_message(&inline).unwrap();
+        let mut related = in_thread("t2", "b1", &["INBOX"], 20);
+        related.headers_json = message("b1", "a@x.pl", "b1", "multipart/related; boundary=y", &[], 20).headers_json;
+        cache.upsert_message(&related).unwrap();
+        cache.put_body(&body("a1", Vec::new())).unwrap();
+        cache
+            .put_body(&body(
+                "b1",
+                vec![EmailAttachment { id: "att1".into(), filename: "f.pdf".into(), size: 10, mime_type: "application/pdf".into() }],
+            ))
+            .unwrap();
+
+        let flags: Vec<(String, bool)> = cache
+            .load_thread_page(&["INBOX"], None, 5)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.thread_id, t.has_attachment))
+            .collect();
+        assert_eq!(flags, vec![("t2".to_string(), true), ("t1".to_string(), false)]);
+        assert_eq!(search(&cache, "has:attachment"), vec!["b1"]);
+    }
+
+    #[test]
+    fn draft_threads_skip_sent_and_trashed_drafts() {
+        let cache = test_cache();
+        cache.upsert_message(&in_thread("t1", "a1", &["DRAFT"], 10)).unwrap();
+        cache.upsert_message(&in_thread("t2", "b1", &["DRAFT", "SENT"], 20)).unwrap();
+        cache.upsert_message(&in_thread("t3", "c1", &["DRAFT", "TRASH"], 30)).unwrap();
+        cache.upsert_message(&in_thread("t4", "d1", &["DRAFT", "SPAM"], 40)).unwrap();
+
+        assert_eq!(thread_pages(&cache, &["draft"], 5), vec![vec!["t1"]]);
+        assert_eq!(thread_pages(&cache, &["DRAFT", "SENT"], 5), vec![vec!["t2", "t1"]]);
+    }
+
+    #[test]
+    fn label_filter_excludes_each_label_once() {
+        assert_eq!(LabelFilter::new(&["DRAFT"]).excluded, vec!["TRASH", "SENT", "SPAM"]);
+        assert_eq!(LabelFilter::new(&["draft", "INBOX"]).excluded, vec!["TRASH", "SENT", "SPAM"]);
+        assert_eq!(LabelFilter::new(&["TRASH", "DRAFT"]).excluded, vec!["SENT", "SPAM", "TRASH"]);
+        assert_eq!(LabelFilter::new(&["TRASH"]).excluded, Vec::<&str>::new());
+        assert_eq!(LabelFilter::new(&[]).excluded, vec!["TRASH"]);
+    }
+
+    #[test]
+    fn stale_bodies_are_sanitized_on_read() {
+        let cache = test_cache();
+        cache.upsert_message(&message("a", "jan@example.com", "stare", "text/html", &["INBOX"], 1)).unwrap();
+        cache.upsert_message(&message("b", "jan@example.com", "tekst", "text/plain", &["INBOX"], 2)).unwrap();
+        cache.put_body(&body("a", Vec::new())).unwrap();
+        cache.put_body(&body("b", Vec::new())).unwrap();
+        // Body zapisane przed sanityzacją (jak po migracji v9)
+        cache
+            .conn()
+            .unwrap()
+            .execute_batch(
+                "UPDATE bodies SET sanitizer_version = 0, body = '<p onclick=\"x()\">skrypt</p><script>alert(1)</script>' WHERE message_id = 'a';
+                 UPDATE bodies SET sanitizer_version = 0, body = 'a <b> c' WHERE message_id = 'b';",
+            )
+            .unwrap();
+
+        assert_eq!(cache.get_body("a").unwrap().unwrap().body, "<p>skrypt</p>");
+        // Tekst z '<' (webview uznałby go za znaczniki)
+        assert_eq!(cache.get_body("b").unwrap().unwrap().body, "<div>a &lt;b&gt; c</div>");
+        let stored: (String, i64) = cache
+            .conn()
+            .unwrap()
+            .query_row("SELECT body, sanitizer_version FROM bodies WHERE message_id = 'a'", [], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(stored, ("<p>skrypt</p>".to_string(), SANITIZER_VERSION));
+        assert_eq!(search(&cache, "skrypt"), vec!["a"]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Pomiar zapytań listy i statystyk na dużym cache (feature "cache-bench").
+//! Dane syntetyczne: wiadomość co minutę wstecz od teraz, 3 wiadomości na wątek.
+//! Górne limity czasów sprawdza test `large_cache_stays_within_limits`:
+//! `cargo test --release --features cache-bench --lib -- --ignored`
+
+use crate::cache::{Cache, PageKey};
+use anyhow::Result;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const PAGE: usize = 21;
+const RUNS: usize = 5;
+
+/// Mediany czasów zapytań (nazwa, mediana) - wypisywane po kolei
+pub fn run(count: i64, path: &Path) -> Result<Vec<(&'static str, Duration)>> {
+    let _ = std::fs::remove_file(path);
+    let cache = Cache::new(Some(path.to_path_buf()))?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let started = Instant::now();
+    seed(path, count, now)?;
+    println!("seeded {} messages in {:.1}s ({})", count, started.elapsed().as_secs_f64(), path.display());
+
+    // Klucz ze środka skrzynki - strona "głęboko" w liście
+    let deep = PageKey {
+        internal_date: now - (count * 4 / 5) * 60_000,
+        id: format!("m{:08}", count * 4 / 5),
+    };
+    let deep_thread = PageKey {
+        internal_date: deep.internal_date,
+        id: format!("t{:08}", count * 4 / 5 / 3),
+    };
+    let today = chrono::Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .map(|d| d.and_utc().timestamp_millis())
+        .unwrap_or(now);
+
+    Ok(vec![
+        bench("emails INBOX, first page", || Ok(cache.load_label_page(&["INBOX"], None, PAGE)?.len()))?,
+        bench("emails INBOX, page at 80%", || Ok(cache.load_label_page(&["INBOX"], Some(&deep), PAGE)?.len()))?,
+        bench("emails INBOX+SENT, first page", || Ok(cache.load_label_page(&["INBOX", "SENT"], None, PAGE)?.len()))?,
+        bench("emails DRAFT, first page", || Ok(cache.load_label_page(&["DRAFT"], None, PAGE)?.len()))?,
+        bench("emails TRASH, page at 80%", || Ok(cache.load_label_page(&["TRASH"], Some(&deep), PAGE)?.len()))?,
+        bench("emails all mail, page at 80%", || Ok(cache.load_label_page(&[], Some(&deep), PAGE)?.len()))?,
+        bench("threads INBOX, first page", || Ok(cache.load_thread_page(&["INBOX"], None, PAGE)?.len()))?,
+        bench("threads INBOX+SENT, first page", || Ok(cache.load_thread_page(&["INBOX", "SENT"], None, PAGE)?.len()))?,
+        bench("threads INBOX, page at 80%", || Ok(cache.load_thread_page(&["INBOX"], Some(&deep_thread), PAGE)?.len()))?,
+        bench("threads all mail, page at 80%", || Ok(cache.load_thread_page(&[], Some(&deep_thread), PAGE)?.len()))?,
+        bench("mailbox stats", || Ok(cache.label_counts()?.len()))?,
+        bench("today stats", || Ok(cache.count_since(today)?.0 as usize))?,
+    ])
+}
+
+/// Jedna transakcja prosto w SQL - triggery wypełniają message_labels jak przy synchronizacji
+fn seed(path: &Path, count: i64, now: i64) -> Result<()> {
+    let mut conn = rusqlite::Connection::open(path)?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        "WITH RECURSIVE seq(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM seq WHERE i + 1 < ?1)
+         INSERT INTO messages (message_id, thread_id, headers_json, label_ids_json, snippet, internal_date, synced_history_id)
+         SELECT printf('m%08d', i), printf('t%08d', i / 3),
+                json_array(json_object('name', 'Subject', 'value', 'Message ' || i),
+                           json_object('name', 'From', 'value', 'Sender ' || (i % 500) || ' <s' || (i % 500) || '@example.com>')),
+                CASE
+                    WHEN i % 50 = 0 THEN '[\"TRASH\"]'
+                    WHEN i % 100 = 1 THEN '[\"DRAFT\"]'
+                    WHEN i % 10 = 2 THEN '[\"SENT\"]'
+                    WHEN i % 3 = 0 THEN '[\"INBOX\",\"UNREAD\",\"CATEGORY_PROMOTIONS\"]'
+                    ELSE '[\"INBOX\",\"CATEGORY_PERSONAL\"]'
+                END,
+                'Snippet ' || i, ?2 - i * 60000, 1
+         FROM seq",
+        rusqlite::params![count, now],
+    )?;
+    tx.commit()?;
+    conn.execute_batch("ANALYZE")?;
+    Ok(())
+}
+
+fn bench(name: &'static str, mut query: impl FnMut() -> Result<usize>) -> Result<(&'static str, Duration)> {
+    let mut times: Vec<Duration> = Vec::with_capacity(RUNS);
+    let mut rows = 0;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        rows = query()?;
+        times.push(started.elapsed());
+    }
+    times.sort();
+    println!(
+        "{:<32} median {:>8.2} ms   max {:>8.2} ms   ({} rows)",
+        name,
+        times[RUNS / 2].as_secs_f64() * 1000.0,
+        times[RUNS - 1].as_secs_f64() * 1000.0,
+        rows
+    );
+    Ok((name, times[RUNS / 2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 500 tys. wiadomości; limity z dużym zapasem ponad pomiar na laptopie (lista < 1 ms, statystyki ~150 ms)
+    #[test]
+    #[ignore = "seeds 500k messages - run with --release --ignored"]
+    fn large_cache_stays_within_limits() {
+        let path = std::env::temp_dir().join(format!("nexdeck_cache_bench_test-{}.sqlite3", std::process::id()));
+        let results = run(500_000, &path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        for (name, median) in results {
+            let limit = if name == "mailbox stats" { Duration::from_millis(1000) } else { Duration::from_millis(20) };
+            assert!(median < limit, "{} took {:?} (limit {:?})", name, median, limit);
+        }
+    }
+}
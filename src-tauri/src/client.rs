@@ -1,42 +1,73 @@
+use crate::attachments::Base64FieldDecoder;
+use crate::auth::TokenStore;
+use crate::batch;
+use crate::config::ApiConfig;
 use crate::types::*;
 use anyhow::{Context, Result};
+use reqwest::header::{CONTENT_RANGE, LOCATION, RANGE};
 use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Semaphore;
 use std::time::Duration;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
-pub const GMAIL_API_BASE: &str = "https://www.googleapis.com/gmail/v1";
+/// Największa wiadomość (bajty RFC 2822) wysyłana wprost jako `raw`; większe idą uploadem wznawianym
+pub const SIMPLE_UPLOAD_LIMIT: u64 = 5 * 1024 * 1024;
+/// Kawałek uploadu wznawianego - Google wymaga wielokrotności 256 KiB
+const UPLOAD_CHUNK: u64 = 32 * 256 * 1024;
+/// Tyle nieudanych kawałków z rzędu i upload się poddaje
+const UPLOAD_RETRIES: u32 = 5;
 
 pub struct GmailClient {
     pub client: Client,
-    pub access_token: Arc<String>,
+    pub tokens: TokenStore,
     pub semaphore: Arc<Semaphore>,
+    pub config: ApiConfig,
 }
 
 impl GmailClient {
-    pub fn new(access_token: String) -> Self {
+    pub fn new(tokens: TokenStore, config: &ApiConfig) -> Self {
         let client = Client::builder()
             .pool_max_idle_per_host(20)
             .build()
             .expect("reqwest client build");
         Self {
             client,
-            access_token: Arc::new(access_token),
+            tokens,
             semaphore: Arc::new(Semaphore::new(8)), // limit concurrency to 8
+            config: config.clone(),
         }
     }
 
-    /// simple retry-send helper (exponential backoff)
+    /// Aktualny access token ze wspólnego magazynu (tam odświeżany po wygaśnięciu)
+    pub async fn access_token(&self) -> Result<String> {
+        self.tokens
+            .get_token()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No access token available"))
+    }
+
+    /// Wysyłka z ponawianiem (backoff wykładniczy).
+    /// `make_req` dostaje bearer token; po 401 token jest raz odświeżany, a żądanie powtarzane.
     async fn send_with_retry<F>(&self, make_req: F) -> Result<reqwest::Response>
     where
-        F: Fn() -> reqwest::RequestBuilder,
+        F: Fn(&str) -> reqwest::RequestBuilder,
     {
         let max_attempts = 5u32;
+        let mut token = self.access_token().await?;
+        let mut refreshed = false;
         for attempt in 0..max_attempts {
-            let req = make_req();
+            let req = make_req(&token);
             match req.send().await {
                 Ok(resp) => {
+                    if resp.status() == StatusCode::UNAUTHORIZED && !refreshed {
+                        token = self.tokens.refresh_after_unauthorized(&token).await?;
+                        refreshed = true;
+                        continue;
+                    }
                     // Retry on 429 or 5xx
                     if resp.status() == StatusCode::TOO_MANY_REQUESTS || resp.status().is_server_error() {
                         let wait = Duration::from_millis((2u64.pow(attempt) * 250).min(10000));
@@ -60,18 +91,35 @@ impl GmailClient {
         Err(anyhow::anyhow!("Retries exhausted"))
     }
 
+    /// Jedna próba dla wywołań nieidempotentnych (wysyłka) - powtarzane jest tylko 401, z nowym tokenem
+    async fn send_authorized<F>(&self, make_req: F) -> Result<reqwest::Response>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+    {
+        let token = self.access_token().await?;
+        let resp = make_req(&token).send().await?;
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            let token = self.tokens.refresh_after_unauthorized(&token).await?;
+            return Ok(make_req(&token).send().await?);
+        }
+        Ok(resp)
+    }
+
     /// Fetch message list metadata only (format=metadata)
-    pub async fn get_messages_metadata(&self, max_results: u32, label_ids: &str, page_token: Option<String>) -> Result<GmailMessageList> {
-        let url = format!("{}/users/me/messages", GMAIL_API_BASE);
+    pub async fn get_messages_metadata(&self, max_results: u32, label_ids: &str, page_token: Option<String>, query: Option<&str>) -> Result<GmailMessageList> {
+        let url = self.config.gmail_url("/users/me/messages");
         let mut params = vec![("maxResults", max_results.to_string()), ("labelIds", label_ids.to_string())];
         if let Some(token) = page_token {
             params.push(("pageToken", token));
         }
+        if let Some(q) = query {
+            params.push(("q", q.to_string()));
+        }
 
-        let make_req = || {
+        let make_req = |token: &str| {
             self.client
                 .get(&url)
-                .bearer_auth(self.access_token.as_str())
+                .bearer_auth(token)
                 .query(&params)
         };
 
@@ -82,13 +130,17 @@ impl GmailClient {
 
     /// Get single email full body (invoked lazily)
     pub async fn get_email_full(&self, message_id: &str) -> Result<GmailMessage> {
+        self.get_email_with_format(message_id, "full").await
+    }
+
+    async fn get_email_with_format(&self, message_id: &str, format: &str) -> Result<GmailMessage> {
         let _permit = self.semaphore.acquire().await.unwrap();
-        let url = format!("{}/users/me/messages/{}", GMAIL_API_BASE, message_id);
-        let make_req = || {
+        let url = self.config.gmail_url(&format!("/users/me/messages/{}", message_id));
+        let make_req = |token: &str| {
             self.client
                 .get(&url)
-                .bearer_auth(self.access_token.as_str())
-                .query(&[("format", "full")])
+                .bearer_auth(token)
+                .query(&[("format", format)])
         };
         let response = self.send_with_retry(make_req).await.context("Failed to fetch email")?;
         let gmail_message: GmailMessage = response
@@ -98,13 +150,149 @@ impl GmailClient {
         Ok(gmail_message)
     }
 
+    /// Cała konwersacja jednym wywołaniem (threads.get, format=full)
+    pub async fn get_thread(&self, thread_id: &str) -> Result<GmailThread> {
+        let _permit = self.semaphore.acquire().await.unwrap();
+        let url = self.config.gmail_url(&format!("/users/me/threads/{}", thread_id));
+        let response = self
+            .send_with_retry(|token: &str| self.client.get(&url).bearer_auth(token).query(&[("format", "full")]))
+            .await
+            .context("Failed to fetch thread")?;
+        if !response.status().is_success() {
+            return Err(api_error(response, "Thread").await);
+        }
+        response.json().await.context("Failed to parse thread")
+    }
+
+    /// Wiele wiadomości przez endpoint batch (do 100 pod-żądań na wywołanie HTTP).
+    /// Ponawiane są tylko pod-żądania z 429/5xx; 404 (usunięta w międzyczasie) jest pomijane.
+    /// Każdy inny błąd albo pozycja nieudana także po ostatniej próbie kończy całe wywołanie
+    /// błędem z listą id - częściowy wynik nie może uchodzić za pełny.
+    /// Kolejność wyników nie jest gwarantowana.
+    pub async fn get_messages_batch(&self, ids: &[String], format: &str) -> Result<Vec<GmailMessage>> {
+        let mut messages = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(batch::MAX_BATCH_SIZE) {
+            messages.extend(self.get_messages_batch_chunk(chunk, format).await?);
+        }
+        Ok(messages)
+    }
+
+    async fn get_messages_batch_chunk(&self, ids: &[String], format: &str) -> Result<Vec<GmailMessage>> {
+        let _permit = self.semaphore.acquire().await.unwrap();
+        let url = self.config.gmail_batch_url();
+        let max_attempts = 5u32;
+
+        let mut messages = Vec::with_capacity(ids.len());
+        let mut pending: Vec<&String> = ids.iter().collect();
+        let mut failed: Vec<&str> = Vec::new();
+
+        for attempt in 0..max_attempts {
+            if pending.is_empty() {
+                break;
+            }
+            if attempt > 0 {
+                let wait = Duration::from_millis((2u64.pow(attempt) * 250).min(10000));
+                tokio::time::sleep(wait).await;
+            }
+
+            // Content-ID = indeks w `pending`, żeby przypisać odpowiedź do id
+            let requests: Vec<batch::BatchRequest> = pending
+                .iter()
+                .enumerate()
+                .map(|(i, id)| batch::BatchRequest {
+                    content_id: format!("item-{}", i),
+                    method: "GET",
+                    path: self.config.gmail_path(&format!("/users/me/messages/{}?format={}", id, format)),
+                })
+                .collect();
+            let boundary = batch::new_boundary();
+            let body = batch::build_body(&boundary, &requests);
+
+            let make_req = |token: &str| {
+                self.client
+                    .post(&url)
+                    .bearer_auth(token)
+                    .header("Content-Type", format!("multipart/mixed; boundary={}", boundary))
+                    .body(body.clone())
+            };
+            let resp = self.send_with_retry(make_req).await.context("Failed to send batch request")?;
+            if !resp.status().is_success() {
+                return Err(anyhow::anyhow!("Batch request returned error: {}", resp.status()));
+            }
+            let content_type = resp
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let text = resp.text().await.context("Failed to read batch response")?;
+            let parts = batch::parse_response(&content_type, &text)?;
+
+            let mut answered = vec![false; pending.len()];
+            let mut retry = Vec::new();
+            for part in parts {
+                let Some(idx) = part
+                    .content_id
+                    .strip_prefix("item-")
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .filter(|&i| i < pending.len())
+                else {
+                    continue;
+                };
+                answered[idx] = true;
+                let id = pending[idx];
+
+                match part.status {
+                    200..=299 => match serde_json::from_str::<GmailMessage>(&part.body) {
+                        Ok(msg) => messages.push(msg),
+                        Err(e) => {
+                            eprintln!("⚠️ Failed to parse batch item {}: {}", id, e);
+                            failed.push(id.as_str());
+                        }
+                    },
+                    404 => eprintln!("⚠️ Message {} no longer exists, skipping", id),
+                    429 | 500..=599 => retry.push(id),
+                    status => {
+                        eprintln!("⚠️ Batch item {} failed with {}", id, status);
+                        failed.push(id.as_str());
+                    }
+                }
+            }
+            // Brak odpowiedzi na część = też ponów
+            retry.extend(pending.iter().zip(answered).filter(|(_, ok)| !ok).map(|(id, _)| *id));
+            pending = retry;
+        }
+
+        failed.extend(pending.iter().map(|id| id.as_str()));
+        if !failed.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} of {} batch items failed after {} attempts: {}",
+                failed.len(),
+                ids.len(),
+                max_attempts,
+                failed.join(", ")
+            ));
+        }
+        Ok(messages)
+    }
+
+    /// users.getProfile - adres email identyfikuje konto
+    pub async fn get_profile(&self) -> Result<UserProfile> {
+        let url = self.config.gmail_url("/users/me/profile");
+        let resp = self
+            .send_with_retry(|token: &str| self.client.get(&url).bearer_auth(token))
+            .await
+            .context("Failed to request users.profile")?;
+        if !resp.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to get profile: {}", resp.status()));
+        }
+        resp.json().await.context("Failed to parse profile json")
+    }
+
     pub async fn get_history_id(&self) -> Result<String> {
-        let url = format!("{}/users/me/profile", GMAIL_API_BASE);
+        let url = self.config.gmail_url("/users/me/profile");
         let resp = self
-            .client
-            .get(&url)
-            .bearer_auth(self.access_token.as_str())
-            .send()
+            .send_with_retry(|token: &str| self.client.get(&url).bearer_auth(token))
             .await
             .context("Failed to request users.profile")?;
 
@@ -119,24 +307,518 @@ impl GmailClient {
         }
     }
 
+    /// users.history.list od `start_history_id`.
+    /// `Ok(None)`, gdy Gmail odpowie 400 (za stary historyId) - trzeba zrobić pełną synchronizację.
+    pub async fn list_history(&self, start_history_id: i64, page_token: Option<String>) -> Result<Option<GmailHistoryList>> {
+        let url = self.config.gmail_url("/users/me/history");
+        let mut params = vec![("startHistoryId", start_history_id.to_string())];
+        if let Some(token) = page_token {
+            params.push(("pageToken", token));
+        }
+
+        let make_req = |token: &str| {
+            self.client
+                .get(&url)
+                .bearer_auth(token)
+                .query(&params)
+        };
+
+        let resp = self.send_with_retry(make_req).await.context("Failed to fetch history")?;
+        if resp.status() == StatusCode::BAD_REQUEST || resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(anyhow::anyhow!("History list returned error: {}", resp.status()));
+        }
+        let list: GmailHistoryList = resp.json().await.context("Failed to parse history list")?;
+        Ok(Some(list))
+    }
+
+    /// messages.send z wiadomością RFC 2822 (base64url); zwraca id nowej wiadomości.
+    /// `thread_id` dołącza odpowiedź do konwersacji, na którą odpowiada.
+    pub async fn send_message(&self, raw: &str, thread_id: Option<&str>) -> Result<String> {
+        let url = self.config.gmail_url("/users/me/messages/send");
+        let mut body = serde_json::json!({ "raw": raw });
+        if let Some(thread_id) = thread_id {
+            body["threadId"] = serde_json::json!(thread_id);
+        }
+        let resp = self
+            .send_authorized(|token: &str| self.client.post(&url).bearer_auth(token).json(&body))
+            .await
+            .context("Failed to send email")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Send").await);
+        }
+        let v: serde_json::Value = resp.json().await.context("Failed to parse send response")?;
+        v.get("id")
+            .and_then(|id| id.as_str())
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("id missing in send response"))
+    }
+
+    /// messages.send uploadem wznawianym (`uploadType=resumable`) pliku RFC 2822 - dla wiadomości
+    /// ponad `SIMPLE_UPLOAD_LIMIT`. Plik idzie kawałkami; po zerwanym połączeniu albo 5xx pytamy
+    /// sesję, ile już ma, i wznawiamy od tego miejsca.
+    /// `progress` dostaje liczbę bajtów potwierdzonych przez Gmaila.
+    pub async fn upload_message(
+        &self,
+        path: &Path,
+        thread_id: Option<&str>,
+        mut progress: impl FnMut(u64) + Send,
+    ) -> Result<String> {
+        let total = tokio::fs::metadata(path).await.context("Failed to read message file")?.len();
+        let url = format!("{}?uploadType=resumable", self.config.gmail_upload_url("/users/me/messages/send"));
+        let mut metadata = serde_json::json!({});
+        if let Some(thread_id) = thread_id {
+            metadata["threadId"] = serde_json::json!(thread_id);
+        }
+        // Otwarcie sesji jeszcze niczego nie wysyła, więc można je ponawiać jak odczyt
+        let resp = self
+            .send_with_retry(|token: &str| {
+                self.client
+                    .post(&url)
+                    .bearer_auth(token)
+                    .header("X-Upload-Content-Type", "message/rfc822")
+                    .header("X-Upload-Content-Length", total)
+                    .json(&metadata)
+            })
+            .await
+            .context("Failed to start upload")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Start upload").await);
+        }
+        let session = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("Upload session URL missing"))?;
+
+        let mut file = tokio::fs::File::open(path).await.context("Failed to open message file")?;
+        let mut offset = 0u64;
+        let mut resync = false;
+        let mut failures = 0u32;
+        loop {
+            let attempt = if resync {
+                self.upload_status(&session, total).await
+            } else {
+                self.upload_chunk(&session, &mut file, offset, total).await
+            };
+            match attempt {
+                Ok(UploadState::Done(id)) => {
+                    progress(total);
+                    return Ok(id);
+                }
+                Ok(UploadState::Received(received)) => {
+                    if received > offset {
+                        failures = 0;
+                    }
+                    offset = received;
+                    resync = false;
+                    progress(offset);
+                }
+                Err(e) if failures < UPLOAD_RETRIES && (is_network_error(&e) || is_transient_api_error(&e)) => {
+                    failures += 1;
+                    resync = true;
+                    eprintln!("⏳ Upload interrupted at {}/{} bytes, resuming: {:#}", offset, total, e);
+                    tokio::time::sleep(Duration::from_millis((2u64.pow(failures) * 250).min(10000))).await;
+                }
+                Err(e) => return Err(e.context("Upload failed")),
+            }
+        }
+    }
+
+    async fn upload_chunk(&self, session: &str, file: &mut tokio::fs::File, offset: u64, total: u64) -> Result<UploadState> {
+        let len = UPLOAD_CHUNK.min(total - offset);
+        let mut chunk = vec![0u8; len as usize];
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.read_exact(&mut chunk).await.context("Message file changed during upload")?;
+        let range = if total == 0 {
+            "bytes */0".to_string()
+        } else {
+            format!("bytes {}-{}/{}", offset, offset + len - 1, total)
+        };
+        let resp = self
+            .send_authorized(|token: &str| {
+                self.client
+                    .put(session)
+                    .bearer_auth(token)
+                    .header(CONTENT_RANGE, &range)
+                    .body(chunk.clone())
+            })
+            .await?;
+        upload_state(resp).await
+    }
+
+    /// Ile uploadu ma już sesja (`Content-Range: bytes */total`)
+    async fn upload_status(&self, session: &str, total: u64) -> Result<UploadState> {
+        let range = format!("bytes */{}", total);
+        let resp = self
+            .send_authorized(|token: &str| {
+                self.client
+                    .put(session)
+                    .bearer_auth(token)
+                    .header(CONTENT_RANGE, &range)
+                    .body(Vec::new())
+            })
+            .await?;
+        upload_state(resp).await
+    }
+
+    /// messages.modify - dodanie/zdjęcie etykiet jednej wiadomości (np. UNREAD)
+    pub async fn modify_message(&self, message_id: &str, add: &[String], remove: &[String]) -> Result<()> {
+        let url = self.config.gmail_url(&format!("/users/me/messages/{}/modify", message_id));
+        let body = serde_json::json!({ "addLabelIds": add, "removeLabelIds": remove });
+        let resp = self
+            .send_with_retry(|token: &str| self.client.post(&url).bearer_auth(token).json(&body))
+            .await
+            .context("Failed to modify message")?;
+        check_status(resp, "Modify").await
+    }
+
+    /// messages.batchModify - ta sama zmiana etykiet na wielu wiadomościach (do 1000 id na wywołanie)
+    pub async fn batch_modify(&self, ids: &[String], add: &[String], remove: &[String]) -> Result<()> {
+        let url = self.config.gmail_url("/users/me/messages/batchModify");
+        for chunk in ids.chunks(1000) {
+            let body = serde_json::json!({ "ids": chunk, "addLabelIds": add, "removeLabelIds": remove });
+            let resp = self
+                .send_with_retry(|token: &str| self.client.post(&url).bearer_auth(token).json(&body))
+                .await
+                .context("Failed to batch modify messages")?;
+            check_status(resp, "Batch modify").await?;
+        }
+        Ok(())
+    }
+
+    /// messages.trash - przeniesienie wiadomości do kosza
+    pub async fn trash_message(&self, message_id: &str) -> Result<()> {
+        let url = self.config.gmail_url(&format!("/users/me/messages/{}/trash", message_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.post(&url).bearer_auth(token))
+            .await
+            .context("Failed to trash message")?;
+        check_status(resp, "Trash").await
+    }
+
+    /// messages.untrash - przywrócenie wiadomości z kosza
+    pub async fn untrash_message(&self, message_id: &str) -> Result<()> {
+        let url = self.config.gmail_url(&format!("/users/me/messages/{}/untrash", message_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.post(&url).bearer_auth(token))
+            .await
+            .context("Failed to untrash message")?;
+        check_status(resp, "Untrash").await
+    }
+
+    /// messages.delete - trwałe usunięcie z pominięciem kosza. 404 = już jej nie ma.
+    pub async fn delete_message(&self, message_id: &str) -> Result<()> {
+        let url = self.config.gmail_url(&format!("/users/me/messages/{}", message_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.delete(&url).bearer_auth(token))
+            .await
+            .context("Failed to delete message")?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_status(resp, "Delete").await
+    }
+
+    /// users.drafts.list - wszystkie strony (same id)
+    pub async fn list_drafts(&self) -> Result<Vec<GmailDraft>> {
+        let url = self.config.gmail_url("/users/me/drafts");
+        let mut drafts = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let resp = self
+                .send_with_retry(|token: &str| {
+                    let mut req = self.client.get(&url).bearer_auth(token).query(&[("maxResults", "500")]);
+                    if let Some(pt) = &page_token {
+                        req = req.query(&[("pageToken", pt)]);
+                    }
+                    req
+                })
+                .await
+                .context("Failed to list drafts")?;
+            if !resp.status().is_success() {
+                return Err(api_error(resp, "Drafts").await);
+            }
+            let page: GmailDraftList = resp.json().await.context("Failed to parse drafts")?;
+            drafts.extend(page.drafts);
+            match page.next_page_token {
+                Some(next) => page_token = Some(next),
+                None => return Ok(drafts),
+            }
+        }
+    }
+
+    pub async fn get_draft(&self, draft_id: &str) -> Result<GmailFullDraft> {
+        let url = self.config.gmail_url(&format!("/users/me/drafts/{}", draft_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.get(&url).bearer_auth(token).query(&[("format", "full")]))
+            .await
+            .context("Failed to fetch draft")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Draft").await);
+        }
+        resp.json().await.context("Failed to parse draft")
+    }
+
+    /// Jedna próba - powtórzone utworzenie zostawiłoby zdublowaną wersję roboczą
+    pub async fn create_draft(&self, raw: &str, thread_id: Option<&str>) -> Result<GmailDraft> {
+        let url = self.config.gmail_url("/users/me/drafts");
+        let body = draft_body(raw, thread_id);
+        let resp = self
+            .send_authorized(|token: &str| self.client.post(&url).bearer_auth(token).json(&body))
+            .await
+            .context("Failed to create draft")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Create draft").await);
+        }
+        resp.json().await.context("Failed to parse created draft")
+    }
+
+    /// Podmienia treść wersji roboczej (dostaje ona nowe id wiadomości)
+    pub async fn update_draft(&self, draft_id: &str, raw: &str, thread_id: Option<&str>) -> Result<GmailDraft> {
+        let url = self.config.gmail_url(&format!("/users/me/drafts/{}", draft_id));
+        let body = draft_body(raw, thread_id);
+        let resp = self
+            .send_with_retry(|token: &str| self.client.put(&url).bearer_auth(token).json(&body))
+            .await
+            .context("Failed to update draft")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Update draft").await);
+        }
+        resp.json().await.context("Failed to parse updated draft")
+    }
+
+    /// Wysyła wersję roboczą w postaci zapisanej w Gmailu; zwraca id wysłanej wiadomości
+    pub async fn send_draft(&self, draft_id: &str) -> Result<String> {
+        let url = self.config.gmail_url("/users/me/drafts/send");
+        let body = serde_json::json!({ "id": draft_id });
+        let resp = self
+            .send_authorized(|token: &str| self.client.post(&url).bearer_auth(token).json(&body))
+            .await
+            .context("Failed to send draft")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Send draft").await);
+        }
+        let v: serde_json::Value = resp.json().await.context("Failed to parse send response")?;
+        v.get("id")
+            .and_then(|id| id.as_str())
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("id missing in send response"))
+    }
+
+    pub async fn delete_draft(&self, draft_id: &str) -> Result<()> {
+        let url = self.config.gmail_url(&format!("/users/me/drafts/{}", draft_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.delete(&url).bearer_auth(token))
+            .await
+            .context("Failed to delete draft")?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_status(resp, "Delete draft").await
+    }
+
+    /// users.labels.list - systemowe i użytkownika
+    pub async fn list_labels(&self) -> Result<Vec<GmailLabel>> {
+        let url = self.config.gmail_url("/users/me/labels");
+        let resp = self
+            .send_with_retry(|token: &str| self.client.get(&url).bearer_auth(token))
+            .await
+            .context("Failed to list labels")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Labels").await);
+        }
+        let list: GmailLabelList = resp.json().await.context("Failed to parse labels")?;
+        Ok(list.labels)
+    }
+
+    pub async fn get_label(&self, label_id: &str) -> Result<GmailLabel> {
+        let url = self.config.gmail_url(&format!("/users/me/labels/{}", label_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.get(&url).bearer_auth(token))
+            .await
+            .context("Failed to fetch label")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Label").await);
+        }
+        resp.json().await.context("Failed to parse label")
+    }
+
+    /// Jedna próba - powtórzone utworzenie mogłoby zostawić zdublowaną etykietę
+    pub async fn create_label(&self, label: &LabelUpdate) -> Result<GmailLabel> {
+        let url = self.config.gmail_url("/users/me/labels");
+        let resp = self
+            .send_authorized(|token: &str| self.client.post(&url).bearer_auth(token).json(label))
+            .await
+            .context("Failed to create label")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Create label").await);
+        }
+        resp.json().await.context("Failed to parse created label")
+    }
+
+    /// Zmienia tylko pola ustawione w `label` (nazwa, kolor, widoczność)
+    pub async fn patch_label(&self, label_id: &str, label: &LabelUpdate) -> Result<GmailLabel> {
+        let url = self.config.gmail_url(&format!("/users/me/labels/{}", label_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.patch(&url).bearer_auth(token).json(label))
+            .await
+            .context("Failed to update label")?;
+        if !resp.status().is_success() {
+            return Err(api_error(resp, "Update label").await);
+        }
+        resp.json().await.context("Failed to parse updated label")
+    }
 
-    /// Stream an attachment to file by messageId + attachmentId (writes response bytes)
-    pub async fn stream_attachment_to_file(&self, message_id: &str, attachment_id: &str, out_path: &str) -> Result<()> {
+    /// Gmail zdejmuje etykietę ze wszystkich wiadomości; już usunięta etykieta to nie błąd
+    pub async fn delete_label(&self, label_id: &str) -> Result<()> {
+        let url = self.config.gmail_url(&format!("/users/me/labels/{}", label_id));
+        let resp = self
+            .send_with_retry(|token: &str| self.client.delete(&url).bearer_auth(token))
+            .await
+            .context("Failed to delete label")?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_status(resp, "Delete label").await
+    }
+
+    /// Załącznik (messageId + attachmentId) strumieniowo do pliku. Pole base64url `data` jest
+    /// dekodowane w locie, więc załącznik nigdy nie leży w pamięci w całości. `progress` dostaje
+    /// liczbę zapisanych już bajtów; zwraca (sha256 hex, rozmiar).
+    pub async fn stream_attachment_to_file(
+        &self,
+        message_id: &str,
+        attachment_id: &str,
+        out_path: &Path,
+        mut progress: impl FnMut(u64) + Send,
+    ) -> Result<(String, u64)> {
         let _permit = self.semaphore.acquire().await.unwrap();
-        let url = format!("{}/users/me/messages/{}/attachments/{}", GMAIL_API_BASE, message_id, attachment_id);
-        let make_req = || {
+        let url = self.config.gmail_url(&format!("/users/me/messages/{}/attachments/{}", message_id, attachment_id));
+        let make_req = |token: &str| {
             self.client
                 .get(&url)
-                .bearer_auth(self.access_token.as_str())
+                .bearer_auth(token)
         };
 
         let mut res = self.send_with_retry(make_req).await.context("Failed to fetch attachment")?;
-        // Stream bytes into file to avoid loading all to memory
+        if !res.status().is_success() {
+            return Err(api_error(res, "Get attachment").await);
+        }
         let mut file = tokio::fs::File::create(out_path).await?;
+        let mut decoder = Base64FieldDecoder::default();
+        let mut hasher = Sha256::new();
+        let mut decoded = Vec::new();
+        let mut written: u64 = 0;
         while let Some(chunk) = res.chunk().await? {
-            file.write_all(&chunk).await?;
+            decoder.feed(&chunk, &mut decoded)?;
+            if !decoded.is_empty() {
+                hasher.update(&decoded);
+                file.write_all(&decoded).await?;
+                written += decoded.len() as u64;
+                decoded.clear();
+                progress(written);
+            }
         }
+        decoder.finish(&mut decoded)?;
+        hasher.update(&decoded);
+        file.write_all(&decoded).await?;
+        written += decoded.len() as u64;
         file.flush().await?;
-        Ok(())
+        progress(written);
+
+        let sha: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+        Ok((sha, written))
     }
-}
\ No newline at end of file
+}
+/// Odpowiedź Gmail API spoza 2xx (po ponowieniach). Typowana, żeby wywołujący odróżnił
+/// odrzucone żądanie (4xx) od błędu przejściowego.
+#[derive(Debug, thiserror::Error)]
+#[error("{what} returned error: {status} {body}")]
+pub struct ApiError {
+    pub what: &'static str,
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl ApiError {
+    /// 408/429/5xx - warto spróbować później
+    pub fn is_transient(&self) -> bool {
+        self.status == StatusCode::REQUEST_TIMEOUT
+            || self.status == StatusCode::TOO_MANY_REQUESTS
+            || self.status.is_server_error()
+    }
+}
+
+async fn api_error(resp: reqwest::Response, what: &'static str) -> anyhow::Error {
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    ApiError { what, status, body }.into()
+}
+
+enum UploadState {
+    /// Bajty zapisane dotąd przez sesję (308 Resume Incomplete)
+    Received(u64),
+    /// Upload zakończony - id wysłanej wiadomości
+    Done(String),
+}
+
+async fn upload_state(resp: reqwest::Response) -> Result<UploadState> {
+    if resp.status() == StatusCode::PERMANENT_REDIRECT {
+        // `Range: bytes=0-N`; brak nagłówka = sesja nic jeszcze nie ma
+        let received = resp
+            .headers()
+            .get(RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit_once('-'))
+            .and_then(|(_, end)| end.trim().parse::<u64>().ok())
+            .map_or(0, |end| end + 1);
+        return Ok(UploadState::Received(received));
+    }
+    if !resp.status().is_success() {
+        return Err(api_error(resp, "Upload").await);
+    }
+    let v: serde_json::Value = resp.json().await.context("Failed to parse upload response")?;
+    v.get("id")
+        .and_then(|id| id.as_str())
+        .map(|id| UploadState::Done(id.to_string()))
+        .ok_or_else(|| anyhow::anyhow!("id missing in upload response"))
+}
+
+fn is_transient_api_error(e: &anyhow::Error) -> bool {
+    e.chain().filter_map(|c| c.downcast_ref::<ApiError>()).any(ApiError::is_transient)
+}
+
+fn draft_body(raw: &str, thread_id: Option<&str>) -> serde_json::Value {
+    let mut message = serde_json::json!({ "raw": raw });
+    if let Some(thread_id) = thread_id {
+        message["threadId"] = serde_json::json!(thread_id);
+    }
+    serde_json::json!({ "message": message })
+}
+
+/// Zmiany nie zwracają przydatnej treści - liczy się tylko status
+async fn check_status(resp: reqwest::Response, what: &'static str) -> Result<()> {
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(api_error(resp, what).await)
+}
+
+/// Żądanie nie dostało odpowiedzi HTTP (brak sieci, DNS, odmowa połączenia, timeout)
+pub fn is_network_error(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|c| c.downcast_ref::<reqwest::Error>())
+        .any(|re| re.is_connect() || re.is_timeout() || re.is_request())
+}
+
+/// Jak `is_network_error`, ale tylko gdy żądanie na pewno nie dotarło do serwera
+/// (połączenie nie powstało) - jedyny przypadek, w którym wysyłkę wolno powtórzyć.
+pub fn is_connect_error(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|c| c.downcast_ref::<reqwest::Error>())
+        .any(|re| re.is_connect())
+}
@@ -1,26 +1,121 @@
 // command.rs z internal_date
 
-use crate::sync::SyncManager;
-use crate::cache::Cache;
+use crate::accounts::{AccountInfo, AccountRegistry, ALL_ACCOUNTS, CACHE_FILE};
+use crate::attachments::{AttachmentFile, DownloadProgress};
+use crate::auth::{open_in_browser, TokenStore};
+use crate::client::{is_network_error, GmailClient};
+use crate::outbox::MailOp;
+use crate::sync::{SendOutcome, SendProgress, SyncLimits, SyncManager};
+use crate::cache::{data_dir, CachedMessage, Cache, LocalDraft, PageKey, PendingOp};
+use crate::config::ApiConfig;
+use crate::reply::ReplyKind;
+use crate::parser::email_from_cached;
+use crate::search::SearchQuery;
 use crate::types::*;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 use tokio::sync::RwLock;
-use base64::engine::general_purpose;
-use base64::Engine as _;
 use serde::{Serialize, Deserialize};
 use anyhow::Error as AnyhowError;
 
 pub struct GmailState {
-    pub sync: Arc<RwLock<Option<Arc<SyncManager>>>>,
+    /// account_id -> SyncManager uruchomiony przez `init_gmail_client`
+    pub accounts: Arc<RwLock<HashMap<String, Arc<SyncManager>>>>,
+    pub registry: Arc<RwLock<AccountRegistry>>,
+    pub config: ApiConfig,
 }
 
 impl GmailState {
-    pub fn new() -> Self {
+    pub fn new(config: ApiConfig) -> Self {
+        let base_dir = data_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
+        let registry = AccountRegistry::load(&base_dir).unwrap_or_else(|e| {
+            eprintln!("⚠️ Failed to load account registry: {}", e);
+            AccountRegistry::empty(&base_dir)
+        });
         Self {
-            sync: Arc::new(RwLock::new(None)),
+            accounts: Arc::new(RwLock::new(HashMap::new())),
+            registry: Arc::new(RwLock::new(registry)),
+            config,
         }
     }
+
+    /// `None` = domyślne (pierwsze dodane) konto
+    pub async fn resolve_account(&self, account_id: Option<&str>) -> Result<String, String> {
+        match account_id {
+            Some(id) => Ok(id.to_string()),
+            None => self
+                .registry
+                .read()
+                .await
+                .default_account()
+                .map(|a| a.id.clone())
+                .ok_or_else(|| "No Gmail account - please log in".to_string()),
+        }
+    }
+
+    pub async fn manager(&self, account_id: Option<&str>) -> Result<Arc<SyncManager>, String> {
+        let id = self.resolve_account(account_id).await?;
+        self.accounts
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| "Gmail client not initialized".to_string())
+    }
+
+    /// Konta, z których czyta komenda: jedno albo wszystkie dla `ALL_ACCOUNTS`
+    async fn sources(&self, account_id: Option<&str>) -> Result<Vec<(String, Arc<SyncManager>)>, String> {
+        if account_id == Some(ALL_ACCOUNTS) {
+            let accounts = self.accounts.read().await;
+            if accounts.is_empty() {
+                return Err("Gmail client not initialized".into());
+            }
+            let mut sources: Vec<_> = accounts.iter().map(|(id, m)| (id.clone(), Arc::clone(m))).collect();
+            sources.sort_by(|a, b| a.0.cmp(&b.0));
+            return Ok(sources);
+        }
+        let id = self.resolve_account(account_id).await?;
+        let manager = self.manager(Some(&id)).await?;
+        Ok(vec![(id, manager)])
+    }
+
+    async fn start_account(&self, account: &AccountInfo, limits: SyncLimits) -> anyhow::Result<Arc<SyncManager>> {
+        let (cache_path, token_store) = {
+            let registry = self.registry.read().await;
+            (
+                registry.account_dir(&account.id).join(CACHE_FILE),
+                registry.token_store(&account.id, &self.config),
+            )
+        };
+        let cache = Cache::new(Some(cache_path))?;
+        let manager = SyncManager::new(cache, self.config.clone(), token_store)
+            .await?
+            .with_limits(limits);
+        let manager = Arc::new(manager);
+        match manager.init_client_from_store().await {
+            // Bez sieci konto działa z cache, a zmiany czekają w kolejce
+            Err(e) if is_network_error(&e) => {
+                eprintln!("📴 {} is offline, working from cache: {}", account.email, e);
+            }
+            Err(e) => return Err(e),
+            Ok(()) => {
+                eprintln!("🔄 Starting initial sync for {}...", account.email);
+                if let Err(e) = manager.initial_sync(100, "INBOX").await {
+                    eprintln!("⚠️ Initial sync failed: {}", e);
+                } else {
+                    eprintln!("✅ Initial sync completed");
+                }
+                if let Err(e) = manager.labels().await {
+                    eprintln!("⚠️ Label refresh failed: {}", e);
+                }
+            }
+        }
+
+        eprintln!("🔄 Starting background sync...");
+        let _ = manager.start_background_sync().await;
+        Ok(manager)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,120 +124,318 @@ pub struct TodayStats {
     pub unreadToday: i64,
 }
 
+/// Uruchamia synchronizację wskazanego konta albo (bez `account_id`) wszystkich zarejestrowanych.
 #[tauri::command]
 pub async fn init_gmail_client(
-    access_token: String,
+    account_id: Option<String>,
+    sync_limits: Option<SyncLimits>,
+    app: AppHandle,
     state: State<'_, GmailState>,
 ) -> Result<(), String> {
-    {
-        let guard = state.sync.read().await;
-        if guard.is_some() {
-            eprintln!("⚠️ Gmail client already initialized, skipping...");
-            return Ok(());
+    eprintln!("🚀 init_gmail_client called");
+
+    // Sesja z wersji jednokontowej staje się pierwszym kontem
+    if let Err(e) = state.registry.write().await.adopt_legacy_account(&state.config).await {
+        eprintln!("⚠️ Failed to adopt existing session: {}", e);
+    }
+
+    let targets: Vec<AccountInfo> = {
+        let registry = state.registry.read().await;
+        match &account_id {
+            Some(id) => vec![registry.get(id).cloned().ok_or_else(|| format!("Unknown account: {}", id))?],
+            None => registry.list().to_vec(),
         }
+    };
+    if targets.is_empty() {
+        return Err("No Gmail account - please log in".into());
     }
-    
-    eprintln!("🚀 init_gmail_client called with token");
-    
-    let cache = Cache::new(None).map_err(|e| e.to_string())?;
-    let manager = SyncManager::new(cache).await.map_err(|e| e.to_string())?;
-    let manager = Arc::new(manager);
-    
-    {
-        let token_store = manager.token_store.clone();
-        token_store.set_token(access_token).await;
-        manager.init_client_from_store().await.map_err(|e| e.to_string())?;
-
-        eprintln!("🔄 Starting initial sync...");
-        if let Err(e) = manager.initial_sync(100, "INBOX").await {
-            eprintln!("⚠️ Initial sync failed: {}", e);
-        } else {
-            eprintln!("✅ Initial sync completed");
+
+    for account in targets {
+        if state.accounts.read().await.contains_key(&account.id) {
+            eprintln!("⚠️ Account {} already initialized, skipping...", account.email);
+            continue;
         }
+        match state.start_account(&account, sync_limits.clone().unwrap_or_default()).await {
+            Ok(manager) => {
+                forward_outbox_failures(&app, &manager, &account.id);
+                state.accounts.write().await.insert(account.id.clone(), manager);
+                eprintln!("✅ Gmail client initialized for {}", account.email);
+            }
+            Err(e) if account_id.is_some() => return Err(e.to_string()),
+            Err(e) => eprintln!("⚠️ Failed to initialize {}: {}", account.email, e),
+        }
+    }
 
-        eprintln!("🔄 Starting background sync...");
-        let _ = manager.start_background_sync().await;
+    if state.accounts.read().await.is_empty() {
+        return Err("No Gmail account could be initialized - please log in again".into());
     }
-    
-    let mut s = state.sync.write().await;
-    *s = Some(manager);
-    
-    eprintln!("✅ Gmail client fully initialized");
     Ok(())
 }
 
+/// Odrzucone i porzucone zmiany z kolejki trafiają do UI jako `outbox-op-failed`
+fn forward_outbox_failures(app: &AppHandle, manager: &SyncManager, account_id: &str) {
+    let mut failures = manager.outbox_failures.subscribe();
+    let app = app.clone();
+    let account_id = account_id.to_string();
+    tokio::spawn(async move {
+        loop {
+            match failures.recv().await {
+                Ok(mut op) => {
+                    op.account_id = Some(account_id.clone());
+                    if let Err(e) = app.emit("outbox-op-failed", op) {
+                        eprintln!("⚠️ Failed to emit outbox-op-failed: {}", e);
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    eprintln!("⚠️ Missed {} outbox failure events", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Logowanie OAuth w przeglądarce systemowej; dodaje (albo odświeża) konto w rejestrze.
+/// Po sukcesie frontend woła `init_gmail_client` z `accountId` nowego konta.
+#[tauri::command]
+pub async fn login_rust(state: State<'_, GmailState>) -> Result<AccountInfo, String> {
+    let session = TokenStore::new(&state.config, None);
+    let tokens = session
+        .auth
+        .login(open_in_browser)
+        .await
+        .map_err(|e| e.to_string())?;
+    session.set_tokens(tokens.clone()).await.map_err(|e| e.to_string())?;
+
+    let profile = GmailClient::new(session, &state.config)
+        .get_profile()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let account = state.registry.write().await.add(&profile.email).map_err(|e| e.to_string())?;
+    let token_store = match state.accounts.read().await.get(&account.id) {
+        Some(manager) => manager.token_store.clone(),
+        None => state.registry.read().await.token_store(&account.id, &state.config),
+    };
+    token_store.set_tokens(tokens).await.map_err(|e| e.to_string())?;
+
+    eprintln!("✅ OAuth login complete for {}", account.email);
+    Ok(account)
+}
+
+/// Wylogowanie jednego konta albo (bez `account_id`) wszystkich - usuwa tokeny i cache konta
+#[tauri::command]
+pub async fn logout_rust(
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<(), String> {
+    let targets: Vec<String> = match account_id {
+        Some(id) => vec![id],
+        None => state.registry.read().await.list().iter().map(|a| a.id.clone()).collect(),
+    };
+
+    for id in targets {
+        if let Some(manager) = state.accounts.write().await.remove(&id) {
+            for handle in [&manager.bg_handle, &manager.outbox_handle] {
+                if let Some(handle) = handle.write().await.take() {
+                    handle.abort();
+                }
+            }
+            let _ = manager.token_store.clear().await;
+        }
+        state.registry.write().await.remove(&id).map_err(|e| e.to_string())?;
+        eprintln!("👋 Logged out {}, stored tokens and cache removed", id);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_accounts_rust(state: State<'_, GmailState>) -> Result<Vec<AccountInfo>, String> {
+    Ok(state.registry.read().await.list().to_vec())
+}
+
+/// `account_id = "all"` łączy skrzynki wszystkich kont (sortowanie po dacie).
+/// `pageToken` to klucz ostatniej wiadomości poprzedniej strony (`PageKey`).
 #[tauri::command]
 pub async fn get_emails_rust(
     options: GetEmailsOptions,
+    account_id: Option<String>,
     state: State<'_, GmailState>,
 ) -> Result<EmailListResponse, String> {
-    let manager_arc = {
-        let guard = state.sync.read().await;
-        guard.as_ref().cloned().ok_or("Gmail client not initialized")?
-    };
+    let sources = state.sources(account_id.as_deref()).await?;
 
     let label_ids = options.label_ids.clone();
-    eprintln!("🔍 get_emails_rust called with label: '{}'", label_ids);
-    
-    let all_metas = manager_arc
-        .get_cached_metadata_list(0, None, &label_ids)
-        .await
-        .map_err(|e| e.to_string())?;
+    eprintln!("🔍 get_emails_rust called with label: '{}' ({} account(s))", label_ids, sources.len());
 
-    eprintln!("✅ get_emails_rust: cache matched {} messages for label '{}'", all_metas.len(), label_ids);
-
-    let messages: Vec<EmailMessage> = all_metas.into_iter().filter_map(|m| {
-        let headers: Vec<crate::types::GmailHeader> = serde_json::from_str(&m.headers_json).unwrap_or_default();
-        let label_ids_vec: Vec<String> = serde_json::from_str(&m.label_ids_json).unwrap_or_default();
-        
-        // ✅ DRAFT FILTER w command
-        if label_ids.eq_ignore_ascii_case("DRAFT") {
-            let has_trash = label_ids_vec.iter().any(|l| l.eq_ignore_ascii_case("TRASH"));
-            let has_sent = label_ids_vec.iter().any(|l| l.eq_ignore_ascii_case("SENT"));
-            let has_spam = label_ids_vec.iter().any(|l| l.eq_ignore_ascii_case("SPAM"));
-            
-            if has_trash || has_sent || has_spam {
-                return None;
-            }
-        }
-        
-        let from = headers.iter().find(|h| h.name.eq_ignore_ascii_case("From")).map(|h| h.value.clone()).unwrap_or_default();
-        let subject = headers.iter().find(|h| h.name.eq_ignore_ascii_case("Subject")).map(|h| h.value.clone()).unwrap_or_default();
-        let date = headers.iter().find(|h| h.name.eq_ignore_ascii_case("Date")).map(|h| h.value.clone()).unwrap_or_default();
-        let unread = label_ids_vec.iter().any(|l| l.eq_ignore_ascii_case("UNREAD"));
-        
-        Some(EmailMessage {
-            id: m.message_id.clone(),
-            thread_id: m.thread_id.clone(),
-            label_ids: label_ids_vec,
-            from,
-            to: String::new(),
-            subject,
-            date,
-            snippet: m.snippet.clone(),
-            body: String::new(),
-            unread,
-            has_attachment: false,
-            attachments: vec![],
-            inline_images: vec![],
-            internal_date: Some(m.internal_date), // ✅
+    let labels: Vec<&str> = label_ids.split(',').collect();
+    let page_size = options.max_results.unwrap_or(20) as usize;
+    let after = options.page_token.as_deref().and_then(PageKey::parse);
+
+    // +1, by wiedzieć, czy jest następna strona; kilka kont scalamy po tym samym kluczu
+    let mut page: Vec<(String, CachedMessage)> = Vec::new();
+    for (id, manager) in &sources {
+        let cached = manager
+            .cache
+            .load_label_page(&labels, after.as_ref(), page_size + 1)
+            .map_err(|e| e.to_string())?;
+        page.extend(cached.into_iter().map(|m| (id.clone(), m)));
+    }
+    if sources.len() > 1 {
+        page.sort_by(|(_, a), (_, b)| (b.internal_date, &b.message_id).cmp(&(a.internal_date, &a.message_id)));
+    }
+
+    let has_more = page.len() > page_size;
+    page.truncate(page_size);
+    let next_page_token = if has_more {
+        page.last().map(|(_, m)| PageKey::of(m).token())
+    } else {
+        None
+    };
+    let slice: Vec<EmailMessage> = page
+        .iter()
+        .map(|(id, m)| {
+            let mut email = email_from_cached(m, None);
+            email.account_id = Some(id.clone());
+            email
         })
-    }).collect();
+        .collect();
+
+    eprintln!("📤 get_emails_rust: returning {} messages for label '{}', next_page_token={:?}", slice.len(), label_ids, next_page_token);
+
+    // ✅ Rozgrzej cache body dla wyświetlanej strony (każde konto osobno)
+    for (id, manager) in &sources {
+        let ids: Vec<String> = slice
+            .iter()
+            .filter(|m| m.account_id.as_deref() == Some(id.as_str()))
+            .map(|m| m.id.clone())
+            .collect();
+        if ids.is_empty() {
+            continue;
+        }
+        let prefetch_manager = Arc::clone(manager);
+        tokio::spawn(async move {
+            prefetch_manager.prefetch_bodies(ids).await;
+        });
+    }
+
+    Ok(EmailListResponse {
+        messages: slice,
+        next_page_token,
+    })
+}
+
+/// Lista konwersacji etykiety; `pageToken` to klucz ostatniego wątku poprzedniej strony (`PageKey`)
+#[tauri::command]
+pub async fn get_threads_rust(
+    options: GetEmailsOptions,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<ThreadListResponse, String> {
+    let sources = state.sources(account_id.as_deref()).await?;
+
+    let page_size = options.max_results.unwrap_or(20) as usize;
+    let after = options.page_token.as_deref().and_then(PageKey::parse);
+
+    // +1, by wiedzieć, czy jest następna strona; kilka kont scalamy po tym samym kluczu
+    let mut threads: Vec<EmailThread> = Vec::new();
+    for (id, manager) in &sources {
+        let mut account_threads = manager
+            .load_thread_list(&options.label_ids, after.as_ref(), page_size + 1)
+            .map_err(|e| e.to_string())?;
+        for t in &mut account_threads {
+            t.account_id = Some(id.clone());
+        }
+        threads.extend(account_threads);
+    }
+    if sources.len() > 1 {
+        threads.sort_by(|a, b| (b.last_activity, &b.thread_id).cmp(&(a.last_activity, &a.thread_id)));
+    }
+
+    let has_more = threads.len() > page_size;
+    threads.truncate(page_size);
+    let next_page_token = if has_more {
+        threads.last().map(|t| PageKey::of_thread(t).token())
+    } else {
+        None
+    };
+
+    eprintln!("🧵 get_threads_rust: {} threads for '{}', next_page_token={:?}", threads.len(), options.label_ids, next_page_token);
+    Ok(ThreadListResponse {
+        threads,
+        next_page_token,
+    })
+}
+
+/// Wszystkie wiadomości konwersacji (z treścią), chronologicznie
+#[tauri::command]
+pub async fn get_thread_rust(
+    thread_id: String,
+    account_id: Option<String>,
+    load_images: Option<bool>,
+    state: State<'_, GmailState>,
+) -> Result<EmailThread, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    let mut thread = manager
+        .load_thread(&thread_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Thread {} not found", thread_id))?;
+    for m in &mut thread.messages {
+        manager
+            .apply_remote_content(m, load_images.unwrap_or(false))
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(id) = account_id {
+        thread.account_id = Some(id.clone());
+        for m in &mut thread.messages {
+            m.account_id = Some(id.clone());
+        }
+    }
+    Ok(thread)
+}
+
+#[tauri::command]
+pub async fn search_emails_rust(
+    options: SearchEmailsOptions,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<EmailListResponse, String> {
+    let manager_arc = state.manager(account_id.as_deref()).await?;
+
+    let query = SearchQuery::parse(&options.query);
+    eprintln!("🔎 search_emails_rust: '{}' -> {:?}", options.query, query);
+
+    if query.is_empty() {
+        return Ok(EmailListResponse {
+            messages: vec![],
+            next_page_token: None,
+        });
+    }
 
     let page_size = options.max_results.unwrap_or(20) as usize;
     let start: usize = options.page_token
         .as_ref()
         .and_then(|s| s.parse::<usize>().ok())
         .unwrap_or(0);
-    let total = messages.len();
-    let end = (start + page_size).min(total);
-    let slice = if start >= total { vec![] } else { messages[start..end].to_vec() };
-    let next_page_token = if end < total { Some(end.to_string()) } else { None };
 
-    eprintln!("📤 get_emails_rust: returning {} messages (start={}, end={}), next_page_token={:?}", slice.len(), start, end, next_page_token);
+    // Pobierz o jeden więcej, żeby wiedzieć czy jest następna strona
+    let mut rows = manager_arc
+        .cache
+        .search_messages(&query, page_size + 1, start)
+        .map_err(|e| e.to_string())?;
+
+    let next_page_token = if rows.len() > page_size {
+        rows.truncate(page_size);
+        Some((start + page_size).to_string())
+    } else {
+        None
+    };
+
+    let messages: Vec<EmailMessage> = rows.iter().map(|m| email_from_cached(m, None)).collect();
+    eprintln!("📤 search_emails_rust: returning {} messages, next_page_token={:?}", messages.len(), next_page_token);
 
     Ok(EmailListResponse {
-        messages: slice,
+        messages,
         next_page_token,
     })
 }
@@ -150,64 +443,74 @@ pub async fn get_emails_rust(
 #[tauri::command]
 pub async fn get_email_rust(
     message_id: String,
+    account_id: Option<String>,
+    load_images: Option<bool>,
     state: State<'_, GmailState>,
 ) -> Result<EmailMessage, String> {
-    let manager_arc = {
-        let guard = state.sync.read().await;
-        guard.as_ref().cloned().ok_or("Gmail client not initialized")?
-    };
+    let manager_arc = state.manager(account_id.as_deref()).await?;
 
-    manager_arc
+    let mut message = manager_arc
         .fetch_full_message_lazy(&message_id)
         .await
-        .map_err(|e: AnyhowError| e.to_string())
+        .map_err(|e: AnyhowError| e.to_string())?;
+    manager_arc
+        .apply_remote_content(&mut message, load_images.unwrap_or(false))
+        .map_err(|e| e.to_string())?;
+    Ok(message)
+}
+
+/// "Zawsze ładuj obrazki" dla nadawcy (adres e-mail) - zapisywane w cache konta
+#[tauri::command]
+pub async fn set_sender_images_rust(
+    sender: String,
+    allowed: bool,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<(), String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager
+        .cache
+        .set_sender_images_allowed(&sender, allowed)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_image_senders_rust(
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<Vec<String>, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.cache.image_senders().map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn get_mailbox_stats_rust(
+    account_id: Option<String>,
     state: State<'_, GmailState>,
 ) -> Result<MailboxStats, String> {
-    let manager_arc = {
-        let guard = state.sync.read().await;
-        guard.as_ref().cloned().ok_or("Gmail client not initialized")?
-    };
+    let manager_arc = state.manager(account_id.as_deref()).await?;
+
+    let counts = manager_arc.cache.label_counts().map_err(|e| e.to_string())?;
+    let labels = manager_arc.cache.load_labels().map_err(|e| e.to_string())?;
+    let cache_empty = counts.is_empty();
 
-    let cached = manager_arc.cache.load_all_messages().map_err(|e| e.to_string())?;
+    // Etykiety bez wiadomości też trafiają do listy (z zerami)
+    let mut totals: std::collections::HashMap<String, (i64, i64)> =
+        counts.into_iter().map(|(label, total, unread)| (label, (total, unread))).collect();
     let mut map = std::collections::HashMap::new();
-    
-    for m in cached {
-        let labels_vec: Vec<String> = serde_json::from_str(&m.label_ids_json).unwrap_or_default();
-        
-        for label in &labels_vec {
-            let stat = map.entry(label.clone()).or_insert(MailboxStat {
-                id: label.clone(),
-                name: label.clone(),
-                total: 0,
-                unread: 0,
-            });
-            
-            if label.eq_ignore_ascii_case("DRAFT") {
-                let has_trash = labels_vec.iter().any(|l| l.eq_ignore_ascii_case("TRASH"));
-                let has_sent = labels_vec.iter().any(|l| l.eq_ignore_ascii_case("SENT"));
-                let has_spam = labels_vec.iter().any(|l| l.eq_ignore_ascii_case("SPAM"));
-                
-                if has_trash || has_sent || has_spam {
-                    continue;
-                }
-            }
-            
-            stat.total += 1;
-            
-            if labels_vec.iter().any(|l| l.eq_ignore_ascii_case("UNREAD")) {
-                stat.unread += 1;
-            }
-        }
+    for label in &labels {
+        let (total, unread) = totals.remove(&label.id).unwrap_or_default();
+        map.insert(label.id.clone(), mailbox_stat(&label.id, Some(label), &labels, total, unread));
+    }
+    // Etykiety, których lista z Gmaila jeszcze nie dotarła
+    for (id, (total, unread)) in totals {
+        map.insert(id.clone(), mailbox_stat(&id, None, &labels, total, unread));
     }
 
-    if map.is_empty() {
+    if cache_empty {
         eprintln!("⚠️ Cache empty, falling back to Node.js for mailbox stats");
         if let Ok(resp) = reqwest::Client::new()
-            .get("http://localhost:3001/api/mailbox/stats")
+            .get(state.config.backend_url("/api/mailbox/stats"))
             .send()
             .await
         {
@@ -222,44 +525,46 @@ pub async fn get_mailbox_stats_rust(
     Ok(MailboxStats { stats: map })
 }
 
+/// Etykiety zagnieżdżone przez "/" w nazwie: "Praca/Projekty" ma rodzica "Praca", jeśli taki istnieje
+fn mailbox_stat(id: &str, label: Option<&GmailLabel>, labels: &[GmailLabel], total: i64, unread: i64) -> MailboxStat {
+    let path = label.map(|l| l.name.clone()).unwrap_or_else(|| id.to_string());
+    let parent = path
+        .rsplit_once('/')
+        .and_then(|(parent, leaf)| labels.iter().find(|l| l.name == parent).map(|p| (p.id.clone(), leaf.to_string())));
+    let (parent_id, name) = match parent {
+        Some((parent_id, leaf)) => (Some(parent_id), leaf),
+        None => (None, path.clone()),
+    };
+    MailboxStat {
+        id: id.to_string(),
+        name,
+        path,
+        parent_id,
+        label_type: label.and_then(|l| l.label_type.clone()),
+        color: label.and_then(|l| l.color.clone()),
+        total: total as usize,
+        unread: unread as usize,
+    }
+}
+
 #[tauri::command]
 pub async fn get_today_stats_rust(
+    account_id: Option<String>,
     state: State<'_, GmailState>,
 ) -> Result<TodayStats, String> {
-    let manager_arc = {
-        let guard = state.sync.read().await;
-        guard.as_ref().cloned()
-    };
+    let manager_arc = state.manager(account_id.as_deref()).await.ok();
     
     if let Some(manager) = manager_arc {
-        let cached = manager.cache.load_all_messages().map_err(|e| e.to_string())?;
-        
         let now = chrono::Utc::now();
         let today_start = now.date_naive().and_hms_opt(0, 0, 0)
             .ok_or("Failed to create today's date")?;
-        
-        let mut total_today = 0;
-        let mut unread_today = 0;
-        
-        for msg in cached {
-            let headers: Vec<crate::types::GmailHeader> = 
-                serde_json::from_str(&msg.headers_json).unwrap_or_default();
-            
-            if let Some(date_header) = headers.iter().find(|h| h.name.eq_ignore_ascii_case("Date")) {
-                if let Ok(parsed_date) = chrono::DateTime::parse_from_rfc2822(&date_header.value) {
-                    let msg_date = parsed_date.naive_utc();
-                    if msg_date >= today_start {
-                        total_today += 1;
-                        
-                        let labels: Vec<String> = serde_json::from_str(&msg.label_ids_json).unwrap_or_default();
-                        if labels.iter().any(|l| l.eq_ignore_ascii_case("UNREAD")) {
-                            unread_today += 1;
-                        }
-                    }
-                }
-            }
-        }
-        
+
+        // Dzień wg daty odebrania (internal_date) - liczone w SQL po indeksie
+        let (total_today, unread_today) = manager
+            .cache
+            .count_since(today_start.and_utc().timestamp_millis())
+            .map_err(|e| e.to_string())?;
+
         if total_today > 0 || unread_today > 0 {
             eprintln!("📅 Today stats from cache: {} total, {} unread", total_today, unread_today);
             return Ok(TodayStats {
@@ -272,7 +577,7 @@ pub async fn get_today_stats_rust(
     eprintln!("⚠️ Cache empty for today stats, falling back to Node.js");
     let client = reqwest::Client::new();
     let resp = client
-        .get("http://localhost:3001/api/emails/stats/today")
+        .get(state.config.backend_url("/api/emails/stats/today"))
         .send()
         .await
         .map_err(|e| format!("Failed to fetch today stats: {}", e))?;
@@ -295,10 +600,17 @@ pub async fn get_today_stats_rust(
 
 #[tauri::command]
 pub async fn get_user_profile_rust(
-    _state: State<'_, GmailState>,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
 ) -> Result<UserProfile, String> {
+    if let Ok(manager) = state.manager(account_id.as_deref()).await {
+        if let Some(client) = manager.client.read().await.as_ref() {
+            return client.get_profile().await.map_err(|e| e.to_string());
+        }
+    }
+
     let resp = reqwest::Client::new()
-        .get("http://localhost:3001/api/user/profile")
+        .get(state.config.backend_url("/api/user/profile"))
         .send()
         .await
         .map_err(|e| e.to_string())?;
@@ -306,112 +618,347 @@ pub async fn get_user_profile_rust(
     Ok(profile)
 }
 
+/// Bez sieci wiadomość trafia do kolejki (`queuedOpId`) i wyjdzie po jej powrocie.
+/// Pliki z `email_data.attachments` idą razem z nią; postęp: zdarzenia `send-progress`.
 #[tauri::command]
 pub async fn send_email_rust(
     email_data: EmailData,
+    account_id: Option<String>,
+    app: AppHandle,
     state: State<'_, GmailState>,
-) -> Result<String, String> {
-    let manager_arc = {
-        let guard = state.sync.read().await;
-        guard.as_ref().cloned().ok_or("Gmail client not initialized")?
-    };
+) -> Result<SendOutcome, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager
+        .send_email(&email_data, send_progress(&app))
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let token_opt = {
-        let client_guard = manager_arc.client.read().await;
-        client_guard.as_ref().map(|c| c.access_token.clone())
-    };
+/// Odpowiedź nadawcy w wątku wiadomości `message_id`; `email_data.body` to sam nowy tekst
+#[tauri::command]
+pub async fn reply_email_rust(
+    message_id: String,
+    email_data: EmailData,
+    account_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, GmailState>,
+) -> Result<SendOutcome, String> {
+    reply_with(ReplyKind::Reply, message_id, email_data, account_id, app, state).await
+}
 
-    if let Some(tok_arc) = token_opt {
-        let mut message = String::new();
-        message.push_str(&format!("To: {}\r\n", email_data.to));
-        if let Some(cc) = email_data.cc {
-            message.push_str(&format!("Cc: {}\r\n", cc));
-        }
-        if let Some(bcc) = email_data.bcc {
-            message.push_str(&format!("Bcc: {}\r\n", bcc));
-        }
-        message.push_str(&format!("Subject: {}\r\n", email_data.subject));
-        message.push_str("Content-Type: text/html; charset=utf-8\r\n");
-        message.push_str("\r\n");
-        message.push_str(&email_data.body);
-        
-        let encoded = general_purpose::URL_SAFE_NO_PAD.encode(message.as_bytes());
-        
-        let url = format!("{}/users/me/messages/send", crate::client::GMAIL_API_BASE);
-        let client_req = reqwest::Client::new();
-        
-        #[derive(serde::Serialize)]
-        struct SendRequest {
-            raw: String,
+/// Odpowiedź nadawcy i pozostałym odbiorcom (bez własnego adresu)
+#[tauri::command]
+pub async fn reply_all_rust(
+    message_id: String,
+    email_data: EmailData,
+    account_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, GmailState>,
+) -> Result<SendOutcome, String> {
+    reply_with(ReplyKind::ReplyAll, message_id, email_data, account_id, app, state).await
+}
+
+/// Przekazanie z cytatem i załącznikami oryginału; odbiorcy z `email_data.to`
+#[tauri::command]
+pub async fn forward_email_rust(
+    message_id: String,
+    email_data: EmailData,
+    account_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, GmailState>,
+) -> Result<SendOutcome, String> {
+    if email_data.to.trim().is_empty() {
+        return Err("Forward needs at least one recipient".into());
+    }
+    reply_with(ReplyKind::Forward, message_id, email_data, account_id, app, state).await
+}
+
+async fn reply_with(
+    kind: ReplyKind,
+    message_id: String,
+    email_data: EmailData,
+    account_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, GmailState>,
+) -> Result<SendOutcome, String> {
+    let id = state.resolve_account(account_id.as_deref()).await?;
+    let own_email = state.registry.read().await.get(&id).map(|a| a.email.clone()).unwrap_or_default();
+    let manager = state.manager(Some(&id)).await?;
+    manager
+        .reply(kind, &message_id, &email_data, &own_email, send_progress(&app))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Co tyle bajtów leci zdarzenie postępu (pobieranie załącznika, wysyłka)
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+/// Callback postępu `(done, total)` wysyłający `event` co `PROGRESS_STEP` bajtów i na końcu
+fn progress_emitter<'a, T: Serialize + Clone>(
+    app: &'a AppHandle,
+    event: &'static str,
+    payload: impl Fn(u64, u64) -> T + Send + 'a,
+) -> impl FnMut(u64, u64) + Send + 'a {
+    let mut last_emitted: Option<u64> = None;
+    move |done, total| {
+        if last_emitted.is_some_and(|last| done.saturating_sub(last) < PROGRESS_STEP) && done < total {
+            return;
         }
-        
-        let res = client_req
-            .post(&url)
-            .bearer_auth(tok_arc.as_str())
-            .json(&SendRequest { raw: encoded })
-            .send()
-            .await
-            .map_err(|e| e.to_string())?;
-        
-        #[derive(serde::Deserialize)]
-        struct SendResponse {
-            id: String,
+        last_emitted = Some(done);
+        if let Err(e) = app.emit(event, payload(done, total)) {
+            eprintln!("⚠️ Failed to emit {}: {}", event, e);
         }
-        
-        let sr: SendResponse = res.json().await.map_err(|e| e.to_string())?;
-        Ok(sr.id)
-    } else {
-        let response = reqwest::Client::new()
-            .post("http://localhost:3001/api/emails/send")
-            .json(&email_data)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?;
-        let v: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        Ok(v
-            .get("id")
-            .and_then(|v| v.as_str())
-            .unwrap_or_default()
-            .to_string())
     }
 }
 
+fn send_progress(app: &AppHandle) -> impl FnMut(u64, u64) + Send + '_ {
+    progress_emitter(app, "send-progress", |sent, total| SendProgress { sent, total })
+}
+
+/// Załącznik jako plik lokalny (magazyn obok cache); `open` otwiera go domyślnym programem.
+/// Postęp pobierania: zdarzenia `attachment-download-progress`.
+#[tauri::command]
+pub async fn download_attachment_rust(
+    message_id: String,
+    attachment_id: String,
+    open: Option<bool>,
+    account_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, GmailState>,
+) -> Result<AttachmentFile, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+
+    let progress = progress_emitter(&app, "attachment-download-progress", |received, total| DownloadProgress {
+        message_id: message_id.clone(),
+        attachment_id: attachment_id.clone(),
+        received,
+        total,
+    });
+    let file = manager
+        .download_attachment(&message_id, &attachment_id, progress)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if open.unwrap_or(false) {
+        open_in_browser(&file.path).map_err(|e| e.to_string())?;
+    }
+    Ok(file)
+}
+
+/// Autozapis wersji roboczej z edytora; bez `local_id` tworzy nową
+#[tauri::command]
+pub async fn save_draft_rust(
+    local_id: Option<i64>,
+    email_data: EmailData,
+    thread_id: Option<String>,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<LocalDraft, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager
+        .save_draft(local_id, &email_data, thread_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_drafts_rust(
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<Vec<LocalDraft>, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.drafts().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_draft_rust(
+    local_id: i64,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<Option<LocalDraft>, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.cache.get_draft(local_id).map_err(|e| e.to_string())
+}
+
+/// Jak `send_email_rust`: bez sieci wysyłka czeka w kolejce (`queuedOpId`)
+#[tauri::command]
+pub async fn send_draft_rust(
+    local_id: i64,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<SendOutcome, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.send_draft(local_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_draft_rust(
+    local_id: i64,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<(), String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.delete_draft(local_id).await.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn mark_email_rust(
     message_id: String,
     read: bool,
-    _state: State<'_, GmailState>,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
 ) -> Result<(), String> {
-    let client = reqwest::Client::new();
-    let res = client
-        .post(&format!("http://localhost:3001/api/emails/{}/mark", message_id))
-        .json(&serde_json::json!({ "read": read }))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-    if res.status().is_success() {
-        Ok(())
-    } else {
-        Err("Failed to mark".into())
+    let unread = vec!["UNREAD".to_string()];
+    let (add_label_ids, remove_label_ids) = if read { (vec![], unread) } else { (unread, vec![]) };
+    enqueue(
+        &state,
+        account_id,
+        MailOp::ModifyLabels { message_ids: vec![message_id], add_label_ids, remove_label_ids },
+    )
+    .await
+}
+
+/// Zmiana etykiet wielu wiadomości naraz (messages.batchModify)
+#[tauri::command]
+pub async fn modify_labels_rust(
+    message_ids: Vec<String>,
+    add_label_ids: Vec<String>,
+    remove_label_ids: Vec<String>,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<(), String> {
+    if message_ids.is_empty() {
+        return Ok(());
     }
+    enqueue(&state, account_id, MailOp::ModifyLabels { message_ids, add_label_ids, remove_label_ids }).await
 }
 
+/// Domyślnie przenosi do kosza; `permanent: true` usuwa na stałe
 #[tauri::command]
 pub async fn delete_email_rust(
     message_id: String,
-    _state: State<'_, GmailState>,
+    permanent: Option<bool>,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
 ) -> Result<(), String> {
-    let client = reqwest::Client::new();
-    let res = client
-        .delete(&format!("http://localhost:3001/api/emails/{}", message_id))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-    if res.status().is_success() {
-        Ok(())
+    let op = if permanent.unwrap_or(false) {
+        MailOp::Delete { message_id }
     } else {
-        Err("Failed to delete".into())
+        MailOp::Trash { message_id }
+    };
+    enqueue(&state, account_id, op).await
+}
+
+#[tauri::command]
+pub async fn untrash_email_rust(
+    message_id: String,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<(), String> {
+    enqueue(&state, account_id, MailOp::Untrash { message_id }).await
+}
+
+/// Zmiana trafia od razu do cache, a do Gmaila przez kolejkę konta
+async fn enqueue(state: &GmailState, account_id: Option<String>, op: MailOp) -> Result<(), String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.enqueue(op).await.map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Kolejka zmian (oczekujące i odrzucone przez Gmail) - do pokazania użytkownikowi
+#[tauri::command]
+pub async fn list_pending_ops_rust(
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<Vec<PendingOp>, String> {
+    let sources = state.sources(account_id.as_deref()).await?;
+    let merged = sources.len() > 1;
+    let mut ops = Vec::new();
+    for (id, manager) in sources {
+        let mut account_ops = manager.cache.list_ops().map_err(|e| e.to_string())?;
+        if merged {
+            for op in &mut account_ops {
+                op.account_id = Some(id.clone());
+            }
+        }
+        ops.extend(account_ops);
     }
+    ops.sort_by_key(|op| op.created_at);
+    Ok(ops)
+}
+
+#[tauri::command]
+pub async fn retry_pending_op_rust(
+    op_id: i64,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<bool, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.retry_op(op_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn discard_pending_op_rust(
+    op_id: i64,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<bool, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.discard_op(op_id).await.map_err(|e| e.to_string())
+}
+
+/// Etykiety konta (odświeżane z Gmaila, offline z cache)
+#[tauri::command]
+pub async fn list_labels_rust(
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<Vec<GmailLabel>, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.labels().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_label_rust(
+    label_id: String,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<GmailLabel, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.get_label(&label_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_label_rust(
+    label: LabelUpdate,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<GmailLabel, String> {
+    if label.name.as_deref().is_none_or(|n| n.trim().is_empty()) {
+        return Err("Label name is required".into());
+    }
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.create_label(&label).await.map_err(|e| e.to_string())
+}
+
+/// Zmiana nazwy, koloru albo widoczności - tylko podane pola
+#[tauri::command]
+pub async fn update_label_rust(
+    label_id: String,
+    label: LabelUpdate,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<GmailLabel, String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.update_label(&label_id, &label).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_label_rust(
+    label_id: String,
+    account_id: Option<String>,
+    state: State<'_, GmailState>,
+) -> Result<(), String> {
+    let manager = state.manager(account_id.as_deref()).await?;
+    manager.delete_label(&label_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -421,7 +968,15 @@ pub fn parse_emails_batch_rust(messages_json: String) -> Result<Vec<EmailMessage
     let gmail_messages: Vec<crate::types::GmailMessage> =
         serde_json::from_str(&messages_json).map_err(|e| e.to_string())?;
 
-    let parsed: Vec<EmailMessage> = gmail_messages.into_iter().map(parse_email_message).collect();
+    // Bez cache nie ma zgód nadawców - zdalne obrazki zawsze zablokowane
+    let parsed: Vec<EmailMessage> = gmail_messages
+        .into_iter()
+        .map(|m| {
+            let mut parsed = parse_email_message(m);
+            crate::parser::apply_remote_content(&mut parsed, false, false);
+            parsed
+        })
+        .collect();
 
     Ok(parsed)
 }
\ No newline at end of file
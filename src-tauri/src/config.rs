@@ -0,0 +1,97 @@
+pub const DEFAULT_GMAIL_API_BASE: &str = "https://www.googleapis.com/gmail/v1";
+pub const DEFAULT_BACKEND_BASE: &str = "http://localhost:3001";
+pub const DEFAULT_OAUTH_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+pub const DEFAULT_OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+/// Adresy usług, z którymi rozmawia backend Rust.
+/// Domyślnie Google + lokalny backend Node; zmienne środowiskowe pozwalają
+/// podpiąć np. mock Gmaila (`NEXDECK_GMAIL_API_BASE`, `NEXDECK_BACKEND_BASE`,
+/// `NEXDECK_OAUTH_AUTH_URL`, `NEXDECK_OAUTH_TOKEN_URL`).
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    pub gmail_api_base: String,
+    pub backend_base: String,
+    pub oauth_auth_url: String,
+    pub oauth_token_url: String,
+    /// Klient OAuth typu "Desktop app" z Google Cloud Console
+    pub oauth_client_id: String,
+    /// Google wymaga sekretu także dla aplikacji desktopowych (nie jest on poufny)
+    pub oauth_client_secret: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            gmail_api_base: DEFAULT_GMAIL_API_BASE.to_string(),
+            backend_base: DEFAULT_BACKEND_BASE.to_string(),
+            oauth_auth_url: DEFAULT_OAUTH_AUTH_URL.to_string(),
+            oauth_token_url: DEFAULT_OAUTH_TOKEN_URL.to_string(),
+            // Można wbudować przy kompilacji albo podać w czasie działania
+            oauth_client_id: option_env!("NEXDECK_GOOGLE_CLIENT_ID").unwrap_or_default().to_string(),
+            oauth_client_secret: option_env!("NEXDECK_GOOGLE_CLIENT_SECRET").map(String::from),
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            gmail_api_base: env_url("NEXDECK_GMAIL_API_BASE").unwrap_or(defaults.gmail_api_base),
+            backend_base: env_url("NEXDECK_BACKEND_BASE").unwrap_or(defaults.backend_base),
+            oauth_auth_url: env_url("NEXDECK_OAUTH_AUTH_URL").unwrap_or(defaults.oauth_auth_url),
+            oauth_token_url: env_url("NEXDECK_OAUTH_TOKEN_URL").unwrap_or(defaults.oauth_token_url),
+            oauth_client_id: env_value("NEXDECK_GOOGLE_CLIENT_ID").unwrap_or(defaults.oauth_client_id),
+            oauth_client_secret: env_value("NEXDECK_GOOGLE_CLIENT_SECRET").or(defaults.oauth_client_secret),
+        }
+    }
+
+    /// URL endpointu Gmail API, np. `gmail_url("/users/me/profile")`
+    pub fn gmail_url(&self, path: &str) -> String {
+        format!("{}{}", self.gmail_api_base, path)
+    }
+
+    /// Endpoint batch: `https://www.googleapis.com/gmail/v1` -> `https://www.googleapis.com/batch/gmail/v1`
+    pub fn gmail_batch_url(&self) -> String {
+        match self.gmail_api_base.find("/gmail/") {
+            Some(idx) => format!("{}/batch{}", &self.gmail_api_base[..idx], &self.gmail_api_base[idx..]),
+            None => format!("{}/batch", self.gmail_api_base),
+        }
+    }
+
+    /// Endpoint uploadu: `https://www.googleapis.com/gmail/v1` + `/users/me/messages/send`
+    /// -> `https://www.googleapis.com/upload/gmail/v1/users/me/messages/send`
+    pub fn gmail_upload_url(&self, path: &str) -> String {
+        match self.gmail_api_base.find("/gmail/") {
+            Some(idx) => format!("{}/upload{}{}", &self.gmail_api_base[..idx], &self.gmail_api_base[idx..], path),
+            None => format!("{}/upload{}", self.gmail_api_base, path),
+        }
+    }
+
+    /// Sama ścieżka (bez hosta) dla pod-żądań batcha, np. `/gmail/v1/users/me/messages/abc`
+    pub fn gmail_path(&self, path: &str) -> String {
+        let base = &self.gmail_api_base;
+        let after_scheme = base.find("://").map(|i| i + 3).unwrap_or(0);
+        let base_path = base[after_scheme..]
+            .find('/')
+            .map(|i| &base[after_scheme + i..])
+            .unwrap_or("");
+        format!("{}{}", base_path, path)
+    }
+
+    /// URL endpointu backendu Node, np. `backend_url("/api/user/profile")`
+    pub fn backend_url(&self, path: &str) -> String {
+        format!("{}{}", self.backend_base, path)
+    }
+}
+
+fn env_url(key: &str) -> Option<String> {
+    env_value(key).map(|v| v.trim_end_matches('/').to_string())
+}
+
+fn env_value(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
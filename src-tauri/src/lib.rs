@@ -1,26 +1,81 @@
 mod client;
 mod command;
+mod config;
 mod parser;
+mod accounts;
+mod attachments;
+mod auth;
+mod batch;
 mod cache;
+#[cfg(feature = "cache-bench")]
+pub mod cache_bench;
+mod migrations;
+mod mime;
+#[cfg(feature = "mock-gmail")]
+pub mod mock_gmail;
+/// Typy backendu dla testów integracyjnych z mockiem Gmaila (`tests/`)
+#[cfg(feature = "mock-gmail")]
+pub mod testing {
+    pub use crate::auth::{OAuthTokens, TokenStore, TokenVault};
+    pub use crate::cache::{Cache, CachedMessage};
+    pub use crate::client::GmailClient;
+    pub use crate::config::ApiConfig;
+    pub use crate::reply::ReplyKind;
+    pub use crate::search::SearchQuery;
+    pub use crate::sync::{SyncLimits, SyncManager};
+    pub use crate::types::EmailData;
+}
+mod outbox;
+mod reply;
+mod sanitize;
+mod search;
 mod sync;
 mod types;
 
 use crate::command::GmailState;
+use crate::config::ApiConfig;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .manage(GmailState::new())
+        .manage(GmailState::new(ApiConfig::from_env()))
         .invoke_handler(tauri::generate_handler![
             command::init_gmail_client,
+            command::login_rust,
+            command::logout_rust,
+            command::list_accounts_rust,
             command::get_emails_rust,
             command::get_email_rust,
+            command::set_sender_images_rust,
+            command::list_image_senders_rust,
+            command::get_threads_rust,
+            command::get_thread_rust,
+            command::search_emails_rust,
             command::get_mailbox_stats_rust,
             command::get_today_stats_rust, // <- zarejestrowana nowa komenda
             command::get_user_profile_rust,
             command::send_email_rust,
+            command::reply_email_rust,
+            command::reply_all_rust,
+            command::forward_email_rust,
+            command::download_attachment_rust,
+            command::save_draft_rust,
+            command::list_drafts_rust,
+            command::get_draft_rust,
+            command::send_draft_rust,
+            command::delete_draft_rust,
             command::mark_email_rust,
             command::delete_email_rust,
+            command::untrash_email_rust,
+            command::modify_labels_rust,
+            command::list_pending_ops_rust,
+            command::retry_pending_op_rust,
+            command::discard_pending_op_rust,
+            command::list_labels_rust,
+            command::get_label_rust,
+            command::create_label_rust,
+            command::update_label_rust,
+            command::delete_label_rust,
             command::parse_emails_batch_rust,
         ])
         .run(tauri::generate_context!())
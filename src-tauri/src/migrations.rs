@@ -0,0 +1,705 @@
+use rusqlite::Connection;
+
+/// Jeden krok schematu cache. Wersja zapisywana jest w `PRAGMA user_version`.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("cache database is at schema v{found}, but this build only supports up to v{supported}; please update NexDeck")]
+    DatabaseTooNew { found: i64, supported: i64 },
+    #[error("cache migration v{version} ({name}) failed: {source}")]
+    Step {
+        version: i64,
+        name: &'static str,
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Kolejne migracje - tylko dopisywać na końcu, nigdy nie zmieniać istniejących.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "messages and meta",
+        // IF NOT EXISTS: bazy sprzed migracji mają już te tabele przy user_version = 0
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS messages (
+                    message_id TEXT PRIMARY KEY,
+                    thread_id TEXT NOT NULL,
+                    headers_json TEXT NOT NULL,
+                    label_ids_json TEXT NOT NULL,
+                    snippet TEXT,
+                    internal_date INTEGER NOT NULL,
+                    synced_history_id INTEGER
+                );
+                CREATE INDEX IF NOT EXISTS idx_thread_id ON messages(thread_id);
+                CREATE INDEX IF NOT EXISTS idx_internal_date ON messages(internal_date DESC);
+                CREATE TABLE IF NOT EXISTS meta (
+                    key TEXT PRIMARY KEY,
+                    value TEXT
+                );",
+            )
+        },
+    },
+    Migration {
+        version: 2,
+        name: "full-text search index",
+        up: |c| {
+            c.execute_batch(
+                "-- rowid w messages_fts == rowid w messages
+                CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+                    subject,
+                    sender,
+                    recipients,
+                    snippet,
+                    body,
+                    has_attachment UNINDEXED,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+                -- Bazy z buildów sprzed migracji mogą mieć już wiersze z treścią body - nie nadpisujemy ich
+                INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+                SELECT
+                    m.rowid,
+                    COALESCE((SELECT group_concat(json_extract(h.value, '$.value'), ', ') FROM json_each(m.headers_json) h
+                              WHERE lower(json_extract(h.value, '$.name')) = 'subject'), ''),
+                    COALESCE((SELECT group_concat(json_extract(h.value, '$.value'), ', ') FROM json_each(m.headers_json) h
+                              WHERE lower(json_extract(h.value, '$.name')) = 'from'), ''),
+                    COALESCE((SELECT group_concat(json_extract(h.value, '$.value'), ', ') FROM json_each(m.headers_json) h
+                              WHERE lower(json_extract(h.value, '$.name')) IN ('to', 'cc')), ''),
+                    COALESCE(m.snippet, ''),
+                    '',
+                    EXISTS (SELECT 1 FROM json_each(m.headers_json) h
+                            WHERE lower(json_extract(h.value, '$.name')) = 'content-type'
+                              AND lower(json_extract(h.value, '$.value')) LIKE 'multipart/mixed%')
+                FROM messages m
+                WHERE m.rowid NOT IN (SELECT rowid FROM messages_fts);",
+            )
+        },
+    },
+    Migration {
+        version: 3,
+        name: "message bodies",
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS bodies (
+                    message_id TEXT PRIMARY KEY,
+                    body TEXT NOT NULL,
+                    body_text TEXT NOT NULL,
+                    attachments_json TEXT NOT NULL,
+                    inline_images_json TEXT NOT NULL,
+                    fetched_at INTEGER NOT NULL
+                );",
+            )
+        },
+    },
+    Migration {
+        version: 4,
+        name: "pending operations outbox",
+        // snapshots_json: stan wiadomości sprzed zmiany, do cofnięcia przy trwałym błędzie
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS pending_ops (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    op_json TEXT NOT NULL,
+                    snapshots_json TEXT NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'pending',
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+                    last_error TEXT,
+                    created_at INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_pending_ops_status ON pending_ops(status, id);",
+            )
+        },
+    },
+    Migration {
+        version: 5,
+        name: "message labels index",
+        // Etykiety jako wiersze (label_id, internal_date, message_id) - lista etykiety to zakres
+        // klucza głównego. Triggery utrzymują tabelę przy każdym zapisie label_ids_json.
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS message_labels (
+                    label_id TEXT NOT NULL,
+                    internal_date INTEGER NOT NULL,
+                    message_id TEXT NOT NULL,
+                    unread INTEGER NOT NULL,
+                    PRIMARY KEY (label_id, internal_date, message_id)
+                ) WITHOUT ROWID;
+                CREATE INDEX IF NOT EXISTS idx_message_labels_message ON message_labels(message_id, label_id);
+                CREATE INDEX IF NOT EXISTS idx_messages_date_id ON messages(internal_date, message_id);
+
+                CREATE TRIGGER IF NOT EXISTS messages_labels_insert AFTER INSERT ON messages BEGIN
+                    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+                    SELECT value, NEW.internal_date, NEW.message_id,
+                           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+                    FROM json_each(NEW.label_ids_json);
+                END;
+                CREATE TRIGGER IF NOT EXISTS messages_labels_update AFTER UPDATE OF label_ids_json, internal_date ON messages BEGIN
+                    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+                    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+                    SELECT value, NEW.internal_date, NEW.message_id,
+                           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+                    FROM json_each(NEW.label_ids_json);
+                END;
+                CREATE TRIGGER IF NOT EXISTS messages_labels_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+                END;
+
+                INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+                SELECT l.value, m.internal_date, m.message_id,
+                       EXISTS (SELECT 1 FROM json_each(m.label_ids_json) WHERE value = 'UNREAD')
+                FROM messages m, json_each(m.label_ids_json) l;",
+            )
+        },
+    },
+    Migration {
+        version: 6,
+        name: "labels",
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS labels (
+                    label_id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    type TEXT,
+                    message_list_visibility TEXT,
+                    label_list_visibility TEXT,
+                    text_color TEXT,
+                    background_color TEXT
+                );",
+            )
+        },
+    },
+    Migration {
+        version: 7,
+        name: "drafts",
+        // Lokalna kopia wersji roboczych: dirty = zmiany jeszcze nie wysłane do Gmaila
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS drafts (
+                    local_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    draft_id TEXT UNIQUE,
+                    message_id TEXT,
+                    thread_id TEXT,
+                    data_json TEXT NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    dirty INTEGER NOT NULL DEFAULT 1
+                );",
+            )
+        },
+    },
+    Migration {
+        version: 8,
+        name: "attachment store",
+        // Pobrane załączniki; `file_name` to plik w katalogu attachments (sha256 treści),
+        // ten sam plik może należeć do kilku wiadomości
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS attachments (
+                    message_id TEXT NOT NULL,
+                    attachment_id TEXT NOT NULL,
+                    filename TEXT NOT NULL,
+                    mime_type TEXT NOT NULL,
+                    sha256 TEXT NOT NULL,
+                    file_name TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    fetched_at INTEGER NOT NULL,
+                    last_used_at INTEGER NOT NULL,
+                    PRIMARY KEY (message_id, attachment_id)
+                );
+                CREATE INDEX IF NOT EXISTS idx_attachments_file ON attachments(file_name);
+                CREATE INDEX IF NOT EXISTS idx_attachments_used ON attachments(last_used_at);",
+            )
+        },
+    },
+    Migration {
+        version: 9,
+        name: "body sanitizer version",
+        // Wersja reguł sanityzacji, którą przeszło body (0 - sprzed sanityzacji). Starsze body
+        // są sanityzowane ponownie przy odczycie (`Cache::get_body`), nie w migracji.
+        up: |c| c.execute_batch("ALTER TABLE bodies ADD COLUMN sanitizer_version INTEGER NOT NULL DEFAULT 0;"),
+    },
+    Migration {
+        version: 10,
+        name: "image senders",
+        // Nadawcy (adres małymi literami), których zdalne obrazki ładują się bez pytania
+        up: |c| {
+            c.execute_batch(
+                "CREATE TABLE IF NOT EXISTS image_senders (
+                    email TEXT PRIMARY KEY,
+                    added_at INTEGER NOT NULL
+                );",
+            )
+        },
+    },
+    Migration {
+        version: 11,
+        name: "thread labels index",
+        // Wątek w widoku etykiety jako wiersz (label_id, last_date, thread_id) - lista wątków to zakres
+        // klucza głównego, jak message_labels dla wiadomości. Triggery przeliczają cały wątek.
+        up: index_threads,
+    },
+];
+
+/// Wiersze thread_labels wątków spełniających `m.thread_id = {thread}`: etykiety wiadomości
+/// widocznych w danym widoku (jak `LabelFilter`) i data najnowszej wiadomości wątku widocznej
+/// w tym widoku (kosz tylko w widoku kosza). Etykieta '' to wszystkie wiadomości poza koszem.
+fn thread_label_rows(thread: &str) -> String {
+    format!(
+        "SELECT l.value,
+                (SELECT MAX(a.internal_date) FROM messages a
+                 WHERE a.thread_id = m.thread_id
+                   AND (l.value = 'TRASH' OR NOT EXISTS (SELECT 1 FROM json_each(a.label_ids_json) WHERE value = 'TRASH'))),
+                m.thread_id
+         FROM messages m, json_each(m.label_ids_json) l
+         WHERE m.thread_id = {thread}
+           AND NOT EXISTS (SELECT 1 FROM json_each(m.label_ids_json) x
+                           WHERE (x.value = 'TRASH' AND l.value <> 'TRASH')
+                              OR (l.value = 'DRAFT' AND x.value IN ('SENT', 'SPAM')))
+         UNION
+         SELECT '', MAX(m.internal_date), m.thread_id FROM messages m
+         WHERE m.thread_id = {thread}
+           AND NOT EXISTS (SELECT 1 FROM json_each(m.label_ids_json) WHERE value = 'TRASH')
+         GROUP BY m.thread_id",
+        thread = thread
+    )
+}
+
+/// OR IGNORE w triggerze nie wystarcza - konflikt rozstrzyga instrukcja, która go odpaliła
+/// (upsert w `Cache::upsert_message` to ABORT), więc żaden wiersz nie może trafić tu dwa razy
+fn index_threads(c: &Connection) -> rusqlite::Result<()> {
+    let insert = "INSERT OR IGNORE INTO thread_labels (label_id, last_date, thread_id)";
+    c.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS thread_labels (
+            label_id TEXT NOT NULL,
+            last_date INTEGER NOT NULL,
+            thread_id TEXT NOT NULL,
+            PRIMARY KEY (label_id, last_date, thread_id)
+        ) WITHOUT ROWID;
+        CREATE INDEX IF NOT EXISTS idx_thread_labels_thread ON thread_labels(thread_id);
+
+        CREATE TRIGGER IF NOT EXISTS messages_threads_insert AFTER INSERT ON messages BEGIN
+            DELETE FROM thread_labels WHERE thread_id = NEW.thread_id;
+            {insert} {new};
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_threads_update AFTER UPDATE OF label_ids_json, internal_date, thread_id ON messages BEGIN
+            DELETE FROM thread_labels WHERE thread_id IN (OLD.thread_id, NEW.thread_id);
+            {insert} {old};
+            {insert} SELECT * FROM ({new}) WHERE NEW.thread_id <> OLD.thread_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS messages_threads_delete AFTER DELETE ON messages BEGIN
+            DELETE FROM thread_labels WHERE thread_id = OLD.thread_id;
+            {insert} {old};
+        END;
+
+        {insert} {all};",
+        insert = insert,
+        new = thread_label_rows("NEW.thread_id"),
+        old = thread_label_rows("OLD.thread_id"),
+        all = thread_label_rows("m.thread_id"),
+    ))
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("PRAGMA user_version", [], |r| r.get(0))
+}
+
+/// Doprowadza bazę do najnowszej wersji. Każdy krok w osobnej transakcji
+/// razem z podbiciem user_version, więc przerwana migracja nie zostawia pół-schematu.
+pub fn migrate(conn: &mut Connection) -> Result<i64, MigrationError> {
+    let current = schema_version(conn)?;
+    let supported = latest_version();
+
+    if current > supported {
+        return Err(MigrationError::DatabaseTooNew { found: current, supported });
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let step_err = |source| MigrationError::Step {
+            version: migration.version,
+            name: migration.name,
+            source,
+        };
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).map_err(step_err)?;
+        // PRAGMA nie przyjmuje parametrów - wersja to nasza stała, nie dane użytkownika
+        tx.execute_batch(&format!("PRAGMA user_version = {}", migration.version))
+            .map_err(step_err)?;
+        tx.commit()?;
+
+        eprintln!("🧱 Cache schema migrated to v{} ({})", migration.version, migration.name);
+    }
+
+    Ok(supported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Baza w pamięci odtworzona ze zrzutu schematu i danych danej wersji
+    fn open_fixture(sql: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(sql).unwrap();
+        conn
+    }
+
+    /// Tabele, indeksy i triggery z kolumnami - bez tekstu SQL (różni się np. IF NOT EXISTS)
+    fn schema(conn: &Connection) -> Vec<String> {
+        let objects = conn
+            .prepare(
+                "SELECT type, name, tbl_name FROM sqlite_master
+                 WHERE name NOT LIKE 'sqlite_%' AND name NOT LIKE 'messages_fts_%'
+                 ORDER BY type, name",
+            )
+            .unwrap()
+            .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+
+        let mut out = Vec::new();
+        for (kind, name, table) in objects {
+            let columns: Vec<String> = if kind == "table" {
+                conn.prepare(&format!("PRAGMA table_info(\"{}\")", name))
+                    .unwrap()
+                    .query_map([], |r| Ok(format!("{} {} {}", r.get::<_, String>(1)?, r.get::<_, String>(2)?, r.get::<_, bool>(3)?)))
+                    .unwrap()
+                    .collect::<rusqlite::Result<_>>()
+                    .unwrap()
+            } else {
+                Vec::new()
+            };
+            out.push(format!("{} {} on {} [{}]", kind, name, table, columns.join(", ")));
+        }
+        out
+    }
+
+    fn fresh_schema() -> Vec<String> {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        schema(&conn)
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |r| r.get(0)).unwrap()
+    }
+
+    fn labels_of(conn: &Connection, message_id: &str) -> Vec<(String, bool)> {
+        conn.prepare("SELECT label_id, unread FROM message_labels WHERE message_id = ?1 ORDER BY label_id")
+            .unwrap()
+            .query_map([message_id], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn fts_hits(conn: &Connection, expr: &str) -> Vec<String> {
+        conn.prepare(
+            "SELECT m.message_id FROM messages_fts JOIN messages m ON m.rowid = messages_fts.rowid
+             WHERE messages_fts MATCH ?1 ORDER BY m.message_id",
+        )
+        .unwrap()
+        .query_map([expr], |r| r.get(0))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+    }
+
+    /// Fixture podniesiona do najnowszej wersji - schemat taki sam jak w świeżej bazie
+    fn upgraded(sql: &str, version: i64) -> Connection {
+        let mut conn = open_fixture(sql);
+        assert_eq!(schema_version(&conn).unwrap(), version);
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert_eq!(schema(&conn), fresh_schema());
+        conn
+    }
+
+    /// Wiersze thread_labels wątku: (etykieta, data), po etykiecie
+    fn thread_rows(conn: &Connection, thread_id: &str) -> Vec<(String, i64)> {
+        conn.prepare("SELECT label_id, last_date FROM thread_labels WHERE thread_id = ?1 ORDER BY label_id")
+            .unwrap()
+            .query_map([thread_id], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn rows(list: &[(&str, i64)]) -> Vec<(String, i64)> {
+        list.iter().map(|(l, d)| (l.to_string(), *d)).collect()
+    }
+
+    #[test]
+    fn versions_are_sequential() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(m.version, i as i64 + 1, "migration {} out of order", m.name);
+        }
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        // Drugi raz nic nie robi
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert_eq!(schema(&conn), fresh_schema());
+    }
+
+    #[test]
+    fn upgrades_v0_baseline() {
+        let mut conn = open_fixture(include_str!("../tests/fixtures/migrations/v0.sql"));
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        assert_eq!(schema(&conn), fresh_schema());
+
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages"), 3);
+        let history: String = conn
+            .query_row("SELECT value FROM meta WHERE key = 'last_history_id'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(history, "102");
+
+        // v2 zbudował indeks FTS z nagłówków, v5 etykiety z label_ids_json
+        assert_eq!(fts_hits(&conn, "subject : faktura"), vec!["m1", "m2"]);
+        assert_eq!(fts_hits(&conn, "sender : kowalski"), vec!["m1"]);
+        assert_eq!(
+            count(&conn, "SELECT has_attachment FROM messages_fts WHERE rowid = (SELECT rowid FROM messages WHERE message_id = 'm1')"),
+            1
+        );
+        assert_eq!(labels_of(&conn, "m1"), vec![("INBOX".to_string(), true), ("UNREAD".to_string(), true)]);
+        assert_eq!(labels_of(&conn, "m3"), vec![("CATEGORY_PROMOTIONS".to_string(), false), ("INBOX".to_string(), false)]);
+    }
+
+    #[test]
+    fn upgrades_v0_with_search_tables() {
+        let mut conn = open_fixture(include_str!("../tests/fixtures/migrations/v0_search.sql"));
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema(&conn), fresh_schema());
+        // Istniejący wiersz FTS z treścią body nie został nadpisany pustym body
+        assert_eq!(fts_hits(&conn, "body : umowy"), vec!["m1"]);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages_fts"), 1);
+        let attachments: String = conn
+            .query_row("SELECT attachments_json FROM bodies WHERE message_id = 'm1'", [], |r| r.get(0))
+            .unwrap();
+        assert!(attachments.contains("umowa.pdf"));
+    }
+
+    #[test]
+    fn upgrades_v4() {
+        let mut conn = open_fixture(include_str!("../tests/fixtures/migrations/v4.sql"));
+        assert_eq!(schema_version(&conn).unwrap(), 4);
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema(&conn), fresh_schema());
+        let (status, attempts, error): (String, i64, String) = conn
+            .query_row("SELECT status, attempts, last_error FROM pending_ops", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap();
+        assert_eq!((status.as_str(), attempts, error.as_str()), ("pending", 2, "network"));
+
+        assert_eq!(
+            labels_of(&conn, "m1"),
+            vec![("INBOX".to_string(), true), ("STARRED".to_string(), true), ("UNREAD".to_string(), true)]
+        );
+        // Triggery z v5 działają na starych wierszach
+        conn.execute("UPDATE messages SET label_ids_json = '[\"INBOX\"]' WHERE message_id = 'm1'", []).unwrap();
+        assert_eq!(labels_of(&conn, "m1"), vec![("INBOX".to_string(), false)]);
+        assert_eq!(fts_hits(&conn, "raport"), vec!["m2"]);
+    }
+
+    #[test]
+    fn upgrades_v8() {
+        let mut conn = open_fixture(include_str!("../tests/fixtures/migrations/v8.sql"));
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema(&conn), fresh_schema());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM bodies"), 1);
+        // Stare body zostaje bez zmian do pierwszego odczytu
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM bodies WHERE sanitizer_version = 0"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM attachments WHERE sha256 = 'ab12'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM drafts WHERE draft_id = 'd1' AND dirty = 0"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM labels WHERE name = 'Wyjazdy'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM image_senders"), 0);
+        // v11 zbudował indeks wątków z istniejących wiadomości
+        assert_eq!(
+            count(&conn, "SELECT COUNT(*) FROM thread_labels WHERE thread_id = 't1' AND last_date = 1700000000000"),
+            3
+        );
+    }
+
+    #[test]
+    fn upgrades_v1() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v1.sql"), 1);
+        // v2 zbudował FTS z nagłówków, v5 etykiety, v11 wątki
+        assert_eq!(fts_hits(&conn, "subject : umowa"), vec!["m1", "m2"]);
+        assert_eq!(fts_hits(&conn, "recipients : ola"), vec!["m1"]);
+        assert_eq!(labels_of(&conn, "m1"), vec![("INBOX".to_string(), true), ("UNREAD".to_string(), true)]);
+        let date = 1700000100000;
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", date), ("INBOX", date), ("SENT", date), ("UNREAD", date)]));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM bodies"), 0);
+    }
+
+    #[test]
+    fn upgrades_v2() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v2.sql"), 2);
+        // Wiersz FTS z treścią body został, bez duplikatu
+        assert_eq!(fts_hits(&conn, "body : cholesterol"), vec!["m1"]);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages_fts"), 1);
+        assert_eq!(labels_of(&conn, "m1"), vec![("INBOX".to_string(), false)]);
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", 1700000000000), ("INBOX", 1700000000000)]));
+    }
+
+    #[test]
+    fn upgrades_v3() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v3.sql"), 3);
+        let (attachments, version): (String, i64) = conn
+            .query_row("SELECT attachments_json, sanitizer_version FROM bodies WHERE message_id = 'm1'", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert!(attachments.contains("bilety.pdf"));
+        // Body sprzed sanityzacji - do ponownej sanityzacji przy odczycie
+        assert_eq!(version, 0);
+        assert_eq!(fts_hits(&conn, "bilety"), vec!["m1"]);
+        assert_eq!(labels_of(&conn, "m1"), vec![("INBOX".to_string(), false), ("STARRED".to_string(), false)]);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM pending_ops"), 0);
+    }
+
+    #[test]
+    fn upgrades_v5() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v5.sql"), 5);
+        assert_eq!(
+            labels_of(&conn, "m2"),
+            vec![("INBOX".to_string(), false), ("TRASH".to_string(), false)]
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM pending_ops WHERE status = 'pending'"), 1);
+        // Wiadomość z kosza liczy się tylko w widoku kosza
+        let date = 1700000000000;
+        assert_eq!(
+            thread_rows(&conn, "t1"),
+            rows(&[("", date), ("INBOX", date), ("TRASH", 1700000300000), ("UNREAD", date)])
+        );
+        // Triggery z v5 i v11 działają razem na starych wierszach
+        conn.execute("UPDATE messages SET label_ids_json = '[\"INBOX\"]' WHERE message_id = 'm2'", []).unwrap();
+        assert_eq!(labels_of(&conn, "m2"), vec![("INBOX".to_string(), false)]);
+        let date = 1700000300000;
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", date), ("INBOX", date), ("UNREAD", date)]));
+    }
+
+    #[test]
+    fn upgrades_v6() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v6.sql"), 6);
+        let (name, background): (String, String) = conn
+            .query_row("SELECT name, background_color FROM labels WHERE label_id = 'Label_7'", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((name.as_str(), background.as_str()), ("Rachunki", "#fb4c2f"));
+        let date = 1700000000000;
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", date), ("INBOX", date), ("Label_7", date)]));
+    }
+
+    #[test]
+    fn upgrades_v7() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v7.sql"), 7);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM drafts WHERE dirty = 1"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM drafts WHERE draft_id = 'd1' AND message_id = 'm9'"), 1);
+        let date = 1700000100000;
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", date), ("DRAFT", date), ("INBOX", date)]));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM attachments"), 0);
+    }
+
+    #[test]
+    fn upgrades_v9() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v9.sql"), 9);
+        let (body, version): (String, i64) = conn
+            .query_row("SELECT body, sanitizer_version FROM bodies WHERE message_id = 'm1'", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert!(body.contains("baner.png"));
+        assert_eq!(version, 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM image_senders"), 0);
+        assert_eq!(
+            thread_rows(&conn, "t1"),
+            rows(&[("", 1700000000000), ("CATEGORY_PROMOTIONS", 1700000000000), ("INBOX", 1700000000000)])
+        );
+    }
+
+    #[test]
+    fn upgrades_v10() {
+        let conn = upgraded(include_str!("../tests/fixtures/migrations/v10.sql"), 10);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM image_senders WHERE email = 'sklep@example.com'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM bodies WHERE sanitizer_version = 1"), 1);
+
+        // Backfill v11: odpowiedź z SENT przesuwa wątek, wiadomość z kosza tylko w koszu,
+        // szkic bez wysłanych
+        let (sent, trashed) = (1700000100000, 1700000200000);
+        assert_eq!(
+            thread_rows(&conn, "t1"),
+            rows(&[("", sent), ("INBOX", sent), ("SENT", sent), ("TRASH", trashed)])
+        );
+        assert_eq!(thread_rows(&conn, "t2"), rows(&[("", 1700000050000), ("DRAFT", 1700000050000)]));
+        assert_eq!(thread_rows(&conn, "t3"), rows(&[("", 1700000060000), ("SENT", 1700000060000)]));
+
+        // Triggery na danych z poprzedniej wersji: nowa wiadomość, przywrócenie z kosza,
+        // zapis bez zmian i usunięcie
+        conn.execute_batch(
+            "INSERT INTO messages VALUES ('m6', 't2', '[]', '[\"INBOX\"]', 'Odpowiedź', 1700000400000, 305);",
+        )
+        .unwrap();
+        let reply = 1700000400000;
+        assert_eq!(thread_rows(&conn, "t2"), rows(&[("", reply), ("DRAFT", reply), ("INBOX", reply)]));
+        conn.execute("UPDATE messages SET label_ids_json = '[\"INBOX\"]' WHERE message_id = 'm3'", []).unwrap();
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", trashed), ("INBOX", trashed), ("SENT", trashed)]));
+        conn.execute("UPDATE messages SET label_ids_json = label_ids_json, internal_date = internal_date", []).unwrap();
+        assert_eq!(thread_rows(&conn, "t1"), rows(&[("", trashed), ("INBOX", trashed), ("SENT", trashed)]));
+        conn.execute("DELETE FROM messages WHERE message_id = 'm6'", []).unwrap();
+        assert_eq!(thread_rows(&conn, "t2"), rows(&[("", 1700000050000), ("DRAFT", 1700000050000)]));
+    }
+
+    #[test]
+    fn rejects_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let newer = latest_version() + 1;
+        conn.execute_batch(&format!("CREATE TABLE future (x); PRAGMA user_version = {}", newer)).unwrap();
+
+        match migrate(&mut conn) {
+            Err(MigrationError::DatabaseTooNew { found, supported }) => {
+                assert_eq!(found, newer);
+                assert_eq!(supported, latest_version());
+            }
+            other => panic!("expected DatabaseTooNew, got {:?}", other),
+        }
+        // Baza nietknięta
+        assert_eq!(schema_version(&conn).unwrap(), newer);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sqlite_master WHERE name = 'messages'"), 0);
+    }
+
+    #[test]
+    fn failed_step_rolls_back() {
+        let mut conn = open_fixture(include_str!("../tests/fixtures/migrations/v4.sql"));
+        // Kolizja nazwy: v5 nie utworzy indeksu, bo "message_labels" jest widokiem
+        conn.execute_batch("CREATE VIEW message_labels AS SELECT 1").unwrap();
+
+        match migrate(&mut conn) {
+            Err(MigrationError::Step { version, .. }) => assert_eq!(version, 5),
+            other => panic!("expected Step error, got {:?}", other),
+        }
+        assert_eq!(schema_version(&conn).unwrap(), 4);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sqlite_master WHERE name = 'messages_labels_insert'"), 0);
+    }
+}
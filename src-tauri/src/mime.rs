@@ -0,0 +1,649 @@
+//! Składanie wiadomości wychodzących: RFC 5322 + MIME (RFC 2045/2046/2047/2231).
+//!
+//! Treść zawsze jako multipart/alternative (tekst + HTML), z załącznikami opakowana
+//! w multipart/mixed. Nagłówki spoza ASCII idą jako encoded-words, linie mają max 78 znaków.
+
+use crate::parser::{html_to_text, parse_address_header};
+use crate::types::{EmailAddress, EmailData};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, FixedOffset};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+const LINE_LIMIT: usize = 78;
+/// Bajty UTF-8 w jednym encoded-word: 39 B -> 52 znaki base64, razem z "=?UTF-8?B?" i "?=" 64,
+/// więc słowo mieści się w linii także zaraz po nazwie nagłówka
+const WORD_BYTES: usize = 39;
+/// Base64 czytany porcjami: 57 B daje pełną linię 76 znaków
+const BASE64_CHUNK: usize = 57 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub mime_type: String,
+    /// Plik z dysku (z edytora albo z magazynu załączników), czytany dopiero przy zapisie
+    pub path: PathBuf,
+}
+
+impl Attachment {
+    /// Plik wskazany w edytorze; typ MIME z pierwszych bajtów, a gdy nic nie pasuje - z rozszerzenia
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let mut file = std::fs::File::open(path).map_err(|e| anyhow::anyhow!("Cannot open attachment {}: {}", path.display(), e))?;
+        if !file.metadata()?.is_file() {
+            anyhow::bail!("Attachment {} is not a file", path.display());
+        }
+        let mut head = vec![0u8; 512];
+        let len = read_full(&mut file, &mut head)?;
+        head.truncate(len);
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "attachment".to_string());
+        Ok(Self {
+            mime_type: sniff_mime_type(&head, &filename).to_string(),
+            filename,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutgoingMessage {
+    /// Puste - Gmail wstawi adres konta
+    pub from: Option<String>,
+    pub to: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub subject: String,
+    /// Treść z edytora: HTML albo zwykły tekst (wtedy HTML powstaje z tekstu)
+    pub body: String,
+    /// Część text/plain; domyślnie tekst wyciągnięty z `body`
+    pub text: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub attachments: Vec<Attachment>,
+    /// Stała data i Message-ID (np. pliki wzorcowe); domyślnie teraz i losowy
+    pub date: Option<DateTime<FixedOffset>>,
+    pub message_id: Option<String>,
+}
+
+impl OutgoingMessage {
+    pub fn from_email_data(data: &EmailData) -> Self {
+        Self {
+            to: data.to.clone(),
+            cc: data.cc.clone(),
+            bcc: data.bcc.clone(),
+            subject: data.subject.clone(),
+            body: data.body.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Format `raw` Gmaila (base64url). Bcc zostaje w nagłówkach: Gmail bierze z nich
+    /// odbiorców i sam usuwa Bcc z dostarczanych kopii (zostaje tylko w "Wysłanych").
+    pub fn to_raw(&self) -> anyhow::Result<String> {
+        Ok(general_purpose::URL_SAFE_NO_PAD.encode(self.to_rfc5322(true)?.as_bytes()))
+    }
+
+    /// Wiadomość z końcami linii CRLF. `include_bcc = false` - kopia dla odbiorców.
+    pub fn to_rfc5322(&self, include_bcc: bool) -> anyhow::Result<String> {
+        let mut out = Vec::new();
+        self.write_to(&mut out, include_bcc)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// Jak `to_rfc5322`, ale prosto do `out` - załączniki z plików nie trafiają w całości do pamięci
+    pub fn write_to(&self, out: &mut dyn Write, include_bcc: bool) -> io::Result<()> {
+        let message_id = self.message_id.clone().unwrap_or_else(|| new_message_id(self.from.as_deref()));
+        let date = self.date.unwrap_or_else(|| chrono::Local::now().fixed_offset());
+
+        let mut head = String::new();
+        head.push_str(&header("Date", &date.to_rfc2822()));
+        head.push_str(&header("Message-ID", &message_id));
+        if let Some(from) = self.from.as_deref().filter(|f| !f.trim().is_empty()) {
+            head.push_str(&address_header("From", from));
+        }
+        head.push_str(&address_header("To", &self.to));
+        if let Some(cc) = self.cc.as_deref().filter(|c| !c.trim().is_empty()) {
+            head.push_str(&address_header("Cc", cc));
+        }
+        if include_bcc {
+            if let Some(bcc) = self.bcc.as_deref().filter(|b| !b.trim().is_empty()) {
+                head.push_str(&address_header("Bcc", bcc));
+            }
+        }
+        head.push_str(&header("Subject", &encode_unstructured(&self.subject)));
+        if let Some(in_reply_to) = &self.in_reply_to {
+            head.push_str(&header("In-Reply-To", in_reply_to));
+        }
+        if !self.references.is_empty() {
+            head.push_str(&header("References", &self.references.join(" ")));
+        }
+        head.push_str("MIME-Version: 1.0\r\n");
+
+        // Granice z Message-ID: ta sama wiadomość daje ten sam wynik
+        let seed = hex(&Sha256::digest(message_id.as_bytes())[..8]);
+        let (html, text) = self.bodies();
+        let alternative = Part::Multipart {
+            subtype: "alternative",
+            boundary: format!("=_NexDeck_{}_alt", seed),
+            parts: vec![Part::Text { subtype: "plain", content: text }, Part::Text { subtype: "html", content: html }],
+        };
+        let root = if self.attachments.is_empty() {
+            alternative
+        } else {
+            let mut parts = vec![alternative];
+            parts.extend(self.attachments.iter().map(Part::Attachment));
+            Part::Multipart { subtype: "mixed", boundary: format!("=_NexDeck_{}_mix", seed), parts }
+        };
+        out.write_all(head.as_bytes())?;
+        root.write(out)
+    }
+
+    fn bodies(&self) -> (String, String) {
+        let (html, text) = if looks_like_html(&self.body) {
+            (self.body.clone(), html_to_text(&self.body))
+        } else {
+            (text_to_html(&self.body), self.body.clone())
+        };
+        (html, self.text.clone().unwrap_or(text))
+    }
+}
+
+/// Wiadomość z pól edytora w formacie `raw` Gmaila. Pliki z `data.attachments` dokłada
+/// dopiero wysyłka (`SyncManager::send_email`) - wersja robocza w Gmailu ma sam tekst.
+pub fn raw_message(data: &EmailData) -> anyhow::Result<String> {
+    OutgoingMessage::from_email_data(data).to_raw()
+}
+
+enum Part<'a> {
+    Text { subtype: &'static str, content: String },
+    Attachment(&'a Attachment),
+    Multipart { subtype: &'static str, boundary: String, parts: Vec<Part<'a>> },
+}
+
+impl Part<'_> {
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Part::Text { subtype, content } => {
+                write!(out, "Content-Type: text/{}; charset=utf-8\r\n", subtype)?;
+                out.write_all(b"Content-Transfer-Encoding: quoted-printable\r\n\r\n")?;
+                out.write_all(quoted_printable(content).as_bytes())?;
+                out.write_all(b"\r\n")
+            }
+            Part::Attachment(a) => {
+                let mime = if a.mime_type.is_empty() { "application/octet-stream" } else { &a.mime_type };
+                write!(out, "Content-Type: {};\r\n name={}\r\n", mime, quoted_param(&a.filename))?;
+                // Spoza ASCII - RFC 2231; starsze klienty biorą nazwę z `name` w Content-Type
+                if a.filename.is_ascii() {
+                    write!(out, "Content-Disposition: attachment;\r\n filename={}", quoted_param(&a.filename))?;
+                } else {
+                    write!(out, "Content-Disposition: attachment;\r\n filename*=UTF-8''{}", percent_encode(&a.filename))?;
+                }
+                out.write_all(b"\r\nContent-Transfer-Encoding: base64\r\n\r\n")?;
+                let file = std::fs::File::open(&a.path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Cannot read attachment {}: {}", a.path.display(), e)))?;
+                write_base64(out, &mut io::BufReader::new(file))
+            }
+            Part::Multipart { subtype, boundary, parts } => {
+                write!(out, "Content-Type: multipart/{};\r\n boundary=\"{}\"\r\n\r\n", subtype, boundary)?;
+                for part in parts {
+                    write!(out, "--{}\r\n", boundary)?;
+                    part.write(out)?;
+                }
+                write!(out, "--{}--\r\n", boundary)
+            }
+        }
+    }
+}
+
+/// Postać do pól edytora (bez encoded-words); `parse_address_header` czyta ją z powrotem
+impl std::fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) if name.chars().any(|c| "()<>[]:;@\\,.\"".contains(c)) => {
+                write!(f, "\"{}\" <{}>", name.replace('\\', "\\\\").replace('"', "\\\""), self.email)
+            }
+            Some(name) => write!(f, "{} <{}>", name, self.email),
+            None => f.write_str(&self.email),
+        }
+    }
+}
+
+fn format_mailbox(mailbox: &EmailAddress) -> String {
+    match &mailbox.name {
+        Some(name) if !name.is_ascii() => format!("{} <{}>", encoded_words(name).join(" "), mailbox.email),
+        _ => mailbox.to_string(),
+    }
+}
+
+fn address_header(name: &str, value: &str) -> String {
+    let list: Vec<String> = parse_address_header(value).iter().map(format_mailbox).collect();
+    header(name, &list.join(", "))
+}
+
+/// Tekst nagłówka (np. Subject): spoza ASCII - w całości jako encoded-words
+fn encode_unstructured(value: &str) -> String {
+    if value.is_ascii() && !value.contains("=?") {
+        value.to_string()
+    } else {
+        encoded_words(value).join(" ")
+    }
+}
+
+/// RFC 2047, kodowanie B; słowa dzielone na granicach znaków
+fn encoded_words(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > WORD_BYTES {
+            words.push(encoded_word(&chunk));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    if !chunk.is_empty() || words.is_empty() {
+        words.push(encoded_word(&chunk));
+    }
+    words
+}
+
+fn encoded_word(chunk: &str) -> String {
+    format!("=?UTF-8?B?{}?=", general_purpose::STANDARD.encode(chunk.as_bytes()))
+}
+
+/// "Nazwa: wartość" zawinięta w miejscach spacji do 78 znaków
+fn header(name: &str, value: &str) -> String {
+    let mut out = format!("{}:", name);
+    let mut line_len = out.len();
+    for word in value.split(' ').filter(|w| !w.is_empty()) {
+        if line_len + 1 + word.len() > LINE_LIMIT && line_len > name.len() + 1 {
+            out.push_str("\r\n");
+            line_len = 0;
+        }
+        out.push(' ');
+        out.push_str(word);
+        line_len += 1 + word.len();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+/// RFC 2045 6.7: linie max 76 znaków, końce linii jako CRLF
+fn quoted_printable(text: &str) -> String {
+    let normalized = text.replace("\r\n", "\n");
+    let mut out = String::with_capacity(normalized.len() + normalized.len() / 8);
+    for (i, line) in normalized.split('\n').enumerate() {
+        if i > 0 {
+            out.push_str("\r\n");
+        }
+        let bytes = line.as_bytes();
+        let mut line_len = 0;
+        for (j, &b) in bytes.iter().enumerate() {
+            let last = j + 1 == bytes.len();
+            let literal = matches!(b, b'!'..=b'<' | b'>'..=b'~') || (matches!(b, b' ' | b'\t') && !last);
+            let token = if literal { (b as char).to_string() } else { format!("={:02X}", b) };
+            // Miękki podział: "=" na końcu linii, razem max 76
+            if line_len + token.len() > 75 {
+                out.push_str("=\r\n");
+                line_len = 0;
+            }
+            out.push_str(&token);
+            line_len += token.len();
+        }
+    }
+    out
+}
+
+/// Base64 w liniach po 76 znaków, czytany porcjami z `reader`
+fn write_base64(out: &mut dyn Write, reader: &mut dyn Read) -> io::Result<()> {
+    let mut chunk = vec![0u8; BASE64_CHUNK];
+    loop {
+        let len = read_full(reader, &mut chunk)?;
+        if len == 0 {
+            return Ok(());
+        }
+        let encoded = general_purpose::STANDARD.encode(&chunk[..len]);
+        for line in encoded.as_bytes().chunks(76) {
+            out.write_all(line)?;
+            out.write_all(b"\r\n")?;
+        }
+        if len < chunk.len() {
+            return Ok(());
+        }
+    }
+}
+
+/// Czyta do zapełnienia `buf` albo końca pliku (pełne porcje = linie base64 bez reszty)
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Typ MIME po sygnaturze pliku; kontenery ZIP (docx, xlsx, odt...) i pliki tekstowe rozróżnia rozszerzenie
+pub fn sniff_mime_type(head: &[u8], filename: &str) -> &'static str {
+    let ext = filename.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase()).unwrap_or_default();
+    let signatures: &[(&[u8], &str)] = &[
+        (b"%PDF-", "application/pdf"),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"II*\0", "image/tiff"),
+        (b"MM\0*", "image/tiff"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"{\\rtf", "application/rtf"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+    ];
+    if let Some((_, mime)) = signatures.iter().find(|(sig, _)| head.starts_with(sig)) {
+        // "BM" to za mało, żeby przebić rozszerzenie innego typu
+        if *mime != "image/bmp" || ext.is_empty() || ext == "bmp" {
+            return mime;
+        }
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" {
+        match &head[8..12] {
+            b"WEBP" => return "image/webp",
+            b"WAVE" => return "audio/wav",
+            b"AVI " => return "video/x-msvideo",
+            _ => {}
+        }
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return match &head[8..12] {
+            b"qt  " => "video/quicktime",
+            b"M4A " => "audio/mp4",
+            b"heic" | b"heix" => "image/heic",
+            _ => "video/mp4",
+        };
+    }
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        return match ext.as_str() {
+            "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+            "odt" => "application/vnd.oasis.opendocument.text",
+            "ods" => "application/vnd.oasis.opendocument.spreadsheet",
+            "odp" => "application/vnd.oasis.opendocument.presentation",
+            "epub" => "application/epub+zip",
+            "jar" => "application/java-archive",
+            _ => "application/zip",
+        };
+    }
+    // Stare formaty Office (OLE2)
+    if head.starts_with(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+        return match ext.as_str() {
+            "xls" => "application/vnd.ms-excel",
+            "ppt" => "application/vnd.ms-powerpoint",
+            "msg" => "application/vnd.ms-outlook",
+            _ => "application/msword",
+        };
+    }
+
+    let by_extension = match ext.as_str() {
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "md" => "text/markdown",
+        "ics" => "text/calendar",
+        "vcf" => "text/vcard",
+        "eml" => "message/rfc822",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "tar" => "application/x-tar",
+        _ => "",
+    };
+    if !by_extension.is_empty() {
+        return by_extension;
+    }
+    let text = String::from_utf8_lossy(head);
+    let trimmed = text.trim_start();
+    if trimmed.starts_with("<svg") || (trimmed.starts_with("<?xml") && trimmed.contains("<svg")) {
+        return "image/svg+xml";
+    }
+    // Bez bajtów sterujących i poprawny UTF-8 (ostatni znak mógł zostać ucięty) - tekst
+    let valid_utf8 = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if !head.is_empty() && valid_utf8 && !head.iter().any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) {
+        return "text/plain";
+    }
+    "application/octet-stream"
+}
+
+/// Wartość parametru w cudzysłowie; spoza ASCII - jako encoded-word (rozumiany przez większość klientów)
+fn quoted_param(value: &str) -> String {
+    let value = if value.is_ascii() { value.to_string() } else { encoded_words(value).join(" ") };
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Treść z edytora traktowana jako HTML, jeśli zawiera typowe znaczniki
+pub fn looks_like_html(body: &str) -> bool {
+    let lower = body.to_ascii_lowercase();
+    ["<html", "<body", "<div", "<p>", "<p ", "<br", "<span", "<table", "<a "]
+        .iter()
+        .any(|tag| lower.contains(tag))
+}
+
+pub fn text_to_html(text: &str) -> String {
+    let escaped = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+    format!("<div>{}</div>", escaped.replace("\r\n", "\n").replace('\n', "<br>"))
+}
+
+fn new_message_id(from: Option<&str>) -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let domain = from
+        .and_then(|f| parse_address_header(f).into_iter().next())
+        .and_then(|m| m.email.rsplit_once('@').map(|(_, d)| d.to_string()))
+        .unwrap_or_else(|| "nexdeck.local".to_string());
+    format!("<{}.{}@{}>", chrono::Utc::now().timestamp_millis(), hex(&bytes), domain)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mime");
+
+    /// Porównanie z plikiem wzorcowym; `UPDATE_GOLDEN=1` zapisuje aktualny wynik
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(FIXTURES).join(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(actual, expected, "{} differs from the golden file", name);
+        for line in actual.split("\r\n") {
+            assert!(line.len() <= LINE_LIMIT, "line over {} characters: {:?}", LINE_LIMIT, line);
+            assert!(!line.contains('\n') && !line.contains('\r'), "bare line break in {:?}", line);
+        }
+    }
+
+    /// Plik załącznika w katalogu tymczasowym testu
+    fn attachment(name: &str, filename: &str, mime_type: &str, content: &[u8]) -> Attachment {
+        let dir = std::env::temp_dir().join(format!("nexdeck-mime-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        Attachment { filename: filename.into(), mime_type: mime_type.into(), path }
+    }
+
+    /// Stała data i Message-ID - granice części też wynikają z Message-ID
+    fn message(message_id: &str) -> OutgoingMessage {
+        OutgoingMessage {
+            from: Some("Jan Kowalski <jan@example.com>".into()),
+            date: DateTime::parse_from_rfc3339("2024-03-05T14:07:00+01:00").ok(),
+            message_id: Some(message_id.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn alternative_with_attachments() {
+        let msg = OutgoingMessage {
+            to: "\"Nowak, Anna\" <anna@example.com>; Łukasz Żółw <lukasz@example.pl>".into(),
+            cc: Some("zespol@example.com".into()),
+            bcc: Some("szef@example.com".into()),
+            subject: "Raport kwartalny – zażółć gęślą jaźń, wersja ostateczna (poprawiona)".into(),
+            body: "<p>Cześć,</p><p>w załączniku <b>raport</b> i notatki.</p>".into(),
+            attachments: vec![
+                attachment("notes", "notatki.txt", "text/plain", b"Pierwsza linia\nDruga linia\n"),
+                attachment("report", "Sprawozdanie końcowe.pdf", "application/pdf", &(0..=255u8).cycle().take(300).collect::<Vec<_>>()),
+            ],
+            ..message("<golden-1@example.com>")
+        };
+
+        // Kopia dla odbiorców - bez Bcc
+        let delivered = msg.to_rfc5322(false).unwrap();
+        assert!(!delivered.contains("Bcc:"));
+        assert_golden("alternative_with_attachments.eml", &delivered);
+
+        // Kopia dla Gmaila - Bcc na swoim miejscu, reszta bez zmian
+        let sent = msg.to_rfc5322(true).unwrap();
+        assert_eq!(sent, delivered.replacen("Subject:", "Bcc: szef@example.com\r\nSubject:", 1));
+    }
+
+    #[test]
+    fn plain_text_reply_with_bcc() {
+        let long_line = "Ta linia jest bardzo długa i musi zostać zawinięta miękkim podziałem, bo quoted-printable pozwala na najwyżej 76 znaków w linii.";
+        let msg = OutgoingMessage {
+            to: "anna@example.com".into(),
+            bcc: Some("Archiwum <archiwum@example.com>, kopia@example.com".into()),
+            subject: "Re: Spotkanie".into(),
+            body: format!("{}\n\nKoszt = 100% budżetu \nSłowo na końcu linii:\t\n-- \nJan", long_line),
+            in_reply_to: Some("<parent@example.com>".into()),
+            references: vec!["<root@example.com>".into(), "<parent@example.com>".into()],
+            ..message("<golden-2@example.com>")
+        };
+
+        let sent = msg.to_rfc5322(true).unwrap();
+        assert!(sent.contains("\r\nBcc: Archiwum <archiwum@example.com>, kopia@example.com\r\n"));
+        assert_golden("plain_text_reply_with_bcc.eml", &sent);
+    }
+
+    #[test]
+    fn generated_date_and_message_id() {
+        let msg = OutgoingMessage {
+            from: Some("Jan Kowalski <jan@example.com>".into()),
+            to: "anna@example.com".into(),
+            body: "hej".into(),
+            ..Default::default()
+        };
+        let header = |out: &str, name: &str| -> String {
+            let prefix = format!("{}: ", name);
+            out.lines().find_map(|l| l.strip_prefix(&prefix)).unwrap_or_else(|| panic!("no {} header", name)).to_string()
+        };
+
+        let first = msg.to_rfc5322(false).unwrap();
+        let second = msg.to_rfc5322(false).unwrap();
+        assert!(DateTime::parse_from_rfc2822(&header(&first, "Date")).is_ok());
+        let id = header(&first, "Message-ID");
+        assert!(id.starts_with('<') && id.ends_with("@example.com>"), "{}", id);
+        assert_ne!(id, header(&second, "Message-ID"));
+    }
+
+    #[test]
+    fn sniffs_magic_bytes_over_extension() {
+        let cases: &[(&[u8], &str, &str)] = &[
+            (b"%PDF-1.7\n%\xe2\xe3", "skan.bin", "application/pdf"),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "zdjecie.jpg", "image/png"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "IMG_0001", "image/jpeg"),
+            (b"GIF89a\x01\0", "a.gif", "image/gif"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "a.png", "image/webp"),
+            (b"RIFF\x24\0\0\0WAVEfmt ", "nagranie", "audio/wav"),
+            (b"\0\0\0\x18ftypmp42\0\0", "film.mov", "video/mp4"),
+            (b"\0\0\0\x14ftypqt  \0\0", "film", "video/quicktime"),
+            (b"\0\0\0\x18ftypheic\0\0", "IMG_2.HEIC", "image/heic"),
+            (b"\x1f\x8b\x08\0", "backup.tgz", "application/gzip"),
+            (b"{\\rtf1\\ansi", "pismo.doc", "application/rtf"),
+            (b"ID3\x04\0", "utwor", "audio/mpeg"),
+            // "BM" na początku tekstu nie przebija rozszerzenia
+            (b"BM", "obraz", "image/bmp"),
+            (b"BMW - cennik", "cennik.txt", "text/plain"),
+        ];
+        for (head, filename, expected) in cases {
+            assert_eq!(sniff_mime_type(head, filename), *expected, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn sniffs_containers_by_extension() {
+        let zip = b"PK\x03\x04\x14\0\x06\0";
+        let ole = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1\0\0";
+        let cases: &[(&[u8], &str, &str)] = &[
+            (zip, "Umowa.DOCX", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+            (zip, "budzet.xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+            (zip, "prezentacja.pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+            (zip, "tekst.odt", "application/vnd.oasis.opendocument.text"),
+            (zip, "ksiazka.epub", "application/epub+zip"),
+            (zip, "archiwum.zip", "application/zip"),
+            (zip, "bez_rozszerzenia", "application/zip"),
+            (b"PK\x05\x06\0\0", "pusty.zip", "application/zip"),
+            (ole, "stary.xls", "application/vnd.ms-excel"),
+            (ole, "slajdy.ppt", "application/vnd.ms-powerpoint"),
+            (ole, "wiadomosc.msg", "application/vnd.ms-outlook"),
+            (ole, "pismo.doc", "application/msword"),
+            (ole, "dokument", "application/msword"),
+        ];
+        for (head, filename, expected) in cases {
+            assert_eq!(sniff_mime_type(head, filename), *expected, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_extension_then_text_or_binary() {
+        assert_eq!(sniff_mime_type(b"a;b\n1;2\n", "dane.csv"), "text/csv");
+        assert_eq!(sniff_mime_type(b"BEGIN:VCALENDAR\r\n", "zaproszenie.ics"), "text/calendar");
+        assert_eq!(sniff_mime_type(b"\0\x01\x02", "notatki.txt"), "text/plain");
+        assert_eq!(sniff_mime_type(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"x\">", "logo"), "image/svg+xml");
+        // Bez rozszerzenia: tekst (także z uciętym ostatnim znakiem UTF-8) albo dane binarne
+        assert_eq!(sniff_mime_type("Zażółć\tgęślą\r\n".as_bytes(), "README"), "text/plain");
+        let cut = "Zażółć".as_bytes();
+        assert_eq!(sniff_mime_type(&cut[..cut.len() - 1], "notatka"), "text/plain");
+        assert_eq!(sniff_mime_type(b"abc\0def", "dane"), "application/octet-stream");
+        assert_eq!(sniff_mime_type(b"\xff\xfe\x41", "dane.xyz"), "application/octet-stream");
+        assert_eq!(sniff_mime_type(b"", "pusty"), "application/octet-stream");
+    }
+}
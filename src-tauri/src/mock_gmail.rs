@@ -0,0 +1,1109 @@
+//! Lokalny mock Gmail API (plus zastępczy serwer OAuth) do testów bez Google.
+//!
+//! Serwer trzyma skryptowalny stan skrzynki: wiadomości, rekordy historii i wysłane maile.
+//! Backend Rust podpina się przez `NEXDECK_GMAIL_API_BASE` / `NEXDECK_OAUTH_AUTH_URL`
+//! / `NEXDECK_OAUTH_TOKEN_URL`,
+//! a stan zmienia się endpointami `/__mock/*` albo bezpośrednio przez `MockGmailServer::mailbox`.
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::middleware::{self, Next};
+use axum::routing::{delete, get, post, put};
+use axum::{Form, Json, Router};
+use crate::batch;
+use base64::engine::general_purpose;
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+pub const MOCK_ACCESS_TOKEN: &str = "mock-access-token";
+pub const MOCK_EMAIL: &str = "me@example.com";
+const SYSTEM_LABELS: &[&str] = &["INBOX", "SENT", "DRAFT", "TRASH", "SPAM", "UNREAD", "STARRED", "IMPORTANT"];
+
+#[derive(Debug, Default)]
+pub struct MockMailbox {
+    messages: BTreeMap<String, Value>,
+    /// Etykiety użytkownika (systemowe są stałe, patrz `SYSTEM_LABELS`)
+    labels: BTreeMap<String, Value>,
+    /// draftId -> id wiadomości z etykietą DRAFT
+    drafts: BTreeMap<String, String>,
+    attachments: HashMap<String, Vec<u8>>,
+    history: Vec<Value>,
+    history_id: u64,
+    /// startHistoryId poniżej tej wartości dostaje 400 (jak wygasła historia w Gmailu)
+    min_history_id: u64,
+    next_id: u64,
+    sent_raw: Vec<String>,
+    /// Tyle kolejnych pod-żądań batcha dostanie 503 (test ponawiania)
+    failing_batch_items: usize,
+    /// Sesje uploadu wznawianego (upload_id -> stan)
+    uploads: HashMap<String, MockUpload>,
+    /// Tyle kolejnych kawałków uploadu zapisze się do połowy i dostanie 503 (test wznawiania)
+    failing_upload_chunks: usize,
+    oauth: MockOAuth,
+}
+
+#[derive(Debug, Default)]
+struct MockUpload {
+    thread_id: Option<Value>,
+    total: usize,
+    received: Vec<u8>,
+}
+
+/// Stan zastępczego serwera OAuth (`/oauth2/auth`, `/oauth2/token`)
+#[derive(Debug, Default)]
+struct MockOAuth {
+    /// code -> code_challenge (S256)
+    codes: HashMap<String, String>,
+    refresh_tokens: HashSet<String>,
+    issued: u64,
+    /// `expires_in` zwracany z tokenem; 0 = domyślne 3600
+    token_lifetime_secs: i64,
+    /// Wydane access tokeny o numerze poniżej tej wartości dostają 401
+    min_valid_token: u64,
+}
+
+/// Scenariusz ładowany z pliku / `PUT /__mock/mailbox`
+#[derive(Debug, Default, Deserialize)]
+pub struct MailboxScript {
+    #[serde(default)]
+    pub messages: Vec<Value>,
+    /// attachmentId -> dane (zwykły tekst)
+    #[serde(default)]
+    pub attachments: HashMap<String, String>,
+    #[serde(rename = "historyId", default)]
+    pub history_id: Option<u64>,
+    /// Etykiety użytkownika (`{"name": "Praca/Projekty", "color": {...}}`)
+    #[serde(default)]
+    pub labels: Vec<Value>,
+}
+
+impl MockMailbox {
+    pub fn new() -> Self {
+        Self {
+            history_id: 1000,
+            min_history_id: 0,
+            next_id: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_script(script: MailboxScript) -> Self {
+        let mut mailbox = Self::new();
+        if let Some(hid) = script.history_id {
+            mailbox.history_id = hid;
+        }
+        for msg in script.messages {
+            mailbox.insert(msg);
+        }
+        for (id, data) in script.attachments {
+            mailbox.attachments.insert(id, data.into_bytes());
+        }
+        for label in script.labels {
+            mailbox.add_label(label);
+        }
+        mailbox
+    }
+
+    pub fn history_id(&self) -> u64 {
+        self.history_id
+    }
+
+    pub fn sent_raw(&self) -> &[String] {
+        &self.sent_raw
+    }
+
+    /// Dodaje wiadomość bez rekordu historii (stan "sprzed" synchronizacji)
+    pub fn insert(&mut self, msg: Value) -> String {
+        let msg = self.normalize(msg);
+        let id = msg["id"].as_str().unwrap_or_default().to_string();
+        self.messages.insert(id.clone(), msg);
+        id
+    }
+
+    /// Dodaje wiadomość i zapisuje `messagesAdded` w historii
+    pub fn deliver(&mut self, msg: Value) -> String {
+        let id = self.insert(msg);
+        let summary = self.summary(&id);
+        self.record(json!({ "messagesAdded": [{ "message": summary }] }));
+        id
+    }
+
+    pub fn modify_labels(&mut self, id: &str, add: &[String], remove: &[String]) -> Option<Value> {
+        let msg = self.messages.get_mut(id)?;
+        let mut labels: Vec<String> = msg["labelIds"]
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+
+        let added: Vec<String> = add.iter().filter(|l| !labels.contains(l)).cloned().collect();
+        let removed: Vec<String> = remove.iter().filter(|l| labels.contains(l)).cloned().collect();
+        labels.retain(|l| !removed.contains(l));
+        labels.extend(added.iter().cloned());
+        msg["labelIds"] = json!(labels);
+
+        let summary = self.summary(id);
+        if !added.is_empty() {
+            self.record(json!({ "labelsAdded": [{ "message": summary, "labelIds": added }] }));
+        }
+        if !removed.is_empty() {
+            self.record(json!({ "labelsRemoved": [{ "message": summary, "labelIds": removed }] }));
+        }
+        self.messages.get(id).cloned()
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let summary = self.summary(id);
+        if self.messages.remove(id).is_none() {
+            return false;
+        }
+        self.record(json!({ "messagesDeleted": [{ "message": summary }] }));
+        true
+    }
+
+    /// Dodaje etykietę użytkownika (id `Label_N`, jeśli nie podano); `None` przy zajętej nazwie
+    pub fn add_label(&mut self, mut label: Value) -> Option<Value> {
+        let name = label["name"].as_str()?.to_string();
+        if self.label_by_name(&name).is_some() {
+            return None;
+        }
+        if label["id"].as_str().is_none() {
+            label["id"] = json!(format!("Label_{}", self.next_id));
+            self.next_id += 1;
+        }
+        label["type"] = json!("user");
+        let id = label["id"].as_str().unwrap_or_default().to_string();
+        self.labels.insert(id, label.clone());
+        Some(label)
+    }
+
+    pub fn drafts(&self) -> &BTreeMap<String, String> {
+        &self.drafts
+    }
+
+    /// Zapisuje wersję roboczą (nową albo w miejsce istniejącej - z nowym id wiadomości)
+    fn store_draft(&mut self, draft_id: Option<String>, raw: &str, thread_id: Option<&Value>) -> Value {
+        let draft_id = draft_id.unwrap_or_else(|| {
+            self.next_id += 1;
+            format!("r{}", self.next_id - 1)
+        });
+        if let Some(old) = self.drafts.get(&draft_id).cloned() {
+            self.remove(&old);
+        }
+        let mut msg = message_from_raw(raw);
+        msg["labelIds"] = json!(["DRAFT"]);
+        if let Some(thread_id) = thread_id {
+            msg["threadId"] = thread_id.clone();
+        }
+        let id = self.deliver(msg);
+        self.drafts.insert(draft_id.clone(), id.clone());
+        json!({ "id": draft_id, "message": self.summary(&id) })
+    }
+
+    fn label_by_name(&self, name: &str) -> Option<&Value> {
+        self.labels.values().find(|l| l["name"].as_str() == Some(name))
+    }
+
+    fn all_labels(&self) -> Vec<Value> {
+        let system = SYSTEM_LABELS
+            .iter()
+            .map(|id| json!({ "id": id, "name": id, "type": "system" }));
+        system.chain(self.labels.values().cloned()).collect()
+    }
+
+    /// `expires_in` dla kolejnych tokenów z `/oauth2/token`
+    pub fn set_token_lifetime(&mut self, secs: i64) {
+        self.oauth.token_lifetime_secs = secs;
+    }
+
+    /// Unieważnia wszystkie refresh tokeny (jak odebranie dostępu w koncie Google)
+    pub fn revoke_refresh_tokens(&mut self) {
+        self.oauth.refresh_tokens.clear();
+    }
+
+    /// Ważny refresh token, jak po zalogowaniu (bez przechodzenia przez `/oauth2/auth`)
+    pub fn grant_refresh_token(&mut self) -> String {
+        let refresh = format!("mock-refresh-granted-{}", self.oauth.refresh_tokens.len() + 1);
+        self.oauth.refresh_tokens.insert(refresh.clone());
+        refresh
+    }
+
+    /// Wszystkie dotąd wydane access tokeny dostają 401 (jak wygaśnięcie przed `expires_in`)
+    pub fn expire_access_tokens(&mut self) {
+        self.oauth.min_valid_token = self.oauth.issued + 1;
+    }
+
+    /// Tokeny spoza `/oauth2/token` (np. podane ręcznie) są zawsze ważne
+    fn access_token_valid(&self, token: &str) -> bool {
+        token
+            .strip_prefix(MOCK_ACCESS_TOKEN)
+            .and_then(|n| n.strip_prefix('-'))
+            .and_then(|n| n.parse::<u64>().ok())
+            .is_none_or(|n| n >= self.oauth.min_valid_token)
+    }
+
+    pub fn access_tokens_issued(&self) -> u64 {
+        self.oauth.issued
+    }
+
+    fn issue_tokens(&mut self, with_refresh: bool) -> Value {
+        self.oauth.issued += 1;
+        let lifetime = if self.oauth.token_lifetime_secs > 0 { self.oauth.token_lifetime_secs } else { 3600 };
+        let mut body = json!({
+            "access_token": format!("{}-{}", MOCK_ACCESS_TOKEN, self.oauth.issued),
+            "expires_in": lifetime,
+            "token_type": "Bearer",
+        });
+        if with_refresh {
+            let refresh = format!("mock-refresh-{}", self.oauth.issued);
+            self.oauth.refresh_tokens.insert(refresh.clone());
+            body["refresh_token"] = json!(refresh);
+        }
+        body
+    }
+
+    /// Następne `count` pod-żądań batcha odpowie 503
+    pub fn fail_next_batch_items(&mut self, count: usize) {
+        self.failing_batch_items = count;
+    }
+
+    /// Następne `count` kawałków uploadu wznawianego dotrze tylko w połowie i odpowie 503
+    pub fn fail_next_upload_chunks(&mut self, count: usize) {
+        self.failing_upload_chunks = count;
+    }
+
+    /// Wysłana wiadomość (`raw` base64url) ląduje w skrzynce z etykietą SENT
+    fn store_sent(&mut self, raw: &str, thread_id: Option<&Value>) -> String {
+        self.sent_raw.push(raw.to_string());
+        let mut msg = message_from_raw(raw);
+        msg["labelIds"] = json!(["SENT"]);
+        if let Some(thread_id) = thread_id {
+            msg["threadId"] = thread_id.clone();
+        }
+        self.deliver(msg)
+    }
+
+    /// Wiadomość tak, jak zwraca ją messages.get dla danego `format`
+    fn message_view(&self, id: &str, format: Option<&str>) -> Option<Value> {
+        let mut msg = self.messages.get(id)?.clone();
+        match format {
+            Some("metadata") => {
+                let headers = msg["payload"]["headers"].clone();
+                let mime = msg["payload"]["mimeType"].clone();
+                msg["payload"] = json!({ "mimeType": mime, "headers": headers });
+            }
+            Some("minimal") => {
+                if let Some(obj) = msg.as_object_mut() {
+                    obj.remove("payload");
+                }
+            }
+            _ => {}
+        }
+        Some(msg)
+    }
+
+    /// Każdy startHistoryId <= bieżącego dostanie 400 - wymusza pełny resync
+    pub fn expire_history(&mut self) {
+        self.history_id += 1;
+        self.min_history_id = self.history_id;
+        self.history.clear();
+    }
+
+    fn normalize(&mut self, mut msg: Value) -> Value {
+        if msg.get("id").and_then(|v| v.as_str()).is_none() {
+            msg["id"] = json!(format!("mock{:06}", self.next_id));
+            self.next_id += 1;
+        }
+        let id = msg["id"].as_str().unwrap_or_default().to_string();
+        if msg.get("threadId").is_none() {
+            msg["threadId"] = json!(id);
+        }
+        if msg.get("labelIds").is_none() {
+            msg["labelIds"] = json!(["INBOX", "UNREAD"]);
+        }
+        if msg.get("snippet").is_none() {
+            msg["snippet"] = json!("");
+        }
+        if msg.get("internalDate").is_none() {
+            msg["internalDate"] = json!(chrono::Utc::now().timestamp_millis().to_string());
+        }
+        if msg.get("payload").is_none() {
+            msg["payload"] = json!({ "mimeType": "text/plain", "headers": [] });
+        }
+        msg
+    }
+
+    fn summary(&self, id: &str) -> Value {
+        let msg = self.messages.get(id);
+        json!({
+            "id": id,
+            "threadId": msg.and_then(|m| m.get("threadId")).cloned().unwrap_or(json!(id)),
+            "labelIds": msg.and_then(|m| m.get("labelIds")).cloned().unwrap_or(json!([])),
+        })
+    }
+
+    fn record(&mut self, mut change: Value) {
+        self.history_id += 1;
+        change["id"] = json!(self.history_id.to_string());
+        self.history.push(change);
+    }
+
+    fn has_label(msg: &Value, label: &str) -> bool {
+        msg["labelIds"]
+            .as_array()
+            .map(|a| a.iter().any(|l| l.as_str().is_some_and(|l| l.eq_ignore_ascii_case(label))))
+            .unwrap_or(false)
+    }
+}
+
+pub type SharedMailbox = Arc<Mutex<MockMailbox>>;
+
+pub struct MockGmailServer {
+    pub addr: SocketAddr,
+    pub mailbox: SharedMailbox,
+    handle: JoinHandle<()>,
+}
+
+impl MockGmailServer {
+    /// Startuje na `addr` (port 0 = losowy wolny port)
+    pub async fn start(addr: SocketAddr, mailbox: MockMailbox) -> std::io::Result<Self> {
+        let mailbox: SharedMailbox = Arc::new(Mutex::new(mailbox));
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let app = router(Arc::clone(&mailbox));
+
+        let handle = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                eprintln!("❌ Mock Gmail server stopped: {}", e);
+            }
+        });
+
+        Ok(Self { addr, mailbox, handle })
+    }
+
+    /// Wartość dla `NEXDECK_GMAIL_API_BASE`
+    pub fn gmail_api_base(&self) -> String {
+        format!("http://{}/gmail/v1", self.addr)
+    }
+
+    /// Wartość dla `NEXDECK_OAUTH_AUTH_URL`
+    pub fn oauth_auth_url(&self) -> String {
+        format!("http://{}/oauth2/auth", self.addr)
+    }
+
+    /// Wartość dla `NEXDECK_OAUTH_TOKEN_URL`
+    pub fn oauth_token_url(&self) -> String {
+        format!("http://{}/oauth2/token", self.addr)
+    }
+
+    /// `ApiConfig` wskazujący na ten serwer
+    pub fn api_config(&self) -> crate::config::ApiConfig {
+        crate::config::ApiConfig {
+            gmail_api_base: self.gmail_api_base(),
+            oauth_auth_url: self.oauth_auth_url(),
+            oauth_token_url: self.oauth_token_url(),
+            oauth_client_id: "mock-client".to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub async fn wait(mut self) {
+        let _ = (&mut self.handle).await;
+    }
+}
+
+impl Drop for MockGmailServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn router(mailbox: SharedMailbox) -> Router {
+    Router::new()
+        .route("/oauth2/auth", get(oauth_authorize))
+        .route("/oauth2/token", post(oauth_token))
+        .route("/gmail/v1/users/me/profile", get(profile))
+        .route("/gmail/v1/users/me/messages", get(list_messages))
+        .route("/gmail/v1/users/me/messages/send", post(send_message))
+        .route("/gmail/v1/users/me/messages/batchModify", post(batch_modify))
+        .route("/gmail/v1/users/me/messages/:id", get(get_message).delete(delete_message))
+        .route("/gmail/v1/users/me/messages/:id/modify", post(modify_message))
+        .route("/gmail/v1/users/me/messages/:id/trash", post(trash_message))
+        .route("/gmail/v1/users/me/messages/:id/untrash", post(untrash_message))
+        .route("/gmail/v1/users/me/messages/:id/attachments/:aid", get(get_attachment))
+        .route("/gmail/v1/users/me/threads/:id", get(get_thread))
+        .route("/gmail/v1/users/me/drafts", get(list_drafts).post(create_draft))
+        .route("/gmail/v1/users/me/drafts/send", post(send_draft))
+        .route("/gmail/v1/users/me/drafts/:id", get(get_draft).put(update_draft).delete(delete_draft))
+        .route("/gmail/v1/users/me/labels", get(list_labels).post(create_label))
+        .route("/gmail/v1/users/me/labels/:id", get(get_label).patch(patch_label).delete(delete_label))
+        .route("/gmail/v1/users/me/history", get(list_history))
+        .route("/upload/gmail/v1/users/me/messages/send", post(start_upload).put(upload_chunk))
+        .route("/batch/gmail/v1", post(batch_request))
+        .route("/__mock/mailbox", put(replace_mailbox))
+        .route("/__mock/messages", post(deliver_message))
+        .route("/__mock/messages/:id", delete(remove_message))
+        .route("/__mock/messages/:id/labels", post(modify_message))
+        .route("/__mock/expire-history", post(expire_history))
+        .route("/__mock/sent", get(sent_messages))
+        .route("/__mock/expire-tokens", post(expire_tokens))
+        .layer(middleware::from_fn_with_state(Arc::clone(&mailbox), check_bearer))
+        // Gmail przyjmuje wiadomości do 35 MB (domyślny limit axum to 2 MB)
+        .layer(DefaultBodyLimit::max(64 * 1024 * 1024))
+        .with_state(mailbox)
+}
+
+/// 401 dla wygasłych tokenów na endpointach Gmaila
+async fn check_bearer(State(mb): State<SharedMailbox>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if path.starts_with("/gmail/") || path.starts_with("/batch/") || path.starts_with("/upload/") {
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !mb.lock().unwrap().access_token_valid(token) {
+            return gmail_error(StatusCode::UNAUTHORIZED, "Request had invalid authentication credentials.");
+        }
+    }
+    next.run(request).await
+}
+
+fn gmail_error(status: StatusCode, message: &str) -> Response {
+    let body = json!({ "error": { "code": status.as_u16(), "message": message } });
+    (status, Json(body)).into_response()
+}
+
+fn page_params(q: &HashMap<String, String>, default_size: usize) -> (usize, usize) {
+    let size = q.get("maxResults").and_then(|v| v.parse().ok()).unwrap_or(default_size).max(1);
+    let offset = q.get("pageToken").and_then(|v| v.parse().ok()).unwrap_or(0);
+    (offset, size)
+}
+
+/// Zgoda "udzielana" od razu: przekierowanie na redirect_uri z kodem
+async fn oauth_authorize(State(mb): State<SharedMailbox>, Query(q): Query<HashMap<String, String>>) -> Response {
+    let (Some(redirect_uri), Some(challenge)) = (q.get("redirect_uri"), q.get("code_challenge")) else {
+        return gmail_error(StatusCode::BAD_REQUEST, "redirect_uri and code_challenge are required");
+    };
+    if q.get("code_challenge_method").map(String::as_str) != Some("S256") {
+        return gmail_error(StatusCode::BAD_REQUEST, "code_challenge_method must be S256");
+    }
+
+    let mut mb = mb.lock().unwrap();
+    let code = format!("mock-code-{}", mb.oauth.codes.len() + 1);
+    mb.oauth.codes.insert(code.clone(), challenge.clone());
+
+    let mut target = format!("{}?code={}", redirect_uri, code);
+    if let Some(state) = q.get("state") {
+        target.push_str(&format!("&state={}", state));
+    }
+    Redirect::to(&target).into_response()
+}
+
+async fn oauth_token(State(mb): State<SharedMailbox>, Form(form): Form<HashMap<String, String>>) -> Response {
+    let oauth_error = |error: &str| (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response();
+    let mut mb = mb.lock().unwrap();
+
+    match form.get("grant_type").map(String::as_str) {
+        Some("authorization_code") => {
+            let challenge = form.get("code").and_then(|c| mb.oauth.codes.remove(c));
+            let verifier = form.get("code_verifier").map(String::as_str).unwrap_or_default();
+            let expected = general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+            match challenge {
+                Some(challenge) if challenge == expected => Json(mb.issue_tokens(true)).into_response(),
+                _ => oauth_error("invalid_grant"),
+            }
+        }
+        Some("refresh_token") => {
+            let valid = form.get("refresh_token").is_some_and(|rt| mb.oauth.refresh_tokens.contains(rt));
+            if valid {
+                Json(mb.issue_tokens(false)).into_response()
+            } else {
+                oauth_error("invalid_grant")
+            }
+        }
+        _ => oauth_error("unsupported_grant_type"),
+    }
+}
+
+async fn profile(State(mb): State<SharedMailbox>) -> Json<Value> {
+    let mb = mb.lock().unwrap();
+    let threads: std::collections::HashSet<&str> =
+        mb.messages.values().filter_map(|m| m["threadId"].as_str()).collect();
+    Json(json!({
+        "emailAddress": MOCK_EMAIL,
+        "messagesTotal": mb.messages.len(),
+        "threadsTotal": threads.len(),
+        "historyId": mb.history_id.to_string(),
+    }))
+}
+
+async fn list_messages(State(mb): State<SharedMailbox>, Query(q): Query<HashMap<String, String>>) -> Json<Value> {
+    let mb = mb.lock().unwrap();
+    let (offset, size) = page_params(&q, 100);
+
+    let mut matching: Vec<&Value> = mb
+        .messages
+        .values()
+        .filter(|m| q.get("labelIds").is_none_or(|l| MockMailbox::has_label(m, l)))
+        .collect();
+    matching.sort_by_key(|m| {
+        std::cmp::Reverse(m["internalDate"].as_str().and_then(|d| d.parse::<i64>().ok()).unwrap_or(0))
+    });
+
+    let page: Vec<Value> = matching
+        .iter()
+        .skip(offset)
+        .take(size)
+        .map(|m| json!({ "id": m["id"], "threadId": m["threadId"] }))
+        .collect();
+
+    let mut body = json!({ "resultSizeEstimate": matching.len() });
+    if !page.is_empty() {
+        body["messages"] = json!(page);
+    }
+    if offset + size < matching.len() {
+        body["nextPageToken"] = json!((offset + size).to_string());
+    }
+    Json(body)
+}
+
+async fn get_message(
+    State(mb): State<SharedMailbox>,
+    Path(id): Path<String>,
+    Query(q): Query<HashMap<String, String>>,
+) -> Response {
+    let mb = mb.lock().unwrap();
+    match mb.message_view(&id, q.get("format").map(String::as_str)) {
+        Some(msg) => Json(msg).into_response(),
+        None => gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found."),
+    }
+}
+
+async fn get_thread(
+    State(mb): State<SharedMailbox>,
+    Path(id): Path<String>,
+    Query(q): Query<HashMap<String, String>>,
+) -> Response {
+    let mb = mb.lock().unwrap();
+    let format = q.get("format").map(String::as_str);
+    let mut messages: Vec<Value> = mb
+        .messages
+        .values()
+        .filter(|m| m["threadId"].as_str() == Some(id.as_str()))
+        .filter_map(|m| m["id"].as_str().and_then(|mid| mb.message_view(mid, format)))
+        .collect();
+    if messages.is_empty() {
+        return gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.");
+    }
+    messages.sort_by_key(|m| m["internalDate"].as_str().and_then(|d| d.parse::<i64>().ok()).unwrap_or(0));
+    Json(json!({ "id": id, "messages": messages })).into_response()
+}
+
+/// `POST /batch/gmail/v1` - obsługuje pod-żądania `GET .../messages/{id}?format=...`
+async fn batch_request(State(mb): State<SharedMailbox>, headers: HeaderMap, body: String) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let Some(boundary) = batch::boundary_from_content_type(content_type) else {
+        return gmail_error(StatusCode::BAD_REQUEST, "Missing multipart boundary");
+    };
+
+    let mut mb = mb.lock().unwrap();
+    let response_boundary = format!("batch_mock_{}", mb.history_id);
+    let mut out = String::new();
+
+    for (part_headers, part_body) in batch::split_parts(&body, &boundary) {
+        let content_id = batch::header_value(part_headers, "Content-ID")
+            .map(|v| v.trim_matches(|c| c == '<' || c == '>').to_string())
+            .unwrap_or_default();
+        let (request_line, _) = batch::split_head(part_body);
+        let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let format = query
+            .split('&')
+            .find_map(|kv| kv.strip_prefix("format="));
+
+        let (status, json_body) = if mb.failing_batch_items > 0 {
+            mb.failing_batch_items -= 1;
+            (503, json!({ "error": { "code": 503, "message": "Backend Error" } }))
+        } else {
+            match path
+                .strip_prefix("/gmail/v1/users/me/messages/")
+                .and_then(|id| mb.message_view(id, format))
+            {
+                Some(msg) => (200, msg),
+                None => (404, json!({ "error": { "code": 404, "message": "Requested entity was not found." } })),
+            }
+        };
+
+        out.push_str(&format!("--{}\r\n", response_boundary));
+        out.push_str("Content-Type: application/http\r\n");
+        out.push_str(&format!("Content-ID: <response-{}>\r\n\r\n", content_id));
+        out.push_str(&format!("HTTP/1.1 {} {}\r\n", status, if status == 200 { "OK" } else { "Error" }));
+        out.push_str("Content-Type: application/json; charset=UTF-8\r\n\r\n");
+        out.push_str(&json_body.to_string());
+        out.push_str("\r\n");
+    }
+    out.push_str(&format!("--{}--\r\n", response_boundary));
+
+    (
+        [(header::CONTENT_TYPE, format!("multipart/mixed; boundary={}", response_boundary))],
+        out,
+    )
+        .into_response()
+}
+
+async fn get_attachment(State(mb): State<SharedMailbox>, Path((_id, aid)): Path<(String, String)>) -> Response {
+    let mb = mb.lock().unwrap();
+    match mb.attachments.get(&aid) {
+        Some(data) => Json(json!({
+            "attachmentId": aid,
+            "size": data.len(),
+            "data": general_purpose::URL_SAFE_NO_PAD.encode(data),
+        }))
+        .into_response(),
+        None => gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found."),
+    }
+}
+
+async fn send_message(State(mb): State<SharedMailbox>, Json(body): Json<Value>) -> Response {
+    let Some(raw) = body.get("raw").and_then(|v| v.as_str()) else {
+        return gmail_error(StatusCode::BAD_REQUEST, "'raw' RFC822 payload message string missing");
+    };
+
+    let mut mb = mb.lock().unwrap();
+    let id = mb.store_sent(raw, body.get("threadId"));
+    Json(mb.summary(&id)).into_response()
+}
+
+/// Początek uploadu wznawianego: sesja pod adresem z nagłówka Location
+async fn start_upload(
+    State(mb): State<SharedMailbox>,
+    Query(q): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    if q.get("uploadType").map(String::as_str) != Some("resumable") {
+        return gmail_error(StatusCode::BAD_REQUEST, "Only uploadType=resumable is supported by the mock");
+    }
+    let header_value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    if header_value("x-upload-content-type") != "message/rfc822" {
+        return gmail_error(StatusCode::BAD_REQUEST, "Media type must be message/rfc822");
+    }
+    let Ok(total) = header_value("x-upload-content-length").parse::<usize>() else {
+        return gmail_error(StatusCode::BAD_REQUEST, "X-Upload-Content-Length missing");
+    };
+
+    let mut mb = mb.lock().unwrap();
+    mb.next_id += 1;
+    let upload_id = format!("upload-{}", mb.next_id);
+    mb.uploads.insert(upload_id.clone(), MockUpload { thread_id: body.get("threadId").cloned(), total, received: Vec::new() });
+    let location = format!(
+        "http://{}/upload/gmail/v1/users/me/messages/send?uploadType=resumable&upload_id={}",
+        header_value("host"),
+        upload_id
+    );
+    (StatusCode::OK, [(header::LOCATION, location)]).into_response()
+}
+
+/// Kawałek uploadu (`Content-Range: bytes a-b/total`) albo pytanie o stan (`bytes */total`)
+async fn upload_chunk(
+    State(mb): State<SharedMailbox>,
+    Query(q): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut mb = mb.lock().unwrap();
+    let Some(upload_id) = q.get("upload_id").filter(|id| mb.uploads.contains_key(*id)).cloned() else {
+        return gmail_error(StatusCode::NOT_FOUND, "Upload session not found.");
+    };
+    let range = headers.get(header::CONTENT_RANGE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let start = range
+        .strip_prefix("bytes ")
+        .and_then(|r| r.split_once('-'))
+        .and_then(|(start, _)| start.parse::<usize>().ok());
+
+    let failing = start.is_some() && mb.failing_upload_chunks > 0;
+    if failing {
+        mb.failing_upload_chunks -= 1;
+    }
+    let upload = mb.uploads.get_mut(&upload_id).unwrap();
+    match start {
+        // Kawałek od innego miejsca niż koniec odebranych danych - klient musi zapytać o stan
+        Some(start) if start != upload.received.len() => {}
+        Some(_) if failing => {
+            upload.received.extend_from_slice(&body[..body.len() / 2]);
+            return gmail_error(StatusCode::SERVICE_UNAVAILABLE, "Backend Error");
+        }
+        Some(_) => upload.received.extend_from_slice(&body),
+        None => {}
+    }
+    if upload.received.len() < upload.total {
+        let mut response = StatusCode::PERMANENT_REDIRECT.into_response();
+        if !upload.received.is_empty() {
+            let range = format!("bytes=0-{}", upload.received.len() - 1);
+            response.headers_mut().insert(header::RANGE, range.parse().unwrap());
+        }
+        return response;
+    }
+
+    let upload = mb.uploads.remove(&upload_id).unwrap();
+    let raw = general_purpose::URL_SAFE_NO_PAD.encode(&upload.received);
+    let id = mb.store_sent(&raw, upload.thread_id.as_ref());
+    Json(mb.summary(&id)).into_response()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModifyRequest {
+    #[serde(rename = "addLabelIds", default)]
+    add_label_ids: Vec<String>,
+    #[serde(rename = "removeLabelIds", default)]
+    remove_label_ids: Vec<String>,
+}
+
+async fn modify_message(
+    State(mb): State<SharedMailbox>,
+    Path(id): Path<String>,
+    Json(req): Json<ModifyRequest>,
+) -> Response {
+    let mut mb = mb.lock().unwrap();
+    match mb.modify_labels(&id, &req.add_label_ids, &req.remove_label_ids) {
+        Some(_) => Json(mb.summary(&id)).into_response(),
+        None => gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found."),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BatchModifyRequest {
+    #[serde(default)]
+    ids: Vec<String>,
+    #[serde(rename = "addLabelIds", default)]
+    add_label_ids: Vec<String>,
+    #[serde(rename = "removeLabelIds", default)]
+    remove_label_ids: Vec<String>,
+}
+
+/// Jak w Gmailu: nieznane id są pomijane, odpowiedź bez treści
+async fn batch_modify(State(mb): State<SharedMailbox>, Json(req): Json<BatchModifyRequest>) -> StatusCode {
+    let mut mb = mb.lock().unwrap();
+    for id in &req.ids {
+        mb.modify_labels(id, &req.add_label_ids, &req.remove_label_ids);
+    }
+    StatusCode::NO_CONTENT
+}
+
+async fn trash_message(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> Response {
+    move_labels(mb, &id, "TRASH", "INBOX")
+}
+
+async fn untrash_message(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> Response {
+    move_labels(mb, &id, "INBOX", "TRASH")
+}
+
+fn move_labels(mb: SharedMailbox, id: &str, add: &str, remove: &str) -> Response {
+    let mut mb = mb.lock().unwrap();
+    match mb.modify_labels(id, &[add.to_string()], &[remove.to_string()]) {
+        Some(_) => Json(mb.summary(id)).into_response(),
+        None => gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found."),
+    }
+}
+
+async fn delete_message(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> Response {
+    if mb.lock().unwrap().remove(&id) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.")
+    }
+}
+
+async fn list_drafts(State(mb): State<SharedMailbox>) -> Json<Value> {
+    let mb = mb.lock().unwrap();
+    let drafts: Vec<Value> = mb
+        .drafts
+        .iter()
+        .map(|(id, mid)| json!({ "id": id, "message": mb.summary(mid) }))
+        .collect();
+    Json(json!({ "drafts": drafts, "resultSizeEstimate": drafts.len() }))
+}
+
+async fn get_draft(
+    State(mb): State<SharedMailbox>,
+    Path(id): Path<String>,
+    Query(q): Query<HashMap<String, String>>,
+) -> Response {
+    let mb = mb.lock().unwrap();
+    let message = mb
+        .drafts
+        .get(&id)
+        .and_then(|mid| mb.message_view(mid, q.get("format").map(String::as_str)));
+    match message {
+        Some(message) => Json(json!({ "id": id, "message": message })).into_response(),
+        None => gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found."),
+    }
+}
+
+async fn create_draft(State(mb): State<SharedMailbox>, Json(body): Json<Value>) -> Response {
+    let Some(raw) = body["message"]["raw"].as_str() else {
+        return gmail_error(StatusCode::BAD_REQUEST, "Missing draft message");
+    };
+    let draft = mb.lock().unwrap().store_draft(None, raw, body["message"].get("threadId"));
+    Json(draft).into_response()
+}
+
+async fn update_draft(State(mb): State<SharedMailbox>, Path(id): Path<String>, Json(body): Json<Value>) -> Response {
+    let Some(raw) = body["message"]["raw"].as_str() else {
+        return gmail_error(StatusCode::BAD_REQUEST, "Missing draft message");
+    };
+    let mut mb = mb.lock().unwrap();
+    if !mb.drafts.contains_key(&id) {
+        return gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.");
+    }
+    Json(mb.store_draft(Some(id), raw, body["message"].get("threadId"))).into_response()
+}
+
+/// Wiadomość wersji roboczej zostaje, zmienia tylko DRAFT na SENT
+async fn send_draft(State(mb): State<SharedMailbox>, Json(body): Json<Value>) -> Response {
+    let mut mb = mb.lock().unwrap();
+    let Some(mid) = body["id"].as_str().and_then(|id| mb.drafts.remove(id)) else {
+        return gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.");
+    };
+    mb.modify_labels(&mid, &["SENT".to_string()], &["DRAFT".to_string()]);
+    Json(mb.summary(&mid)).into_response()
+}
+
+async fn delete_draft(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> Response {
+    let mut mb = mb.lock().unwrap();
+    let Some(mid) = mb.drafts.remove(&id) else {
+        return gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.");
+    };
+    mb.remove(&mid);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Wiadomość z `raw` (base64url RFC 2822) rozłożona na części jak robi to Gmail
+fn message_from_raw(raw: &str) -> Value {
+    let decoded = general_purpose::URL_SAFE_NO_PAD.decode(raw.trim_end_matches('=')).unwrap_or_default();
+    let text = String::from_utf8_lossy(&decoded).to_string();
+    let payload = mime_part(&text);
+    let snippet = first_text(&payload).chars().take(100).collect::<String>();
+    json!({ "snippet": snippet, "payload": payload })
+}
+
+/// Drzewo części jak w `payload` Gmaila: nagłówki rozwinięte, treść zdekodowana z QP/base64
+fn mime_part(text: &str) -> Value {
+    let (head, body) = text.split_once("\r\n\r\n").unwrap_or((text, ""));
+    let unfolded = head.replace("\r\n ", " ").replace("\r\n\t", " ");
+    let headers: Vec<(String, String)> = unfolded
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let find = |name: &str| headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str());
+    let content_type = find("Content-Type").unwrap_or("text/plain");
+    let mime = content_type.split(';').next().unwrap_or("text/plain").trim().to_ascii_lowercase();
+    let param = |value: &str, key: &str| {
+        value.split(';').skip(1).find_map(|p| {
+            let (k, v) = p.split_once('=')?;
+            (k.trim().eq_ignore_ascii_case(key)).then(|| v.trim().trim_matches('"').to_string())
+        })
+    };
+    let headers_json: Vec<Value> = headers.iter().map(|(n, v)| json!({ "name": n, "value": v })).collect();
+
+    if let Some(boundary) = mime.starts_with("multipart/").then(|| param(content_type, "boundary")).flatten() {
+        let delimiter = format!("--{}", boundary);
+        let parts: Vec<Value> = body
+            .split(delimiter.as_str())
+            .skip(1)
+            .take_while(|chunk| !chunk.starts_with("--"))
+            .map(|chunk| mime_part(chunk.trim_start_matches("\r\n").trim_end_matches("\r\n")))
+            .collect();
+        return json!({ "mimeType": mime, "headers": headers_json, "body": { "size": 0 }, "parts": parts });
+    }
+
+    let data = match find("Content-Transfer-Encoding").map(|e| e.to_ascii_lowercase()) {
+        Some(e) if e == "base64" => general_purpose::STANDARD.decode(body.replace("\r\n", "")).unwrap_or_default(),
+        Some(e) if e == "quoted-printable" => decode_quoted_printable(body),
+        _ => body.as_bytes().to_vec(),
+    };
+    let filename = find("Content-Disposition")
+        .and_then(|d| param(d, "filename").or_else(|| param(d, "filename*")))
+        .map(|f| f.strip_prefix("UTF-8''").map(percent_decode).unwrap_or(f))
+        .unwrap_or_default();
+    json!({
+        "mimeType": mime,
+        "filename": filename,
+        "headers": headers_json,
+        "body": { "size": data.len(), "data": general_purpose::URL_SAFE_NO_PAD.encode(&data) },
+    })
+}
+
+fn first_text(part: &Value) -> String {
+    if let Some(parts) = part["parts"].as_array() {
+        return parts.iter().map(first_text).find(|t| !t.is_empty()).unwrap_or_default();
+    }
+    if part["mimeType"] != "text/plain" {
+        return String::new();
+    }
+    let data = part["body"]["data"].as_str().unwrap_or_default();
+    String::from_utf8_lossy(&general_purpose::URL_SAFE_NO_PAD.decode(data).unwrap_or_default()).to_string()
+}
+
+fn decode_quoted_printable(body: &str) -> Vec<u8> {
+    decode_escapes(body.replace("=\r\n", "").as_bytes(), b'=')
+}
+
+fn percent_decode(value: &str) -> String {
+    String::from_utf8_lossy(&decode_escapes(value.as_bytes(), b'%')).to_string()
+}
+
+/// `=XX` / `%XX` -> bajt; niepoprawne sekwencje zostają bez zmian
+fn decode_escapes(bytes: &[u8], marker: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.filter(|_| bytes[i] == marker).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+async fn list_labels(State(mb): State<SharedMailbox>) -> Json<Value> {
+    Json(json!({ "labels": mb.lock().unwrap().all_labels() }))
+}
+
+async fn get_label(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> Response {
+    let mb = mb.lock().unwrap();
+    match mb.all_labels().into_iter().find(|l| l["id"].as_str() == Some(id.as_str())) {
+        Some(label) => Json(label).into_response(),
+        None => gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found."),
+    }
+}
+
+async fn create_label(State(mb): State<SharedMailbox>, Json(mut label): Json<Value>) -> Response {
+    if label["name"].as_str().is_none_or(|n| n.trim().is_empty()) {
+        return gmail_error(StatusCode::BAD_REQUEST, "Invalid label name");
+    }
+    if let Some(obj) = label.as_object_mut() {
+        obj.remove("id");
+    }
+    match mb.lock().unwrap().add_label(label) {
+        Some(created) => Json(created).into_response(),
+        None => gmail_error(StatusCode::CONFLICT, "Label name exists or conflicts"),
+    }
+}
+
+/// Systemowych etykiet nie da się zmienić; nowa nazwa nie może być zajęta
+async fn patch_label(State(mb): State<SharedMailbox>, Path(id): Path<String>, Json(patch): Json<Value>) -> Response {
+    let mut mb = mb.lock().unwrap();
+    if let Some(name) = patch["name"].as_str() {
+        if mb.label_by_name(name).is_some_and(|l| l["id"].as_str() != Some(id.as_str())) {
+            return gmail_error(StatusCode::CONFLICT, "Label name exists or conflicts");
+        }
+    }
+    let Some(label) = mb.labels.get_mut(&id) else {
+        return gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.");
+    };
+    if let (Some(label), Some(fields)) = (label.as_object_mut(), patch.as_object()) {
+        for (key, value) in fields.iter().filter(|(k, _)| !matches!(k.as_str(), "id" | "type")) {
+            label.insert(key.clone(), value.clone());
+        }
+    }
+    Json(label.clone()).into_response()
+}
+
+/// Jak w Gmailu: usunięcie etykiety zdejmuje ją ze wszystkich wiadomości
+async fn delete_label(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> Response {
+    let mut mb = mb.lock().unwrap();
+    if mb.labels.remove(&id).is_none() {
+        return gmail_error(StatusCode::NOT_FOUND, "Requested entity was not found.");
+    }
+    let labeled: Vec<String> = mb
+        .messages
+        .iter()
+        .filter(|(_, m)| m["labelIds"].as_array().is_some_and(|l| l.iter().any(|x| x.as_str() == Some(id.as_str()))))
+        .map(|(mid, _)| mid.clone())
+        .collect();
+    for mid in labeled {
+        mb.modify_labels(&mid, &[], std::slice::from_ref(&id));
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn list_history(State(mb): State<SharedMailbox>, Query(q): Query<HashMap<String, String>>) -> Response {
+    let mb = mb.lock().unwrap();
+    let Some(start) = q.get("startHistoryId").and_then(|v| v.parse::<u64>().ok()) else {
+        return gmail_error(StatusCode::BAD_REQUEST, "Invalid startHistoryId");
+    };
+    if start < mb.min_history_id {
+        return gmail_error(StatusCode::BAD_REQUEST, "Invalid startHistoryId");
+    }
+
+    let (offset, size) = page_params(&q, 100);
+    let newer: Vec<&Value> = mb
+        .history
+        .iter()
+        .filter(|r| r["id"].as_str().and_then(|id| id.parse::<u64>().ok()).is_some_and(|id| id > start))
+        .collect();
+
+    let page: Vec<Value> = newer.iter().skip(offset).take(size).map(|r| (*r).clone()).collect();
+    let mut body = json!({ "historyId": mb.history_id.to_string() });
+    if !page.is_empty() {
+        body["history"] = json!(page);
+    }
+    if offset + size < newer.len() {
+        body["nextPageToken"] = json!((offset + size).to_string());
+    }
+    Json(body).into_response()
+}
+
+async fn replace_mailbox(State(mb): State<SharedMailbox>, Json(script): Json<MailboxScript>) -> StatusCode {
+    *mb.lock().unwrap() = MockMailbox::from_script(script);
+    StatusCode::NO_CONTENT
+}
+
+async fn deliver_message(State(mb): State<SharedMailbox>, Json(msg): Json<Value>) -> Json<Value> {
+    let mut mb = mb.lock().unwrap();
+    let id = mb.deliver(msg);
+    Json(mb.summary(&id))
+}
+
+async fn remove_message(State(mb): State<SharedMailbox>, Path(id): Path<String>) -> StatusCode {
+    if mb.lock().unwrap().remove(&id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn expire_history(State(mb): State<SharedMailbox>) -> StatusCode {
+    mb.lock().unwrap().expire_history();
+    StatusCode::NO_CONTENT
+}
+
+async fn expire_tokens(State(mb): State<SharedMailbox>) -> StatusCode {
+    mb.lock().unwrap().expire_access_tokens();
+    StatusCode::NO_CONTENT
+}
+
+async fn sent_messages(State(mb): State<SharedMailbox>) -> Json<Value> {
+    Json(json!({ "raw": mb.lock().unwrap().sent_raw() }))
+}
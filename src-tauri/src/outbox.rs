@@ -0,0 +1,397 @@
+//! Kolejka zmian użytkownika (outbox). Zmiana trafia od razu do cache i tabeli
+//! `pending_ops`, a worker wysyła kolejkę do Gmaila po kolei - także po powrocie sieci.
+
+use crate::cache::{Cache, CachedMessage, PendingOp, OP_DEAD_LETTER, OP_FAILED};
+use crate::client::{is_connect_error, is_network_error, ApiError, GmailClient};
+use crate::mime::raw_message;
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Po tylu próbach z nierozpoznanym błędem zmiana jest uznawana za odrzuconą
+const MAX_ATTEMPTS: i64 = 10;
+/// Po tylu próbach z błędem przejściowym (429/5xx, zerwane połączenie) kolejka się poddaje
+/// (ok. godzina przy maksymalnym backoffie)
+const MAX_TRANSIENT_ATTEMPTS: i64 = 20;
+const MAX_BACKOFF_MS: i64 = 5 * 60 * 1000;
+/// Bez sieci próbujemy rzadko i nie liczymy tych prób - powrót sieci i tak zeruje backoff
+const OFFLINE_BACKOFF_MS: i64 = 30 * 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum MailOp {
+    ModifyLabels {
+        message_ids: Vec<String>,
+        add_label_ids: Vec<String>,
+        remove_label_ids: Vec<String>,
+    },
+    Trash { message_id: String },
+    Untrash { message_id: String },
+    /// Trwałe usunięcie (z pominięciem kosza)
+    Delete { message_id: String },
+    /// Wiadomość w formacie `raw` (base64url), czekająca na sieć; odpowiedź z `thread_id` swojego wątku
+    Send {
+        raw: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread_id: Option<String>,
+    },
+    /// Wiadomość ponad limit `raw` złożona do pliku w `outgoing` - idzie uploadem wznawianym
+    SendFile {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread_id: Option<String>,
+    },
+    /// Zapis wersji roboczej - wysyłana jest treść aktualna w chwili wykonania
+    SaveDraft { local_id: i64 },
+    SendDraft { local_id: i64 },
+    /// Usunięcie wersji roboczej z Gmaila (lokalnie już jej nie ma)
+    DeleteDraft { draft_id: String, message_id: Option<String> },
+}
+
+impl MailOp {
+    pub fn message_ids(&self) -> Vec<String> {
+        match self {
+            MailOp::ModifyLabels { message_ids, .. } => message_ids.clone(),
+            MailOp::Trash { message_id } | MailOp::Untrash { message_id } | MailOp::Delete { message_id } => {
+                vec![message_id.clone()]
+            }
+            MailOp::DeleteDraft { message_id, .. } => message_id.iter().cloned().collect(),
+            MailOp::Send { .. } | MailOp::SendFile { .. } | MailOp::SaveDraft { .. } | MailOp::SendDraft { .. } => {
+                Vec::new()
+            }
+        }
+    }
+
+    /// Wysyłka nie jest idempotentna
+    fn is_send(&self) -> bool {
+        matches!(self, MailOp::Send { .. } | MailOp::SendFile { .. } | MailOp::SendDraft { .. })
+    }
+
+    /// Etykiety (dodane, zdjęte) zmieniane przez operację
+    fn label_delta(&self) -> Option<(Vec<String>, Vec<String>)> {
+        match self {
+            MailOp::ModifyLabels { add_label_ids, remove_label_ids, .. } => {
+                Some((add_label_ids.clone(), remove_label_ids.clone()))
+            }
+            // Jak Gmail: kosz zdejmuje INBOX, przywrócenie wraca do INBOX
+            MailOp::Trash { .. } => Some((labels(&["TRASH"]), labels(&["INBOX"]))),
+            MailOp::Untrash { .. } => Some((labels(&["INBOX"]), labels(&["TRASH"]))),
+            _ => None,
+        }
+    }
+
+    /// Zmiana w cache, widoczna od razu (przed wysłaniem do Gmaila)
+    pub fn apply_local(&self, cache: &Cache) -> Result<()> {
+        if let Some((add, remove)) = self.label_delta() {
+            for id in self.message_ids() {
+                cache.apply_label_changes(&id, &add, &remove, None)?;
+            }
+            return Ok(());
+        }
+        match self {
+            MailOp::Delete { message_id } => cache.delete_message(message_id)?,
+            // Wiadomość wersji roboczej (etykieta DRAFT) znika z list od razu
+            MailOp::DeleteDraft { message_id: Some(message_id), .. } => cache.delete_message(message_id)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Cofa w cache tylko to, co zmieniła ta operacja: etykiety przez nią dodane znikają,
+    /// zdjęte wracają, a usunięta wiadomość wraca, jeśli synchronizacja jej nie przywróciła.
+    /// Pozostałe pola i zmiany z Gmaila, które przyszły w międzyczasie, zostają.
+    fn undo_local(&self, cache: &Cache, snapshot: &CachedMessage) -> Result<()> {
+        if let Some((added, removed)) = self.label_delta() {
+            let before: Vec<String> = serde_json::from_str(&snapshot.label_ids_json).unwrap_or_default();
+            // Etykieta, która już była przed zmianą, nie została przez nią dodana (i odwrotnie)
+            let restore: Vec<String> = removed.into_iter().filter(|l| before.contains(l)).collect();
+            let drop: Vec<String> = added.into_iter().filter(|l| !before.contains(l)).collect();
+            cache.apply_label_changes(&snapshot.message_id, &restore, &drop, None)?;
+            return Ok(());
+        }
+        if matches!(self, MailOp::Delete { .. } | MailOp::DeleteDraft { .. }) && !cache.has_message(&snapshot.message_id)? {
+            cache.upsert_message(snapshot)?;
+        }
+        Ok(())
+    }
+
+    pub async fn execute(&self, cache: &Cache, client: &GmailClient) -> Result<()> {
+        match self {
+            MailOp::ModifyLabels { message_ids, add_label_ids, remove_label_ids } => match message_ids.as_slice() {
+                [id] => client.modify_message(id, add_label_ids, remove_label_ids).await,
+                _ => client.batch_modify(message_ids, add_label_ids, remove_label_ids).await,
+            },
+            MailOp::Trash { message_id } => client.trash_message(message_id).await,
+            MailOp::Untrash { message_id } => client.untrash_message(message_id).await,
+            MailOp::Delete { message_id } => client.delete_message(message_id).await,
+            MailOp::Send { raw, thread_id } => {
+                let id = client.send_message(raw, thread_id.as_deref()).await?;
+                eprintln!("📤 Queued message sent: {}", id);
+                Ok(())
+            }
+            MailOp::SendFile { path, thread_id } => {
+                let id = client.upload_message(Path::new(path), thread_id.as_deref(), |_| {}).await?;
+                eprintln!("📤 Queued message uploaded: {}", id);
+                if let Err(e) = tokio::fs::remove_file(path).await {
+                    eprintln!("⚠️ Failed to remove outgoing message {}: {}", path, e);
+                }
+                Ok(())
+            }
+            MailOp::SaveDraft { local_id } => push_draft(cache, client, *local_id).await.map(|_| ()),
+            MailOp::SendDraft { local_id } => {
+                if let Some(id) = send_draft(cache, client, *local_id).await? {
+                    eprintln!("📤 Queued draft sent: {}", id);
+                }
+                Ok(())
+            }
+            MailOp::DeleteDraft { draft_id, .. } => client.delete_draft(draft_id).await,
+        }
+    }
+}
+
+/// Zapisuje w Gmailu aktualną treść wersji roboczej (create albo update).
+/// Zwraca id wersji w Gmailu albo `None`, gdy lokalnie już jej nie ma.
+pub async fn push_draft(cache: &Cache, client: &GmailClient, local_id: i64) -> Result<Option<String>> {
+    let Some(draft) = cache.get_draft(local_id)? else {
+        return Ok(None);
+    };
+    if draft.synced {
+        return Ok(draft.draft_id);
+    }
+    let raw = raw_message(&draft.data)?;
+    let thread_id = draft.thread_id.as_deref();
+    let saved = match &draft.draft_id {
+        Some(id) => match client.update_draft(id, &raw, thread_id).await {
+            // Usunięta w innym kliencie - zapisujemy jako nową
+            Err(e) if api_status(&e) == Some(404) => client.create_draft(&raw, thread_id).await?,
+            result => result?,
+        },
+        None => client.create_draft(&raw, thread_id).await?,
+    };
+    cache.mark_draft_synced(local_id, &saved.id, &saved.message.id, draft.updated_at)?;
+    Ok(Some(saved.id))
+}
+
+/// Zapisuje ostatnie zmiany i wysyła wersję roboczą; zwraca id wysłanej wiadomości
+pub async fn send_draft(cache: &Cache, client: &GmailClient, local_id: i64) -> Result<Option<String>> {
+    let Some(draft_id) = push_draft(cache, client, local_id).await? else {
+        return Ok(None);
+    };
+    let id = client.send_draft(&draft_id).await?;
+    cache.remove_draft(local_id)?;
+    Ok(Some(id))
+}
+
+fn api_status(e: &anyhow::Error) -> Option<u16> {
+    e.chain().find_map(|c| c.downcast_ref::<ApiError>()).map(|api| api.status.as_u16())
+}
+
+/// Zapisuje stan wiadomości, dopisuje zmianę do kolejki i nakłada ją na cache
+pub fn record(cache: &Cache, op: &MailOp) -> Result<i64> {
+    let mut snapshots = Vec::new();
+    for id in op.message_ids() {
+        if let Some(snapshot) = cache.load_message(&id)? {
+            snapshots.push(snapshot);
+        }
+    }
+    let op_id = cache.enqueue_op(op, &snapshots)?;
+    op.apply_local(cache)?;
+    Ok(op_id)
+}
+
+/// Cofa lokalną zmianę (body usuniętej wiadomości pobierze się ponownie)
+pub fn rollback(cache: &Cache, op: &PendingOp) {
+    for snapshot in &op.snapshots {
+        if let Err(e) = op.op.undo_local(cache, snapshot) {
+            eprintln!("⚠️ Rollback failed for {}: {}", snapshot.message_id, e);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// Kolejna próba; `counted` = wlicza się do limitu prób
+    Retry { counted: bool },
+    /// Gmail odrzucił zmianę
+    Reject,
+    /// Błędy przejściowe przez `MAX_TRANSIENT_ATTEMPTS` prób albo wysyłka o niepewnym wyniku -
+    /// zmiana trafia do dead letter
+    GiveUp,
+}
+
+fn classify(op: &MailOp, e: &anyhow::Error, attempts: i64) -> Outcome {
+    match e.chain().find_map(|c| c.downcast_ref::<ApiError>()) {
+        Some(api) if !api.is_transient() => return Outcome::Reject,
+        // 429/503 znaczą, że Gmail wysyłki nie przyjął; po innym 5xx/408 mogła już wyjść -
+        // nie powtarzamy, zostaje w dead letter do sprawdzenia
+        Some(api)
+            if op.is_send()
+                && api.status != StatusCode::TOO_MANY_REQUESTS
+                && api.status != StatusCode::SERVICE_UNAVAILABLE =>
+        {
+            return Outcome::GiveUp
+        }
+        Some(_) => {}
+        // Wysyłka nie jest idempotentna - powtarzamy tylko, gdy połączenie w ogóle nie powstało
+        None if op.is_send() && is_network_error(e) && !is_connect_error(e) => return Outcome::Reject,
+        // Brak sieci może trwać długo - nie liczy się do limitu
+        None if is_connect_error(e) => return Outcome::Retry { counted: false },
+        None if is_network_error(e) => {}
+        None if attempts + 1 < MAX_ATTEMPTS => return Outcome::Retry { counted: true },
+        None => return Outcome::Reject,
+    }
+    if attempts + 1 < MAX_TRANSIENT_ATTEMPTS {
+        Outcome::Retry { counted: true }
+    } else {
+        Outcome::GiveUp
+    }
+}
+
+/// Wysyła oczekujące zmiany po kolei.
+/// `on_failed` dostaje zmianę, z której kolejka zrezygnowała (odrzuconą albo w dead letter) -
+/// jej lokalny efekt jest już cofnięty.
+/// Zwraca czas do następnej próby albo `None`, gdy kolejka jest pusta.
+pub async fn replay(cache: &Cache, client: &GmailClient, on_failed: &(dyn Fn(PendingOp) + Send + Sync)) -> Result<Option<Duration>> {
+    while let Some(op) = cache.next_pending_op()? {
+        let now = chrono::Utc::now().timestamp_millis();
+        if op.next_attempt_at > now {
+            return Ok(Some(Duration::from_millis((op.next_attempt_at - now) as u64)));
+        }
+
+        let Err(e) = op.op.execute(cache, client).await else {
+            cache.remove_op(op.id)?;
+            continue;
+        };
+        let error = format!("{:#}", e);
+        let status = match classify(&op.op, &e, op.attempts) {
+            Outcome::Retry { counted } => {
+                let backoff = if counted {
+                    (1000 * 2i64.pow(op.attempts.min(16) as u32)).min(MAX_BACKOFF_MS)
+                } else {
+                    OFFLINE_BACKOFF_MS
+                };
+                cache.reschedule_op(op.id, now + backoff, &error, counted)?;
+                eprintln!("⏳ Outbox: op {} failed, retrying in {}s: {}", op.id, backoff / 1000, error);
+                return Ok(Some(Duration::from_millis(backoff as u64)));
+            }
+            Outcome::Reject => {
+                eprintln!("❌ Outbox: op {} rejected by Gmail, local change reverted: {}", op.id, error);
+                OP_FAILED
+            }
+            Outcome::GiveUp => {
+                eprintln!("❌ Outbox: op {} still failing after {} attempts, giving up: {}", op.id, op.attempts + 1, error);
+                OP_DEAD_LETTER
+            }
+        };
+        cache.fail_op(op.id, status, &error)?;
+        rollback(cache, &op);
+        if let Some(failed) = cache.get_op(op.id)? {
+            on_failed(PendingOp { snapshots: Vec::new(), ..failed });
+        }
+    }
+    Ok(None)
+}
+
+fn labels(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|s| s.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::tests::{message, test_cache};
+    use reqwest::StatusCode;
+
+    fn labels_of(cache: &Cache, id: &str) -> Vec<String> {
+        let msg = cache.load_message(id).unwrap().expect("message in cache");
+        let mut labels: Vec<String> = serde_json::from_str(&msg.label_ids_json).unwrap();
+        labels.sort();
+        labels
+    }
+
+    fn api_error(status: StatusCode) -> anyhow::Error {
+        anyhow::Error::new(ApiError { what: "modify", status, body: String::new() })
+    }
+
+    #[test]
+    fn rollback_keeps_labels_changed_meanwhile() {
+        let cache = test_cache();
+        cache.upsert_message(&message("m1", "a@x.pl", "Hej", "text/plain", &["INBOX", "UNREAD"], 1)).unwrap();
+        let op = MailOp::ModifyLabels {
+            message_ids: vec!["m1".into()],
+            add_label_ids: labels(&["STARRED"]),
+            remove_label_ids: labels(&["UNREAD"]),
+        };
+        let id = record(&cache, &op).unwrap();
+        // Synchronizacja przynosi zmianę z innego klienta, zanim Gmail odrzuci operację
+        cache.apply_label_changes("m1", &labels(&["IMPORTANT"]), &[], None).unwrap();
+
+        rollback(&cache, &cache.get_op(id).unwrap().unwrap());
+        assert_eq!(labels_of(&cache, "m1"), labels(&["IMPORTANT", "INBOX", "UNREAD"]));
+    }
+
+    #[test]
+    fn rollback_keeps_labels_present_before() {
+        let cache = test_cache();
+        cache.upsert_message(&message("m1", "a@x.pl", "Hej", "text/plain", &["INBOX", "STARRED"], 1)).unwrap();
+        let op = MailOp::ModifyLabels {
+            message_ids: vec!["m1".into()],
+            add_label_ids: labels(&["STARRED"]),
+            remove_label_ids: labels(&["UNREAD"]),
+        };
+        let id = record(&cache, &op).unwrap();
+
+        rollback(&cache, &cache.get_op(id).unwrap().unwrap());
+        assert_eq!(labels_of(&cache, "m1"), labels(&["INBOX", "STARRED"]));
+    }
+
+    #[test]
+    fn rollback_of_delete_does_not_overwrite_resynced_message() {
+        let cache = test_cache();
+        cache.upsert_message(&message("m1", "a@x.pl", "Hej", "text/plain", &["INBOX"], 1)).unwrap();
+        cache.upsert_message(&message("m2", "b@x.pl", "Cześć", "text/plain", &["INBOX"], 2)).unwrap();
+        let first = record(&cache, &MailOp::Delete { message_id: "m1".into() }).unwrap();
+        let second = record(&cache, &MailOp::Delete { message_id: "m2".into() }).unwrap();
+        assert!(!cache.has_message("m1").unwrap());
+        // m2 wróciła z synchronizacji z nowymi etykietami
+        cache.upsert_message(&message("m2", "b@x.pl", "Cześć", "text/plain", &["INBOX", "STARRED"], 2)).unwrap();
+
+        rollback(&cache, &cache.get_op(first).unwrap().unwrap());
+        rollback(&cache, &cache.get_op(second).unwrap().unwrap());
+        assert_eq!(labels_of(&cache, "m1"), labels(&["INBOX"]));
+        assert_eq!(labels_of(&cache, "m2"), labels(&["INBOX", "STARRED"]));
+    }
+
+    #[test]
+    fn transient_errors_give_up_after_limit() {
+        let op = MailOp::Trash { message_id: "m1".into() };
+        let unavailable = api_error(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(classify(&op, &unavailable, 0), Outcome::Retry { counted: true });
+        assert_eq!(classify(&op, &unavailable, MAX_TRANSIENT_ATTEMPTS - 2), Outcome::Retry { counted: true });
+        assert_eq!(classify(&op, &unavailable, MAX_TRANSIENT_ATTEMPTS - 1), Outcome::GiveUp);
+        assert_eq!(classify(&op, &api_error(StatusCode::BAD_REQUEST), 0), Outcome::Reject);
+        assert_eq!(classify(&op, &anyhow::anyhow!("coś dziwnego"), MAX_ATTEMPTS - 1), Outcome::Reject);
+    }
+
+    #[test]
+    fn sends_retry_only_when_gmail_refused_them() {
+        let send = MailOp::SendFile { path: "/tmp/x.eml".into(), thread_id: None };
+        let trash = MailOp::Trash { message_id: "m1".into() };
+        for status in [StatusCode::TOO_MANY_REQUESTS, StatusCode::SERVICE_UNAVAILABLE] {
+            assert_eq!(classify(&send, &api_error(status), 0), Outcome::Retry { counted: true });
+            assert_eq!(classify(&send, &api_error(status), MAX_TRANSIENT_ATTEMPTS - 1), Outcome::GiveUp);
+        }
+        for status in [
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::GATEWAY_TIMEOUT,
+            StatusCode::REQUEST_TIMEOUT,
+        ] {
+            assert_eq!(classify(&send, &api_error(status), 0), Outcome::GiveUp, "{}", status);
+            assert_eq!(classify(&trash, &api_error(status), 0), Outcome::Retry { counted: true }, "{}", status);
+        }
+        assert_eq!(classify(&send, &api_error(StatusCode::BAD_REQUEST), 0), Outcome::Reject);
+    }
+}
@@ -1,7 +1,48 @@
-use crate::types::{EmailAttachment, EmailMessage, GmailMessage, InlineImage};
+use crate::cache::{CachedBody, CachedMessage, ThreadRow};
+use crate::mime::text_to_html;
+use crate::sanitize::{block_remote_content, sanitize_html};
+use crate::types::{
+    EmailAddress, EmailAttachment, EmailData, EmailMessage, EmailThread, GmailHeader, GmailMessage, InlineImage,
+    MessageAddresses,
+};
 use base64::{Engine as _, engine::general_purpose};
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use encoding_rs::Encoding;
 
+/// Wiadomość dla UI: treść HTML po sanityzacji, tekst z '<' zamieniony na HTML
+/// (webview uznałby go za znaczniki)
 pub fn parse_email_message(message: GmailMessage) -> EmailMessage {
+    let (mut parsed, html) = parse_message(message);
+    parsed.body = safe_body(parsed.body, html);
+    parsed
+}
+
+/// Treść gotowa do wyświetlenia w webview (też dla body zapisanych w cache przed sanityzacją)
+pub fn safe_body(body: String, html: bool) -> String {
+    if html {
+        sanitize_html(&body)
+    } else if body.contains('<') {
+        text_to_html(&body)
+    } else {
+        body
+    }
+}
+
+/// Treść dla UI: zdalne obrazki zablokowane, chyba że nadawca ma zgodę albo `load_images`
+/// (jednorazowo). Raport trafia do `remote_content`; w cache zostaje treść z oryginalnymi adresami.
+pub fn apply_remote_content(message: &mut EmailMessage, sender_allowed: bool, load_images: bool) {
+    message.remote_content.sender_allowed = sender_allowed;
+    if !message.body.contains('<') {
+        return;
+    }
+    let (body, resources) = block_remote_content(&message.body, sender_allowed || load_images);
+    message.body = body;
+    message.remote_content.resources = resources;
+}
+
+/// Wiadomość z surową treścią i informacją, czy treść to HTML
+fn parse_message(message: GmailMessage) -> (EmailMessage, bool) {
     let headers = &message.payload.headers;
     
     let mut attachments: Vec<EmailAttachment> = Vec::new();
@@ -10,19 +51,19 @@ pub fn parse_email_message(message: GmailMessage) -> EmailMessage {
     let from = headers
         .iter()
         .find(|h| h.name.eq_ignore_ascii_case("From"))
-        .map(|h| h.value.clone())
+        .map(|h| decode_address_header(&h.value))
         .unwrap_or_default();
 
     let to = headers
         .iter()
         .find(|h| h.name.eq_ignore_ascii_case("To"))
-        .map(|h| h.value.clone())
+        .map(|h| decode_address_header(&h.value))
         .unwrap_or_default();
 
     let subject = headers
         .iter()
         .find(|h| h.name.eq_ignore_ascii_case("Subject"))
-        .map(|h| h.value.clone())
+        .map(|h| decode_header(&h.value))
         .unwrap_or_default();
 
     let date = headers
@@ -38,8 +79,9 @@ pub fn parse_email_message(message: GmailMessage) -> EmailMessage {
     if let Some(ref gmail_body) = message.payload.body {
         if let Some(ref data) = gmail_body.data {
             if let Ok(decoded) = general_purpose::URL_SAFE_NO_PAD.decode(data) {
-                let text = String::from_utf8_lossy(&decoded).to_string();
-                
+                let html = message.payload.mime_type.contains("text/html");
+                let text = decode_part_text(&decoded, headers, html);
+
                 // Sprawdź MIME type
                 if message.payload.mime_type.contains("text/html") {
                     body = text;
@@ -56,7 +98,8 @@ pub fn parse_email_message(message: GmailMessage) -> EmailMessage {
     }
 
     // ✅ KLUCZOWE: Jeśli mamy HTML - użyj HTML. Jeśli nie - użyj plain text
-    let final_body = if !body.is_empty() {
+    let html = !body.is_empty();
+    let final_body = if html {
         body
     } else if !plain_text_body.is_empty() {
         plain_text_body
@@ -69,8 +112,9 @@ pub fn parse_email_message(message: GmailMessage) -> EmailMessage {
 
     let internal_date = message.internal_date
         .and_then(|s| s.parse::<i64>().ok());
+    let addresses = message_addresses(headers);
 
-    EmailMessage {
+    let message = EmailMessage {
         id: message.id,
         thread_id: message.thread_id,
         label_ids: message.label_ids,
@@ -85,6 +129,35 @@ pub fn parse_email_message(message: GmailMessage) -> EmailMessage {
         attachments,
         inline_images,
         internal_date,
+        account_id: None,
+        addresses,
+        remote_content: Default::default(),
+    };
+    (message, html)
+}
+
+/// Wersja robocza z Gmaila w postaci pól edytora
+pub fn email_data_from_message(message: GmailMessage) -> EmailData {
+    let header = |name: &str| {
+        message
+            .payload
+            .headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| decode_address_header(&h.value))
+    };
+    let cc = header("Cc");
+    let bcc = header("Bcc");
+    // Edytor jest tekstowy - z wersji HTML (np. z Gmaila w przeglądarce) bierzemy sam tekst
+    let (parsed, html) = parse_message(message);
+    EmailData {
+        to: parsed.to,
+        subject: parsed.subject,
+        body: if html { html_to_text(&parsed.body) } else { parsed.body },
+        cc,
+        bcc,
+        // Załączniki wersji roboczej zostają w Gmailu - lokalnie tylko pliki z dysku
+        attachments: Vec::new(),
     }
 }
 
@@ -103,23 +176,28 @@ fn extract_parts(
             extract_parts(subparts, html_body, plain_text_body, attachments, inline_images);
         }
 
+        // Plik tekstowy (np. .txt, .html) z nazwą i attachmentId to załącznik, nie treść
+        let is_file = part.filename.as_deref().is_some_and(|f| !f.is_empty())
+            && part.body.as_ref().is_some_and(|b| b.attachment_id.is_some());
+        let part_headers = part.headers.as_deref().unwrap_or_default();
+
         // ✅ Obsługa body (text/html i text/plain)
         if let Some(ref part_body) = part.body {
             // ✅ HTML - PRIORYTET! Zawsze nadpisuj jeśli znajdziemy HTML
-            if mime.starts_with("text/html") {
+            if mime.starts_with("text/html") && !is_file {
                 if let Some(ref data) = part_body.data {
                     if let Ok(decoded) = general_purpose::URL_SAFE_NO_PAD.decode(data) {
-                        let text = String::from_utf8_lossy(&decoded).to_string();
+                        let text = decode_part_text(&decoded, part_headers, true);
                         *html_body = text; // ZAWSZE zastąp HTML
                         eprintln!("✅ Found HTML body, length: {}", html_body.len());
                     }
                 }
             }
             // ✅ Plain text - tylko jeśli nie mamy jeszcze HTML
-            else if mime.starts_with("text/plain") {
+            else if mime.starts_with("text/plain") && !is_file {
                 if let Some(ref data) = part_body.data {
                     if let Ok(decoded) = general_purpose::URL_SAFE_NO_PAD.decode(data) {
-                        let text = String::from_utf8_lossy(&decoded).to_string();
+                        let text = decode_part_text(&decoded, part_headers, false);
                         if plain_text_body.is_empty() {
                             *plain_text_body = text;
                             eprintln!("✅ Found plain text body, length: {}", plain_text_body.len());
@@ -165,4 +243,804 @@ fn extract_parts(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Treść części w charsecie z jej `Content-Type` (a w HTML bez niego - z `<meta charset>`).
+/// Gmail oddaje bajty części bez przekodowania, więc np. ISO-8859-2 czy Shift_JIS dekodujemy sami.
+fn decode_part_text(bytes: &[u8], headers: &[GmailHeader], html: bool) -> String {
+    let charset = headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("Content-Type"))
+        .and_then(|h| header_param(&h.value, "charset"))
+        .or_else(|| if html { html_meta_charset(bytes) } else { None });
+    decode_charset(bytes, charset.as_deref())
+}
+
+/// Bajty w podanym charsecie. Nieznany albo brak charsetu: UTF-8, jeśli bajty są poprawne,
+/// a w przeciwnym razie Windows-1252 (jak przeglądarki) - bez gubienia znaków jako U+FFFD.
+pub fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    // "us-ascii" to najczęściej źle opisany UTF-8; treść czysto ASCII wychodzi tak samo
+    let ascii_label = charset.is_some_and(|c| {
+        matches!(c.trim().to_ascii_lowercase().as_str(), "us-ascii" | "ascii" | "ansi_x3.4-1968" | "us")
+    });
+    let encoding = charset.filter(|_| !ascii_label).and_then(encoding_for_charset);
+    match encoding {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => {
+                if let Some(charset) = charset.filter(|_| !ascii_label) {
+                    eprintln!("⚠️ Unknown charset {:?}, decoding as Windows-1252", charset);
+                }
+                encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned()
+            }
+        },
+    }
+}
+
+/// Etykieta charsetu z nagłówka -> kodowanie (etykiety WHATWG plus nazwy spotykane w poczcie z Windows)
+fn encoding_for_charset(label: &str) -> Option<&'static Encoding> {
+    let label = label.trim().trim_matches('"').to_ascii_lowercase();
+    let windows = label
+        .strip_prefix("windows")
+        .or_else(|| label.strip_prefix("win"))
+        .or_else(|| label.strip_prefix("cp"))
+        .map(|n| n.trim_start_matches(['-', '_']));
+    let alias = match (label.as_str(), windows) {
+        ("cp932" | "ms932" | "windows-932", _) => "shift_jis".to_string(),
+        ("cp936" | "ms936" | "windows-936", _) => "gbk".to_string(),
+        ("cp949" | "ms949" | "uhc" | "windows-949", _) => "euc-kr".to_string(),
+        ("cp950" | "ms950" | "windows-950", _) => "big5".to_string(),
+        (_, Some(n)) if n.len() == 4 && n.starts_with("125") => format!("windows-{}", n),
+        _ => label.clone(),
+    };
+    // WHATWG "replacement" (np. ISO-2022-KR) zamieniłby całą treść na jeden znak
+    Encoding::for_label(alias.as_bytes()).filter(|e| *e != encoding_rs::REPLACEMENT)
+}
+
+/// Parametr nagłówka MIME, np. `charset` z `text/plain; charset="iso-8859-2"`
+pub fn header_param(value: &str, name: &str) -> Option<String> {
+    let mut params = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in value.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                params.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    params.push(current);
+    params.iter().skip(1).find_map(|param| {
+        let (key, val) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| val.trim().trim_matches('"').to_string())
+            .filter(|v| !v.is_empty())
+    })
+}
+
+/// `<meta charset="...">` albo `<meta http-equiv="Content-Type" content="...; charset=...">` z początku HTML
+fn html_meta_charset(bytes: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(2048)]).to_ascii_lowercase();
+    let mut rest = head.as_str();
+    while let Some(pos) = rest.find("<meta") {
+        rest = &rest[pos + 5..];
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        if let Some(at) = tag.find("charset=") {
+            let value: String = tag[at + 8..]
+                .trim_start_matches(['"', '\''])
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+                .collect();
+            if !value.is_empty() {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+/// EmailMessage z wiersza cache. Bez `body` (lista) zwraca lekką wersję bez treści.
+pub fn email_from_cached(m: &CachedMessage, body: Option<&CachedBody>) -> EmailMessage {
+    let headers: Vec<GmailHeader> = serde_json::from_str(&m.headers_json).unwrap_or_default();
+    let label_ids: Vec<String> = serde_json::from_str(&m.label_ids_json).unwrap_or_default();
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.clone())
+            .unwrap_or_default()
+    };
+
+    let unread = label_ids.iter().any(|l| l.eq_ignore_ascii_case("UNREAD"));
+
+    let (body_html, attachments, inline_images) = match body {
+        Some(b) => (
+            b.body.clone(),
+            serde_json::from_str::<Vec<EmailAttachment>>(&b.attachments_json).unwrap_or_default(),
+            serde_json::from_str::<Vec<InlineImage>>(&b.inline_images_json).unwrap_or_default(),
+        ),
+        None => (String::new(), vec![], vec![]),
+    };
+
+    EmailMessage {
+        id: m.message_id.clone(),
+        thread_id: m.thread_id.clone(),
+        label_ids,
+        from: decode_address_header(&header("From")),
+        to: decode_address_header(&header("To")),
+        subject: decode_header(&header("Subject")),
+        date: header("Date"),
+        snippet: m.snippet.clone(),
+        body: body_html,
+        unread,
+        has_attachment: !attachments.is_empty(),
+        attachments,
+        inline_images,
+        internal_date: Some(m.internal_date), // ✅
+        account_id: None,
+        addresses: message_addresses(&headers),
+        remote_content: Default::default(),
+    }
+}
+
+/// Wiersz wątku: agregaty z SQL + temat/nadawcy z wiadomości wątku (chronologicznie)
+pub fn thread_from_cached(row: &ThreadRow, messages: &[CachedMessage]) -> EmailThread {
+    let emails: Vec<EmailMessage> = messages.iter().map(|m| email_from_cached(m, None)).collect();
+    let first = emails.first();
+    let latest = emails.last();
+
+    let mut participants: Vec<String> = Vec::new();
+    let mut label_ids: Vec<String> = Vec::new();
+    for email in &emails {
+        let name = sender_name(&email.from);
+        if !name.is_empty() && !participants.contains(&name) {
+            participants.push(name);
+        }
+        for label in &email.label_ids {
+            if !label_ids.contains(label) {
+                label_ids.push(label.clone());
+            }
+        }
+    }
+
+    EmailThread {
+        thread_id: row.thread_id.clone(),
+        messages: Vec::new(),
+        last_activity: row.last_activity,
+        subject: first.map(|e| e.subject.clone()).unwrap_or_default(),
+        snippet: latest.map(|e| e.snippet.clone()).unwrap_or_default(),
+        from: latest.map(|e| e.from.clone()).unwrap_or_default(),
+        participants,
+        date: latest.map(|e| e.date.clone()).unwrap_or_default(),
+        unread: row.unread,
+        has_attachment: row.has_attachment,
+        label_ids,
+        message_count: row.message_count,
+        account_id: None,
+    }
+}
+
+/// Nagłówek tekstowy (np. Subject) z encoded-words RFC 2047 (kodowania B i Q, dowolne charsety).
+/// Odstęp między sąsiednimi encoded-words znika; słowa w tym samym charsecie są łączone przed
+/// dekodowaniem (nadawcy dzielą czasem znak wielobajtowy między dwa słowa). Błędne słowo zostaje dosłownie.
+pub fn decode_header(value: &str) -> String {
+    if !value.contains("=?") {
+        return value.to_string();
+    }
+    let value = value.replace("\r\n", "").replace('\n', "");
+    let mut out = String::with_capacity(value.len());
+    // Bajty ostatnich sąsiednich słów w jednym charsecie
+    let mut pending: Option<(String, Vec<u8>)> = None;
+    let flush = |out: &mut String, pending: &mut Option<(String, Vec<u8>)>| {
+        if let Some((charset, bytes)) = pending.take() {
+            let text = decode_charset(&bytes, Some(&charset));
+            // Zdekodowany CR/LF nie może rozbić nagłówka
+            out.extend(text.chars().map(|c| if c.is_control() && c != '\t' { ' ' } else { c }));
+        }
+    };
+
+    let mut rest = value.as_str();
+    while let Some(start) = rest.find("=?") {
+        let before = &rest[..start];
+        let Some((len, charset, bytes)) = parse_encoded_word(&rest[start..]) else {
+            flush(&mut out, &mut pending);
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            continue;
+        };
+        let adjacent = pending.is_some() && before.chars().all(char::is_whitespace);
+        if !adjacent {
+            flush(&mut out, &mut pending);
+            out.push_str(before);
+        }
+        match &mut pending {
+            Some((current, buf)) if current.eq_ignore_ascii_case(&charset) => buf.extend(bytes),
+            _ => {
+                flush(&mut out, &mut pending);
+                pending = Some((charset, bytes));
+            }
+        }
+        rest = &rest[start + len..];
+    }
+    flush(&mut out, &mut pending);
+    out.push_str(rest);
+    out
+}
+
+/// Base64 z encoded-words: padding bywa pominięty, a bity na końcu niezerowe
+const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// `=?charset?B|Q?tekst?=` na początku `s` -> (długość słowa, charset, bajty)
+fn parse_encoded_word(s: &str) -> Option<(usize, String, Vec<u8>)> {
+    let inner = s.strip_prefix("=?")?;
+    let (charset, rest) = inner.split_once('?')?;
+    let (encoding, rest) = rest.split_once('?')?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    if charset.is_empty() || charset.contains(char::is_whitespace) || text.contains(char::is_whitespace) {
+        return None;
+    }
+    let bytes = match encoding {
+        "B" | "b" => LENIENT_BASE64.decode(text).ok()?,
+        "Q" | "q" => {
+            let raw = text.as_bytes();
+            let mut bytes = Vec::with_capacity(raw.len());
+            let mut i = 0;
+            while i < raw.len() {
+                match raw[i] {
+                    b'_' => bytes.push(b' '),
+                    b'=' if i + 2 < raw.len() => {
+                        let hex = std::str::from_utf8(&raw[i + 1..i + 3]).ok()?;
+                        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                        i += 2;
+                    }
+                    b => bytes.push(b),
+                }
+                i += 1;
+            }
+            bytes
+        }
+        _ => return None,
+    };
+    // RFC 2231: `charset*język`
+    let charset = charset.split('*').next().unwrap_or(charset).to_string();
+    Some((2 + inner.len() - rest.len() + end + 2, charset, bytes))
+}
+
+/// Lista adresów z nagłówka (From/To/Cc) z encoded-words w nazwach. Nazwa ze znakami specjalnymi
+/// (np. przecinkiem) trafia po zdekodowaniu w cudzysłów, żeby listę dało się przeczytać z powrotem.
+pub fn decode_address_header(value: &str) -> String {
+    if !value.contains("=?") {
+        return value.to_string();
+    }
+    parse_address_header(value)
+        .iter()
+        .map(EmailAddress::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Adresy wszystkich nagłówków o danej nazwie (np. kilka linii To:)
+fn header_addresses(headers: &[GmailHeader], name: &str) -> Vec<EmailAddress> {
+    headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case(name))
+        .flat_map(|h| parse_address_header(&h.value))
+        .collect()
+}
+
+fn message_addresses(headers: &[GmailHeader]) -> MessageAddresses {
+    MessageAddresses {
+        from: header_addresses(headers, "From"),
+        to: header_addresses(headers, "To"),
+        cc: header_addresses(headers, "Cc"),
+        bcc: header_addresses(headers, "Bcc"),
+        reply_to: header_addresses(headers, "Reply-To"),
+    }
+}
+
+/// Lista adresów RFC 5322 z nagłówka: nazwy w cudzysłowie, komentarze, grupy i encoded-words.
+/// Grupy (`Zespół: a@x.pl, b@x.pl;`) są spłaszczane, pusta grupa (`undisclosed-recipients:;`)
+/// nie daje adresów. Komentarz służy za nazwę tylko wtedy, gdy adres nie ma własnej
+/// (`jan@x.pl (Jan Kowalski)`). Trasa źródłowa w `<@a,@b:jan@x.pl>` jest pomijana.
+/// Czyta też pola edytora, gdzie adresy można rozdzielać średnikiem.
+pub fn parse_address_header(value: &str) -> Vec<EmailAddress> {
+    let value = value.replace("\r\n", "").replace('\n', "");
+    let mut chars = value.char_indices();
+    let mut result = Vec::new();
+    let mut mailbox = PendingMailbox::default();
+    let mut in_group = false;
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                let mut word = String::new();
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => word.extend(chars.next().map(|(_, c)| c)),
+                        '"' => break,
+                        _ => word.push(c),
+                    }
+                }
+                mailbox.push_word(word);
+            }
+            '(' => {
+                let mut comment = String::new();
+                let mut depth = 1;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => comment.extend(chars.next().map(|(_, c)| c)),
+                        '(' => depth += 1,
+                        ')' if depth == 1 => break,
+                        ')' => depth -= 1,
+                        _ => comment.push(c),
+                    }
+                }
+                mailbox.end_atom();
+                mailbox.comments.push(comment.trim().to_string());
+            }
+            '<' => {
+                let mut addr = String::new();
+                let mut quoted = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' if quoted => {
+                            addr.push(c);
+                            addr.extend(chars.next().map(|(_, c)| c));
+                        }
+                        '"' => {
+                            quoted = !quoted;
+                            addr.push(c);
+                        }
+                        '>' if !quoted => break,
+                        _ => addr.push(c),
+                    }
+                }
+                mailbox.end_atom();
+                mailbox.angle = Some(addr);
+            }
+            // Nazwa grupy nie jest adresem
+            ':' if !in_group && mailbox.angle.is_none() => {
+                mailbox = PendingMailbox::default();
+                in_group = true;
+            }
+            ',' => result.extend(std::mem::take(&mut mailbox).finish()),
+            ';' => {
+                result.extend(std::mem::take(&mut mailbox).finish());
+                in_group = false;
+            }
+            // Encoded-word w całości, nawet z przecinkiem czy dwukropkiem w środku
+            '=' if value[i..].starts_with("=?") => match parse_encoded_word(&value[i..]) {
+                Some((len, ..)) => {
+                    mailbox.atom.push_str(&value[i..i + len]);
+                    for _ in value[i + 1..i + len].chars() {
+                        chars.next();
+                    }
+                }
+                None => mailbox.atom.push(c),
+            },
+            _ if c.is_whitespace() => mailbox.end_atom(),
+            _ => mailbox.atom.push(c),
+        }
+    }
+    result.extend(mailbox.finish());
+    result
+}
+
+/// Bieżący adres w `parse_address_header`
+#[derive(Default)]
+struct PendingMailbox {
+    /// Słowa przed `<...>` (nazwa) albo części samego adresu
+    words: Vec<String>,
+    atom: String,
+    comments: Vec<String>,
+    angle: Option<String>,
+}
+
+impl PendingMailbox {
+    fn end_atom(&mut self) {
+        if !self.atom.is_empty() {
+            let atom = std::mem::take(&mut self.atom);
+            self.words.push(atom);
+        }
+    }
+
+    fn push_word(&mut self, word: String) {
+        self.end_atom();
+        self.words.push(word);
+    }
+
+    fn finish(mut self) -> Option<EmailAddress> {
+        self.end_atom();
+        let (email, name) = match self.angle {
+            Some(addr) => {
+                // `@trasa,@trasa:adres` - liczy się tylko część po dwukropku
+                let addr = match addr.rfind(':') {
+                    Some(i) if addr.starts_with('@') => addr[i + 1..].to_string(),
+                    _ => addr,
+                };
+                (addr.trim().to_string(), decode_header(&self.words.join(" ")))
+            }
+            // Sam addr-spec: odstępy (np. przy zawinięciu) nie należą do adresu
+            None => (self.words.concat(), String::new()),
+        };
+        if email.is_empty() {
+            return None;
+        }
+        let name = Some(name.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .or_else(|| Some(decode_header(&self.comments.join(" "))).filter(|c| !c.trim().is_empty()));
+        Some(EmailAddress { name, email })
+    }
+}
+
+/// `"Jan Kowalski" <jan@example.com>` -> `Jan Kowalski`, sam adres zostaje adresem
+fn sender_name(from: &str) -> String {
+    match from.find('<') {
+        Some(lt) if lt > 0 => from[..lt].trim().trim_matches('"').trim().to_string(),
+        _ => from.trim().trim_start_matches('<').trim_end_matches('>').to_string(),
+    }
+}
+
+/// Zamienia HTML na zwykły tekst (indeks wyszukiwania, część text/plain)
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        out.push_str(&decode_entities(&rest[..lt]));
+        rest = &rest[lt..];
+
+        let Some(gt) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[1..gt].trim().to_ascii_lowercase();
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        rest = &rest[gt + 1..];
+
+        // Treść <style>/<script>/<head> nie jest tekstem wiadomości
+        if !tag.starts_with('/') && matches!(name.as_str(), "style" | "script" | "head" | "title") {
+            let close = format!("</{}", name);
+            match rest.to_ascii_lowercase().find(&close) {
+                Some(pos) => {
+                    rest = &rest[pos..];
+                    if let Some(end) = rest.find('>') {
+                        rest = &rest[end + 1..];
+                    }
+                }
+                None => rest = "",
+            }
+            continue;
+        }
+
+        match name.as_str() {
+            "br" | "p" | "div" | "tr" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "table" => {
+                out.push('\n')
+            }
+            "td" | "th" => out.push(' '),
+            _ => {}
+        }
+    }
+    out.push_str(&decode_entities(rest));
+
+    // Zwiń puste linie i spacje
+    let mut text = String::with_capacity(out.len());
+    let mut blank = false;
+    for line in out.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            if !blank && !text.is_empty() {
+                text.push('\n');
+            }
+            blank = true;
+        } else {
+            text.push_str(&line);
+            text.push('\n');
+            blank = false;
+        }
+    }
+    text.trim_end().to_string()
+}
+
+pub fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').filter(|&semi| semi <= 10).and_then(|semi| {
+            let entity = &rest[1..semi];
+            let ch = match entity {
+                "nbsp" => Some(' '),
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse::<u32>().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            ch.map(|c| (c, semi))
+        });
+
+        match decoded {
+            Some((c, semi)) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wiadomość z jedną częścią tekstową `content_type` o surowych bajtach `body`
+    fn message(content_type: &str, body: &[u8]) -> GmailMessage {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        serde_json::from_value(serde_json::json!({
+            "id": "m1",
+            "threadId": "t1",
+            "labelIds": ["INBOX"],
+            "snippet": "",
+            "payload": {
+                "mimeType": "multipart/mixed",
+                "headers": [{ "name": "Subject", "value": "Test" }],
+                "parts": [{
+                    "partId": "0",
+                    "mimeType": mime,
+                    "headers": [{ "name": "Content-Type", "value": content_type }],
+                    "body": { "size": body.len(), "data": general_purpose::URL_SAFE_NO_PAD.encode(body) },
+                }],
+            },
+        }))
+        .unwrap()
+    }
+
+    fn body(content_type: &str, bytes: &[u8]) -> String {
+        parse_email_message(message(content_type, bytes)).body
+    }
+
+    #[test]
+    fn decodes_eight_bit_charsets() {
+        // "Zażółć gęślą jaźń" - ś, ą i ź leżą w obu kodowaniach pod innymi bajtami
+        let iso_8859_2 = b"Za\xbf\xf3\xb3\xe6 g\xea\xb6l\xb1 ja\xbc\xf1";
+        let windows_1250 = b"Za\xbf\xf3\xb3\xe6 g\xea\x9cl\xb9 ja\x9f\xf1";
+        assert_eq!(body("text/plain; charset=iso-8859-2", iso_8859_2), "Zażółć gęślą jaźń");
+        assert_eq!(body("text/plain; charset=\"ISO-8859-2\"", iso_8859_2), "Zażółć gęślą jaźń");
+        assert_eq!(body("text/plain; charset=windows-1250", windows_1250), "Zażółć gęślą jaźń");
+        assert_eq!(body("text/plain; charset=cp1250", windows_1250), "Zażółć gęślą jaźń");
+        assert_eq!(body("text/plain; charset=koi8-r", b"\xf0\xd2\xc9\xd7\xc5\xd4, \xcd\xc9\xd2"), "Привет, мир");
+    }
+
+    #[test]
+    fn decodes_shift_jis() {
+        let text = b"\x93\xfa\x96\x7b\x8c\xea\x82\xcc\x83\x81\x81\x5b\x83\x8b";
+        assert_eq!(body("text/plain; charset=Shift_JIS", text), "日本語のメール");
+        assert_eq!(body("text/plain; charset=cp932", text), "日本語のメール");
+    }
+
+    #[test]
+    fn unknown_charset_falls_back() {
+        // Poprawny UTF-8 zostaje UTF-8, reszta - Windows-1252 zamiast znaków zastępczych
+        assert_eq!(body("text/plain; charset=x-unknown-8bit", "Cześć".as_bytes()), "Cześć");
+        assert_eq!(body("text/plain; charset=x-unknown-8bit", b"Caf\xe9 \x80 5"), "Café € 5");
+        assert_eq!(body("text/plain", b"na\xefve"), "naïve");
+        // "us-ascii" z bajtami UTF-8 to zwykle źle opisany UTF-8
+        assert_eq!(body("text/plain; charset=us-ascii", "Łódź".as_bytes()), "Łódź");
+    }
+
+    #[test]
+    fn html_meta_charset_applies_without_header_charset() {
+        let html = b"<html><head><meta charset=\"iso-8859-2\"></head><body><p>Zg\xb3oszenie</p></body></html>";
+        assert!(body("text/html", html).contains("<p>Zgłoszenie</p>"));
+
+        let http_equiv =
+            b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1250\"><p>\x9croda</p>";
+        assert!(body("text/html", http_equiv).contains("<p>środa</p>"));
+
+        // Charset z nagłówka wygrywa z <meta>
+        let utf8 = "<meta charset=\"iso-8859-2\"><p>Zgłoszenie</p>".as_bytes();
+        assert!(body("text/html; charset=utf-8", utf8).contains("<p>Zgłoszenie</p>"));
+    }
+
+    #[test]
+    fn decodes_b_and_q_encoded_words() {
+        assert_eq!(decode_header("=?UTF-8?B?WmHFvMOzxYLEhyBnxJnFm2zEhSBqYcW6xYQ=?="), "Zażółć gęślą jaźń");
+        assert_eq!(decode_header("=?UTF-8?Q?Za=C5=BC=C3=B3=C5=82=C4=87_g=C4=99=C5=9Bl=C4=85?="), "Zażółć gęślą");
+        assert_eq!(decode_header("=?ISO-8859-2?Q?=A3=F3d=BC?="), "Łódź");
+        // Małe litery w kodowaniu i charsecie, base64 bez paddingu, język z RFC 2231
+        assert_eq!(decode_header("=?utf-8?b?S3Jha8Ozdw?="), "Kraków");
+        assert_eq!(decode_header("=?iso-8859-2?q?=B3=F3d=BC?="), "łódź");
+        assert_eq!(decode_header("=?UTF-8*pl?Q?=C5=BC?="), "ż");
+        assert_eq!(decode_header("Zwykły temat"), "Zwykły temat");
+    }
+
+    #[test]
+    fn whitespace_between_encoded_words() {
+        // Między sąsiednimi słowami odstęp (też zawinięcie linii) znika
+        assert_eq!(decode_header("=?UTF-8?Q?Dzie=C5=84?= =?UTF-8?Q?_dobry?="), "Dzień dobry");
+        assert_eq!(decode_header("=?UTF-8?Q?Dzie=C5=84?=\r\n\t=?UTF-8?Q?_dobry?="), "Dzień dobry");
+        // Obok zwykłego tekstu zostaje
+        assert_eq!(decode_header("Re: =?UTF-8?B?xYHDs2TFug==?= i okolice"), "Re: Łódź i okolice");
+        assert_eq!(decode_header("=?UTF-8?Q?a?= x =?UTF-8?Q?b?="), "a x b");
+    }
+
+    #[test]
+    fn characters_split_across_words() {
+        // "ż" (C5 BC) rozdzielone między dwa słowa w tym samym charsecie
+        assert_eq!(decode_header("=?UTF-8?Q?=C5?= =?UTF-8?Q?=BC=C3=B3=C5=82w?="), "żółw");
+        assert_eq!(decode_header("=?UTF-8?B?xbzD?= =?utf-8?B?s8WCdw==?="), "żółw");
+        // Różne charsety obok siebie dekodowane osobno
+        assert_eq!(decode_header("=?UTF-8?Q?=C5=81=C3=B3d=C5=BA?= =?ISO-8859-2?Q?_=B3=F3d=BC?="), "Łódź łódź");
+    }
+
+    #[test]
+    fn malformed_encoded_words_stay_literal() {
+        for raw in [
+            "=?UTF-8?X?abc?=",
+            "=?UTF-8?Q?bez końca",
+            "=?UTF-8?Q?a b?=",
+            "=??Q?abc?=",
+            "=?UTF-8?B?!!!?=",
+            "=?UTF-8?Q?=ZZ?=",
+            "cena =? 100",
+        ] {
+            assert_eq!(decode_header(raw), raw);
+        }
+        assert_eq!(decode_header("=?UTF-8?X?abc?= =?UTF-8?Q?=C5=BC?="), "=?UTF-8?X?abc?= ż");
+    }
+
+    #[test]
+    fn decoded_control_characters_are_neutralized() {
+        assert_eq!(decode_header("=?UTF-8?Q?Temat=0D=0ABcc:_x@y.pl?="), "Temat  Bcc: x@y.pl");
+        assert_eq!(decode_header("=?UTF-8?B?YQ1iAGM=?="), "a b c");
+        assert_eq!(decode_header("=?UTF-8?Q?a=09b?="), "a\tb");
+    }
+
+    #[test]
+    fn editor_addresses_round_trip() {
+        let list = vec![
+            EmailAddress { name: Some("Nowak, Jan".into()), email: "j@x.pl".into() },
+            EmailAddress { name: Some("Ala \"As\" Kot".into()), email: "a@x.pl".into() },
+            EmailAddress { name: Some("Łucja".into()), email: "l@x.pl".into() },
+            EmailAddress { name: None, email: "b@x.pl".into() },
+        ];
+        let field = list.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ");
+        let parsed = parse_address_header(&field);
+        assert_eq!(parsed.len(), list.len());
+        for (parsed, expected) in parsed.iter().zip(&list) {
+            assert_eq!((&parsed.name, &parsed.email), (&expected.name, &expected.email));
+        }
+    }
+
+    /// (nazwa, adres); brak nazwy jako ""
+    fn addresses(value: &str) -> Vec<(String, String)> {
+        parse_address_header(value).into_iter().map(|a| (a.name.unwrap_or_default(), a.email)).collect()
+    }
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|(n, e)| (n.to_string(), e.to_string())).collect()
+    }
+
+    #[test]
+    fn quoted_display_names() {
+        assert_eq!(
+            addresses(r#""Nowak, Jan" <jan@x.pl>, "Ala \"Kot\" Nowak" <ala@x.pl>, ola@x.pl"#),
+            pairs(&[("Nowak, Jan", "jan@x.pl"), (r#"Ala "Kot" Nowak"#, "ala@x.pl"), ("", "ola@x.pl")])
+        );
+        assert_eq!(addresses("Jan Kowalski <jan@x.pl>"), pairs(&[("Jan Kowalski", "jan@x.pl")]));
+        assert_eq!(addresses(r#""a\\b" <ab@x.pl>"#), pairs(&[(r"a\b", "ab@x.pl")]));
+        // Przecinek i '>' w cudzysłowie części lokalnej nie dzielą adresu
+        assert_eq!(addresses(r#"<"a,b>c"@x.pl>"#), pairs(&[("", r#""a,b>c"@x.pl"#)]));
+    }
+
+    #[test]
+    fn groups_are_flattened() {
+        assert_eq!(
+            addresses("Zespół: a@x.pl, Bartek <b@x.pl>;, c@x.pl"),
+            pairs(&[("", "a@x.pl"), ("Bartek", "b@x.pl"), ("", "c@x.pl")])
+        );
+        assert_eq!(addresses("undisclosed-recipients:;"), pairs(&[]));
+        assert_eq!(addresses("undisclosed-recipients:; , jan@x.pl"), pairs(&[("", "jan@x.pl")]));
+        assert_eq!(addresses(""), pairs(&[]));
+    }
+
+    #[test]
+    fn comments_name_only_bare_addresses() {
+        assert_eq!(addresses("jan@x.pl (Jan)"), pairs(&[("Jan", "jan@x.pl")]));
+        assert_eq!(addresses("jan@x.pl (Jan (z działu) Nowak)"), pairs(&[("Jan z działu Nowak", "jan@x.pl")]));
+        assert_eq!(addresses("Jan Nowak <jan@x.pl> (praca)"), pairs(&[("Jan Nowak", "jan@x.pl")]));
+        assert_eq!(addresses("<jan@x.pl> (Jan)"), pairs(&[("Jan", "jan@x.pl")]));
+    }
+
+    #[test]
+    fn encoded_word_display_names() {
+        assert_eq!(
+            addresses("=?UTF-8?Q?Pawe=C5=82_Nowak?= <p@x.pl>, =?ISO-8859-2?Q?=A3ucja?= <l@x.pl>"),
+            pairs(&[("Paweł Nowak", "p@x.pl"), ("Łucja", "l@x.pl")])
+        );
+        // Przecinek w encoded-word nie dzieli listy
+        assert_eq!(addresses("=?UTF-8?Q?Nowak,_Pawe=C5=82?= <p@x.pl>"), pairs(&[("Nowak, Paweł", "p@x.pl")]));
+        assert_eq!(addresses("p@x.pl (=?UTF-8?B?UGF3ZcWC?=)"), pairs(&[("Paweł", "p@x.pl")]));
+        assert_eq!(
+            decode_address_header("=?UTF-8?Q?Nowak,_Pawe=C5=82?= <p@x.pl>"),
+            r#""Nowak, Paweł" <p@x.pl>"#
+        );
+    }
+
+    #[test]
+    fn route_addresses_and_folding() {
+        assert_eq!(addresses("Jan <@a.pl,@b.pl:jan@x.pl>"), pairs(&[("Jan", "jan@x.pl")]));
+        assert_eq!(
+            addresses("\"Nowak,\r\n Jan\" <jan@x.pl>,\r\n\tola@x.pl,\n =?UTF-8?Q?Pawe=C5=82?=\r\n <p@x.pl>"),
+            pairs(&[("Nowak, Jan", "jan@x.pl"), ("", "ola@x.pl"), ("Paweł", "p@x.pl")])
+        );
+    }
+
+    #[test]
+    fn message_addresses_fill_every_field() {
+        let message: GmailMessage = serde_json::from_value(serde_json::json!({
+            "id": "m1",
+            "threadId": "t1",
+            "labelIds": ["INBOX"],
+            "snippet": "",
+            "payload": {
+                "mimeType": "text/plain",
+                "headers": [
+                    { "name": "From", "value": "=?UTF-8?Q?Pawe=C5=82?= <p@x.pl>" },
+                    { "name": "To", "value": "a@x.pl, \"Nowak, Jan\" <jan@x.pl>" },
+                    { "name": "to", "value": "Zespół: b@x.pl;" },
+                    { "name": "Cc", "value": "c@x.pl (Celina)" },
+                    { "name": "Bcc", "value": "d@x.pl" },
+                    { "name": "Reply-To", "value": "Lista <lista@x.pl>" },
+                ],
+                "body": { "size": 1, "data": "YQ" },
+            },
+        }))
+        .unwrap();
+        let parsed = parse_email_message(message);
+        let list = |v: &[EmailAddress]| -> Vec<(String, String)> {
+            v.iter().map(|a| (a.name.clone().unwrap_or_default(), a.email.clone())).collect()
+        };
+        let a = &parsed.addresses;
+        assert_eq!(list(&a.from), pairs(&[("Paweł", "p@x.pl")]));
+        assert_eq!(list(&a.to), pairs(&[("", "a@x.pl"), ("Nowak, Jan", "jan@x.pl"), ("", "b@x.pl")]));
+        assert_eq!(list(&a.cc), pairs(&[("Celina", "c@x.pl")]));
+        assert_eq!(list(&a.bcc), pairs(&[("", "d@x.pl")]));
+        assert_eq!(list(&a.reply_to), pairs(&[("Lista", "lista@x.pl")]));
+        assert_eq!(parsed.from, "Paweł <p@x.pl>");
+    }
+}
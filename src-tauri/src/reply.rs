@@ -0,0 +1,360 @@
+//! Odpowiedź, odpowiedź wszystkim i przekazanie: adresaci, temat, nagłówki wątku
+//! (In-Reply-To/References) i cytat oryginału. Załączniki do przekazania dokłada `SyncManager`.
+
+use crate::mime::{looks_like_html, text_to_html, OutgoingMessage};
+use crate::parser::{decode_address_header, html_to_text, parse_address_header};
+use crate::types::{EmailAddress, EmailData, EmailMessage, GmailHeader};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyKind {
+    Reply,
+    ReplyAll,
+    Forward,
+}
+
+/// Wiadomość źródłowa: nagłówki z cache i sparsowana treść
+pub struct Source<'a> {
+    pub headers: &'a [GmailHeader],
+    pub message: &'a EmailMessage,
+}
+
+impl Source<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.trim())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn addresses(&self, name: &str) -> Vec<EmailAddress> {
+        self.header(name).map(parse_address_header).unwrap_or_default()
+    }
+}
+
+/// Wiadomość do wysłania z pól edytora (`data.body` to tylko nowy tekst, bez cytatu).
+/// Adresy wpisane w edytorze są dokładane do wyliczonych, bez powtórzeń.
+pub fn compose(kind: ReplyKind, source: &Source, data: &EmailData, own_email: &str) -> OutgoingMessage {
+    let own = |m: &EmailAddress| m.email.eq_ignore_ascii_case(own_email);
+    let (mut to, mut cc) = match kind {
+        ReplyKind::Forward => (Vec::new(), Vec::new()),
+        _ => {
+            let from = source.addresses("From");
+            // Odpowiedź na własną wiadomość (np. z "Wysłanych") idzie do jej odbiorców, jak w Gmailu
+            let sent_by_us = !from.is_empty() && from.iter().all(own);
+            let to = if sent_by_us {
+                source.addresses("To")
+            } else {
+                Some(source.addresses("Reply-To")).filter(|r| !r.is_empty()).unwrap_or(from)
+            };
+            let cc = match kind {
+                ReplyKind::ReplyAll if sent_by_us => source.addresses("Cc"),
+                ReplyKind::ReplyAll => [source.addresses("To"), source.addresses("Cc")].concat(),
+                _ => Vec::new(),
+            };
+            (to, cc)
+        }
+    };
+    to.extend(parse_address_header(&data.to));
+    cc.extend(data.cc.as_deref().map(parse_address_header).unwrap_or_default());
+
+    let mut seen: Vec<String> = Vec::new();
+    let mut unique = |list: Vec<EmailAddress>, keep_own: bool| -> Vec<EmailAddress> {
+        list.into_iter()
+            .filter(|m| keep_own || !own(m))
+            .filter(|m| {
+                let key = m.email.to_ascii_lowercase();
+                if seen.contains(&key) {
+                    return false;
+                }
+                seen.push(key);
+                true
+            })
+            .collect()
+    };
+    // W "Do" własny adres zostaje tylko wtedy, gdy ktoś wpisał go celowo albo to jedyny odbiorca
+    let to = unique(to, false);
+    let cc = unique(cc, false);
+    let to = if to.is_empty() && kind != ReplyKind::Forward {
+        unique(source.addresses("From"), true)
+    } else {
+        to
+    };
+
+    let message_id = source.header("Message-ID").map(String::from);
+    // RFC 5322 3.6.4: References rodzica (albo jego In-Reply-To) + jego Message-ID
+    let mut references: Vec<String> = source
+        .header("References")
+        .or_else(|| source.header("In-Reply-To"))
+        .map(|r| r.split_whitespace().map(String::from).collect())
+        .unwrap_or_default();
+    if let Some(id) = &message_id {
+        if !references.contains(id) {
+            references.push(id.clone());
+        }
+    }
+
+    let (html, text) = quoted_body(kind, source, &data.body);
+    OutgoingMessage {
+        to: join(&to),
+        cc: Some(join(&cc)).filter(|c| !c.is_empty()),
+        bcc: data.bcc.clone().filter(|b| !b.trim().is_empty()),
+        subject: subject(kind, &data.subject, &source.message.subject),
+        body: html,
+        text: Some(text),
+        in_reply_to: message_id,
+        references,
+        ..Default::default()
+    }
+}
+
+fn join(list: &[EmailAddress]) -> String {
+    list.iter().map(EmailAddress::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Temat z edytora albo temat oryginału z "Re:"/"Fwd:" (bez dublowania prefiksu)
+fn subject(kind: ReplyKind, typed: &str, original: &str) -> String {
+    if !typed.trim().is_empty() {
+        return typed.to_string();
+    }
+    let lower = original.trim_start().to_lowercase();
+    match kind {
+        ReplyKind::Forward if lower.starts_with("fwd:") || lower.starts_with("fw:") => original.to_string(),
+        ReplyKind::Forward => format!("Fwd: {}", original),
+        _ if lower.starts_with("re:") => original.to_string(),
+        _ => format!("Re: {}", original),
+    }
+}
+
+/// Nowy tekst + cytat: w HTML jako blockquote, w części tekstowej z "> " (przekazanie bez prefiksu)
+fn quoted_body(kind: ReplyKind, source: &Source, typed: &str) -> (String, String) {
+    let original = &source.message;
+    let (original_html, original_text) = if looks_like_html(&original.body) {
+        (original.body.clone(), html_to_text(&original.body))
+    } else {
+        (text_to_html(&original.body), original.body.clone())
+    };
+    let (typed_html, typed_text) = if looks_like_html(typed) {
+        (typed.to_string(), html_to_text(typed))
+    } else {
+        (text_to_html(typed), typed.to_string())
+    };
+    let date = source.header("Date").unwrap_or(&original.date);
+
+    match kind {
+        ReplyKind::Forward => {
+            let mut intro = format!(
+                "---------- Przekazana wiadomość ----------\nOd: {}\nData: {}\nTemat: {}\nDo: {}",
+                original.from, date, original.subject, original.to
+            );
+            if let Some(cc) = source.header("Cc") {
+                intro.push_str(&format!("\nDW: {}", decode_address_header(cc)));
+            }
+            let html = format!("{}<br><br><div class=\"gmail_quote\">{}<br><br>{}</div>", typed_html, text_to_html(&intro), original_html);
+            let text = format!("{}\n\n{}\n\n{}", typed_text, intro, original_text);
+            (html, text)
+        }
+        _ => {
+            let attribution = if date.is_empty() {
+                format!("{} napisał(a):", original.from)
+            } else {
+                format!("W dniu {} {} napisał(a):", date, original.from)
+            };
+            let html = format!(
+                "{}<br><br><div class=\"gmail_quote\">{}<blockquote class=\"gmail_quote\" \
+                 style=\"margin:0 0 0 .8ex;border-left:1px solid #ccc;padding-left:1ex\">{}</blockquote></div>",
+                typed_html,
+                text_to_html(&attribution),
+                original_html
+            );
+            let quoted: Vec<String> = original_text
+                .lines()
+                .map(|l| if l.is_empty() { ">".to_string() } else { format!("> {}", l) })
+                .collect();
+            let text = format!("{}\n\n{}\n{}", typed_text, attribution, quoted.join("\n"));
+            (html, text)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN: &str = "me@example.com";
+    const DATE: &str = "Mon, 6 May 2024 10:00:00 +0200";
+
+    /// Nagłówki i wiadomość źródłowa; From/To/Temat jak po parsowaniu
+    fn original(headers: &[(&str, &str)], body: &str) -> (Vec<GmailHeader>, EmailMessage) {
+        let headers: Vec<GmailHeader> =
+            headers.iter().map(|(n, v)| GmailHeader { name: n.to_string(), value: v.to_string() }).collect();
+        let header = |name: &str| headers.iter().find(|h| h.name == name).map_or("", |h| h.value.as_str());
+        let message = EmailMessage {
+            id: "m1".into(),
+            thread_id: "t1".into(),
+            label_ids: vec!["INBOX".into()],
+            from: decode_address_header(header("From")),
+            to: decode_address_header(header("To")),
+            subject: crate::parser::decode_header(header("Subject")),
+            date: DATE.into(),
+            snippet: String::new(),
+            body: body.into(),
+            unread: false,
+            has_attachment: false,
+            attachments: Vec::new(),
+            inline_images: Vec::new(),
+            internal_date: None,
+            account_id: None,
+            addresses: Default::default(),
+            remote_content: Default::default(),
+        };
+        (headers, message)
+    }
+
+    fn reply(kind: ReplyKind, headers: &[(&str, &str)], data: &EmailData) -> OutgoingMessage {
+        let (headers, message) = original(headers, "Treść");
+        compose(kind, &Source { headers: &headers, message: &message }, data, OWN)
+    }
+
+    fn typed(body: &str) -> EmailData {
+        EmailData { body: body.into(), ..Default::default() }
+    }
+
+    #[test]
+    fn reply_all_drops_own_address() {
+        let headers = [
+            ("From", "Jan <jan@x.pl>"),
+            ("To", "Ja <ME@Example.com>, ola@x.pl"),
+            ("Cc", "Me@example.com, piotr@x.pl, Ola <OLA@x.pl>"),
+        ];
+        let out = reply(ReplyKind::ReplyAll, &headers, &typed("ok"));
+        assert_eq!(out.to, "Jan <jan@x.pl>");
+        assert_eq!(out.cc.as_deref(), Some("ola@x.pl, piotr@x.pl"));
+
+        let out = reply(ReplyKind::Reply, &headers, &typed("ok"));
+        assert_eq!((out.to.as_str(), out.cc), ("Jan <jan@x.pl>", None));
+
+        // Adresy z edytora dochodzą bez powtórzeń
+        let data = EmailData { to: "JAN@x.pl, anna@x.pl".into(), cc: Some("piotr@x.pl".into()), ..typed("ok") };
+        let out = reply(ReplyKind::ReplyAll, &headers, &data);
+        assert_eq!(out.to, "Jan <jan@x.pl>, anna@x.pl");
+        assert_eq!(out.cc.as_deref(), Some("ola@x.pl, piotr@x.pl"));
+    }
+
+    #[test]
+    fn reply_to_own_message_goes_to_its_recipients() {
+        let headers = [
+            ("From", "Ja <Me@Example.com>"),
+            ("To", "ola@x.pl, Piotr <piotr@x.pl>"),
+            ("Cc", "anna@x.pl, me@example.com"),
+        ];
+        let out = reply(ReplyKind::Reply, &headers, &typed("ok"));
+        assert_eq!((out.to.as_str(), out.cc), ("ola@x.pl, Piotr <piotr@x.pl>", None));
+        let out = reply(ReplyKind::ReplyAll, &headers, &typed("ok"));
+        assert_eq!(out.to, "ola@x.pl, Piotr <piotr@x.pl>");
+        assert_eq!(out.cc.as_deref(), Some("anna@x.pl"));
+
+        // Notatka do siebie: jedynym odbiorcą zostaje własny adres
+        let out = reply(ReplyKind::Reply, &[("From", "me@example.com"), ("To", "me@example.com")], &typed("ok"));
+        assert_eq!(out.to, "me@example.com");
+    }
+
+    #[test]
+    fn reply_to_header_wins_over_from() {
+        let headers = [("From", "Jan <jan@x.pl>"), ("Reply-To", "Lista <lista@x.pl>"), ("To", "me@example.com")];
+        assert_eq!(reply(ReplyKind::Reply, &headers, &typed("ok")).to, "Lista <lista@x.pl>");
+        let out = reply(ReplyKind::ReplyAll, &headers, &typed("ok"));
+        assert_eq!((out.to.as_str(), out.cc), ("Lista <lista@x.pl>", None));
+    }
+
+    #[test]
+    fn references_extend_the_parent_chain() {
+        let out = reply(
+            ReplyKind::Reply,
+            &[("From", "jan@x.pl"), ("Message-ID", "<c@x.pl>"), ("References", "<a@x.pl>\r\n <b@x.pl>")],
+            &typed("ok"),
+        );
+        assert_eq!(out.in_reply_to.as_deref(), Some("<c@x.pl>"));
+        assert_eq!(out.references, vec!["<a@x.pl>", "<b@x.pl>", "<c@x.pl>"]);
+
+        // Message-ID rodzica już w References - bez duplikatu
+        let out = reply(
+            ReplyKind::Reply,
+            &[("From", "jan@x.pl"), ("Message-Id", "<c@x.pl>"), ("References", "<a@x.pl> <c@x.pl>")],
+            &typed("ok"),
+        );
+        assert_eq!(out.references, vec!["<a@x.pl>", "<c@x.pl>"]);
+
+        // Bez References - łańcuch z In-Reply-To
+        let headers = [("From", "jan@x.pl"), ("Message-ID", "<c@x.pl>"), ("In-Reply-To", "<b@x.pl>")];
+        let out = reply(ReplyKind::Reply, &headers, &typed("ok"));
+        assert_eq!(out.references, vec!["<b@x.pl>", "<c@x.pl>"]);
+        let out = reply(ReplyKind::Reply, &[("From", "jan@x.pl")], &typed("ok"));
+        assert_eq!((out.in_reply_to, out.references), (None, Vec::<String>::new()));
+    }
+
+    #[test]
+    fn subject_prefixes_are_not_doubled() {
+        let subject = |kind, original: &str| {
+            reply(kind, &[("From", "jan@x.pl"), ("Subject", original)], &typed("ok")).subject
+        };
+        assert_eq!(subject(ReplyKind::Reply, "Spotkanie"), "Re: Spotkanie");
+        assert_eq!(subject(ReplyKind::ReplyAll, "Re: Spotkanie"), "Re: Spotkanie");
+        assert_eq!(subject(ReplyKind::Reply, "RE: Spotkanie"), "RE: Spotkanie");
+        assert_eq!(subject(ReplyKind::Forward, "Spotkanie"), "Fwd: Spotkanie");
+        assert_eq!(subject(ReplyKind::Forward, "Fwd: Spotkanie"), "Fwd: Spotkanie");
+        assert_eq!(subject(ReplyKind::Forward, "FW: Spotkanie"), "FW: Spotkanie");
+        assert_eq!(subject(ReplyKind::Forward, "Re: Spotkanie"), "Fwd: Re: Spotkanie");
+        assert_eq!(subject(ReplyKind::Reply, "=?UTF-8?Q?Re:_Za=C5=BC=C3=B3=C5=82=C4=87?="), "Re: Zażółć");
+
+        let data = EmailData { subject: "Nowy temat".into(), ..typed("ok") };
+        let (headers, message) = original(&[("From", "jan@x.pl"), ("Subject", "Spotkanie")], "x");
+        let out = compose(ReplyKind::Reply, &Source { headers: &headers, message: &message }, &data, OWN);
+        assert_eq!(out.subject, "Nowy temat");
+    }
+
+    #[test]
+    fn reply_quotes_the_original() {
+        let (headers, message) = original(&[("From", "Jan <jan@x.pl>"), ("Date", DATE)], "Linia 1\n\nLinia <2>");
+        let source = Source { headers: &headers, message: &message };
+        let out = compose(ReplyKind::Reply, &source, &typed("Dzięki"), OWN);
+        assert_eq!(
+            out.text.as_deref(),
+            Some("Dzięki\n\nW dniu Mon, 6 May 2024 10:00:00 +0200 Jan <jan@x.pl> napisał(a):\n> Linia 1\n>\n> Linia <2>")
+        );
+        assert!(out.body.starts_with("<div>Dzięki</div><br><br><div class=\"gmail_quote\">"), "{}", out.body);
+        assert!(out.body.contains("<blockquote class=\"gmail_quote\""));
+        assert!(out.body.ends_with("<div>Linia 1<br><br>Linia &lt;2&gt;</div></blockquote></div>"), "{}", out.body);
+
+        // Oryginał w HTML cytowany bez zmian, w części tekstowej jako tekst
+        let (headers, message) = original(&[("From", "jan@x.pl")], "<p>Akapit</p><p>Drugi</p>");
+        let out = compose(ReplyKind::Reply, &Source { headers: &headers, message: &message }, &typed("ok"), OWN);
+        assert!(out.body.contains("<p>Akapit</p><p>Drugi</p></blockquote>"));
+        assert!(out.text.unwrap().contains("> Akapit"));
+    }
+
+    #[test]
+    fn forward_intro_lists_decoded_headers() {
+        let (headers, message) = original(
+            &[
+                ("From", "=?UTF-8?Q?Pawe=C5=82?= <p@x.pl>"),
+                ("To", "me@example.com"),
+                ("Cc", "=?UTF-8?B?xYF1Y2ph?= <l@x.pl>, ola@x.pl"),
+                ("Subject", "Umowa"),
+                ("Date", DATE),
+            ],
+            "Treść umowy",
+        );
+        let data = EmailData { to: "anna@x.pl".into(), ..typed("Przesyłam") };
+        let out = compose(ReplyKind::Forward, &Source { headers: &headers, message: &message }, &data, OWN);
+        assert_eq!((out.to.as_str(), out.cc), ("anna@x.pl", None));
+        assert_eq!(
+            out.text.as_deref(),
+            Some(
+                "Przesyłam\n\n---------- Przekazana wiadomość ----------\nOd: Paweł <p@x.pl>\n\
+                 Data: Mon, 6 May 2024 10:00:00 +0200\nTemat: Umowa\nDo: me@example.com\n\
+                 DW: Łucja <l@x.pl>, ola@x.pl\n\nTreść umowy"
+            )
+        );
+    }
+}
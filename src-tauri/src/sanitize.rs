@@ -0,0 +1,804 @@
+//! Sanityzacja HTML z treści wiadomości, zanim trafi do webview.
+//!
+//! Dokument jest dzielony na tokeny i składany od nowa: zostają tylko tagi i atrybuty z allowlisty,
+//! wartości atrybutów są dekodowane i escapowane ponownie, więc przeglądarka widzi dokładnie to,
+//! co zostało sprawdzone. Linki i obrazki przechodzą przez filtr schematów URL, a CSS (atrybut
+//! `style` i bloki `<style>`) traci `expression()`, `@import`, bindingi i `url()` z obcym schematem.
+//!
+//! Drugi przebieg (`block_remote_content`, na wyjściu do UI) zamienia zdalne obrazki i tła na
+//! placeholdery i wykrywa piksele śledzące.
+
+use crate::parser::decode_entities;
+use crate::types::RemoteResource;
+
+/// Wersja reguł sanityzacji - podbijana przy każdej ich zmianie. Body zapisane w cache starszą
+/// wersją są sanityzowane ponownie przy odczycie.
+pub const SANITIZER_VERSION: i64 = 1;
+
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "abbr", "address", "b", "bdi", "bdo", "big", "blockquote", "br", "caption", "center", "cite", "code",
+    "col", "colgroup", "dd", "del", "dfn", "div", "dl", "dt", "em", "font", "h1", "h2", "h3", "h4", "h5", "h6",
+    "hr", "i", "img", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q", "s", "samp", "small", "span", "strike",
+    "strong", "sub", "sup", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "tt", "u", "ul", "var", "wbr",
+];
+
+const VOID_TAGS: &[&str] = &["br", "col", "hr", "img", "wbr"];
+
+/// Treść jest tekstem do `</nazwa>` (bez zagnieżdżonych tagów)
+const RAW_TEXT_TAGS: &[&str] = &[
+    "script", "style", "textarea", "title", "xmp", "iframe", "noembed", "noframes", "noscript", "plaintext",
+];
+
+/// Znikają razem z zawartością (formularze, osadzone obiekty, SVG/MathML z własnymi wektorami XSS)
+const DROPPED_TAGS: &[&str] = &["applet", "button", "form", "frameset", "math", "object", "select", "svg", "template"];
+
+const ALLOWED_ATTRS: &[&str] = &[
+    "abbr", "align", "alt", "background", "bgcolor", "border", "cellpadding", "cellspacing", "cite", "class",
+    "color", "colspan", "datetime", "dir", "face", "headers", "height", "href", "hspace", "lang", "nowrap",
+    "reversed", "rowspan", "scope", "size", "span", "src", "start", "style", "summary", "title", "type", "valign",
+    "vspace", "width",
+];
+
+/// Domeny usług śledzących otwarcia wiadomości (także subdomeny)
+const TRACKER_DOMAINS: &[&str] = &[
+    "bananatag.com", "doubleclick.net", "emltrk.com", "exct.net", "getnotify.com", "google-analytics.com",
+    "list-manage.com", "mailfoogae.appspot.com", "mailstat.us", "mailtrack.io", "mandrillapp.com", "mixmax.com",
+    "pixel.wp.com", "sailthru.com", "sendgrid.net", "superhuman.com", "track.hubspot.com", "yesware.com",
+];
+
+/// Typy `data:` dozwolone w obrazkach (bez SVG - może zawierać skrypt)
+const DATA_IMAGE_TYPES: &[&str] = &["image/png", "image/gif", "image/jpeg", "image/jpg", "image/webp", "image/bmp"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum UrlKind {
+    /// href, cite: http(s), mailto, tel i kotwice `#...`
+    Link,
+    /// src, background, url() w CSS: http(s), cid (obrazki inline) i data: z obrazkiem
+    Image,
+}
+
+/// Bezpieczna wersja treści HTML wiadomości
+pub fn sanitize_html(html: &str) -> String {
+    let mut sanitizer = Sanitizer::new(html, None);
+    sanitizer.run(html);
+    sanitizer.out
+}
+
+/// Treść z placeholderami zamiast zdalnych obrazków, teł i `url()` w CSS oraz lista znalezionych
+/// zasobów. Z `load_images` zdalne zasoby zostają, poza pikselami śledzącymi (1x1, znane domeny).
+pub fn block_remote_content(html: &str, load_images: bool) -> (String, Vec<RemoteResource>) {
+    let mut sanitizer = Sanitizer::new(html, Some(RemoteBlocker { load_images, found: Vec::new() }));
+    sanitizer.run(html);
+    let found = sanitizer.remote.map(|r| r.found).unwrap_or_default();
+    (sanitizer.out, found)
+}
+
+struct Sanitizer {
+    out: String,
+    /// Otwarte dozwolone elementy - zamykane na końcu, osierocone `</x>` są pomijane
+    open: Vec<String>,
+    /// Usuwany element z zawartością: (nazwa, zagnieżdżenie)
+    skip: Option<(String, usize)>,
+    remote: Option<RemoteBlocker>,
+}
+
+struct RemoteBlocker {
+    load_images: bool,
+    found: Vec<RemoteResource>,
+}
+
+impl RemoteBlocker {
+    /// Zapisuje zasób w raporcie; `false` - zasób ma zostać zablokowany
+    fn allow(&mut self, url: &str, kind: &str, pixel: bool) -> bool {
+        if !url.to_ascii_lowercase().starts_with("http") {
+            return true;
+        }
+        let tracking = if pixel { Some("pixel") } else { tracking_reason(url) };
+        // Piksel albo znana domena nie pokazuje nic użytecznego - blokowany zawsze
+        let blocked = !self.load_images || matches!(tracking, Some("pixel" | "tracker-domain"));
+        self.found.push(RemoteResource {
+            url: url.to_string(),
+            kind: kind.to_string(),
+            tracking: tracking.map(str::to_string),
+            blocked,
+        });
+        !blocked
+    }
+}
+
+struct Tag {
+    name: String,
+    closing: bool,
+    attrs: Vec<(String, String)>,
+}
+
+impl Sanitizer {
+    fn new(html: &str, remote: Option<RemoteBlocker>) -> Self {
+        Sanitizer { out: String::with_capacity(html.len()), open: Vec::new(), skip: None, remote }
+    }
+
+    fn run(&mut self, html: &str) {
+        let mut rest = html;
+        while let Some(lt) = rest.find('<') {
+            self.text(&rest[..lt]);
+            rest = &rest[lt..];
+
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                // `<!-->` też kończy komentarz
+                rest = match comment.strip_prefix('>').or_else(|| comment.strip_prefix("->")) {
+                    Some(after) => after,
+                    None => comment.find("-->").map_or("", |end| &comment[end + 3..]),
+                };
+                continue;
+            }
+            let name_start = if rest.starts_with("</") { 2 } else { 1 };
+            if !rest[name_start..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                if rest.starts_with("<!") || rest.starts_with("<?") || rest.starts_with("</") {
+                    // Doctype, instrukcje przetwarzania i `</ coś>` - pomijane do '>'
+                    rest = rest.find('>').map_or("", |gt| &rest[gt + 1..]);
+                } else {
+                    self.text("<");
+                    rest = &rest[1..];
+                }
+                continue;
+            }
+            // Urwany tag na końcu dokumentu przeglądarka też pomija
+            let Some((tag, len)) = parse_tag(rest) else {
+                rest = "";
+                break;
+            };
+            rest = &rest[len..];
+
+            if tag.closing {
+                self.end_tag(&tag.name);
+            } else if RAW_TEXT_TAGS.contains(&tag.name.as_str()) {
+                let (content, after) = split_raw_text(rest, &tag.name);
+                rest = after;
+                if tag.name == "style" && self.skip.is_none() {
+                    let css = scrub_stylesheet(content, &mut self.remote);
+                    self.out.push_str("<style>");
+                    self.out.push_str(&css);
+                    self.out.push_str("</style>");
+                }
+            } else {
+                self.start_tag(tag);
+            }
+        }
+        self.text(rest);
+        while let Some(name) = self.open.pop() {
+            self.out.push_str(&format!("</{}>", name));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip.is_none() {
+            self.out.push_str(&text.replace('<', "&lt;"));
+        }
+    }
+
+    fn start_tag(&mut self, tag: Tag) {
+        let name = tag.name.as_str();
+        if let Some((skipped, depth)) = &mut self.skip {
+            if skipped == name {
+                *depth += 1;
+            }
+            return;
+        }
+        if DROPPED_TAGS.contains(&name) {
+            self.skip = Some((tag.name, 1));
+            return;
+        }
+        let Some(&name) = ALLOWED_TAGS.iter().find(|&&t| t == name) else {
+            // Pozostałe (html, body, head, meta, link, base, input...) - sam tag znika, treść zostaje
+            return;
+        };
+
+        let pixel = name == "img" && is_pixel(&tag.attrs);
+        let mut element = format!("<{}", name);
+        let mut seen: Vec<&str> = Vec::new();
+        let mut has_link = false;
+        let mut blocked = false;
+        for (attr, value) in &tag.attrs {
+            // Powtórzony atrybut: przeglądarka bierze pierwszy
+            let Some(&attr) = ALLOWED_ATTRS.iter().find(|&&a| a == attr) else { continue };
+            if seen.contains(&attr) {
+                continue;
+            }
+            seen.push(attr);
+            let value = match attr {
+                "href" | "cite" => safe_url(value, UrlKind::Link),
+                "src" | "background" => safe_url(value, UrlKind::Image).filter(|url| {
+                    let kind = if attr == "src" { "image" } else { "background" };
+                    let allowed = self.remote.as_mut().is_none_or(|r| r.allow(url, kind, pixel));
+                    blocked |= !allowed;
+                    allowed
+                }),
+                "style" => {
+                    Some(scrub_declarations(&strip_css_comments(value), true, &mut self.remote)).filter(|s| !s.is_empty())
+                }
+                _ => Some(value.clone()),
+            };
+            let Some(value) = value else { continue };
+            has_link |= attr == "href";
+            element.push_str(&format!(" {}=\"{}\"", attr, escape_attr(&value)));
+        }
+        if name == "a" && has_link {
+            element.push_str(" target=\"_blank\" rel=\"noopener noreferrer\"");
+        }
+        if blocked {
+            // Zablokowany piksel nie ma czego pokazać; zwykły obrazek zostaje jako placeholder
+            if pixel {
+                return;
+            }
+            element.push_str(" data-remote=\"blocked\"");
+        }
+        self.out.push_str(&element);
+        self.out.push('>');
+        if !VOID_TAGS.contains(&name) {
+            self.open.push(name.to_string());
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        if let Some((skipped, depth)) = &mut self.skip {
+            if skipped == name {
+                *depth -= 1;
+                if *depth == 0 {
+                    self.skip = None;
+                }
+            }
+            return;
+        }
+        if let Some(pos) = self.open.iter().rposition(|open| open == name) {
+            for open in self.open.drain(pos..).rev() {
+                self.out.push_str(&format!("</{}>", open));
+            }
+        }
+    }
+}
+
+/// Tag od `<` do `>` -> (tag, długość). `None`, gdy tag nie jest domknięty.
+fn parse_tag(s: &str) -> Option<(Tag, usize)> {
+    let closing = s.starts_with("</");
+    let body = &s[if closing { 2 } else { 1 }..];
+    let name_end = body.find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')?;
+    let name = body[..name_end].to_ascii_lowercase();
+
+    let mut attrs = Vec::new();
+    let mut rest = &body[name_end..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if let Some(after) = rest.strip_prefix('>') {
+            return Some((Tag { name, closing, attrs }, s.len() - after.len()));
+        }
+        if rest.is_empty() {
+            return None;
+        }
+        // Nazwa atrybutu: '=' na początku należy do nazwy
+        let first = rest.chars().next().map_or(1, char::len_utf8);
+        let attr_end = rest[first..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>' || c == '=')
+            .map_or(rest.len(), |i| i + first);
+        let attr = rest[..attr_end].to_ascii_lowercase();
+        rest = rest[attr_end..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        let mut value = String::new();
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            match after_eq.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let end = after_eq[1..].find(quote)?;
+                    value = decode_entities(&after_eq[1..end + 1]);
+                    rest = &after_eq[end + 2..];
+                }
+                _ => {
+                    let end = after_eq.find(|c: char| c.is_ascii_whitespace() || c == '>').unwrap_or(after_eq.len());
+                    value = decode_entities(&after_eq[..end]);
+                    rest = &after_eq[end..];
+                }
+            }
+        }
+        attrs.push((attr, value));
+    }
+}
+
+/// Treść elementu raw-text i reszta dokumentu za jego `</nazwa>`
+fn split_raw_text<'a>(s: &'a str, name: &str) -> (&'a str, &'a str) {
+    let close = format!("</{}", name);
+    let lower = s.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&close).map(|p| p + from) {
+        // `</styles>` nie zamyka `<style>`
+        let after = pos + close.len();
+        if lower[after..].starts_with(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>') {
+            let end = s[after..].find('>').map_or(s.len(), |gt| after + gt + 1);
+            return (&s[..pos], &s[end..]);
+        }
+        from = after;
+    }
+    (s, "")
+}
+
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// URL po normalizacji jak w przeglądarce, jeśli schemat jest dozwolony dla danego użycia
+fn safe_url(value: &str, kind: UrlKind) -> Option<String> {
+    // Przeglądarka obcina spacje/znaki sterujące na brzegach i usuwa tab/CR/LF ze środka
+    let url: String = value
+        .trim_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let scheme = match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => url[..i].to_ascii_lowercase(),
+        // Bez schematu (względny) - tylko kotwice w obrębie wiadomości
+        _ => return (kind == UrlKind::Link && url.starts_with('#')).then_some(url),
+    };
+    let allowed = match (kind, scheme.as_str()) {
+        (_, "http" | "https") => true,
+        (UrlKind::Link, "mailto" | "tel") => true,
+        (UrlKind::Image, "cid") => true,
+        (UrlKind::Image, "data") => {
+            let media = url[5..].to_ascii_lowercase();
+            DATA_IMAGE_TYPES
+                .iter()
+                .any(|t| media.strip_prefix(t).is_some_and(|r| r.starts_with([';', ','])))
+        }
+        _ => false,
+    };
+    allowed.then_some(url)
+}
+
+fn strip_css_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        // Komentarz rozdziela tokeny (`expr/**/ession`) - zostaje spacja
+        out.push(' ');
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Pozycja pierwszego znaku z `stops` poza cudzysłowem i nawiasami okrągłymi
+fn find_top_level(css: &str, stops: &[char]) -> Option<usize> {
+    let (mut quote, mut parens) = (None, 0);
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, _) if parens <= 0 && stops.contains(&c) => return Some(i),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => parens += 1,
+            (None, ')') => parens -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Deklaracje `właściwość: wartość; ...` bez niebezpiecznych wartości; bez `allow_urls` znikają też te z `url()`
+fn scrub_declarations(css: &str, allow_urls: bool, remote: &mut Option<RemoteBlocker>) -> String {
+    let mut kept = Vec::new();
+    let mut rest = css;
+    while !rest.trim().is_empty() {
+        let end = find_top_level(rest, &[';']).unwrap_or(rest.len());
+        let declaration = &rest[..end];
+        rest = rest.get(end + 1..).unwrap_or("");
+
+        let Some((property, value)) = declaration.split_once(':') else { continue };
+        let property = property.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let valid_name = !property.is_empty() && property.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid_name || matches!(property.as_str(), "behavior" | "-moz-binding") {
+            continue;
+        }
+        if let Some(value) = scrub_css_value(value, allow_urls, remote) {
+            kept.push(format!("{}: {}", property, value));
+        }
+    }
+    kept.join("; ")
+}
+
+/// Wartość deklaracji albo `None`, gdy jest niebezpieczna. Zablokowane zdalne `url()` zamienia na `none`.
+fn scrub_css_value(value: &str, allow_urls: bool, remote: &mut Option<RemoteBlocker>) -> Option<String> {
+    let lower = value.to_ascii_lowercase();
+    // Escape'y CSS (`\75rl(`) pozwalają ukryć każdy z poniższych wzorców
+    if ["\\", "<", "expression(", "javascript:", "vbscript:", "-moz-binding", "@import"]
+        .iter()
+        .any(|p| lower.contains(p))
+    {
+        return None;
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut pos = 0;
+    while let Some(start) = lower[pos..].find("url(").map(|i| i + pos) {
+        if !allow_urls {
+            return None;
+        }
+        let end = start + 4 + find_top_level(&lower[start + 4..], &[')'])?;
+        let target = value[start + 4..end].trim().trim_matches(|c| c == '"' || c == '\'');
+        let url = safe_url(target, UrlKind::Image)?;
+        out.push_str(&value[pos..start]);
+        if remote.as_mut().is_none_or(|r| r.allow(&url, "css", false)) {
+            out.push_str(&value[start..=end]);
+        } else {
+            out.push_str("none");
+        }
+        pos = end + 1;
+    }
+    out.push_str(&value[pos..]);
+    Some(out)
+}
+
+/// Zawartość `<style>`: reguły z oczyszczonymi deklaracjami; `@import` i nieznane at-rules znikają
+fn scrub_stylesheet(css: &str, remote: &mut Option<RemoteBlocker>) -> String {
+    let mut out = String::with_capacity(css.len());
+    scrub_rules(&strip_css_comments(css), &mut out, remote);
+    // `</style` w treści zamknąłby element
+    out.replace('<', "")
+}
+
+fn scrub_rules(css: &str, out: &mut String, remote: &mut Option<RemoteBlocker>) {
+    let mut rest = css;
+    loop {
+        rest = rest.trim_start();
+        let Some(stop) = find_top_level(rest, &['{', ';']) else { return };
+        let prelude = rest[..stop].trim();
+        if rest[stop..].starts_with(';') {
+            // Samodzielne at-rules: @import, @charset, @namespace
+            rest = &rest[stop + 1..];
+            continue;
+        }
+        let Some(len) = block_len(&rest[stop..]) else { return };
+        let block = &rest[stop + 1..stop + len - 1];
+        rest = &rest[stop + len..];
+
+        let at_rule = prelude.strip_prefix('@').map(|r| {
+            r.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or("").to_ascii_lowercase()
+        });
+        match at_rule.as_deref() {
+            Some("media" | "supports" | "keyframes" | "-webkit-keyframes") => {
+                out.push_str(prelude);
+                out.push('{');
+                scrub_rules(block, out, remote);
+                out.push('}');
+            }
+            None | Some("font-face" | "page") if !prelude.contains('\\') => {
+                // Selektor atrybutu + url() to eksfiltracja przez CSS (żądanie tylko przy pasującej wartości)
+                let allow_urls = !prelude.contains('[');
+                out.push_str(prelude);
+                out.push('{');
+                out.push_str(&scrub_declarations(block, allow_urls, remote));
+                out.push('}');
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Długość bloku `{...}` od początku `s` (z klamrami), z zagnieżdżeniem
+fn block_len(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut rest = s;
+    loop {
+        let i = find_top_level(rest, &['{', '}'])?;
+        depth += if rest[i..].starts_with('{') { 1 } else { -1 };
+        rest = &rest[i + 1..];
+        if depth == 0 {
+            return Some(s.len() - rest.len());
+        }
+    }
+}
+
+/// Obrazek 1x1 (albo mniejszy) lub ukryty - typowy piksel śledzący
+fn is_pixel(attrs: &[(String, String)]) -> bool {
+    let attr = |name: &str| attrs.iter().find(|(a, _)| a == name).map(|(_, v)| v.as_str());
+    let style: String = attr("style").unwrap_or("").to_ascii_lowercase().split_whitespace().collect();
+    let size = |name: &str| {
+        let css = style.split(';').find_map(|d| d.strip_prefix(name)?.strip_prefix(':'));
+        attr(name).or(css).and_then(|v| {
+            let digits: String = v.trim().chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        })
+    };
+    let hidden = style.contains("display:none") || style.contains("visibility:hidden");
+    hidden || matches!((size("width"), size("height")), (Some(w), Some(h)) if w <= 1 && h <= 1)
+}
+
+/// Powód uznania zdalnego URL-a za śledzący: domena z listy albo identyfikator w zapytaniu
+fn tracking_reason(url: &str) -> Option<&'static str> {
+    let (_, rest) = url.split_once("://")?;
+    let host_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..host_end];
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+    if TRACKER_DOMAINS.iter().any(|d| host == *d || host.ends_with(&format!(".{}", d))) {
+        return Some("tracker-domain");
+    }
+    let query = rest[host_end..].split('#').next()?.split_once('?')?.1;
+    query
+        .split('&')
+        .map(|pair| pair.split_once('=').map_or(pair, |(_, value)| value))
+        .any(looks_unique)
+        .then_some("unique-query")
+}
+
+/// Identyfikator odbiorcy w parametrze: długi ciąg liter i cyfr (hex, base64, UUID)
+fn looks_unique(value: &str) -> bool {
+    value.len() >= 16
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_=%.".contains(c))
+        && value.chars().any(|c| c.is_ascii_digit())
+        && value.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wynik nie może zawierać niczego, co przeglądarka wykona
+    fn assert_inert(input: &str) -> String {
+        let out = sanitize_html(input);
+        let lower = out.to_ascii_lowercase();
+        for needle in [
+            "javascript:", "vbscript:", "data:text", "<script", "<svg", "<math", " on", "expression(", "@import",
+        ] {
+            assert!(!lower.contains(needle), "{:?} w {:?} (wejście {:?})", needle, out, input);
+        }
+        out
+    }
+
+    #[test]
+    fn script_urls_are_dropped_despite_obfuscation() {
+        for href in [
+            "javascript:alert(1)",
+            "JaVaScRiPt:alert(1)",
+            " \u{1}javascript:alert(1)",
+            "&#106;avascript:alert(1)",
+            "&#x6A;&#x61;vascript:alert(1)",
+            "jav&#x09;ascript:alert(1)",
+            "jav&#10;ascript:alert(1)",
+            "java\nscript:alert(1)",
+            "&#x20;&#x0D;javascript:alert(1)",
+            "javascript&colon;alert(1)",
+            "vbscript:msgbox(1)",
+            "VBScript&#58;msgbox(1)",
+            "data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==",
+            "data:text/html,<script>alert(1)</script>",
+            " DATA:text/html,x",
+        ] {
+            for quoted in [format!("\"{}\"", href), format!("'{}'", href)] {
+                let out = assert_inert(&format!("<a href={}>x</a>", quoted));
+                assert_eq!(out, "<a>x</a>", "href {:?}", href);
+                let out = assert_inert(&format!("<img src={}>", quoted));
+                assert_eq!(out, "<img>", "src {:?}", href);
+            }
+        }
+    }
+
+    #[test]
+    fn safe_urls_survive() {
+        assert_eq!(
+            sanitize_html("<a href=\"https://example.com/?a=1&amp;b=2\">x</a>"),
+            "<a href=\"https://example.com/?a=1&amp;b=2\" target=\"_blank\" rel=\"noopener noreferrer\">x</a>"
+        );
+        assert!(sanitize_html("<a href='#sekcja'>x</a>").starts_with("<a href=\"#sekcja\""));
+        assert_eq!(sanitize_html("<img src=\"cid:logo@x\">"), "<img src=\"cid:logo@x\">");
+        let png = "<img src=\"data:image/png;base64,iVBORw0K\">";
+        assert_eq!(sanitize_html(png), png);
+        // SVG w data: może zawierać skrypt
+        assert_eq!(assert_inert("<img src=\"data:image/svg+xml;base64,PHN2Zz4=\">"), "<img>");
+    }
+
+    #[test]
+    fn event_handlers_and_unknown_tags_are_dropped() {
+        assert_eq!(
+            assert_inert("<img src=\"https://x.pl/a.png\" onerror=\"alert(1)\" ONLOAD=alert(1)>"),
+            "<img src=\"https://x.pl/a.png\">"
+        );
+        assert_eq!(assert_inert("<p/onclick=alert(1)>x</p>"), "<p>x</p>");
+        assert_eq!(assert_inert("<script>alert(1)</script>tekst"), "tekst");
+        assert_eq!(assert_inert("<SCRIPT SRC=//x.pl/a.js></SCRIPT>"), "");
+        assert_eq!(assert_inert("<iframe src=\"javascript:alert(1)\"></iframe>"), "");
+        assert_eq!(assert_inert("<body onload=alert(1)><b>x</b></body>"), "<b>x</b>");
+        assert_eq!(assert_inert("<form action=\"https://x.pl\"><input name=p></form>po"), "po");
+    }
+
+    #[test]
+    fn svg_and_math_vanish_with_content() {
+        assert_eq!(assert_inert("<svg onload=alert(1)><script>alert(1)</script></svg>po"), "po");
+        assert_eq!(
+            assert_inert("<svg><a xlink:href=\"javascript:alert(1)\"><text>x</text></a><svg></svg></svg>po"),
+            "po"
+        );
+        assert_eq!(assert_inert("<SVG/onload=alert(1)>"), "");
+        // Klasyczny mXSS: <style> w MathML parsowany inaczej niż w HTML
+        assert_eq!(
+            assert_inert("<math><mtext><table><mglyph><style><img src=x onerror=alert(1)></style></math>po"),
+            "po"
+        );
+        assert_eq!(assert_inert("<math href=\"javascript:alert(1)\">x</math>"), "");
+    }
+
+    #[test]
+    fn unclosed_markup() {
+        assert_eq!(assert_inert("<b>pogrubione<i>i kursywa"), "<b>pogrubione<i>i kursywa</i></b>");
+        assert_eq!(assert_inert("przed<img src=x onerror=alert(1)"), "przed");
+        assert_eq!(assert_inert("przed<a href=\"javascript:alert(1)>x</a>"), "przed");
+        assert_eq!(assert_inert("<svg><p>x"), "");
+        assert_eq!(assert_inert("<script>alert(1)"), "");
+        assert_eq!(assert_inert("a <!-- <img src=x onerror=alert(1)> b"), "a ");
+        assert_eq!(assert_inert("a < b <3"), "a &lt; b &lt;3");
+        assert_eq!(assert_inert("</p></div>tekst</b>"), "tekst");
+    }
+
+    #[test]
+    fn dangerous_css_is_removed() {
+        assert_eq!(
+            assert_inert("<div style=\"width: expression(alert(1)); color: red\">x</div>"),
+            "<div style=\"color: red\">x</div>"
+        );
+        assert_eq!(assert_inert("<div style=\"width: EXPRESSION(alert(1))\">x</div>"), "<div>x</div>");
+        assert_eq!(assert_inert("<div style=\"width: \\65xpression(alert(1))\">x</div>"), "<div>x</div>");
+        assert_eq!(
+            assert_inert("<div style=\"width: exp/**/ression(alert(1))\">x</div>"),
+            "<div style=\"width: exp ression(alert(1))\">x</div>"
+        );
+        assert_eq!(
+            assert_inert("<div style=\"background: url(javascript:alert(1))\">x</div>"),
+            "<div>x</div>"
+        );
+        assert_eq!(
+            assert_inert("<div style=\"background: url( 'jav&#x09;ascript:alert(1)' )\">x</div>"),
+            "<div>x</div>"
+        );
+        assert_eq!(
+            assert_inert("<div style=\"background-image: url(data:text/html,x)\">x</div>"),
+            "<div>x</div>"
+        );
+        assert_eq!(
+            assert_inert("<div style=\"behavior: url(x.htc); -moz-binding: url(x.xml#x)\">x</div>"),
+            "<div>x</div>"
+        );
+        assert_eq!(
+            sanitize_html("<div style=\"background: url('https://x.pl/t.png') no-repeat\">x</div>"),
+            "<div style=\"background: url('https://x.pl/t.png') no-repeat\">x</div>"
+        );
+    }
+
+    #[test]
+    fn stylesheets_lose_imports_and_unsafe_rules() {
+        assert_eq!(
+            assert_inert("<style>@import url(https://x.pl/a.css); @IMPORT 'b.css'; p { color: red }</style>"),
+            "<style>p{color: red}</style>"
+        );
+        assert_eq!(
+            assert_inert("<style>@media screen { p { width: expression(alert(1)); margin: 0 } }</style>"),
+            "<style>@media screen{p{margin: 0}}</style>"
+        );
+        assert_eq!(
+            assert_inert("<style>@\\69mport 'a.css'; p{color:red}</style>"),
+            "<style>p{color: red}</style>"
+        );
+        // Selektor atrybutu z url() wysyłałby wartość pola na zewnątrz
+        assert_eq!(
+            assert_inert("<style>input[value^=a] { background: url(https://x.pl/?a) }</style>"),
+            "<style>input[value^=a]{}</style>"
+        );
+    }
+
+    #[test]
+    fn raw_text_ends_only_at_matching_close_tag() {
+        // `</styles>` nie zamyka <style>, więc img zostaje częścią CSS (i znika)
+        assert_eq!(
+            assert_inert("<style>p{color:red}</styles><img src=x onerror=alert(1)></style><b>ok</b>"),
+            "<style>p{color: red}</style><b>ok</b>"
+        );
+        assert_eq!(assert_inert("<style>p{color:red}</STYLE ><b>ok</b>"), "<style>p{color: red}</style><b>ok</b>");
+        assert_eq!(assert_inert("<style>p{color:red}</style/><b>ok</b>"), "<style>p{color: red}</style><b>ok</b>");
+        assert_eq!(assert_inert("<title></titles><img src=x onerror=alert(1)></title>po"), "po");
+        assert_eq!(assert_inert("<textarea><script>alert(1)</script></textarea>po"), "po");
+        assert_eq!(assert_inert("<style>p{color:red}"), "<style>p{color: red}</style>");
+    }
+
+    /// (url, rodzaj, powód śledzenia, zablokowany) dla każdego znalezionego zasobu
+    fn remote(html: &str, load_images: bool) -> (String, Vec<(String, String, Option<String>, bool)>) {
+        let (out, found) = block_remote_content(html, load_images);
+        (out, found.into_iter().map(|r| (r.url, r.kind, r.tracking, r.blocked)).collect())
+    }
+
+    fn resource(url: &str, kind: &str, tracking: Option<&str>, blocked: bool) -> (String, String, Option<String>, bool) {
+        (url.to_string(), kind.to_string(), tracking.map(str::to_string), blocked)
+    }
+
+    #[test]
+    fn remote_content_is_blocked_by_default() {
+        let html = "<img src=\"https://cdn.x.pl/logo.png\" alt=\"logo\"><table><tr>\
+                    <td background=\"http://x.pl/tlo.jpg\">\
+                    <div style=\"color: red; background: url('https://x.pl/a.png') no-repeat\">x</div>\
+                    </td></tr></table><style>p { background-image: url(https://x.pl/b.png) }</style>";
+        let (out, found) = remote(html, false);
+        assert_eq!(
+            out,
+            "<img alt=\"logo\" data-remote=\"blocked\"><table><tr><td data-remote=\"blocked\">\
+             <div style=\"color: red; background: none no-repeat\">x</div></td></tr></table>\
+             <style>p{background-image: none}</style>"
+        );
+        assert_eq!(
+            found,
+            vec![
+                resource("https://cdn.x.pl/logo.png", "image", None, true),
+                resource("http://x.pl/tlo.jpg", "background", None, true),
+                resource("https://x.pl/a.png", "css", None, true),
+                resource("https://x.pl/b.png", "css", None, true),
+            ]
+        );
+
+        // Za zgodą wszystko zostaje, raport nadal jest
+        let (out, found) = remote(html, true);
+        assert_eq!(out, sanitize_html(html));
+        assert!(found.iter().all(|(_, _, _, blocked)| !blocked));
+        assert_eq!(found.len(), 4);
+    }
+
+    #[test]
+    fn pixels_are_dropped_even_with_images_loaded() {
+        for pixel in [
+            "<img src=\"https://shop.pl/open.gif\" width=\"1\" height=\"1\">",
+            "<img src=\"https://shop.pl/open.gif\" width=\"0\" height=\"0\" alt=\"\">",
+            "<img src=\"https://shop.pl/open.gif\" style=\"width: 1px; height: 1px\">",
+            "<img src=\"https://shop.pl/open.gif\" style=\"display: none\">",
+            "<img style=\"visibility:hidden\" src=\"https://shop.pl/open.gif\">",
+        ] {
+            for load_images in [false, true] {
+                let (out, found) = remote(&format!("a{}b", pixel), load_images);
+                assert_eq!(out, "ab", "{}", pixel);
+                assert_eq!(found, vec![resource("https://shop.pl/open.gif", "image", Some("pixel"), true)]);
+            }
+        }
+        // 2x1 to już nie piksel
+        let (out, _) = remote("<img src=\"https://shop.pl/a.gif\" width=\"2\" height=\"1\">", true);
+        assert_eq!(out, "<img src=\"https://shop.pl/a.gif\" width=\"2\" height=\"1\">");
+    }
+
+    #[test]
+    fn tracking_urls_are_flagged() {
+        let html = "<img src=\"https://mailtrack.io/trace/mail/1.png\">\
+                    <img src=\"https://a.b.list-manage.com/track/open.php\">\
+                    <img src=\"https://notmailtrack.io/a.png\">\
+                    <img src=\"https://shop.pl/banner.png?u=a1b2c3d4e5f6a7b8c9d0&w=600\">\
+                    <img src=\"https://shop.pl/banner.png?w=600&lang=pl\">";
+        let (out, found) = remote(html, true);
+        assert_eq!(
+            found,
+            vec![
+                // Znana domena blokowana zawsze, podejrzane zapytanie tylko raportowane
+                resource("https://mailtrack.io/trace/mail/1.png", "image", Some("tracker-domain"), true),
+                resource("https://a.b.list-manage.com/track/open.php", "image", Some("tracker-domain"), true),
+                resource("https://notmailtrack.io/a.png", "image", None, false),
+                resource("https://shop.pl/banner.png?u=a1b2c3d4e5f6a7b8c9d0&w=600", "image", Some("unique-query"), false),
+                resource("https://shop.pl/banner.png?w=600&lang=pl", "image", None, false),
+            ]
+        );
+        assert!(out.starts_with("<img data-remote=\"blocked\"><img data-remote=\"blocked\"><img src="));
+    }
+
+    #[test]
+    fn embedded_images_are_untouched() {
+        let html = "<img src=\"cid:logo@firma.pl\"><img src=\"data:image/png;base64,iVBORw0K\" width=\"1\" height=\"1\">\
+                    <div style=\"background: url(cid:tlo)\">x</div>";
+        for load_images in [false, true] {
+            let (out, found) = remote(html, load_images);
+            assert_eq!(out, sanitize_html(html));
+            assert!(found.is_empty());
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use chrono::NaiveDate;
+
+/// Zapytanie w składni Gmaila (`from:`, `to:`, `subject:`, `label:`,
+/// `has:attachment`, `is:unread`, `before:`/`after:`, frazy w cudzysłowie, `-zaprzeczenie`).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub from: Vec<String>,
+    pub to: Vec<String>,
+    pub subject: Vec<String>,
+    pub labels: Vec<String>,
+    pub has_attachment: bool,
+    pub unread: Option<bool>,
+    /// Górna granica (wyłącznie), ms - początek dnia z `before:`
+    pub before: Option<i64>,
+    /// Dolna granica (włącznie), ms - początek dnia z `after:`
+    pub after: Option<i64>,
+    /// Zaprzeczone słowa i operatory (`-foo`, `-from:x`, `-label:y`, `-has:attachment`);
+    /// wiadomość pasująca do któregokolwiek odpada
+    pub exclude: Option<Box<SearchQuery>>,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = SearchQuery::default();
+
+        for token in tokenize(input) {
+            if !token.negated {
+                query.push(token);
+                continue;
+            }
+
+            // Zaprzeczenia flag i dat da się wyrazić wprost, reszta trafia do `exclude`
+            match token.key.as_deref().map(str::to_ascii_lowercase).as_deref() {
+                Some("is") if token.value.eq_ignore_ascii_case("unread") => query.unread = Some(false),
+                Some("is") if token.value.eq_ignore_ascii_case("read") => query.unread = Some(true),
+                Some("before") if parse_date_ms(&token.value).is_some() => query.after = parse_date_ms(&token.value),
+                Some("after") if parse_date_ms(&token.value).is_some() => query.before = parse_date_ms(&token.value),
+                _ => query.exclude.get_or_insert_with(Default::default).push(token),
+            }
+        }
+
+        query
+    }
+
+    fn push(&mut self, token: Token) {
+        let Token { key, value, quoted, .. } = token;
+        let Some(key) = key else {
+            self.terms.push(value);
+            return;
+        };
+
+        match key.to_ascii_lowercase().as_str() {
+            "from" => self.from.push(value),
+            "to" => self.to.push(value),
+            "subject" => self.subject.push(value),
+            "label" | "in" => self.labels.push(value),
+            "has" if value.eq_ignore_ascii_case("attachment") => self.has_attachment = true,
+            "is" if value.eq_ignore_ascii_case("unread") => self.unread = Some(true),
+            "is" if value.eq_ignore_ascii_case("read") => self.unread = Some(false),
+            "is" if value.eq_ignore_ascii_case("starred") => self.labels.push("STARRED".to_string()),
+            "is" if value.eq_ignore_ascii_case("important") => self.labels.push("IMPORTANT".to_string()),
+            "before" if parse_date_ms(&value).is_some() => self.before = parse_date_ms(&value),
+            "after" if parse_date_ms(&value).is_some() => self.after = parse_date_ms(&value),
+            _ => {
+                // Nieznany operator (np. "http://...") - traktuj jako zwykły tekst
+                let raw = if quoted { format!("{}:\"{}\"", key, value) } else { format!("{}:{}", key, value) };
+                self.terms.push(raw);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SearchQuery::default()
+    }
+
+    /// Wyrażenie FTS5 MATCH albo None, gdy zapytanie ma same filtry
+    pub fn fts_match(&self) -> Option<String> {
+        let clauses = self.fts_clauses();
+        if clauses.is_empty() {
+            None
+        } else {
+            Some(clauses.join(" AND "))
+        }
+    }
+
+    /// Wyrażenie MATCH zaprzeczonych słów - pasująca wiadomość odpada.
+    /// Osobno, bo FTS5 `NOT` wymaga dodatniej lewej strony.
+    pub fn fts_exclude(&self) -> Option<String> {
+        let clauses = self.exclude.as_ref()?.fts_clauses();
+        if clauses.is_empty() {
+            None
+        } else {
+            Some(clauses.join(" OR "))
+        }
+    }
+
+    /// Etykiety z `-label:` / `-in:`
+    pub fn excluded_labels(&self) -> &[String] {
+        self.exclude.as_ref().map(|e| e.labels.as_slice()).unwrap_or(&[])
+    }
+
+    /// `-has:attachment`
+    pub fn excludes_attachment(&self) -> bool {
+        self.exclude.as_ref().is_some_and(|e| e.has_attachment)
+    }
+
+    fn fts_clauses(&self) -> Vec<String> {
+        let mut clauses: Vec<String> = Vec::new();
+
+        clauses.extend(self.terms.iter().map(|t| fts_phrase(t)));
+        clauses.extend(self.from.iter().map(|t| format!("sender : {}", fts_phrase(t))));
+        clauses.extend(self.to.iter().map(|t| format!("recipients : {}", fts_phrase(t))));
+        clauses.extend(self.subject.iter().map(|t| format!("subject : {}", fts_phrase(t))));
+        clauses
+    }
+}
+
+/// Słowo użytkownika jako napis FTS5, żeby operatory w środku nie były interpretowane
+fn fts_phrase(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+/// Przyjmuje gmailowe `YYYY/MM/DD` i `YYYY-MM-DD`
+fn parse_date_ms(value: &str) -> Option<i64> {
+    let date = NaiveDate::parse_from_str(value, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp_millis())
+}
+
+struct Token {
+    key: Option<String>,
+    value: String,
+    quoted: bool,
+    negated: bool,
+}
+
+/// Podział zapytania na tokeny operator/wartość:
+/// `from:"Jan Kowalski"` -> (from, "Jan Kowalski", quoted), `"foo bar"` -> (-, "foo bar", quoted),
+/// `-label:spam` -> (label, "spam", negated)
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        // "-" na początku słowa to zaprzeczenie; samotny "-" zostaje zwykłym tekstem
+        let mut negated = false;
+        if c == '-' {
+            chars.next();
+            match chars.peek() {
+                Some(&n) if !n.is_whitespace() && n != '-' => negated = true,
+                _ => {
+                    tokens.push(Token { key: None, value: "-".to_string(), quoted: false, negated: false });
+                    continue;
+                }
+            }
+        }
+
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let phrase = read_quoted(&mut chars);
+            if !phrase.trim().is_empty() {
+                tokens.push(Token { key: None, value: phrase, quoted: true, negated });
+            }
+            continue;
+        }
+
+        let mut word = String::new();
+        let mut key: Option<String> = None;
+        let mut quoted = false;
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            if c == ':' && key.is_none() && !word.is_empty() {
+                chars.next();
+                key = Some(std::mem::take(&mut word));
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    word = read_quoted(&mut chars);
+                    quoted = true;
+                    break;
+                }
+                continue;
+            }
+            word.push(c);
+            chars.next();
+        }
+
+        match key {
+            Some(k) if !word.is_empty() => tokens.push(Token { key: Some(k), value: word, quoted, negated }),
+            // "from:" bez wartości - zostaw jako zwykły tekst
+            Some(k) => tokens.push(Token { key: None, value: k, quoted: false, negated }),
+            None if !word.is_empty() => tokens.push(Token { key: None, value: word, quoted: false, negated }),
+            None => {}
+        }
+    }
+
+    tokens
+}
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut out = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            break;
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_phrase_is_one_term() {
+        let q = SearchQuery::parse(r#"faktura "umowa najmu" 2024"#);
+        assert_eq!(q.terms, vec!["faktura", "umowa najmu", "2024"]);
+        assert_eq!(q.fts_match().unwrap(), r#""faktura" AND "umowa najmu" AND "2024""#);
+    }
+
+    #[test]
+    fn quoted_operator_value() {
+        let q = SearchQuery::parse(r#"from:"Jan Kowalski" subject:"re: oferta""#);
+        assert_eq!(q.from, vec!["Jan Kowalski"]);
+        assert_eq!(q.subject, vec!["re: oferta"]);
+        assert_eq!(q.fts_match().unwrap(), r#"sender : "Jan Kowalski" AND subject : "re: oferta""#);
+    }
+
+    #[test]
+    fn from_and_label_operators() {
+        let q = SearchQuery::parse("from:jan@example.com label:Work in:inbox to:ola");
+        assert_eq!(q.from, vec!["jan@example.com"]);
+        assert_eq!(q.to, vec!["ola"]);
+        assert_eq!(q.labels, vec!["Work", "inbox"]);
+        assert!(q.terms.is_empty());
+        assert_eq!(q.fts_match().unwrap(), r#"sender : "jan@example.com" AND recipients : "ola""#);
+    }
+
+    #[test]
+    fn filters_without_text_have_no_match_expression() {
+        let q = SearchQuery::parse("has:attachment is:unread label:INBOX after:2024/01/01 before:2024-02-01");
+        assert!(q.has_attachment);
+        assert_eq!(q.unread, Some(true));
+        assert_eq!(q.after, Some(1704067200000));
+        assert_eq!(q.before, Some(1706745600000));
+        assert_eq!(q.fts_match(), None);
+    }
+
+    #[test]
+    fn unknown_operator_stays_text() {
+        let q = SearchQuery::parse("https://example.com foo: cc:\"a b\"");
+        assert_eq!(q.terms, vec!["https://example.com", "foo", "cc:\"a b\""]);
+    }
+
+    #[test]
+    fn negated_terms_and_operators() {
+        let q = SearchQuery::parse(r#"raport -szkic -"wersja robocza" -from:bot@example.com -label:SPAM -has:attachment"#);
+        assert_eq!(q.terms, vec!["raport"]);
+        let ex = q.exclude.as_ref().unwrap();
+        assert_eq!(ex.terms, vec!["szkic", "wersja robocza"]);
+        assert_eq!(ex.from, vec!["bot@example.com"]);
+        assert_eq!(q.excluded_labels(), ["SPAM"]);
+        assert!(q.excludes_attachment());
+        assert!(!q.has_attachment);
+        assert_eq!(q.fts_match().unwrap(), r#""raport""#);
+        assert_eq!(
+            q.fts_exclude().unwrap(),
+            r#""szkic" OR "wersja robocza" OR sender : "bot@example.com""#
+        );
+    }
+
+    #[test]
+    fn negated_flags_and_dates_flip() {
+        let q = SearchQuery::parse("-is:unread -before:2024/01/01");
+        assert_eq!(q.unread, Some(false));
+        assert_eq!(q.after, Some(1704067200000));
+        assert!(q.exclude.is_none());
+
+        let q = SearchQuery::parse("-is:read -after:2024/01/01");
+        assert_eq!(q.unread, Some(true));
+        assert_eq!(q.before, Some(1704067200000));
+    }
+
+    #[test]
+    fn dash_inside_word_or_alone_is_text() {
+        let q = SearchQuery::parse("e-mail - x");
+        assert_eq!(q.terms, vec!["e-mail", "-", "x"]);
+        assert!(q.exclude.is_none());
+    }
+
+    #[test]
+    fn fts_special_characters_are_quoted() {
+        let q = SearchQuery::parse(r#"foo* NEAR(a b) ^start col:umn"#);
+        assert_eq!(
+            q.fts_match().unwrap(),
+            r#""foo*" AND "NEAR(a" AND "b)" AND "^start" AND "col:umn""#
+        );
+
+        // Cudzysłów w środku słowa jest podwajany, a nie kończy frazy FTS
+        assert_eq!(fts_phrase(r#"say "hi""#), r#""say ""hi""""#);
+        let q = SearchQuery::parse(r#"a"b AND OR"#);
+        assert_eq!(q.fts_match().unwrap(), r#""a""b" AND "AND" AND "OR""#);
+    }
+
+    #[test]
+    fn empty_query() {
+        assert!(SearchQuery::parse("   ").is_empty());
+        assert!(!SearchQuery::parse("-foo").is_empty());
+    }
+}
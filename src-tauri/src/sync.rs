@@ -1,84 +1,109 @@
 // Pełny plik sync.rs z internal_date w WSZYSTKICH miejscach
 
-use crate::cache::{Cache, CachedMessage};
-use crate::client::GmailClient;
+use crate::attachments::{self, AttachmentFile};
+use crate::auth::TokenStore;
+use crate::cache::{Cache, CachedMessage, LocalDraft, PageKey, PendingOp, StoredAttachment, ThreadRow, OP_PENDING};
+use crate::client::{is_connect_error, is_network_error, GmailClient, SIMPLE_UPLOAD_LIMIT};
+use crate::config::ApiConfig;
+use crate::mime::{Attachment, OutgoingMessage};
+use crate::outbox::{self, MailOp};
+use crate::reply::{self, ReplyKind};
 use crate::types::*;
-use crate::parser::parse_email_message;
-use anyhow::Result;
+use crate::parser::{email_data_from_message, parse_email_message, thread_from_cached};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::{RwLock, Semaphore};
+use tokio::sync::{broadcast, Notify, RwLock, RwLockReadGuard, Semaphore};
 use tokio::task::JoinHandle;
-use reqwest::Client as HttpClient;
-use reqwest::StatusCode;
-use std::time::Instant;
-use futures::stream::{self, StreamExt};
-
-#[derive(Clone)]
-pub struct TokenStore {
-    pub token: Arc<RwLock<Option<(String, Instant)>>>,
-    pub http: HttpClient,
+use serde::{Serialize, Deserialize};
+
+/// Etykiety pobierane przy synchronizacji początkowej i pełnym resyncu
+const SYNC_LABELS: &[&str] = &[
+    "INBOX", "SENT", "DRAFT", "STARRED", "TRASH", "SPAM",
+    "CATEGORY_PERSONAL", "CATEGORY_SOCIAL", "CATEGORY_PROMOTIONS",
+    "CATEGORY_UPDATES", "CATEGORY_FORUMS"
+];
+
+/// Limity synchronizacji (na etykietę). `None` = bez limitu.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncLimits {
+    #[serde(rename = "maxMessagesPerLabel")]
+    pub max_messages_per_label: Option<usize>,
+    /// Tylko wiadomości z ostatnich N dni (Gmail `newer_than:Nd`)
+    #[serde(rename = "newerThanDays")]
+    pub newer_than_days: Option<u32>,
 }
 
-impl TokenStore {
-    pub fn new() -> Self {
+impl Default for SyncLimits {
+    fn default() -> Self {
         Self {
-            token: Arc::new(RwLock::new(None)),
-            http: HttpClient::new(),
+            max_messages_per_label: Some(1000),
+            newer_than_days: None,
         }
     }
+}
 
-    pub async fn get_token(&self) -> Result<Option<String>> {
-        {
-            let guard = self.token.read().await;
-            if let Some((tok, instant)) = &*guard {
-                if instant.elapsed().as_secs() < 50 * 60 {
-                    return Ok(Some(tok.clone()));
-                }
-            }
-        }
-        let resp = self.http.get("http://localhost:3001/auth/token").send().await?;
-        if resp.status().is_success() {
-            let v: serde_json::Value = resp.json().await?;
-            if let Some(at) = v.get("accessToken").and_then(|s| s.as_str()) {
-                let mut guard = self.token.write().await;
-                *guard = Some((at.to_string(), Instant::now()));
-                return Ok(Some(at.to_string()));
-            }
-        }
-        Ok(None)
-    }
+/// Co kolejka sprawdza, czy wróciła sieć, gdy nie ma jeszcze klienta
+const OUTBOX_OFFLINE_RECHECK: std::time::Duration = std::time::Duration::from_secs(30);
 
-    pub async fn set_token(&self, token: String) {
-        let mut guard = self.token.write().await;
-        *guard = Some((token, Instant::now()));
-    }
+/// Wynik wysyłki: `id` z Gmaila albo numer zmiany w kolejce, gdy nie było sieci
+#[derive(Debug, Clone, Serialize)]
+pub struct SendOutcome {
+    pub id: Option<String>,
+    #[serde(rename = "queuedOpId")]
+    pub queued_op_id: Option<i64>,
+}
+
+/// Postęp wysyłki (zdarzenie `send-progress`): bajty wiadomości przyjęte przez Gmaila
+#[derive(Debug, Clone, Serialize)]
+pub struct SendProgress {
+    pub sent: u64,
+    pub total: u64,
 }
 
 pub struct SyncManager {
     pub cache: Arc<Cache>,
     pub client: Arc<RwLock<Option<GmailClient>>>,
     pub token_store: TokenStore,
+    pub config: ApiConfig,
+    pub limits: SyncLimits,
     pub bg_handle: Arc<RwLock<Option<JoinHandle<()>>>>,
+    pub outbox_handle: Arc<RwLock<Option<JoinHandle<()>>>>,
+    pub outbox_notify: Arc<Notify>,
+    /// Operacje, które kolejka odrzuciła albo porzuciła - dla UI
+    pub outbox_failures: broadcast::Sender<PendingOp>,
     pub prefetch_sem: Arc<Semaphore>,
 }
 
 impl SyncManager {
-    pub async fn new(cache: Cache) -> Result<Self> {
-        let token_store = TokenStore::new();
+    pub async fn new(cache: Cache, config: ApiConfig, token_store: TokenStore) -> Result<Self> {
         let client = Arc::new(RwLock::new(None));
         let mgr = Self {
             cache: Arc::new(cache),
             client,
             token_store,
+            config,
+            limits: SyncLimits::default(),
             bg_handle: Arc::new(RwLock::new(None)),
+            outbox_handle: Arc::new(RwLock::new(None)),
+            outbox_notify: Arc::new(Notify::new()),
+            outbox_failures: broadcast::channel(32).0,
             prefetch_sem: Arc::new(Semaphore::new(4)),
         };
         Ok(mgr)
     }
 
+    pub fn with_limits(mut self, limits: SyncLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     pub async fn init_client_from_store(&self) -> Result<()> {
-        if let Some(tok) = self.token_store.get_token().await? {
-            let g = GmailClient::new(tok);
+        // Klient trzyma klon TokenStore - odświeżony token widzą wszyscy jego użytkownicy
+        if self.token_store.get_token().await?.is_some() {
+            let g = GmailClient::new(self.token_store.clone(), &self.config);
             let mut guard = self.client.write().await;
             *guard = Some(g);
             Ok(())
@@ -97,86 +122,65 @@ impl SyncManager {
         let client = client_guard.as_ref()
             .ok_or_else(|| anyhow::anyhow!("Gmail client not initialized"))?;
 
-        if let Ok(hid) = client.get_history_id().await {
-            let _ = self.cache.set_meta("last_history_id", &hid);
-            eprintln!("📝 Stored initial historyId: {}", hid);
-        }
+        // Przerwana synchronizacja (zamknięcie aplikacji, brak sieci) - wznów zamiast czyścić cache
+        let resuming = self.cache.get_meta("initial_sync_state")?.as_deref() == Some("running");
+        if resuming {
+            eprintln!("⏯️  Resuming interrupted initial sync...");
+        } else {
+            match client.get_history_id().await {
+                Ok(hid) => {
+                    let _ = self.cache.set_meta("last_history_id", &hid);
+                    eprintln!("📝 Stored initial historyId: {}", hid);
+                }
+                // Bez sieci nie czyść cache - to jedyne, co użytkownik ma offline
+                Err(e) if is_network_error(&e) => return Err(e),
+                Err(_) => {}
+            }
 
-        eprintln!("🗑️  Clearing old cache before initial sync...");
-        self.cache.clear_all_messages()?;
+            eprintln!("🗑️  Clearing old cache before initial sync...");
+            self.cache.clear_all_messages()?;
+            self.cache.set_meta("initial_sync_state", "running")?;
+        }
 
-        let labels_to_sync = vec![
-            "INBOX", "SENT", "DRAFT", "STARRED", "TRASH", "SPAM",
-            "CATEGORY_PERSONAL", "CATEGORY_SOCIAL", "CATEGORY_PROMOTIONS",
-            "CATEGORY_UPDATES", "CATEGORY_FORUMS"
-        ];
-        
         let mut total_synced = 0;
-        let mut seen_ids = std::collections::HashSet::new();
-        
-        for label in labels_to_sync {
+        let mut complete = true;
+
+        for label in SYNC_LABELS {
+            let done_key = format!("initial_sync:done:{}", label);
+            if self.cache.get_meta(&done_key)?.is_some() {
+                continue;
+            }
+
             eprintln!("🔄 Syncing label: {}", label);
-            
-            match client.get_messages_metadata(max_results, label, None).await {
-                Ok(list) => {
-                    let refs = list.messages.unwrap_or_default();
-                    eprintln!("📥 Found {} messages in {}", refs.len(), label);
-                    
-                    let messages_fut = stream::iter(refs.into_iter())
-                        .map(|msg_ref| {
-                            let cli = client;
-                            async move {
-                                match cli.get_email_full(&msg_ref.id).await {
-                                    Ok(full) => Some(full),
-                                    Err(e) => {
-                                        eprintln!("Initial sync: error fetching message {}: {}", msg_ref.id, e);
-                                        None
-                                    }
-                                }
-                            }
-                        })
-                        .buffer_unordered(8);
-
-                    tokio::pin!(messages_fut);
-
-                    while let Some(opt_msg) = messages_fut.next().await {
-                        if let Some(full) = opt_msg {
-                            if seen_ids.contains(&full.id) {
-                                continue;
-                            }
-                            seen_ids.insert(full.id.clone());
-                            
-                            let internal_date = full.internal_date
-                                .and_then(|s| s.parse::<i64>().ok())
-                                .unwrap_or(0);
-                            
-                            let headers_json = serde_json::to_string(&full.payload.headers).unwrap_or_default();
-                            let labels_json = serde_json::to_string(&full.label_ids).unwrap_or_default();
-                            
-                            let cached = CachedMessage {
-                                message_id: full.id.clone(),
-                                thread_id: full.thread_id.clone(),
-                                headers_json,
-                                label_ids_json: labels_json,
-                                snippet: full.snippet.clone(),
-                                internal_date,
-                                synced_history_id: None,
-                            };
-                            
-                            if let Err(e) = self.cache.upsert_message(&cached) {
-                                eprintln!("Initial sync: failed to upsert message {}: {}", full.id, e);
-                            } else {
-                                total_synced += 1;
-                            }
-                        }
-                    }
+            match sync_label(&self.cache, client, label, max_results, &self.limits, "initial_sync", true).await {
+                Ok(n) => {
+                    eprintln!("📥 Cached {} new messages from {}", n, label);
+                    total_synced += n;
+                    self.cache.set_meta(&done_key, "1")?;
                 }
                 Err(e) => {
+                    complete = false;
                     eprintln!("⚠️ Failed to sync label {}: {}", label, e);
                 }
             }
         }
 
+        if !complete {
+            eprintln!("⚠️ Initial sync incomplete ({} messages cached), will resume on next start", total_synced);
+            return Ok(());
+        }
+
+        for label in SYNC_LABELS {
+            self.cache.delete_meta(&format!("initial_sync:done:{}", label))?;
+        }
+        self.cache.delete_meta("initial_sync_state")?;
+
+        match self.cache.prune_orphan_bodies() {
+            Ok(n) if n > 0 => eprintln!("🗑️  Pruned {} cached bodies of removed messages", n),
+            Ok(_) => {}
+            Err(e) => eprintln!("⚠️ Failed to prune cached bodies: {}", e),
+        }
+
         eprintln!("✅ Initial sync complete: {} unique messages cached", total_synced);
         Ok(())
     }
@@ -185,328 +189,886 @@ impl SyncManager {
         let cache = Arc::clone(&self.cache);
         let client_lock = Arc::clone(&self.client);
         let token_store = self.token_store.clone();
+        let config = self.config.clone();
+        let limits = self.limits.clone();
+        let outbox_notify = Arc::clone(&self.outbox_notify);
 
         let handle = tokio::spawn(async move {
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
 
-                if client_lock.read().await.is_none() {
-                    if let Ok(Some(tok)) = token_store.get_token().await {
-                        let new_client = GmailClient::new(tok);
-                        *client_lock.write().await = Some(new_client);
-                    } else {
-                        continue;
-                    }
+                if !ensure_client(&client_lock, &token_store, &config).await {
+                    continue;
                 }
 
                 if let Some(ref client) = *client_lock.read().await {
-                    let last_history_opt = match cache.get_meta("last_history_id") {
-                        Ok(Some(s)) if !s.trim().is_empty() => {
-                            match s.trim().parse::<i64>() {
-                                Ok(v) => Some(v),
-                                Err(_) => {
-                                    let _ = cache.delete_meta("last_history_id");
-                                    None
-                                }
-                            }
-                        }
-                        _ => None,
-                    };
-
-                    if last_history_opt.is_none() {
-                        eprintln!("⚠️ No valid last_history_id, skipping history sync");
-                        continue;
-                    }
-
-                    let start_h = last_history_opt.unwrap();
-                    let url = format!("{}/users/me/history", crate::client::GMAIL_API_BASE);
-                    let http = reqwest::Client::new();
-                    let req = http.get(&url)
-                        .bearer_auth(client.access_token.as_str())
-                        .query(&[("startHistoryId", start_h.to_string())]);
-
-                    match req.send().await {
-                        Ok(resp) => {
-                            if resp.status() == StatusCode::BAD_REQUEST {
-                                eprintln!("⚠️ History API returned 400 - historyId expired, doing full resync");
-                                
-                                let _ = cache.delete_meta("last_history_id");
-                                
-                                let labels_to_resync = vec![
-                                    "INBOX", "SENT", "DRAFT", "STARRED", "TRASH", "SPAM",
-                                    "CATEGORY_SOCIAL", "CATEGORY_PROMOTIONS", "CATEGORY_UPDATES",
-                                    "CATEGORY_FORUMS", "CATEGORY_PERSONAL"
-                                ];
-                                
-                                for label in labels_to_resync {
-                                    if let Ok(list) = client.get_messages_metadata(200, label, None).await {
-                                        let refs = list.messages.unwrap_or_default();
-                                        let fetches = stream::iter(refs.into_iter())
-                                            .map(|msg_ref| {
-                                                let cli = client;
-                                                async move {
-                                                    match cli.get_email_full(&msg_ref.id).await {
-                                                        Ok(full) => Some(full),
-                                                        Err(e) => {
-                                                            eprintln!("Resync error for {}: {}", msg_ref.id, e);
-                                                            None
-                                                        }
-                                                    }
-                                                }
-                                            })
-                                            .buffer_unordered(8);
-
-                                        tokio::pin!(fetches);
-                                        while let Some(opt_full) = fetches.next().await {
-                                            if let Some(full) = opt_full {
-                                                // ✅ MIEJSCE 1: resync
-                                                let internal_date = full.internal_date
-                                                    .and_then(|s| s.parse::<i64>().ok())
-                                                    .unwrap_or(0);
-                                                
-                                                let headers_json = serde_json::to_string(&full.payload.headers).unwrap_or_default();
-                                                let labels_json = serde_json::to_string(&full.label_ids).unwrap_or_default();
-                                                let cached = CachedMessage {
-                                                    message_id: full.id.clone(),
-                                                    thread_id: full.thread_id.clone(),
-                                                    headers_json,
-                                                    label_ids_json: labels_json,
-                                                    snippet: full.snippet.clone(),
-                                                    internal_date,
-                                                    synced_history_id: None,
-                                                };
-                                                let _ = cache.upsert_message(&cached);
-                                            }
-                                        }
-                                    }
-                                }
-                                
-                                if let Ok(hid) = client.get_history_id().await {
-                                    let _ = cache.set_meta("last_history_id", &hid);
-                                }
-                                
-                                continue;
-                            }
-
-                            if resp.status().is_success() {
-                                if let Ok(json) = resp.json::<serde_json::Value>().await {
-                                    eprintln!("🔄 Processing history changes...");
-                                    
-                                    if let Some(histories) = json.get("history") {
-                                        for item in histories.as_array().unwrap_or(&vec![]) {
-                                            // ✅ MIEJSCE 2: messagesAdded
-                                            if let Some(added) = item.get("messagesAdded") {
-                                                for m in added.as_array().unwrap_or(&vec![]) {
-                                                    if let Some(msg) = m.get("message") {
-                                                        if let Some(id) = msg.get("id").and_then(|v| v.as_str()) {
-                                                            if let Ok(full) = client.get_email_full(id).await {
-                                                                let internal_date = full.internal_date
-                                                                    .and_then(|s| s.parse::<i64>().ok())
-                                                                    .unwrap_or(0);
-                                                                
-                                                                let headers_json = serde_json::to_string(&full.payload.headers).unwrap_or_default();
-                                                                let labels_json = serde_json::to_string(&full.label_ids).unwrap_or_default();
-                                                                let cached = CachedMessage {
-                                                                    message_id: full.id.clone(),
-                                                                    thread_id: full.thread_id.clone(),
-                                                                    headers_json,
-                                                                    label_ids_json: labels_json,
-                                                                    snippet: full.snippet.clone(),
-                                                                    internal_date,
-                                                                    synced_history_id: json.get("historyId").and_then(|h| h.as_i64()),
-                                                                };
-                                                                let _ = cache.upsert_message(&cached);
-                                                                eprintln!("➕ Added message: {}", id);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            
-                                            if let Some(deleted) = item.get("messagesDeleted") {
-                                                for m in deleted.as_array().unwrap_or(&vec![]) {
-                                                    if let Some(msg) = m.get("message") {
-                                                        if let Some(id) = msg.get("id").and_then(|v| v.as_str()) {
-                                                            let _ = cache.delete_message(id);
-                                                            eprintln!("🗑️  Deleted message: {}", id);
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            
-                                            // ✅ MIEJSCE 3: labelsAdded
-                                            if let Some(labels_added) = item.get("labelsAdded") {
-                                                for m in labels_added.as_array().unwrap_or(&vec![]) {
-                                                    if let Some(msg) = m.get("message") {
-                                                        if let Some(id) = msg.get("id").and_then(|v| v.as_str()) {
-                                                            if let Ok(full) = client.get_email_full(id).await {
-                                                                let internal_date = full.internal_date
-                                                                    .and_then(|s| s.parse::<i64>().ok())
-                                                                    .unwrap_or(0);
-                                                                
-                                                                let headers_json = serde_json::to_string(&full.payload.headers).unwrap_or_default();
-                                                                let labels_json = serde_json::to_string(&full.label_ids).unwrap_or_default();
-                                                                let cached = CachedMessage {
-                                                                    message_id: full.id.clone(),
-                                                                    thread_id: full.thread_id.clone(),
-                                                                    headers_json,
-                                                                    label_ids_json: labels_json,
-                                                                    snippet: full.snippet.clone(),
-                                                                    internal_date,
-                                                                    synced_history_id: json.get("historyId").and_then(|h| h.as_i64()),
-                                                                };
-                                                                let _ = cache.upsert_message(&cached);
-                                                                eprintln!("🏷️  Updated labels for: {}", id);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            
-                                            // ✅ MIEJSCE 4: labelsRemoved
-                                            if let Some(labels_removed) = item.get("labelsRemoved") {
-                                                for m in labels_removed.as_array().unwrap_or(&vec![]) {
-                                                    if let Some(msg) = m.get("message") {
-                                                        if let Some(id) = msg.get("id").and_then(|v| v.as_str()) {
-                                                            if let Ok(full) = client.get_email_full(id).await {
-                                                                let internal_date = full.internal_date
-                                                                    .and_then(|s| s.parse::<i64>().ok())
-                                                                    .unwrap_or(0);
-                                                                
-                                                                let headers_json = serde_json::to_string(&full.payload.headers).unwrap_or_default();
-                                                                let labels_json = serde_json::to_string(&full.label_ids).unwrap_or_default();
-                                                                let cached = CachedMessage {
-                                                                    message_id: full.id.clone(),
-                                                                    thread_id: full.thread_id.clone(),
-                                                                    headers_json,
-                                                                    label_ids_json: labels_json,
-                                                                    snippet: full.snippet.clone(),
-                                                                    internal_date,
-                                                                    synced_history_id: json.get("historyId").and_then(|h| h.as_i64()),
-                                                                };
-                                                                let _ = cache.upsert_message(&cached);
-                                                                eprintln!("🏷️  Updated labels for: {}", id);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    
-                                    if let Some(hid) = json.get("historyId").and_then(|v| v.as_str()) {
-                                        let _ = cache.set_meta("last_history_id", hid);
-                                        eprintln!("✅ Updated historyId to: {}", hid);
-                                    }
-                                }
-                            } else {
-                                eprintln!("⚠️ History list returned error: {}", resp.status());
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("❌ Error fetching history: {}", e);
+                    match sync_history_once(&cache, client, &limits).await {
+                        Ok(()) => {
+                            // Gmail odpowiada - kolejka nie musi czekać do końca backoffu
+                            let _ = cache.reset_op_backoff();
+                            outbox_notify.notify_one();
                         }
+                        Err(e) => eprintln!("❌ Error fetching history: {}", e),
                     }
                 }
             }
         });
 
         *self.bg_handle.write().await = Some(handle);
+        self.start_outbox_worker().await;
         Ok(())
     }
 
-    pub async fn get_cached_metadata_list(
-        &self, 
-        _max_results: u32, 
-        _page_token: Option<String>, 
-        label_ids: &str
-    ) -> Result<Vec<CachedMessage>> {
-        let items = self.cache.load_all_messages()?;
-        
-        eprintln!("📬 Total messages in cache: {}", items.len());
-        eprintln!("📬 Filtering for label(s): '{}'", label_ids);
-        
-        if label_ids.is_empty() {
-            return Ok(items);
-        }
-        
-        let requested: Vec<&str> = label_ids.split(',')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .collect();
-        
-        if requested.is_empty() {
-            return Ok(items);
-        }
-        
-        let is_draft_query = requested.iter().any(|l| l.eq_ignore_ascii_case("DRAFT"));
-        
-        let filtered: Vec<CachedMessage> = items.into_iter().filter(|m| {
-            let labels: Vec<String> = serde_json::from_str(&m.label_ids_json).unwrap_or_default();
-            
-            let has_requested_label = requested.iter().any(|req| {
-                labels.iter().any(|l| l.eq_ignore_ascii_case(req))
-            });
-            
-            if !has_requested_label {
-                return false;
-            }
-            
-            if is_draft_query {
-                let has_trash = labels.iter().any(|l| l.eq_ignore_ascii_case("TRASH"));
-                let has_sent = labels.iter().any(|l| l.eq_ignore_ascii_case("SENT"));
-                let has_spam = labels.iter().any(|l| l.eq_ignore_ascii_case("SPAM"));
-                
-                if has_trash || has_sent || has_spam {
-                    return false;
-                }
-            }
-            
-            if !requested.iter().any(|l| l.eq_ignore_ascii_case("TRASH")) {
-                let has_trash = labels.iter().any(|l| l.eq_ignore_ascii_case("TRASH"));
-                if has_trash {
-                    return false;
-                }
-            }
-            
-            true
-        }).collect();
-        
-        eprintln!("📬 Filtered {} messages for label(s): {}", filtered.len(), label_ids);
-        
-        if !filtered.is_empty() {
-            let sample = &filtered[0];
-            eprintln!("📬 Sample message labels: {}", sample.label_ids_json);
+    /// Jedna runda synchronizacji historii - to samo, co robi pętla w tle co 30 s
+    pub async fn sync_history(&self) -> Result<()> {
+        if !ensure_client(&self.client, &self.token_store, &self.config).await {
+            anyhow::bail!("Gmail client not initialized");
         }
-        
-        Ok(filtered)
+        let client_guard = self.client.read().await;
+        let client = client_guard.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Gmail client not initialized"))?;
+        sync_history_once(&self.cache, client, &self.limits).await
     }
 
     pub async fn fetch_full_message_lazy(&self, message_id: &str) -> Result<EmailMessage> {
+        // ✅ Najpierw cache - działa też offline
+        if let Some(cached) = self.load_cached_full_message(message_id)? {
+            return Ok(cached);
+        }
+
         if self.client.read().await.is_none() {
             self.init_client_from_store().await?;
         }
         
         if let Some(ref client) = *self.client.read().await {
             let gmail_message = client.get_email_full(message_id).await?;
-            Ok(parse_email_message(gmail_message))
+            Ok(store_full_message(&self.cache, gmail_message, None))
         } else {
             anyhow::bail!("Gmail client not initialized");
         }
     }
 
+    /// Pełna wiadomość z cache (metadane + body), jeśli body było już pobrane
+    pub fn load_cached_full_message(&self, message_id: &str) -> Result<Option<EmailMessage>> {
+        let Some(body) = self.cache.get_body(message_id)? else {
+            return Ok(None);
+        };
+        let Some(meta) = self.cache.load_message(message_id)? else {
+            return Ok(None);
+        };
+        Ok(Some(crate::parser::email_from_cached(&meta, Some(&body))))
+    }
+
+    /// Blokuje zdalne treści wiadomości przed wysłaniem do UI - wg zgody nadawcy zapisanej w cache
+    pub fn apply_remote_content(&self, message: &mut EmailMessage, load_images: bool) -> Result<()> {
+        let sender_allowed = match message.addresses.from.first() {
+            Some(sender) => self.cache.sender_images_allowed(&sender.email)?,
+            None => false,
+        };
+        crate::parser::apply_remote_content(message, sender_allowed, load_images);
+        Ok(())
+    }
+
+    /// Strona wątków etykiety po kluczu `after` - ładowane są tylko wiadomości tej strony
+    pub fn load_thread_list(&self, label_ids: &str, after: Option<&PageKey>, limit: usize) -> Result<Vec<EmailThread>> {
+        let labels: Vec<&str> = label_ids.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+        let rows = self.cache.load_thread_page(&labels, after, limit)?;
+
+        let ids: Vec<String> = rows.iter().map(|r| r.thread_id.clone()).collect();
+        let include_trash = labels.iter().any(|l| l.eq_ignore_ascii_case("TRASH"));
+        let mut by_thread: HashMap<String, Vec<CachedMessage>> = HashMap::new();
+        for m in self.cache.load_thread_messages(&ids, include_trash)? {
+            by_thread.entry(m.thread_id.clone()).or_default().push(m);
+        }
+
+        Ok(rows
+            .iter()
+            .map(|row| thread_from_cached(row, by_thread.get(&row.thread_id).map(Vec::as_slice).unwrap_or(&[])))
+            .collect())
+    }
+
+    /// Cała konwersacja z treściami. Brakujące body dociąga jednym threads.get;
+    /// offline zwraca to, co jest w cache.
+    pub async fn load_thread(&self, thread_id: &str) -> Result<Option<EmailThread>> {
+        let thread_ids = [thread_id.to_string()];
+        let cached = self.cache.load_thread_messages(&thread_ids, true)?;
+
+        let mut complete = !cached.is_empty();
+        for m in &cached {
+            complete &= self.cache.has_body(&m.message_id)?;
+        }
+        if !complete {
+            let fetched = match self.ready_client().await {
+                Ok(guard) => match guard.as_ref() {
+                    Some(client) => client.get_thread(thread_id).await,
+                    None => Err(anyhow::anyhow!("Gmail client not initialized")),
+                },
+                Err(e) => Err(e),
+            };
+            match fetched {
+                Ok(thread) => {
+                    for full in thread.messages {
+                        store_full_message(&self.cache, full, None);
+                    }
+                }
+                Err(e) => eprintln!("⚠️ Thread {} served from cache only: {}", thread_id, e),
+            }
+        }
+
+        let messages = self.cache.load_thread_messages(&thread_ids, true)?;
+        let Some(last) = messages.last() else {
+            return Ok(None);
+        };
+        let row = ThreadRow {
+            thread_id: thread_id.to_string(),
+            last_activity: last.internal_date,
+            message_count: messages.len(),
+            unread: false,
+            has_attachment: false,
+        };
+        let mut thread = thread_from_cached(&row, &messages);
+        for m in &messages {
+            let body = self.cache.get_body(&m.message_id)?;
+            thread.messages.push(crate::parser::email_from_cached(m, body.as_ref()));
+        }
+        thread.unread = thread.messages.iter().any(|m| m.unread);
+        thread.has_attachment = thread.messages.iter().any(|m| m.has_attachment);
+        Ok(Some(thread))
+    }
+
+    /// Pobiera w tle body wiadomości, których jeszcze nie ma w cache
     pub async fn prefetch_bodies(&self, message_ids: Vec<String>) {
         let sem = self.prefetch_sem.clone();
         let client_clone = self.client.clone();
         for id in message_ids {
+            if self.cache.has_body(&id).unwrap_or(false) {
+                continue;
+            }
             let permit = sem.clone().acquire_owned().await.unwrap();
             let client_ref = client_clone.clone();
+            let cache = Arc::clone(&self.cache);
             tokio::spawn(async move {
                 if let Some(ref client) = *client_ref.read().await {
-                    let _ = client.get_email_full(&id).await;
+                    match client.get_email_full(&id).await {
+                        Ok(full) => {
+                            store_full_message(&cache, full, None);
+                        }
+                        Err(e) => eprintln!("Prefetch: error fetching message {}: {}", id, e),
+                    }
                 }
                 drop(permit);
             });
         }
     }
-}
\ No newline at end of file
+
+    /// Zmiana użytkownika: od razu w cache, do Gmaila przez kolejkę (działa też offline)
+    pub async fn enqueue(&self, op: MailOp) -> Result<i64> {
+        let op_id = outbox::record(&self.cache, &op)?;
+        self.outbox_notify.notify_one();
+        Ok(op_id)
+    }
+
+    /// Ponawia zmianę odrzuconą przez Gmail (ponownie nakładając ją na cache)
+    pub async fn retry_op(&self, op_id: i64) -> Result<bool> {
+        let Some(op) = self.cache.get_op(op_id)? else {
+            return Ok(false);
+        };
+        if !self.cache.retry_op(op_id)? {
+            return Ok(false);
+        }
+        op.op.apply_local(&self.cache)?;
+        self.outbox_notify.notify_one();
+        Ok(true)
+    }
+
+    /// Usuwa zmianę z kolejki; jeśli jeszcze czekała na wysłanie - cofa ją w cache
+    pub async fn discard_op(&self, op_id: i64) -> Result<bool> {
+        let Some(op) = self.cache.get_op(op_id)? else {
+            return Ok(false);
+        };
+        self.cache.remove_op(op_id)?;
+        if op.status == OP_PENDING {
+            outbox::rollback(&self.cache, &op);
+        }
+        if let MailOp::SendFile { path, .. } = &op.op {
+            if let Err(e) = tokio::fs::remove_file(path).await {
+                eprintln!("⚠️ Failed to remove outgoing message {}: {}", path, e);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Wysyła od razu; bez sieci wiadomość trafia do kolejki i wyjdzie po jej powrocie
+    pub async fn send_or_queue(&self, raw: String, thread_id: Option<String>) -> Result<SendOutcome> {
+        let attempt = match self.ready_client().await {
+            Ok(guard) => match guard.as_ref() {
+                Some(client) => client.send_message(&raw, thread_id.as_deref()).await,
+                None => Err(anyhow::anyhow!("Gmail client not initialized")),
+            },
+            Err(e) => Err(e),
+        };
+        match attempt {
+            Ok(id) => Ok(SendOutcome { id: Some(id), queued_op_id: None }),
+            Err(e) if is_connect_error(&e) => {
+                let op_id = self.enqueue(MailOp::Send { raw, thread_id }).await?;
+                eprintln!("📮 Offline - message queued in outbox (op {})", op_id);
+                Ok(SendOutcome { id: None, queued_op_id: Some(op_id) })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Nowa wiadomość z edytora razem z plikami z `data.attachments`
+    pub async fn send_email(&self, data: &EmailData, progress: impl FnMut(u64, u64) + Send) -> Result<SendOutcome> {
+        let mut message = OutgoingMessage::from_email_data(data);
+        message.attachments = local_attachments(data)?;
+        self.send_outgoing(message, None, progress).await
+    }
+
+    /// Wysyła gotową wiadomość. Najpierw składa ją do pliku w `outgoing` (załączniki nie trafiają
+    /// w całości do pamięci); do `SIMPLE_UPLOAD_LIMIT` idzie jako `raw`, większa - uploadem
+    /// wznawianym. Bez sieci czeka w kolejce. `progress(sent, total)` - bajty przyjęte przez Gmaila.
+    pub async fn send_outgoing(
+        &self,
+        message: OutgoingMessage,
+        thread_id: Option<String>,
+        mut progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<SendOutcome> {
+        let dir = self.cache.outgoing_dir().to_path_buf();
+        let path = dir.join(format!("{:016x}.eml", rand::random::<u64>()));
+        let spool = path.clone();
+        let written = tokio::task::spawn_blocking(move || -> Result<u64> {
+            std::fs::create_dir_all(&dir)?;
+            let mut out = std::io::BufWriter::new(std::fs::File::create(&spool)?);
+            message.write_to(&mut out, true)?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            Ok(std::fs::metadata(&spool)?.len())
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+        let total = match written {
+            Ok(total) => total,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                return Err(e.context("Failed to build message"));
+            }
+        };
+
+        if total <= SIMPLE_UPLOAD_LIMIT {
+            let bytes = tokio::fs::read(&path).await;
+            let _ = tokio::fs::remove_file(&path).await;
+            progress(0, total);
+            let outcome = self.send_or_queue(general_purpose::URL_SAFE_NO_PAD.encode(bytes?), thread_id).await?;
+            if outcome.id.is_some() {
+                progress(total, total);
+            }
+            return Ok(outcome);
+        }
+
+        progress(0, total);
+        let attempt = match self.ready_client().await {
+            Ok(guard) => match guard.as_ref() {
+                Some(client) => client.upload_message(&path, thread_id.as_deref(), |sent| progress(sent, total)).await,
+                None => Err(anyhow::anyhow!("Gmail client not initialized")),
+            },
+            Err(e) => Err(e),
+        };
+        match attempt {
+            Ok(id) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                Ok(SendOutcome { id: Some(id), queued_op_id: None })
+            }
+            // Plik zostaje w `outgoing` do wysłania przez kolejkę
+            Err(e) if is_connect_error(&e) => {
+                let path = path.to_string_lossy().to_string();
+                let op_id = self.enqueue(MailOp::SendFile { path, thread_id }).await?;
+                eprintln!("📮 Offline - message ({} bytes) queued in outbox (op {})", total, op_id);
+                Ok(SendOutcome { id: None, queued_op_id: Some(op_id) })
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Odpowiedź albo przekazanie wiadomości w jej wątku. Przekazanie bierze załączniki oryginału
+    /// z magazynu (brakujące pobiera strumieniowo), więc bez nich w magazynie wymaga sieci;
+    /// gotowa wiadomość bez sieci trafia do kolejki.
+    pub async fn reply(
+        &self,
+        kind: ReplyKind,
+        message_id: &str,
+        data: &EmailData,
+        own_email: &str,
+        progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<SendOutcome> {
+        let message = self.fetch_full_message_lazy(message_id).await?;
+        let meta = self
+            .cache
+            .load_message(message_id)?
+            .ok_or_else(|| anyhow::anyhow!("Message {} is not cached", message_id))?;
+        let headers: Vec<GmailHeader> = serde_json::from_str(&meta.headers_json).unwrap_or_default();
+
+        let source = reply::Source { headers: &headers, message: &message };
+        let mut outgoing = reply::compose(kind, &source, data, own_email);
+        if kind == ReplyKind::Forward {
+            // Pliki magazynu czyta dopiero składanie wiadomości; właśnie użyte są ostatnie w kolejce do usunięcia
+            for attachment in &message.attachments {
+                let file = self
+                    .download_attachment(message_id, &attachment.id, |_, _| {})
+                    .await
+                    .with_context(|| format!("Failed to fetch attachment {} for forwarding", attachment.filename))?;
+                outgoing.attachments.push(Attachment {
+                    filename: attachment.filename.clone(),
+                    mime_type: attachment.mime_type.clone(),
+                    path: PathBuf::from(file.path),
+                });
+            }
+        }
+        outgoing.attachments.extend(local_attachments(data)?);
+        self.send_outgoing(outgoing, Some(meta.thread_id), progress).await
+    }
+
+    /// Załącznik jako plik lokalny: z magazynu, a jeśli go tam nie ma - pobrany z Gmaila.
+    /// `progress(received, total)` dostaje postęp pobierania.
+    pub async fn download_attachment(
+        &self,
+        message_id: &str,
+        attachment_id: &str,
+        mut progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<AttachmentFile> {
+        let dir = self.cache.attachments_dir().to_path_buf();
+        let now = chrono::Utc::now().timestamp_millis();
+        if let Some(stored) = self.cache.find_attachment(message_id, attachment_id, now)? {
+            let path = dir.join(&stored.file_name);
+            if path.exists() {
+                return Ok(AttachmentFile {
+                    path: path.to_string_lossy().to_string(),
+                    filename: stored.filename,
+                    mime_type: stored.mime_type,
+                    size: stored.size,
+                    sha256: stored.sha256,
+                    cached: true,
+                });
+            }
+            // Plik usunięty poza aplikacją - pobieramy ponownie
+            self.cache.remove_attachment(message_id, attachment_id)?;
+        }
+
+        let message = self.fetch_full_message_lazy(message_id).await?;
+        let info = message
+            .attachments
+            .iter()
+            .find(|a| a.id == attachment_id)
+            .ok_or_else(|| anyhow::anyhow!("Attachment {} not found in message {}", attachment_id, message_id))?;
+        let total = info.size.max(0) as u64;
+
+        tokio::fs::create_dir_all(&dir).await?;
+        let part = dir.join(format!(".download-{}-{}", message_id, rand::random::<u32>()));
+        let client = self.online_client().await?;
+        let downloaded = client
+            .stream_attachment_to_file(message_id, attachment_id, &part, |received| progress(received, total))
+            .await;
+        let (sha256, size) = match downloaded {
+            Ok(done) => done,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&part).await;
+                return Err(e);
+            }
+        };
+
+        // Ta sama treść już jest w magazynie (np. ten sam plik w innej wiadomości) - zostaje jedna kopia
+        let file_name = attachments::store_file_name(&sha256, &info.filename);
+        let path = dir.join(&file_name);
+        if path.exists() {
+            tokio::fs::remove_file(&part).await?;
+        } else {
+            tokio::fs::rename(&part, &path).await?;
+        }
+        self.cache.put_attachment(&StoredAttachment {
+            message_id: message_id.to_string(),
+            attachment_id: attachment_id.to_string(),
+            filename: info.filename.clone(),
+            mime_type: info.mime_type.clone(),
+            sha256: sha256.clone(),
+            file_name: file_name.clone(),
+            size: size as i64,
+            fetched_at: now,
+        })?;
+        attachments::evict(&self.cache, attachments::STORE_LIMIT_BYTES, &file_name)?;
+
+        Ok(AttachmentFile {
+            path: path.to_string_lossy().to_string(),
+            filename: info.filename.clone(),
+            mime_type: info.mime_type.clone(),
+            size: size as i64,
+            sha256,
+            cached: false,
+        })
+    }
+
+    async fn ready_client(&self) -> Result<tokio::sync::RwLockReadGuard<'_, Option<GmailClient>>> {
+        if self.client.read().await.is_none() {
+            self.init_client_from_store().await?;
+        }
+        Ok(self.client.read().await)
+    }
+
+    async fn online_client(&self) -> Result<RwLockReadGuard<'_, GmailClient>> {
+        RwLockReadGuard::try_map(self.ready_client().await?, |c| c.as_ref())
+            .map_err(|_| anyhow::anyhow!("Gmail client not initialized"))
+    }
+
+    /// Autozapis z edytora: od razu w cache, do Gmaila przez kolejkę.
+    /// Kolejne zapisy tej samej wersji przed wysłaniem łączą się w jedną operację.
+    pub async fn save_draft(&self, local_id: Option<i64>, data: &EmailData, thread_id: Option<&str>) -> Result<LocalDraft> {
+        let draft = self.cache.save_draft(local_id, data, thread_id)?;
+        let op = MailOp::SaveDraft { local_id: draft.local_id };
+        if !self.cache.has_pending_op(&op)? {
+            self.enqueue(op).await?;
+        }
+        Ok(draft)
+    }
+
+    /// Wersje robocze z cache, uzupełnione o zmiany z innych klientów (gdy jest sieć)
+    pub async fn drafts(&self) -> Result<Vec<LocalDraft>> {
+        let pulled = match self.online_client().await {
+            Ok(client) => self.pull_drafts(&client).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = pulled {
+            eprintln!("⚠️ Drafts served from cache only: {}", e);
+        }
+        self.cache.list_drafts()
+    }
+
+    /// Lokalne zmiany mają pierwszeństwo - z Gmaila bierzemy tylko wersje bez nich
+    async fn pull_drafts(&self, client: &GmailClient) -> Result<()> {
+        let remote = client.list_drafts().await?;
+        let local = self.cache.list_drafts()?;
+        // Usunięte lokalnie, ale jeszcze nie w Gmailu - nie wracają na listę
+        let deleting: Vec<String> = self
+            .cache
+            .list_ops()?
+            .into_iter()
+            .filter_map(|op| match op.op {
+                MailOp::DeleteDraft { draft_id, .. } => Some(draft_id),
+                _ => None,
+            })
+            .collect();
+
+        for draft in &local {
+            let gone = draft.draft_id.as_ref().is_some_and(|id| !remote.iter().any(|r| &r.id == id));
+            if draft.synced && gone {
+                self.cache.remove_draft(draft.local_id)?;
+            }
+        }
+        for r in remote.iter().filter(|r| !deleting.contains(&r.id)) {
+            let known = local.iter().find(|d| d.draft_id.as_deref() == Some(r.id.as_str()));
+            if known.is_some_and(|d| !d.synced || d.message_id.as_deref() == Some(r.message.id.as_str())) {
+                continue;
+            }
+            let full = client.get_draft(&r.id).await?;
+            let message_id = full.message.id.clone();
+            let thread_id = full.message.thread_id.clone();
+            let data = email_data_from_message(full.message);
+            self.cache
+                .store_remote_draft(known.map(|d| d.local_id), &full.id, &message_id, Some(&thread_id), &data)?;
+        }
+        Ok(())
+    }
+
+    /// Wysyła wersję roboczą; bez sieci (albo gdy kolejka nie jest pusta - by zachować
+    /// kolejność z wcześniejszymi zapisami) wysyłka czeka w kolejce
+    pub async fn send_draft(&self, local_id: i64) -> Result<SendOutcome> {
+        if self.cache.get_draft(local_id)?.is_none() {
+            anyhow::bail!("Draft {} not found", local_id);
+        }
+        let queue_busy = self.cache.next_pending_op()?.is_some();
+        let attempt = match self.online_client().await {
+            Ok(_) if queue_busy => Err(anyhow::anyhow!("outbox busy")),
+            Ok(client) => outbox::send_draft(&self.cache, &client, local_id).await,
+            Err(e) => Err(e),
+        };
+        match attempt {
+            Ok(id) => Ok(SendOutcome { id, queued_op_id: None }),
+            Err(e) if queue_busy || is_connect_error(&e) => {
+                let op_id = self.enqueue(MailOp::SendDraft { local_id }).await?;
+                eprintln!("📮 Draft {} queued for sending (op {})", local_id, op_id);
+                Ok(SendOutcome { id: None, queued_op_id: Some(op_id) })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Usuwa lokalnie od razu; wersja zapisana już w Gmailu jest usuwana przez kolejkę
+    pub async fn delete_draft(&self, local_id: i64) -> Result<()> {
+        let Some(draft) = self.cache.get_draft(local_id)? else {
+            return Ok(());
+        };
+        self.cache.remove_draft(local_id)?;
+        if let Some(draft_id) = draft.draft_id {
+            self.enqueue(MailOp::DeleteDraft { draft_id, message_id: draft.message_id }).await?;
+        }
+        Ok(())
+    }
+
+    /// Etykiety z Gmaila zapisane w cache; offline - ostatnia zapisana lista
+    pub async fn labels(&self) -> Result<Vec<GmailLabel>> {
+        let fetched = match self.online_client().await {
+            Ok(client) => client.list_labels().await,
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok(labels) => self.cache.replace_labels(&labels)?,
+            Err(e) => eprintln!("⚠️ Labels served from cache only: {}", e),
+        }
+        self.cache.load_labels()
+    }
+
+    pub async fn get_label(&self, label_id: &str) -> Result<GmailLabel> {
+        let label = self.online_client().await?.get_label(label_id).await?;
+        self.cache.upsert_label(&label)?;
+        Ok(label)
+    }
+
+    pub async fn create_label(&self, label: &LabelUpdate) -> Result<GmailLabel> {
+        let created = self.online_client().await?.create_label(label).await?;
+        self.cache.upsert_label(&created)?;
+        eprintln!("🏷️ Label created: {} ({})", created.name, created.id);
+        Ok(created)
+    }
+
+    pub async fn update_label(&self, label_id: &str, label: &LabelUpdate) -> Result<GmailLabel> {
+        let updated = self.online_client().await?.patch_label(label_id, label).await?;
+        self.cache.upsert_label(&updated)?;
+        Ok(updated)
+    }
+
+    pub async fn delete_label(&self, label_id: &str) -> Result<()> {
+        self.online_client().await?.delete_label(label_id).await?;
+        self.cache.delete_label(label_id)?;
+        eprintln!("🗑️ Label deleted: {}", label_id);
+        Ok(())
+    }
+
+    /// Worker kolejki: budzony przy każdej nowej zmianie, poza tym czeka na koniec backoffu
+    pub async fn start_outbox_worker(&self) {
+        let cache = Arc::clone(&self.cache);
+        let client_lock = Arc::clone(&self.client);
+        let token_store = self.token_store.clone();
+        let config = self.config.clone();
+        let notify = Arc::clone(&self.outbox_notify);
+        let failures = self.outbox_failures.clone();
+
+        let handle = tokio::spawn(async move {
+            // Brak subskrybentów nie jest błędem - operacja i tak zostaje w kolejce jako nieudana
+            let on_failed = move |op: PendingOp| {
+                let _ = failures.send(op);
+            };
+            loop {
+                let wait = match cache.next_pending_op() {
+                    Ok(None) => None,
+                    Ok(Some(_)) if !ensure_client(&client_lock, &token_store, &config).await => Some(OUTBOX_OFFLINE_RECHECK),
+                    Ok(Some(_)) => match client_lock.read().await.as_ref() {
+                        Some(client) => outbox::replay(&cache, client, &on_failed).await.unwrap_or_else(|e| {
+                            eprintln!("❌ Outbox error: {}", e);
+                            Some(OUTBOX_OFFLINE_RECHECK)
+                        }),
+                        None => Some(OUTBOX_OFFLINE_RECHECK),
+                    },
+                    Err(e) => {
+                        eprintln!("❌ Outbox error: {}", e);
+                        Some(OUTBOX_OFFLINE_RECHECK)
+                    }
+                };
+                match wait {
+                    Some(delay) => {
+                        let _ = tokio::time::timeout(delay, notify.notified()).await;
+                    }
+                    None => notify.notified().await,
+                }
+            }
+        });
+
+        *self.outbox_handle.write().await = Some(handle);
+    }
+}
+
+/// Tworzy klienta, jeśli go jeszcze nie ma (np. start bez sieci). false = nadal brak tokenu/sieci.
+async fn ensure_client(client_lock: &RwLock<Option<GmailClient>>, token_store: &TokenStore, config: &ApiConfig) -> bool {
+    if client_lock.read().await.is_some() {
+        return true;
+    }
+    match token_store.get_token().await {
+        Ok(Some(_)) => {
+            *client_lock.write().await = Some(GmailClient::new(token_store.clone(), config));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Parsuje pełną wiadomość i zapisuje metadane + body w cache
+fn store_full_message(cache: &Cache, full: GmailMessage, synced_history_id: Option<i64>) -> EmailMessage {
+    let meta = CachedMessage::from_gmail(&full, synced_history_id);
+    let parsed = parse_email_message(full);
+
+    if let Err(e) = cache.upsert_message(&meta) {
+        eprintln!("⚠️ Failed to cache metadata for {}: {}", parsed.id, e);
+    }
+    if let Err(e) = cache.put_body(&parsed) {
+        eprintln!("⚠️ Failed to cache body for {}: {}", parsed.id, e);
+    }
+    parsed
+}
+
+fn last_history_id(cache: &Cache) -> Option<i64> {
+    match cache.get_meta("last_history_id") {
+        Ok(Some(s)) if !s.trim().is_empty() => match s.trim().parse::<i64>() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                let _ = cache.delete_meta("last_history_id");
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+/// users.history.list od ostatniego historyId (wszystkie strony) i zastosowanie zmian w cache
+async fn sync_history_once(cache: &Cache, client: &GmailClient, limits: &SyncLimits) -> Result<()> {
+    // Niedokończony resync po wygaśnięciu historii ma pierwszeństwo
+    if cache.get_meta("resync_history_id")?.is_some() {
+        return resync_after_expired_history(cache, client, limits).await;
+    }
+
+    let Some(start_h) = last_history_id(cache) else {
+        eprintln!("⚠️ No valid last_history_id, skipping history sync");
+        return Ok(());
+    };
+
+    // Wznów od zapisanej strony, jeśli poprzednia runda przerwała się w tym samym miejscu
+    let mut page_token = match (cache.get_meta("history_page_start")?, cache.get_meta("history_page_token")?) {
+        (Some(start), Some(token)) if start == start_h.to_string() => Some(token),
+        _ => None,
+    };
+    let mut latest_history_id = None;
+
+    loop {
+        let Some(list) = client.list_history(start_h, page_token.clone()).await? else {
+            eprintln!("⚠️ History API returned 400 - historyId expired, doing full resync");
+            cache.delete_meta("history_page_start")?;
+            cache.delete_meta("history_page_token")?;
+            return resync_after_expired_history(cache, client, limits).await;
+        };
+
+        if list.history.as_ref().is_some_and(|h| !h.is_empty()) {
+            eprintln!("🔄 Processing history changes...");
+        }
+        // Niepełna strona = błąd: ani token strony, ani historyId nie idą do przodu
+        apply_history_records(cache, client, list.history.unwrap_or_default()).await?;
+        latest_history_id = list.history_id.or(latest_history_id);
+
+        match list.next_page_token {
+            Some(next) => {
+                cache.set_meta("history_page_start", &start_h.to_string())?;
+                cache.set_meta("history_page_token", &next)?;
+                page_token = Some(next);
+            }
+            None => break,
+        }
+    }
+
+    cache.delete_meta("history_page_start")?;
+    cache.delete_meta("history_page_token")?;
+
+    if let Some(hid) = latest_history_id {
+        let _ = cache.set_meta("last_history_id", &hid);
+        eprintln!("✅ Updated historyId to: {}", hid);
+    }
+    Ok(())
+}
+
+/// Stosuje jedną stronę rekordów historii. Błąd, jeśli którykolwiek rekord nie został zastosowany.
+async fn apply_history_records(cache: &Cache, client: &GmailClient, records: Vec<GmailHistoryRecord>) -> Result<()> {
+    // Wiadomości do pobrania zbieramy z całej strony historii i pobieramy jednym batchem na końcu
+    let mut to_fetch: HashMap<String, Option<i64>> = HashMap::new();
+
+    for record in records {
+        let record_hid = record.id.parse::<i64>().ok();
+
+        // ✅ MIEJSCE 2: messagesAdded - jedyny przypadek, gdy wiadomość trzeba pobrać
+        for change in record.messages_added.unwrap_or_default() {
+            to_fetch.insert(change.message.id, record_hid);
+        }
+
+        for change in record.messages_deleted.unwrap_or_default() {
+            let id = change.message.id;
+            to_fetch.remove(&id);
+            cache.delete_message(&id)?;
+            eprintln!("🗑️  Deleted message: {}", id);
+        }
+
+        // ✅ MIEJSCE 3/4: labelsAdded / labelsRemoved - zmiana etykiet prosto z rekordu
+        let added = record.labels_added.unwrap_or_default().into_iter().map(|c| (c, true));
+        let removed = record.labels_removed.unwrap_or_default().into_iter().map(|c| (c, false));
+        for (change, is_add) in added.chain(removed) {
+            let id = change.message.id;
+            if to_fetch.contains_key(&id) {
+                // i tak pobierzemy aktualne etykiety
+                continue;
+            }
+            let labels = change.label_ids.unwrap_or_default();
+            let (add, remove): (&[String], &[String]) = if is_add { (&labels, &[]) } else { (&[], &labels) };
+
+            let applied = cache
+                .apply_label_changes(&id, add, remove, record_hid)
+                .with_context(|| format!("Failed to apply label change for {}", id))?;
+            if applied {
+                eprintln!("🏷️  Updated labels for: {}", id);
+            } else {
+                // Nieznana wiadomość (np. spoza zsynchronizowanych etykiet) - dociągnij metadane
+                to_fetch.insert(id, record_hid);
+            }
+        }
+    }
+
+    if to_fetch.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<String> = to_fetch.keys().cloned().collect();
+    let messages = client
+        .get_messages_batch(&ids, "metadata")
+        .await
+        .with_context(|| format!("Failed to fetch {} messages from history", ids.len()))?;
+    for meta in messages {
+        let hid = to_fetch.get(&meta.id).copied().flatten();
+        cache.upsert_message(&CachedMessage::from_gmail(&meta, hid))?;
+        eprintln!("➕ Added message: {}", meta.id);
+    }
+    Ok(())
+}
+
+/// historyId wygasł (400) - pobierz etykiety od nowa i zacznij historię od bieżącego profilu.
+/// Nowy historyId zapisujemy na starcie, więc przerwany resync wznawia się w następnej rundzie.
+async fn resync_after_expired_history(cache: &Cache, client: &GmailClient, limits: &SyncLimits) -> Result<()> {
+    let resync_hid = match cache.get_meta("resync_history_id")? {
+        Some(hid) => {
+            eprintln!("⏯️  Resuming interrupted resync...");
+            hid
+        }
+        None => {
+            let hid = client.get_history_id().await?;
+            cache.set_meta("resync_history_id", &hid)?;
+            hid
+        }
+    };
+    let _ = cache.delete_meta("last_history_id");
+
+    for label in SYNC_LABELS {
+        let done_key = format!("resync:done:{}", label);
+        if cache.get_meta(&done_key)?.is_some() {
+            continue;
+        }
+        // ✅ MIEJSCE 1: resync - etykiety mogły się zmienić, więc pobieramy też znane wiadomości
+        sync_label(cache, client, label, 200, limits, "resync", false).await?;
+        cache.set_meta(&done_key, "1")?;
+    }
+
+    for label in SYNC_LABELS {
+        cache.delete_meta(&format!("resync:done:{}", label))?;
+    }
+    cache.set_meta("last_history_id", &resync_hid)?;
+    cache.delete_meta("resync_history_id")?;
+    eprintln!("✅ Full resync complete, historyId: {}", resync_hid);
+    Ok(())
+}
+
+/// Synchronizuje jedną etykietę strona po stronie (nextPageToken) aż do końca albo limitu.
+/// Token następnej strony trafia do meta, więc przerwana synchronizacja wznawia się od tego miejsca.
+async fn sync_label(
+    cache: &Cache,
+    client: &GmailClient,
+    label: &str,
+    page_size: u32,
+    limits: &SyncLimits,
+    state_prefix: &str,
+    skip_cached: bool,
+) -> Result<usize> {
+    let token_key = format!("{}:page_token:{}", state_prefix, label);
+    let listed_key = format!("{}:listed:{}", state_prefix, label);
+
+    let mut page_token = cache.get_meta(&token_key)?;
+    let mut listed: usize = cache.get_meta(&listed_key)?.and_then(|s| s.parse().ok()).unwrap_or(0);
+    let query = limits.newer_than_days.map(|d| format!("newer_than:{}d", d));
+    let mut stored = 0;
+
+    if page_token.is_some() {
+        eprintln!("⏯️  Resuming {} after {} messages", label, listed);
+    }
+
+    loop {
+        let remaining = limits.max_messages_per_label.map(|max| max.saturating_sub(listed));
+        if remaining == Some(0) {
+            break;
+        }
+        let size = remaining.map_or(page_size, |r| r.min(page_size as usize) as u32);
+
+        let list = client
+            .get_messages_metadata(size, label, page_token.clone(), query.as_deref())
+            .await?;
+        let refs = list.messages.unwrap_or_default();
+        listed += refs.len();
+
+        // Ta sama wiadomość bywa w kilku etykietach (INBOX + CATEGORY_*) - nie pobieraj jej drugi raz
+        let to_fetch: Vec<GmailMessageRef> = refs
+            .into_iter()
+            .filter(|r| !skip_cached || !cache.has_message(&r.id).unwrap_or(false))
+            .collect();
+        // Błąd pobrania przerywa etykietę przed zapisem tokenu - wznowienie zacznie od tej samej strony
+        stored += fetch_and_store(cache, client, to_fetch).await?;
+
+        match list.next_page_token {
+            Some(next) => {
+                cache.set_meta(&token_key, &next)?;
+                cache.set_meta(&listed_key, &listed.to_string())?;
+                page_token = Some(next);
+            }
+            None => break,
+        }
+    }
+
+    cache.delete_meta(&token_key)?;
+    cache.delete_meta(&listed_key)?;
+    Ok(stored)
+}
+
+/// Pobiera metadane wiadomości batchem (do 100 na żądanie) i zapisuje je w cache. Zwraca liczbę zapisanych.
+/// Body dociąga prefetch / otwarcie wiadomości.
+async fn fetch_and_store(cache: &Cache, client: &GmailClient, refs: Vec<GmailMessageRef>) -> Result<usize> {
+    if refs.is_empty() {
+        return Ok(0);
+    }
+    let ids: Vec<String> = refs.into_iter().map(|r| r.id).collect();
+    let messages = client
+        .get_messages_batch(&ids, "metadata")
+        .await
+        .with_context(|| format!("Sync: error fetching {} messages", ids.len()))?;
+
+    let mut stored = 0;
+    for meta in messages {
+        cache
+            .upsert_message(&CachedMessage::from_gmail(&meta, None))
+            .with_context(|| format!("Sync: failed to upsert message {}", meta.id))?;
+        stored += 1;
+    }
+    Ok(stored)
+}
+
+/// Pliki wskazane w edytorze (`EmailData::attachments`)
+fn local_attachments(data: &EmailData) -> Result<Vec<Attachment>> {
+    data.attachments.iter().map(|path| Attachment::from_path(Path::new(path))).collect()
+}
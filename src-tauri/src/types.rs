@@ -55,6 +55,85 @@ pub struct GmailMessageList {
     pub next_page_token: Option<String>,
 }
 
+/// users.threads.get - wszystkie wiadomości konwersacji
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GmailThread {
+    pub id: String,
+    #[serde(default)]
+    pub messages: Vec<GmailMessage>,
+}
+
+/// users.drafts - wersja robocza; na liście i po zapisie `message` ma tylko id
+#[derive(Debug, Deserialize)]
+pub struct GmailDraft {
+    pub id: String,
+    pub message: GmailDraftMessage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GmailDraftMessage {
+    pub id: String,
+}
+
+/// drafts.get z format=full
+#[derive(Debug, Deserialize)]
+pub struct GmailFullDraft {
+    pub id: String,
+    pub message: GmailMessage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GmailDraftList {
+    #[serde(default)]
+    pub drafts: Vec<GmailDraft>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+}
+
+/// users.labels - etykieta systemowa albo użytkownika (zagnieżdżenie przez "/" w nazwie)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailLabel {
+    pub id: String,
+    pub name: String,
+    /// "system" albo "user"
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub label_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_list_visibility: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_list_visibility: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<LabelColor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelColor {
+    pub text_color: String,
+    pub background_color: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GmailLabelList {
+    #[serde(default)]
+    pub labels: Vec<GmailLabel>,
+}
+
+/// Treść labels.create / labels.patch - pominięte pola zostają bez zmian
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_list_visibility: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_list_visibility: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<LabelColor>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GmailMessageRef {
     pub id: String,
@@ -62,6 +141,45 @@ pub struct GmailMessageRef {
     pub thread_id: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GmailHistoryList {
+    pub history: Option<Vec<GmailHistoryRecord>>,
+    #[serde(rename = "historyId")]
+    pub history_id: Option<String>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GmailHistoryRecord {
+    pub id: String,
+    #[serde(rename = "messagesAdded")]
+    pub messages_added: Option<Vec<GmailHistoryChange>>,
+    #[serde(rename = "messagesDeleted")]
+    pub messages_deleted: Option<Vec<GmailHistoryChange>>,
+    #[serde(rename = "labelsAdded")]
+    pub labels_added: Option<Vec<GmailHistoryChange>>,
+    #[serde(rename = "labelsRemoved")]
+    pub labels_removed: Option<Vec<GmailHistoryChange>>,
+}
+
+/// Wpis historii: wiadomość + (dla labelsAdded/labelsRemoved) zmienione etykiety
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GmailHistoryChange {
+    pub message: GmailHistoryMessage,
+    #[serde(rename = "labelIds")]
+    pub label_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GmailHistoryMessage {
+    pub id: String,
+    #[serde(rename = "threadId")]
+    pub thread_id: String,
+    #[serde(rename = "labelIds")]
+    pub label_ids: Option<Vec<String>>,
+}
+
 // ✅ Dodaj Clone dla EmailAttachment
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EmailAttachment {
@@ -82,19 +200,29 @@ pub struct InlineImage {
     pub mime_type: String,
 }
 
+/// Konwersacja na liście wątków. `messages` jest wypełniane tylko przez `get_thread_rust`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EmailThread {
+    #[serde(rename = "threadId")]
     pub thread_id: String,
     pub messages: Vec<EmailMessage>,
+    #[serde(rename = "lastActivity")]
     pub last_activity: i64,
     pub subject: String,
     pub snippet: String,
     pub from: String,
+    /// Nadawcy w kolejności pierwszej wiadomości (bez powtórzeń)
+    pub participants: Vec<String>,
     pub date: String,
     pub unread: bool,
+    #[serde(rename = "hasAttachment")]
     pub has_attachment: bool,
+    #[serde(rename = "labelIds")]
     pub label_ids: Vec<String>,
+    #[serde(rename = "messageCount")]
     pub message_count: usize,
+    #[serde(rename = "accountId", default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
 }
 
 // ✅ Dodaj Clone dla EmailMessage
@@ -119,6 +247,53 @@ pub struct EmailMessage {
     pub inline_images: Vec<InlineImage>,
     #[serde(rename = "internalDate")]
     pub internal_date: Option<i64>,
+    /// Konto, z którego pochodzi wiadomość (widok "wszystkie skrzynki")
+    #[serde(rename = "accountId", default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    /// Nadawca i odbiorcy jako listy adresów; `from` i `to` wyżej zostają surowymi napisami
+    #[serde(default)]
+    pub addresses: MessageAddresses,
+    /// Zdalne obrazki i tła z treści HTML oraz co z nich zablokowano
+    #[serde(rename = "remoteContent", default)]
+    pub remote_content: RemoteContent,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteContent {
+    pub resources: Vec<RemoteResource>,
+    /// Nadawca ma włączone "zawsze ładuj obrazki"
+    pub sender_allowed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteResource {
+    pub url: String,
+    /// "image", "background" (atrybut) albo "css" (`url()` w stylach)
+    pub kind: String,
+    /// Podejrzenie piksela śledzącego: "pixel", "tracker-domain" albo "unique-query"
+    pub tracking: Option<String>,
+    /// Zamieniony na placeholder (obrazek bez src, `none` w CSS)
+    pub blocked: bool,
+}
+
+/// Adres z nagłówka: nazwa wyświetlana (zdekodowana) i sam adres
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct EmailAddress {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+/// Adresy wiadomości; członkowie grup RFC 5322 są spłaszczeni do zwykłej listy
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MessageAddresses {
+    pub from: Vec<EmailAddress>,
+    pub to: Vec<EmailAddress>,
+    pub cc: Vec<EmailAddress>,
+    pub bcc: Vec<EmailAddress>,
+    #[serde(rename = "replyTo")]
+    pub reply_to: Vec<EmailAddress>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -148,7 +323,15 @@ pub struct UserProfile {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MailboxStat {
     pub id: String,
+    /// Nazwa do wyświetlenia (ostatni człon ścieżki)
     pub name: String,
+    /// Pełna nazwa etykiety, np. "Praca/Projekty"
+    pub path: String,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<String>,
+    #[serde(rename = "type")]
+    pub label_type: Option<String>,
+    pub color: Option<LabelColor>,
     pub total: usize,
     pub unread: usize,
 }
@@ -170,10 +353,22 @@ pub struct GetEmailsOptions {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SearchEmailsOptions {
+    pub query: String,
+    #[serde(rename = "maxResults")]
+    pub max_results: Option<u32>,
+    #[serde(rename = "pageToken")]
+    pub page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EmailData {
     pub to: String,
     pub subject: String,
     pub body: String,
     pub cc: Option<String>,   // ✅ dodaj
     pub bcc: Option<String>,  // ✅ dodaj
+    /// Ścieżki plików do załączenia
+    #[serde(default)]
+    pub attachments: Vec<String>,
 }
\ No newline at end of file
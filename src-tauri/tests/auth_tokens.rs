@@ -0,0 +1,122 @@
+//! Odświeżanie tokenów przeciw zastępczemu endpointowi `/oauth2/token` z mocka.
+
+mod common;
+
+use common::start_server;
+use nexdeck_lib::mock_gmail::{MockGmailServer, MockMailbox};
+use nexdeck_lib::testing::{GmailClient, OAuthTokens, TokenStore};
+use std::sync::Arc;
+
+/// Sesja z ważnym refresh tokenem i już wygasłym access tokenem
+async fn logged_in(server: &MockGmailServer) -> TokenStore {
+    let refresh = server.mailbox.lock().unwrap().grant_refresh_token();
+    let store = TokenStore::new(&server.api_config(), None);
+    store
+        .set_tokens(OAuthTokens {
+            access_token: "expired".to_string(),
+            refresh_token: Some(refresh),
+            expires_at: 0,
+        })
+        .await
+        .unwrap();
+    store
+}
+
+fn issued(server: &MockGmailServer) -> u64 {
+    server.mailbox.lock().unwrap().access_tokens_issued()
+}
+
+#[tokio::test]
+async fn expired_token_is_refreshed() {
+    let server = start_server(MockMailbox::new()).await;
+    let store = logged_in(&server).await;
+
+    let token = store.get_token().await.unwrap().unwrap();
+    assert_eq!(token, "mock-access-token-1");
+    assert_eq!(issued(&server), 1);
+
+    // Świeży token z `expires_in` - bez kolejnego odświeżania
+    assert_eq!(store.get_token().await.unwrap().unwrap(), token);
+    assert_eq!(issued(&server), 1);
+    // Google nie zwraca nowego refresh tokenu przy odświeżeniu - stary zostaje
+    let tokens = store.tokens.read().await.clone().unwrap();
+    assert!(tokens.refresh_token.unwrap().starts_with("mock-refresh-granted-"));
+}
+
+#[tokio::test]
+async fn short_lived_token_is_refreshed_before_expiry() {
+    let server = start_server(MockMailbox::new()).await;
+    // Krócej niż margines odświeżania - każdy token jest od razu "prawie wygasły"
+    server.mailbox.lock().unwrap().set_token_lifetime(30);
+    let store = logged_in(&server).await;
+
+    assert_eq!(store.get_token().await.unwrap().unwrap(), "mock-access-token-1");
+    assert_eq!(store.get_token().await.unwrap().unwrap(), "mock-access-token-2");
+}
+
+#[tokio::test]
+async fn unauthorized_request_refreshes_and_retries() {
+    let server = start_server(MockMailbox::new()).await;
+    let store = logged_in(&server).await;
+    let client = GmailClient::new(store.clone(), &server.api_config());
+
+    client.get_profile().await.unwrap();
+    assert_eq!(issued(&server), 1);
+
+    // Gmail odrzuca token przed `expires_in` -> 401, odświeżenie i powtórzenie żądania
+    server.mailbox.lock().unwrap().expire_access_tokens();
+    let profile = client.get_profile().await.unwrap();
+    assert_eq!(profile.email, nexdeck_lib::mock_gmail::MOCK_EMAIL);
+    assert_eq!(issued(&server), 2);
+    assert_eq!(store.get_token().await.unwrap().unwrap(), "mock-access-token-2");
+}
+
+#[tokio::test]
+async fn concurrent_unauthorized_requests_refresh_once() {
+    let server = start_server(MockMailbox::new()).await;
+    let store = logged_in(&server).await;
+    let client = Arc::new(GmailClient::new(store.clone(), &server.api_config()));
+    client.get_profile().await.unwrap();
+
+    server.mailbox.lock().unwrap().expire_access_tokens();
+    let requests: Vec<_> = (0..8)
+        .map(|_| {
+            let client = Arc::clone(&client);
+            tokio::spawn(async move { client.get_profile().await })
+        })
+        .collect();
+    for request in requests {
+        request.await.unwrap().unwrap();
+    }
+
+    // Wszystkie 401 czekały na jedno odświeżenie pod `refresh_lock`
+    assert_eq!(issued(&server), 2);
+}
+
+#[tokio::test]
+async fn concurrent_get_token_refreshes_once() {
+    let server = start_server(MockMailbox::new()).await;
+    let store = logged_in(&server).await;
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move { store.get_token().await })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap().unwrap(), "mock-access-token-1");
+    }
+    assert_eq!(issued(&server), 1);
+}
+
+#[tokio::test]
+async fn revoked_refresh_token_fails() {
+    let server = start_server(MockMailbox::new()).await;
+    let store = logged_in(&server).await;
+    server.mailbox.lock().unwrap().revoke_refresh_tokens();
+
+    let err = store.get_token().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("invalid_grant"), "{:#}", err);
+    assert_eq!(issued(&server), 0);
+}
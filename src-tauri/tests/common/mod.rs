@@ -0,0 +1,72 @@
+//! Wspólne przygotowanie testów integracyjnych: mock Gmaila na losowym porcie i świeża baza cache.
+
+#![allow(dead_code)]
+
+use nexdeck_lib::mock_gmail::{MockGmailServer, MockMailbox, MOCK_ACCESS_TOKEN};
+use nexdeck_lib::testing::{Cache, OAuthTokens, SyncLimits, SyncManager, TokenStore};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Osobny katalog na bazę / tokeny dla każdego testu
+pub fn temp_dir(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "nexdeck-{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub async fn start_server(mailbox: MockMailbox) -> MockGmailServer {
+    MockGmailServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), mailbox).await.unwrap()
+}
+
+/// SyncManager podpięty pod mock, ze stałym tokenem (mock zawsze go przyjmuje)
+pub async fn sync_manager(server: &MockGmailServer, name: &str) -> SyncManager {
+    sync_manager_in(server, &temp_dir(name)).await
+}
+
+/// Jak `sync_manager`, ale z bazą w podanym katalogu (np. ponowne otwarcie tej samej bazy)
+pub async fn sync_manager_in(server: &MockGmailServer, dir: &Path) -> SyncManager {
+    let config = server.api_config();
+    let cache = Cache::new(Some(dir.join("cache.sqlite3"))).unwrap();
+    let tokens = TokenStore::new(&config, None);
+    tokens
+        .set_tokens(OAuthTokens {
+            access_token: MOCK_ACCESS_TOKEN.to_string(),
+            refresh_token: None,
+            expires_at: i64::MAX,
+        })
+        .await
+        .unwrap();
+    SyncManager::new(cache, config, tokens)
+        .await
+        .unwrap()
+        .with_limits(SyncLimits { max_messages_per_label: None, newer_than_days: None })
+}
+
+/// Wiadomość w formacie Gmail API (`format=full`)
+pub fn gmail_message(id: &str, from: &str, subject: &str, labels: &[&str], date_ms: i64) -> Value {
+    json!({
+        "id": id,
+        "threadId": format!("t-{}", id),
+        "labelIds": labels,
+        "snippet": subject,
+        "internalDate": date_ms.to_string(),
+        "payload": {
+            "mimeType": "text/plain",
+            "headers": [
+                { "name": "From", "value": from },
+                { "name": "To", "value": "me@example.com" },
+                { "name": "Subject", "value": subject },
+            ],
+            "body": { "size": 4, "data": "dGVzdA" },
+        },
+    })
+}
@@ -0,0 +1,25 @@
+-- Cache sprzed migracji (baseline): tylko messages i meta, user_version = 0
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"Jan Kowalski <jan@example.com>"},{"name":"To","value":"ola@example.com"},{"name":"Subject","value":"Faktura za marzec"},{"name":"Content-Type","value":"multipart/mixed; boundary=x"}]',
+     '["INBOX","UNREAD"]', 'W załączniku faktura', 1700000000000, 100),
+    ('m2', 't1', '[{"name":"From","value":"ola@example.com"},{"name":"To","value":"jan@example.com"},{"name":"Subject","value":"Re: Faktura za marzec"}]',
+     '["SENT"]', 'Dzięki', 1700000100000, 101),
+    ('m3', 't2', '[{"name":"From","value":"news@example.org"},{"name":"Subject","value":"Newsletter"}]',
+     '["CATEGORY_PROMOTIONS","INBOX"]', 'Nowości', 1700000200000, 102);
+INSERT INTO meta VALUES ('last_history_id', '102');
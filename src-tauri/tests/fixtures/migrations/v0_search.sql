@@ -0,0 +1,43 @@
+-- Cache sprzed migracji z buildów z wyszukiwarką i body (messages_fts, bodies), user_version = 0
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE bodies (
+    message_id TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    attachments_json TEXT NOT NULL,
+    inline_images_json TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Umowa najmu"}]',
+     '["INBOX"]', 'Umowa w załączniku', 1700000000000, 200);
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, 'Umowa najmu', 'jan@example.com', '', 'Umowa w załączniku', 'pełna treść umowy', 1 FROM messages;
+INSERT INTO bodies VALUES
+    ('m1', '<p>pełna treść umowy</p>', 'pełna treść umowy',
+     '[{"id":"a1","filename":"umowa.pdf","size":1024,"mimeType":"application/pdf"}]', '[]', 1700000001000);
+INSERT INTO meta VALUES ('last_history_id', '200');
@@ -0,0 +1,24 @@
+-- Cache po v1 (messages and meta) - bez indeksu FTS
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Umowa najmu"},{"name":"To","value":"ola@example.com"}]',
+     '["INBOX","UNREAD"]', 'Umowa do podpisu', 1700000000000, 200),
+    ('m2', 't1', '[{"name":"From","value":"ola@example.com"},{"name":"Subject","value":"Re: Umowa najmu"}]',
+     '["SENT"]', 'Podpisana', 1700000100000, 201);
+INSERT INTO meta VALUES ('last_history_id', '201');
+PRAGMA user_version = 1;
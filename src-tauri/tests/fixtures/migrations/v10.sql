@@ -0,0 +1,126 @@
+-- Cache po v10 (image senders), przed indeksem wątków
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE bodies (
+    message_id TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    attachments_json TEXT NOT NULL,
+    inline_images_json TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+CREATE TABLE pending_ops (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    op_json TEXT NOT NULL,
+    snapshots_json TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX idx_pending_ops_status ON pending_ops(status, id);
+CREATE TABLE message_labels (
+    label_id TEXT NOT NULL,
+    internal_date INTEGER NOT NULL,
+    message_id TEXT NOT NULL,
+    unread INTEGER NOT NULL,
+    PRIMARY KEY (label_id, internal_date, message_id)
+) WITHOUT ROWID;
+CREATE INDEX idx_message_labels_message ON message_labels(message_id, label_id);
+CREATE INDEX idx_messages_date_id ON messages(internal_date, message_id);
+CREATE TRIGGER messages_labels_insert AFTER INSERT ON messages BEGIN
+    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+    SELECT value, NEW.internal_date, NEW.message_id,
+           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+    FROM json_each(NEW.label_ids_json);
+END;
+CREATE TRIGGER messages_labels_update AFTER UPDATE OF label_ids_json, internal_date ON messages BEGIN
+    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+    SELECT value, NEW.internal_date, NEW.message_id,
+           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+    FROM json_each(NEW.label_ids_json);
+END;
+CREATE TRIGGER messages_labels_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+END;
+CREATE TABLE labels (
+    label_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    type TEXT,
+    message_list_visibility TEXT,
+    label_list_visibility TEXT,
+    text_color TEXT,
+    background_color TEXT
+);
+CREATE TABLE drafts (
+    local_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    draft_id TEXT UNIQUE,
+    message_id TEXT,
+    thread_id TEXT,
+    data_json TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    dirty INTEGER NOT NULL DEFAULT 1
+);
+CREATE TABLE attachments (
+    message_id TEXT NOT NULL,
+    attachment_id TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    last_used_at INTEGER NOT NULL,
+    PRIMARY KEY (message_id, attachment_id)
+);
+CREATE INDEX idx_attachments_file ON attachments(file_name);
+CREATE INDEX idx_attachments_used ON attachments(last_used_at);
+ALTER TABLE bodies ADD COLUMN sanitizer_version INTEGER NOT NULL DEFAULT 0;
+CREATE TABLE image_senders (
+    email TEXT PRIMARY KEY,
+    added_at INTEGER NOT NULL
+);
+
+-- Wątki na różnych etykietach: v11 buduje z nich thread_labels
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Remont"}]',
+     '["INBOX"]', 'Ekipa wchodzi w poniedziałek', 1700000000000, 300),
+    ('m2', 't1', '[{"name":"From","value":"me@example.com"},{"name":"Subject","value":"Re: Remont"}]',
+     '["SENT"]', 'Pasuje', 1700000100000, 301),
+    ('m3', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Re: Remont"}]',
+     '["INBOX","TRASH"]', 'Jednak wtorek', 1700000200000, 302),
+    ('m4', 't2', '[{"name":"From","value":"me@example.com"},{"name":"Subject","value":"Oferta"}]',
+     '["DRAFT"]', 'Szkic oferty', 1700000050000, 303),
+    ('m5', 't3', '[{"name":"From","value":"me@example.com"},{"name":"Subject","value":"Wysłany szkic"}]',
+     '["DRAFT","SENT"]', 'Wysłany', 1700000060000, 304);
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, json_extract(headers_json, '$[1].value'), json_extract(headers_json, '$[0].value'), '', snippet, '', 0 FROM messages;
+INSERT INTO bodies (message_id, body, body_text, attachments_json, inline_images_json, fetched_at, sanitizer_version) VALUES
+    ('m1', '<p>Ekipa wchodzi w poniedziałek</p>', 'Ekipa wchodzi w poniedziałek', '[]', '[]', 1700000001000, 1);
+INSERT INTO image_senders VALUES ('sklep@example.com', 1700000002000);
+INSERT INTO meta VALUES ('last_history_id', '304');
+PRAGMA user_version = 10;
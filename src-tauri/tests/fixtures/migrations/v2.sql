@@ -0,0 +1,34 @@
+-- Cache po v2 (full-text search index)
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Wyniki badań"}]',
+     '["INBOX"]', 'Wyniki w załączniku', 1700000000000, 210);
+-- Wiersz z treścią body (zapisany przez build z v2) - migracje go nie nadpisują
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, 'Wyniki badań', 'jan@example.com', '', 'Wyniki w załączniku', 'morfologia cholesterol', 0 FROM messages;
+INSERT INTO meta VALUES ('last_history_id', '210');
+PRAGMA user_version = 2;
@@ -0,0 +1,44 @@
+-- Cache po v3 (message bodies)
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE bodies (
+    message_id TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    attachments_json TEXT NOT NULL,
+    inline_images_json TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Bilety"}]',
+     '["INBOX","STARRED"]', 'Bilety na koncert', 1700000000000, 220);
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, json_extract(headers_json, '$[1].value'), json_extract(headers_json, '$[0].value'), '', snippet, '', 0 FROM messages;
+INSERT INTO bodies VALUES
+    ('m1', '<p>Bilety w załączniku</p>', 'Bilety w załączniku',
+     '[{"id":"a1","filename":"bilety.pdf","size":512,"mimeType":"application/pdf"}]', '[]', 1700000001000);
+INSERT INTO meta VALUES ('last_history_id', '220');
+PRAGMA user_version = 3;
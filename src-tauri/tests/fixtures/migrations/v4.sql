@@ -0,0 +1,56 @@
+-- Cache po v4 (pending operations outbox)
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE bodies (
+    message_id TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    attachments_json TEXT NOT NULL,
+    inline_images_json TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+CREATE TABLE pending_ops (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    op_json TEXT NOT NULL,
+    snapshots_json TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX idx_pending_ops_status ON pending_ops(status, id);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Spotkanie"}]',
+     '["INBOX","UNREAD","STARRED"]', 'Jutro o 10', 1700000000000, 300),
+    ('m2', 't2', '[{"name":"From","value":"ola@example.com"},{"name":"Subject","value":"Raport"}]',
+     '["INBOX"]', 'Raport tygodniowy', 1700000500000, 301);
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, json_extract(headers_json, '$[1].value'), json_extract(headers_json, '$[0].value'), '', snippet, '', 0 FROM messages;
+INSERT INTO pending_ops (op_json, snapshots_json, status, attempts, next_attempt_at, last_error, created_at) VALUES
+    ('{"kind":"markRead","messageIds":["m1"]}', '[]', 'pending', 2, 1700000600000, 'network', 1700000550000);
+INSERT INTO meta VALUES ('last_history_id', '301');
+PRAGMA user_version = 4;
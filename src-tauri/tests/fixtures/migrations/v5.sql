@@ -0,0 +1,81 @@
+-- Cache po v5 (message labels index); etykiety wiadomości wypełnione triggerami
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE bodies (
+    message_id TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    attachments_json TEXT NOT NULL,
+    inline_images_json TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+CREATE TABLE pending_ops (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    op_json TEXT NOT NULL,
+    snapshots_json TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX idx_pending_ops_status ON pending_ops(status, id);
+CREATE TABLE message_labels (
+    label_id TEXT NOT NULL,
+    internal_date INTEGER NOT NULL,
+    message_id TEXT NOT NULL,
+    unread INTEGER NOT NULL,
+    PRIMARY KEY (label_id, internal_date, message_id)
+) WITHOUT ROWID;
+CREATE INDEX idx_message_labels_message ON message_labels(message_id, label_id);
+CREATE INDEX idx_messages_date_id ON messages(internal_date, message_id);
+CREATE TRIGGER messages_labels_insert AFTER INSERT ON messages BEGIN
+    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+    SELECT value, NEW.internal_date, NEW.message_id,
+           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+    FROM json_each(NEW.label_ids_json);
+END;
+CREATE TRIGGER messages_labels_update AFTER UPDATE OF label_ids_json, internal_date ON messages BEGIN
+    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+    SELECT value, NEW.internal_date, NEW.message_id,
+           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+    FROM json_each(NEW.label_ids_json);
+END;
+CREATE TRIGGER messages_labels_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+END;
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Plan sprintu"}]',
+     '["INBOX","UNREAD"]', 'Plan na ten tydzień', 1700000000000, 250),
+    ('m2', 't1', '[{"name":"From","value":"ola@example.com"},{"name":"Subject","value":"Re: Plan sprintu"}]',
+     '["INBOX","TRASH"]', 'Nieaktualne', 1700000300000, 251);
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, json_extract(headers_json, '$[1].value'), json_extract(headers_json, '$[0].value'), '', snippet, '', 0 FROM messages;
+INSERT INTO pending_ops (op_json, snapshots_json, status, attempts, next_attempt_at, last_error, created_at) VALUES
+    ('{"kind":"trash","messageIds":["m2"]}', '[]', 'pending', 0, 0, NULL, 1700000310000);
+INSERT INTO meta VALUES ('last_history_id', '251');
+PRAGMA user_version = 5;
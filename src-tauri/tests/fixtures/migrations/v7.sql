@@ -0,0 +1,100 @@
+-- Cache po v7 (drafts)
+CREATE TABLE messages (
+    message_id TEXT PRIMARY KEY,
+    thread_id TEXT NOT NULL,
+    headers_json TEXT NOT NULL,
+    label_ids_json TEXT NOT NULL,
+    snippet TEXT,
+    internal_date INTEGER NOT NULL,
+    synced_history_id INTEGER
+);
+CREATE INDEX idx_thread_id ON messages(thread_id);
+CREATE INDEX idx_internal_date ON messages(internal_date DESC);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    subject,
+    sender,
+    recipients,
+    snippet,
+    body,
+    has_attachment UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE bodies (
+    message_id TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    body_text TEXT NOT NULL,
+    attachments_json TEXT NOT NULL,
+    inline_images_json TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+CREATE TABLE pending_ops (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    op_json TEXT NOT NULL,
+    snapshots_json TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX idx_pending_ops_status ON pending_ops(status, id);
+CREATE TABLE message_labels (
+    label_id TEXT NOT NULL,
+    internal_date INTEGER NOT NULL,
+    message_id TEXT NOT NULL,
+    unread INTEGER NOT NULL,
+    PRIMARY KEY (label_id, internal_date, message_id)
+) WITHOUT ROWID;
+CREATE INDEX idx_message_labels_message ON message_labels(message_id, label_id);
+CREATE INDEX idx_messages_date_id ON messages(internal_date, message_id);
+CREATE TRIGGER messages_labels_insert AFTER INSERT ON messages BEGIN
+    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+    SELECT value, NEW.internal_date, NEW.message_id,
+           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+    FROM json_each(NEW.label_ids_json);
+END;
+CREATE TRIGGER messages_labels_update AFTER UPDATE OF label_ids_json, internal_date ON messages BEGIN
+    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+    INSERT OR IGNORE INTO message_labels (label_id, internal_date, message_id, unread)
+    SELECT value, NEW.internal_date, NEW.message_id,
+           EXISTS (SELECT 1 FROM json_each(NEW.label_ids_json) WHERE value = 'UNREAD')
+    FROM json_each(NEW.label_ids_json);
+END;
+CREATE TRIGGER messages_labels_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM message_labels WHERE message_id = OLD.message_id;
+END;
+CREATE TABLE labels (
+    label_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    type TEXT,
+    message_list_visibility TEXT,
+    label_list_visibility TEXT,
+    text_color TEXT,
+    background_color TEXT
+);
+CREATE TABLE drafts (
+    local_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    draft_id TEXT UNIQUE,
+    message_id TEXT,
+    thread_id TEXT,
+    data_json TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    dirty INTEGER NOT NULL DEFAULT 1
+);
+
+INSERT INTO messages VALUES
+    ('m1', 't1', '[{"name":"From","value":"jan@example.com"},{"name":"Subject","value":"Urlop"}]',
+     '["INBOX"]', 'Kiedy urlop?', 1700000000000, 270),
+    ('m9', 't1', '[{"name":"From","value":"me@example.com"},{"name":"Subject","value":"Re: Urlop"}]',
+     '["DRAFT"]', 'W sierpniu', 1700000100000, 271);
+INSERT INTO messages_fts (rowid, subject, sender, recipients, snippet, body, has_attachment)
+    SELECT rowid, json_extract(headers_json, '$[1].value'), json_extract(headers_json, '$[0].value'), '', snippet, '', 0 FROM messages;
+INSERT INTO drafts (draft_id, message_id, thread_id, data_json, updated_at, dirty) VALUES
+    ('d1', 'm9', 't1', '{"to":"jan@example.com","subject":"Re: Urlop","body":"W sierpniu"}', 1700000100000, 1),
+    (NULL, NULL, NULL, '{"to":"","subject":"Szkic","body":"..."}', 1700000200000, 1);
+INSERT INTO meta VALUES ('last_history_id', '271');
+PRAGMA user_version = 7;
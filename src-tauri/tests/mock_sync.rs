@@ -0,0 +1,292 @@
+//! Synchronizacja przeciw mockowi Gmaila: początkowa, przyrostowa z historii i pełny resync po 400.
+
+mod common;
+
+use common::{gmail_message, start_server, sync_manager, sync_manager_in, temp_dir};
+use base64::{engine::general_purpose, Engine as _};
+use nexdeck_lib::mock_gmail::{MailboxScript, MockMailbox};
+use nexdeck_lib::testing::{EmailData, ReplyKind, SearchQuery, SyncManager};
+use serde_json::json;
+
+fn mailbox(inbox: usize, sent: usize) -> MockMailbox {
+    let mut mailbox = MockMailbox::new();
+    for i in 0..inbox {
+        mailbox.insert(gmail_message(&format!("in{}", i), "jan@example.com", &format!("Inbox {}", i), &["INBOX", "UNREAD"], 1_700_000_000_000 + i as i64));
+    }
+    for i in 0..sent {
+        mailbox.insert(gmail_message(&format!("out{}", i), "me@example.com", &format!("Sent {}", i), &["SENT"], 1_700_000_100_000 + i as i64));
+    }
+    mailbox
+}
+
+fn labels(mgr: &SyncManager, id: &str) -> Option<Vec<String>> {
+    let msg = mgr.cache.load_message(id).unwrap()?;
+    let mut labels: Vec<String> = serde_json::from_str(&msg.label_ids_json).unwrap();
+    labels.sort();
+    Some(labels)
+}
+
+fn meta(mgr: &SyncManager, key: &str) -> Option<String> {
+    mgr.cache.get_meta(key).unwrap()
+}
+
+#[tokio::test]
+async fn initial_sync_pages_through_labels() {
+    let server = start_server(mailbox(7, 3)).await;
+    let mgr = sync_manager(&server, "initial-sync").await;
+
+    // Strony po 3 wiadomości - INBOX ma 3 strony
+    mgr.initial_sync(3, "").await.unwrap();
+
+    for i in 0..7 {
+        assert_eq!(labels(&mgr, &format!("in{}", i)).unwrap(), vec!["INBOX", "UNREAD"]);
+    }
+    for i in 0..3 {
+        assert_eq!(labels(&mgr, &format!("out{}", i)).unwrap(), vec!["SENT"]);
+    }
+    let history_id = server.mailbox.lock().unwrap().history_id();
+    assert_eq!(meta(&mgr, "last_history_id"), Some(history_id.to_string()));
+    // Stan wznawiania posprzątany
+    assert_eq!(meta(&mgr, "initial_sync_state"), None);
+    assert_eq!(meta(&mgr, "initial_sync:page_token:INBOX"), None);
+
+    let found = mgr.cache.search_messages(&SearchQuery::parse("subject:\"Inbox 3\""), 10, 0).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].message_id, "in3");
+}
+
+#[tokio::test]
+async fn history_sync_applies_changes() {
+    let server = start_server(mailbox(3, 0)).await;
+    let mgr = sync_manager(&server, "history-sync").await;
+    mgr.initial_sync(100, "").await.unwrap();
+
+    {
+        let mut mb = server.mailbox.lock().unwrap();
+        mb.deliver(gmail_message("new1", "ola@example.com", "Nowa wiadomość", &["INBOX", "UNREAD"], 1_700_000_500_000));
+        mb.modify_labels("in0", &["STARRED".to_string()], &["UNREAD".to_string()]);
+        mb.remove("in1");
+    }
+    mgr.sync_history().await.unwrap();
+
+    assert_eq!(labels(&mgr, "new1").unwrap(), vec!["INBOX", "UNREAD"]);
+    assert_eq!(labels(&mgr, "in0").unwrap(), vec!["INBOX", "STARRED"]);
+    assert_eq!(labels(&mgr, "in1"), None);
+    assert_eq!(labels(&mgr, "in2").unwrap(), vec!["INBOX", "UNREAD"]);
+
+    let history_id = server.mailbox.lock().unwrap().history_id();
+    assert_eq!(meta(&mgr, "last_history_id"), Some(history_id.to_string()));
+
+    // Druga runda bez zmian niczego nie psuje
+    mgr.sync_history().await.unwrap();
+    assert_eq!(meta(&mgr, "last_history_id"), Some(history_id.to_string()));
+}
+
+#[tokio::test]
+async fn history_sync_follows_pages() {
+    let server = start_server(mailbox(0, 0)).await;
+    let mgr = sync_manager(&server, "history-pages").await;
+    mgr.initial_sync(100, "").await.unwrap();
+
+    // Mock zwraca po 100 rekordów na stronę
+    {
+        let mut mb = server.mailbox.lock().unwrap();
+        for i in 0..150 {
+            mb.deliver(gmail_message(&format!("n{}", i), "ola@example.com", "Seria", &["INBOX"], 1_700_000_000_000 + i));
+        }
+    }
+    mgr.sync_history().await.unwrap();
+
+    for i in 0..150 {
+        assert!(mgr.cache.has_message(&format!("n{}", i)).unwrap(), "n{} missing", i);
+    }
+    assert_eq!(meta(&mgr, "history_page_token"), None);
+}
+
+#[tokio::test]
+async fn expired_history_triggers_full_resync() {
+    let server = start_server(mailbox(2, 1)).await;
+    let mgr = sync_manager(&server, "history-expired").await;
+    mgr.initial_sync(100, "").await.unwrap();
+
+    {
+        let mut mb = server.mailbox.lock().unwrap();
+        mb.modify_labels("in0", &[], &["UNREAD".to_string()]);
+        mb.insert(gmail_message("late", "ola@example.com", "Po wygaśnięciu", &["INBOX"], 1_700_000_900_000));
+        // Gmail odpowie 400 na stary startHistoryId
+        mb.expire_history();
+    }
+    mgr.sync_history().await.unwrap();
+
+    assert_eq!(labels(&mgr, "in0").unwrap(), vec!["INBOX"]);
+    assert_eq!(labels(&mgr, "late").unwrap(), vec!["INBOX"]);
+    assert_eq!(labels(&mgr, "out0").unwrap(), vec!["SENT"]);
+
+    let history_id = server.mailbox.lock().unwrap().history_id();
+    assert_eq!(meta(&mgr, "last_history_id"), Some(history_id.to_string()));
+    assert_eq!(meta(&mgr, "resync_history_id"), None);
+    assert_eq!(meta(&mgr, "resync:done:INBOX"), None);
+
+    // Po resyncu historia działa dalej od nowego historyId
+    server.mailbox.lock().unwrap().deliver(gmail_message("after", "ola@example.com", "Dalej", &["INBOX"], 1_700_001_000_000));
+    mgr.sync_history().await.unwrap();
+    assert!(mgr.cache.has_message("after").unwrap());
+}
+
+#[tokio::test]
+async fn failed_batch_keeps_history_id() {
+    let server = start_server(mailbox(1, 0)).await;
+    let mgr = sync_manager(&server, "history-batch-fail").await;
+    mgr.initial_sync(100, "").await.unwrap();
+    let start = meta(&mgr, "last_history_id");
+
+    {
+        let mut mb = server.mailbox.lock().unwrap();
+        mb.deliver(gmail_message("lost", "ola@example.com", "Nie dotarła", &["INBOX"], 1_700_000_500_000));
+        // Każda z 5 prób dostaje 503
+        mb.fail_next_batch_items(5);
+    }
+    assert!(mgr.sync_history().await.is_err());
+    assert!(!mgr.cache.has_message("lost").unwrap());
+    assert_eq!(meta(&mgr, "last_history_id"), start);
+
+    // Następna runda pobiera to samo okno historii jeszcze raz
+    mgr.sync_history().await.unwrap();
+    assert!(mgr.cache.has_message("lost").unwrap());
+    let history_id = server.mailbox.lock().unwrap().history_id();
+    assert_eq!(meta(&mgr, "last_history_id"), Some(history_id.to_string()));
+}
+
+#[tokio::test]
+async fn forward_sends_attachments_from_the_store() {
+    let mut original = gmail_message("m1", "jan@example.com", "Umowa", &["INBOX"], 1_700_000_000_000);
+    original["payload"] = json!({
+        "mimeType": "multipart/mixed",
+        "headers": original["payload"]["headers"].clone(),
+        "parts": [
+            { "partId": "0", "mimeType": "text/plain", "body": { "size": 4, "data": "dGVzdA" } },
+            {
+                "partId": "1",
+                "mimeType": "application/pdf",
+                "filename": "umowa.pdf",
+                "headers": [{ "name": "Content-Disposition", "value": "attachment; filename=\"umowa.pdf\"" }],
+                "body": { "attachmentId": "att-1", "size": 18 },
+            },
+        ],
+    });
+    let script = MailboxScript {
+        messages: vec![original],
+        attachments: [("att-1".to_string(), "%PDF-1.4 zawartosc".to_string())].into(),
+        ..Default::default()
+    };
+    let server = start_server(MockMailbox::from_script(script)).await;
+    let mgr = sync_manager(&server, "forward").await;
+    mgr.initial_sync(10, "").await.unwrap();
+
+    let data = EmailData {
+        to: "anna@example.com".into(),
+        subject: String::new(),
+        body: "Przesyłam dalej".into(),
+        cc: None,
+        bcc: None,
+        attachments: Vec::new(),
+    };
+    let outcome = mgr.reply(ReplyKind::Forward, "m1", &data, "me@example.com", |_, _| {}).await.unwrap();
+    assert!(outcome.id.is_some());
+
+    // Załącznik przeszedł przez magazyn, a wysłana wiadomość ma go w base64
+    let stored = mgr.cache.find_attachment("m1", "att-1", 0).unwrap().expect("attachment in the store");
+    assert_eq!(stored.size, 18);
+    let raw = server.mailbox.lock().unwrap().sent_raw()[0].clone();
+    let sent = String::from_utf8(general_purpose::URL_SAFE_NO_PAD.decode(raw).unwrap()).unwrap();
+    assert!(sent.contains("Subject: Fwd: Umowa\r\n"), "{}", sent);
+    assert!(sent.contains("filename=\"umowa.pdf\""), "{}", sent);
+    assert!(sent.contains(&general_purpose::STANDARD.encode("%PDF-1.4 zawartosc")), "{}", sent);
+}
+
+#[tokio::test]
+async fn sender_image_permission_persists() {
+    let html = general_purpose::URL_SAFE_NO_PAD.encode("<p>Oferta</p><img src=\"https://cdn.shop.pl/baner.png\">");
+    let mut mailbox = MockMailbox::new();
+    for (id, from) in [("m1", "Sklep <Oferty@Shop.pl>"), ("m2", "inny@example.com")] {
+        let mut message = gmail_message(id, from, "Oferta", &["INBOX"], 1_700_000_000_000);
+        message["payload"]["mimeType"] = json!("text/html");
+        message["payload"]["body"] = json!({ "size": 60, "data": html });
+        mailbox.insert(message);
+    }
+    let server = start_server(mailbox).await;
+    let dir = temp_dir("image-senders");
+
+    let mgr = sync_manager_in(&server, &dir).await;
+    let mut message = mgr.fetch_full_message_lazy("m1").await.unwrap();
+    mgr.fetch_full_message_lazy("m2").await.unwrap();
+    mgr.apply_remote_content(&mut message, false).unwrap();
+    assert!(!message.remote_content.sender_allowed);
+    assert!(message.body.contains("data-remote=\"blocked\""), "{}", message.body);
+    assert!(message.remote_content.resources[0].blocked);
+
+    mgr.cache.set_sender_images_allowed("oferty@shop.pl", true).unwrap();
+    drop(mgr);
+
+    // Zgoda zapisana w bazie konta - działa po ponownym otwarciu, bez sieci (treść z cache)
+    let mgr = sync_manager_in(&server, &dir).await;
+    assert_eq!(mgr.cache.image_senders().unwrap(), vec!["oferty@shop.pl"]);
+    let mut message = mgr.fetch_full_message_lazy("m1").await.unwrap();
+    mgr.apply_remote_content(&mut message, false).unwrap();
+    assert!(message.remote_content.sender_allowed);
+    assert!(message.body.contains("<img src=\"https://cdn.shop.pl/baner.png\">"), "{}", message.body);
+    assert!(!message.remote_content.resources[0].blocked);
+
+    // Inny nadawca nadal bez obrazków
+    let mut other = mgr.fetch_full_message_lazy("m2").await.unwrap();
+    mgr.apply_remote_content(&mut other, false).unwrap();
+    assert!(!other.remote_content.sender_allowed);
+    assert!(other.body.contains("data-remote=\"blocked\""));
+
+    mgr.cache.set_sender_images_allowed("Oferty@Shop.pl", false).unwrap();
+    let mut message = mgr.fetch_full_message_lazy("m1").await.unwrap();
+    mgr.apply_remote_content(&mut message, false).unwrap();
+    assert!(message.body.contains("data-remote=\"blocked\""));
+}
+
+#[tokio::test]
+async fn large_message_upload_resumes_after_dropped_chunk() {
+    let server = start_server(MockMailbox::new()).await;
+    server.mailbox.lock().unwrap().fail_next_upload_chunks(1);
+    let mgr = sync_manager(&server, "upload").await;
+
+    // 7 MB w base64 daje ~9.5 MB wiadomości: ponad limit `raw` i więcej niż jeden kawałek uploadu
+    let file = temp_dir("upload-file").join("archiwum.bin");
+    let content: Vec<u8> = (0..7_000_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    std::fs::write(&file, &content).unwrap();
+    let data = EmailData {
+        to: "anna@example.com".into(),
+        subject: "Archiwum".into(),
+        body: "W załączniku".into(),
+        cc: None,
+        bcc: None,
+        attachments: vec![file.to_string_lossy().to_string()],
+    };
+    let mut reports = Vec::new();
+    let outcome = mgr.send_email(&data, |sent, total| reports.push((sent, total))).await.unwrap();
+    assert!(outcome.id.is_some());
+    assert!(outcome.queued_op_id.is_none());
+
+    // Postęp rośnie od zera do całości; po zerwanym kawałku sesja zgłasza jego połowę (4 MiB z 8 MiB)
+    let total = reports[0].1;
+    assert!(total > 5 * 1024 * 1024, "{}", total);
+    assert!(reports.iter().all(|(_, t)| *t == total));
+    assert!(reports.windows(2).all(|w| w[0].0 <= w[1].0), "{:?}", reports);
+    assert_eq!(reports.first().unwrap().0, 0);
+    assert_eq!(reports.last().unwrap().0, total);
+    assert!(reports.contains(&(4 * 1024 * 1024, total)), "{:?}", reports);
+
+    // Gmail dostał całą wiadomość bez dziur i powtórzeń
+    let raw = server.mailbox.lock().unwrap().sent_raw()[0].clone();
+    let sent = general_purpose::URL_SAFE_NO_PAD.decode(raw).unwrap();
+    assert_eq!(sent.len() as u64, total);
+    let sent = String::from_utf8(sent).unwrap();
+    assert!(sent.contains("filename=\"archiwum.bin\""));
+    let encoded: String = sent.split("\r\n\r\n").last().unwrap().lines().take_while(|l| !l.starts_with("--")).collect();
+    assert_eq!(general_purpose::STANDARD.decode(encoded).unwrap(), content);
+}
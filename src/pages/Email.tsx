@@ -1,6 +1,7 @@
 import { useState, useEffect, useCallback, useRef } from 'react';
+import { getCurrentWebview } from '@tauri-apps/api/webview';
 import EmailSidebar from '../components/sidebar/EmailSidebar';
-import emailAPI, { type EmailMessage, type UserProfile, type MailboxStats } from '../services/emailAPI-rust';
+import emailAPI, { type EmailMessage, type UserProfile, type MailboxStats, type ReplyKind } from '../services/emailAPI-rust';
 
 interface EmailProps {
   sidebarVisible: boolean;
@@ -16,8 +17,21 @@ function Email({ sidebarVisible }: EmailProps) {
   const [composeData, setComposeData] = useState({
     to: '',
     subject: '',
-    body: ''
+    body: '',
+    // Ścieżki plików z dysku (przeciągnięte do edytora albo wpisane)
+    attachments: [] as string[]
   });
+  const [attachmentPath, setAttachmentPath] = useState('');
+  // Postęp wysyłki w procentach; null = nic nie jest wysyłane
+  const [sendProgress, setSendProgress] = useState<number | null>(null);
+  // Postęp pobierania załączników: attachmentId -> procent
+  const [attachmentProgress, setAttachmentProgress] = useState<Record<string, number>>({});
+  // Odpowiedź / przekazanie: adresatów, cytat i nagłówki wątku dokłada Rust przy wysyłce
+  const [replyContext, setReplyContext] = useState<{ kind: ReplyKind; messageId: string } | null>(null);
+  // Wersja robocza otwartego edytora (autozapis z opóźnieniem)
+  const [draftId, setDraftId] = useState<number | null>(null);
+  const draftTimerRef = useRef<ReturnType<typeof setTimeout> | null>(null);
+  const draftSaveRef = useRef<Promise<number | null> | null>(null);
   const [userProfile, setUserProfile] = useState<UserProfile | null>(null);
   const [selectedLabel, setSelectedLabel] = useState('INBOX');
   const [nextPageToken, setNextPageToken] = useState<string | undefined>(undefined);
@@ -255,6 +269,24 @@ function Email({ sidebarVisible }: EmailProps) {
     }
   };
 
+  // Zablokowane zdalne obrazki: jednorazowo albo na stałe dla nadawcy
+  const loadRemoteImages = async (email: EmailMessage) => {
+    try {
+      setSelectedEmail(await emailAPI.getEmail(email.id, email.accountId, true));
+    } catch (error) {
+      console.error('❌ Loading images failed:', error);
+    }
+  };
+
+  const setSenderImages = async (email: EmailMessage, allowed: boolean) => {
+    try {
+      await emailAPI.setSenderImages(senderEmail(email), allowed, email.accountId);
+      setSelectedEmail(await emailAPI.getEmail(email.id, email.accountId));
+    } catch (error) {
+      console.error('❌ Saving image setting failed:', error);
+    }
+  };
+
   const handleEmailClick = async (email: EmailMessage) => {
     try {
       const fullEmail = await emailAPI.getEmail(email.id);
@@ -281,11 +313,150 @@ function Email({ sidebarVisible }: EmailProps) {
     }
   };
 
+  const saveDraftNow = useCallback(async (data: typeof composeData, localId: number | null) => {
+    const draft = await emailAPI.saveDraft(localId, data);
+    setDraftId(draft.localId);
+    return draft.localId;
+  }, []);
+
+  // Autozapis: 1.5 s po ostatniej zmianie w edytorze
+  useEffect(() => {
+    if (!showCompose || (!composeData.to && !composeData.subject && !composeData.body)) return;
+    draftTimerRef.current = setTimeout(() => {
+      draftTimerRef.current = null;
+      draftSaveRef.current = saveDraftNow(composeData, draftId).catch(error => {
+        console.error('Error saving draft:', error);
+        return draftId;
+      });
+    }, 1500);
+    return () => {
+      if (draftTimerRef.current) clearTimeout(draftTimerRef.current);
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [composeData, showCompose]);
+
+  // Zamknięcie edytora zapisuje zmiany, na które autozapis jeszcze czekał
+  const closeCompose = () => {
+    if (draftTimerRef.current) {
+      clearTimeout(draftTimerRef.current);
+      draftTimerRef.current = null;
+      saveDraftNow(composeData, draftId).catch(error => console.error('Error saving draft:', error));
+    }
+    draftSaveRef.current = null;
+    setShowCompose(false);
+    setDraftId(null);
+    setReplyContext(null);
+    setComposeData({ to: '', subject: '', body: '', attachments: [] });
+    setAttachmentPath('');
+  };
+
+  const addAttachments = (paths: string[]) => {
+    setComposeData(prev => ({
+      ...prev,
+      attachments: [...prev.attachments, ...paths.filter(p => p.trim() && !prev.attachments.includes(p))]
+    }));
+  };
+
+  const removeAttachment = (path: string) => {
+    setComposeData(prev => ({ ...prev, attachments: prev.attachments.filter(p => p !== path) }));
+  };
+
+  // Pliki przeciągnięte na okno trafiają do otwartego edytora
+  useEffect(() => {
+    if (!showCompose) return;
+    const unlisten = getCurrentWebview().onDragDropEvent(event => {
+      if (event.payload.type === 'drop') addAttachments(event.payload.paths);
+    });
+    return () => {
+      unlisten.then(stop => stop());
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [showCompose]);
+
+  useEffect(() => {
+    const unlisten = emailAPI.onSendProgress(({ sent, total }) => {
+      setSendProgress(total > 0 ? Math.round((sent / total) * 100) : 0);
+    });
+    return () => {
+      unlisten.then(stop => stop());
+    };
+  }, []);
+
+  // Cache jest już cofnięty - przeładuj listę i powiedz, czego Gmail nie przyjął
+  useEffect(() => {
+    const unlisten = emailAPI.onOutboxOpFailed(op => {
+      console.error('Outbox op failed:', op);
+      loadEmails(currentLabelRef.current, false);
+      const reason = op.status === 'dead_letter' ? 'brak połączenia z Gmailem po wielu próbach' : op.lastError;
+      alert(`Nie udało się zapisać zmiany w Gmailu (${reason})`);
+    });
+    return () => {
+      unlisten.then(stop => stop());
+    };
+  }, [loadEmails]);
+
+  useEffect(() => {
+    const unlisten = emailAPI.onAttachmentProgress(({ attachmentId, received, total }) => {
+      setAttachmentProgress(prev => ({ ...prev, [attachmentId]: total > 0 ? Math.round((received / total) * 100) : 0 }));
+    });
+    return () => {
+      unlisten.then(stop => stop());
+    };
+  }, []);
+
+  const openAttachment = async (messageId: string, attachmentId: string) => {
+    try {
+      await emailAPI.downloadAttachment(messageId, attachmentId, true);
+    } catch (error) {
+      console.error('Error opening attachment:', error);
+      alert('Błąd pobierania załącznika');
+    } finally {
+      setAttachmentProgress(prev => {
+        const { [attachmentId]: _done, ...rest } = prev;
+        return rest;
+      });
+    }
+  };
+
+  const openReply = (kind: ReplyKind, email: EmailMessage) => {
+    const prefix = kind === 'forward' ? 'Fwd: ' : 'Re: ';
+    const alreadyPrefixed = kind === 'forward' ? /^(fwd?):/i.test(email.subject) : /^re:/i.test(email.subject);
+    setComposeData({
+      to: kind === 'forward' ? '' : email.from,
+      subject: alreadyPrefixed ? email.subject : `${prefix}${email.subject}`,
+      body: '',
+      attachments: []
+    });
+    setReplyContext({ kind, messageId: email.id });
+    setShowCompose(true);
+  };
+
   const handleSendEmail = async () => {
+    setSendProgress(0);
     try {
-      await emailAPI.sendEmail(composeData);
-      setShowCompose(false);
-      setComposeData({ to: '', subject: '', body: '' });
+      if (draftTimerRef.current) {
+        clearTimeout(draftTimerRef.current);
+        draftTimerRef.current = null;
+      }
+      // Trwający autozapis mógł właśnie utworzyć wersję roboczą
+      const pendingId = draftSaveRef.current ? await draftSaveRef.current : null;
+      const localId = draftId ?? pendingId;
+      if (replyContext) {
+        await emailAPI.replyEmail(replyContext.kind, replyContext.messageId, composeData);
+        // Wersja robocza była tylko zabezpieczeniem treści - odpowiedź poszła z nagłówkami wątku
+        if (localId != null) await emailAPI.deleteDraft(localId);
+      } else if (composeData.attachments.length > 0) {
+        // Pliki z dysku nie trafiają do wersji roboczej w Gmailu - wiadomość idzie bezpośrednio
+        await emailAPI.sendEmail(composeData);
+        if (localId != null) await emailAPI.deleteDraft(localId);
+      } else if (localId != null) {
+        await saveDraftNow(composeData, localId);
+        await emailAPI.sendDraft(localId);
+      } else {
+        await emailAPI.sendEmail(composeData);
+      }
+      draftSaveRef.current = null;
+      closeCompose();
 
       loadEmails(selectedLabel, false);
       loadMailboxStats();
@@ -293,6 +464,8 @@ function Email({ sidebarVisible }: EmailProps) {
     } catch (error) {
       console.error('Error sending email:', error);
       alert('Błąd wysyłania wiadomości');
+    } finally {
+      setSendProgress(null);
     }
   };
 
@@ -465,6 +638,14 @@ function Email({ sidebarVisible }: EmailProps) {
       border-top: 1px solid rgba(255, 255, 255, 0.1);
     }
     
+    /* Placeholder zablokowanego zdalnego obrazka */
+    img[data-remote="blocked"] {
+      display: inline-block;
+      min-width: 24px;
+      min-height: 24px;
+      border: 1px dashed rgba(255, 255, 255, 0.2);
+    }
+    
     /* Zapobiegaj bardzo szerokim tabelom */
     div, span {
       max-width: 100%;
@@ -560,6 +741,16 @@ function Email({ sidebarVisible }: EmailProps) {
     return email.substring(0, 2).toUpperCase();
   };
 
+  // Nadawca z adresów sparsowanych w Rust; bez nich (starszy backend) - z surowego nagłówka
+  const senderName = (email: EmailMessage) => {
+    const sender = email.addresses?.from[0];
+    if (sender) return sender.name || sender.email;
+    return email.from.split('<')[0].trim().replace(/^"|"$/g, '') || email.from;
+  };
+
+  const senderEmail = (email: EmailMessage) =>
+    email.addresses?.from[0]?.email || email.from.match(/<(.+)>/)?.[1] || email.from;
+
   const formatDate = (dateString: string) => {
     const date = new Date(dateString);
     const now = new Date();
@@ -706,12 +897,12 @@ function Email({ sidebarVisible }: EmailProps) {
                     >
                       <div className="flex items-start gap-3">
                         <div className="w-10 h-10 bg-gradient-to-br from-[#5b9dff] to-[#a87bc4] rounded-full flex items-center justify-center text-sm font-semibold flex-shrink-0">
-                          {getInitials(email.from)}
+                          {getInitials(senderEmail(email))}
                         </div>
                         <div className="flex-1 min-w-0">
                           <div className="flex items-start justify-between gap-2 mb-1">
                             <h4 className={`font-medium truncate ${email.unread ? 'text-white' : 'text-white/80'}`}>
-                              {email.from.split('<')[0].trim() || email.from}
+                              {senderName(email)}
                             </h4>
                             <span className="text-xs text-white/40 flex-shrink-0">{formatDate(email.date)}</span>
                           </div>
@@ -770,28 +961,33 @@ function Email({ sidebarVisible }: EmailProps) {
                 </div>
                 <div className="flex items-start gap-3 mb-4">
                   <div className="w-12 h-12 bg-gradient-to-br from-[#5b9dff] to-[#a87bc4] rounded-full flex items-center justify-center font-semibold">
-                    {getInitials(selectedEmail.from)}
+                    {getInitials(senderEmail(selectedEmail))}
                   </div>
                   <div className="flex-1">
-                    <div className="font-medium">{selectedEmail.from.split('<')[0].trim()}</div>
-                    <div className="text-sm text-white/40">{selectedEmail.from.match(/<(.+)>/)?.[1] || selectedEmail.from}</div>
+                    <div className="font-medium">{senderName(selectedEmail)}</div>
+                    <div className="text-sm text-white/40">{senderEmail(selectedEmail)}</div>
                   </div>
                   <div className="text-sm text-white/40">{formatDate(selectedEmail.date)}</div>
                 </div>
                 <div className="flex gap-2">
                   <button
-                    onClick={() => {
-                      setComposeData({
-                        to: selectedEmail.from.match(/<(.+)>/)?.[1] || selectedEmail.from,
-                        subject: `Re: ${selectedEmail.subject}`,
-                        body: `\n\n---\n${selectedEmail.body}`
-                      });
-                      setShowCompose(true);
-                    }}
+                    onClick={() => openReply('reply', selectedEmail)}
                     className="px-3 py-1.5 bg-white/5 hover:bg-white/10 rounded text-sm transition-colors"
                   >
                     Odpowiedz
                   </button>
+                  <button
+                    onClick={() => openReply('replyAll', selectedEmail)}
+                    className="px-3 py-1.5 bg-white/5 hover:bg-white/10 rounded text-sm transition-colors"
+                  >
+                    Odpowiedz wszystkim
+                  </button>
+                  <button
+                    onClick={() => openReply('forward', selectedEmail)}
+                    className="px-3 py-1.5 bg-white/5 hover:bg-white/10 rounded text-sm transition-colors"
+                  >
+                    Przekaż
+                  </button>
                   <button
                     onClick={() => handleDeleteEmail(selectedEmail.id)}
                     className="px-3 py-1.5 bg-red-600/20 hover:bg-red-600/30 text-red-400 rounded text-sm transition-colors"
@@ -802,6 +998,40 @@ function Email({ sidebarVisible }: EmailProps) {
               </div>
 
               <div className="flex-1 overflow-y-auto custom-scrollbar p-6">
+                {(() => {
+                  const resources = selectedEmail.remoteContent?.resources ?? [];
+                  const blocked = resources.filter(r => r.blocked);
+                  const trackers = resources.filter(r => r.tracking && r.blocked);
+                  const senderAllowed = selectedEmail.remoteContent?.senderAllowed ?? false;
+                  if (blocked.length === 0 && !senderAllowed) return null;
+                  return (
+                    <div className="mb-4 px-4 py-2 rounded-lg bg-white/5 text-sm text-white/60 flex items-center gap-3 flex-wrap">
+                      <span>
+                        {blocked.length > trackers.length
+                          ? `Zablokowano zdalne obrazki (${blocked.length})`
+                          : senderAllowed
+                            ? 'Obrazki od tego nadawcy ładują się automatycznie'
+                            : 'Zablokowano zdalne obrazki'}
+                        {trackers.length > 0 && `, w tym piksele śledzące: ${trackers.length}`}
+                      </span>
+                      {blocked.length > trackers.length && (
+                        <button
+                          onClick={() => loadRemoteImages(selectedEmail)}
+                          className="px-3 py-1 rounded bg-white/10 hover:bg-white/20 transition-colors"
+                        >
+                          Pokaż obrazki
+                        </button>
+                      )}
+                      <button
+                        onClick={() => setSenderImages(selectedEmail, !senderAllowed)}
+                        className="px-3 py-1 rounded bg-white/10 hover:bg-white/20 transition-colors"
+                      >
+                        {senderAllowed ? 'Nie ładuj automatycznie' : `Zawsze od ${senderEmail(selectedEmail)}`}
+                      </button>
+                    </div>
+                  );
+                })()}
+
                 {/* ✅ IFRAME dla email body - pełna izolacja CSS */}
                 <iframe
                   ref={(iframe) => {
@@ -862,10 +1092,12 @@ function Email({ sidebarVisible }: EmailProps) {
                             rel="noopener noreferrer"
                             className="flex items-center gap-4 p-4 bg-[#5b9dff]/10 hover:bg-[#5b9dff]/20 border border-[#5b9dff]/30 rounded-lg transition-all group"
                             onClick={(e) => {
+                              e.preventDefault();
                               if (!attachment.id) {
-                                e.preventDefault();
                                 alert('Załącznik niedostępny');
+                                return;
                               }
+                              openAttachment(selectedEmail.id, attachment.id);
                             }}
                           >
                             <div className="w-12 h-12 bg-[#5b9dff]/30 rounded-lg flex items-center justify-center flex-shrink-0">
@@ -877,6 +1109,7 @@ function Email({ sidebarVisible }: EmailProps) {
                               <div className="text-base font-semibold truncate text-white">{attachment.filename}</div>
                               <div className="text-sm text-white/60">
                                 {(attachment.size / 1024).toFixed(1)} KB • {attachment.mimeType}
+                                {attachment.id && attachmentProgress[attachment.id] != null && ` • ${attachmentProgress[attachment.id]}%`}
                               </div>
                             </div>
                             <svg className="w-6 h-6 text-[#5b9dff]/60 group-hover:text-[#5b9dff] transition-colors" fill="none" stroke="currentColor" viewBox="0 0 24 24">
@@ -900,7 +1133,7 @@ function Email({ sidebarVisible }: EmailProps) {
             <div className="flex items-center justify-between p-4 border-b border-white/5">
               <h3 className="text-lg font-semibold">Nowa wiadomość</h3>
               <button
-                onClick={() => setShowCompose(false)}
+                onClick={closeCompose}
                 className="text-white/40 hover:text-white/60 transition-colors"
               >
                 <svg className="w-6 h-6" fill="none" stroke="currentColor" viewBox="0 0 24 24">
@@ -940,21 +1173,58 @@ function Email({ sidebarVisible }: EmailProps) {
                   placeholder="Treść wiadomości..."
                 />
               </div>
+              <div>
+                <label className="block text-sm text-white/60 mb-2">Załączniki</label>
+                {composeData.attachments.length > 0 && (
+                  <ul className="mb-2 space-y-1">
+                    {composeData.attachments.map(path => (
+                      <li key={path} className="flex items-center justify-between gap-2 px-3 py-1.5 bg-[#15161b] rounded-lg text-sm">
+                        <span className="truncate" title={path}>{path.split(/[\\/]/).pop()}</span>
+                        <button
+                          onClick={() => removeAttachment(path)}
+                          className="text-white/40 hover:text-white/60 transition-colors"
+                        >
+                          Usuń
+                        </button>
+                      </li>
+                    ))}
+                  </ul>
+                )}
+                <div className="flex gap-2">
+                  <input
+                    type="text"
+                    value={attachmentPath}
+                    onChange={(e) => setAttachmentPath(e.target.value)}
+                    className="flex-1 px-4 py-2 bg-[#15161b] border border-white/10 rounded-lg focus:outline-none focus:border-[#5b9dff] transition-colors"
+                    placeholder="Ścieżka pliku albo przeciągnij pliki na okno"
+                  />
+                  <button
+                    onClick={() => {
+                      addAttachments([attachmentPath.trim()]);
+                      setAttachmentPath('');
+                    }}
+                    disabled={!attachmentPath.trim()}
+                    className="px-4 py-2 bg-white/5 hover:bg-white/10 rounded-lg transition-colors disabled:opacity-50 disabled:cursor-not-allowed"
+                  >
+                    Dodaj
+                  </button>
+                </div>
+              </div>
             </div>
 
             <div className="flex items-center justify-end gap-2 p-4 border-t border-white/5">
               <button
-                onClick={() => setShowCompose(false)}
+                onClick={closeCompose}
                 className="px-4 py-2 bg-white/5 hover:bg-white/10 rounded-lg transition-colors"
               >
                 Anuluj
               </button>
               <button
                 onClick={handleSendEmail}
-                disabled={!composeData.to || !composeData.body}
+                disabled={!composeData.to || !composeData.body || sendProgress != null}
                 className="px-4 py-2 bg-[#5b9dff] hover:bg-[#4a8ce6] rounded-lg transition-colors disabled:opacity-50 disabled:cursor-not-allowed"
               >
-                Wyślij
+                {sendProgress != null ? `Wysyłanie… ${sendProgress}%` : 'Wyślij'}
               </button>
             </div>
           </div>
@@ -1,7 +1,11 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 const API_BASE_URL = 'http://localhost:3001';
 
+// ✅ accountId widoku "wszystkie skrzynki"
+export const ALL_ACCOUNTS = 'all';
+
 // Types
 export interface Attachment {
   id: string;
@@ -18,6 +22,8 @@ export interface EmailData {
   body: string;
   cc?: string;
   bcc?: string;
+  // Ścieżki plików z dysku do załączenia (tylko backend Rust)
+  attachments?: string[];
 }
 
 export interface EmailMessage {
@@ -34,6 +40,97 @@ export interface EmailMessage {
   hasAttachment: boolean;
   attachments: Attachment[];
   inlineImages: Attachment[];
+  accountId?: string;
+  /** Adresy rozłożone z nagłówków; `from`/`to` to surowe napisy */
+  addresses?: MessageAddresses;
+  /** Zdalne obrazki z treści i co z nich zablokowano */
+  remoteContent?: RemoteContent;
+}
+
+export interface RemoteResource {
+  url: string;
+  kind: 'image' | 'background' | 'css';
+  /** Podejrzenie piksela śledzącego */
+  tracking: 'pixel' | 'tracker-domain' | 'unique-query' | null;
+  blocked: boolean;
+}
+
+export interface RemoteContent {
+  resources: RemoteResource[];
+  senderAllowed: boolean;
+}
+
+export interface EmailAddress {
+  name: string | null;
+  email: string;
+}
+
+export interface MessageAddresses {
+  from: EmailAddress[];
+  to: EmailAddress[];
+  cc: EmailAddress[];
+  bcc: EmailAddress[];
+  replyTo: EmailAddress[];
+}
+
+export interface Account {
+  id: string;
+  email: string;
+  addedAt: number;
+}
+
+export interface SendOutcome {
+  id: string | null;
+  queuedOpId: number | null;
+}
+
+export interface AttachmentFile {
+  path: string;
+  filename: string;
+  mimeType: string;
+  size: number;
+  sha256: string;
+  cached: boolean;
+}
+
+export interface AttachmentProgress {
+  messageId: string;
+  attachmentId: string;
+  received: number;
+  total: number;
+}
+
+export interface SendProgress {
+  sent: number;
+  total: number;
+}
+
+export type ReplyKind = 'reply' | 'replyAll' | 'forward';
+
+export interface PendingOp {
+  id: number;
+  op: {
+    kind: 'modifyLabels' | 'trash' | 'untrash' | 'delete' | 'send' | 'sendFile' | 'saveDraft' | 'sendDraft' | 'deleteDraft';
+    [key: string]: unknown;
+  };
+  status: 'pending' | 'failed' | 'dead_letter';
+  attempts: number;
+  nextAttemptAt: number;
+  lastError: string | null;
+  createdAt: number;
+  accountId?: string;
+}
+
+// Wersja robocza z lokalnego cache (edytowalna offline)
+export interface Draft {
+  localId: number;
+  draftId: string | null;
+  messageId: string | null;
+  threadId: string | null;
+  data: EmailData;
+  updatedAt: number;
+  // Aktualna treść zapisana już w Gmailu
+  synced: boolean;
 }
 
 export interface EmailListResponse {
@@ -41,10 +138,32 @@ export interface EmailListResponse {
   nextPageToken?: string;
 }
 
+export interface EmailThread {
+  threadId: string;
+  messages: EmailMessage[]; // tylko z getThread
+  lastActivity: number;
+  subject: string;
+  snippet: string;
+  from: string;
+  participants: string[];
+  date: string;
+  unread: boolean;
+  hasAttachment: boolean;
+  labelIds: string[];
+  messageCount: number;
+  accountId?: string;
+}
+
+export interface ThreadListResponse {
+  threads: EmailThread[];
+  nextPageToken?: string;
+}
+
 export interface GetEmailsOptions {
   maxResults?: number;
   pageToken?: string;
   labelIds?: string;
+  accountId?: string; // brak = domyślne konto, ALL_ACCOUNTS = wszystkie
 }
 
 export interface UserProfile {
@@ -53,16 +172,32 @@ export interface UserProfile {
   picture?: string;
 }
 
+export interface LabelColor {
+  textColor: string;
+  backgroundColor: string;
+}
+
 export interface Label {
   id: string;
+  // Pełna nazwa, zagnieżdżenie przez "/" (np. "Praca/Projekty")
   name: string;
   type: string;
+  messageListVisibility?: 'show' | 'hide';
+  labelListVisibility?: 'labelShow' | 'labelShowIfUnread' | 'labelHide';
+  color?: LabelColor;
 }
 
+// Pola do utworzenia / zmiany etykiety - pominięte zostają bez zmian
+export type LabelUpdate = Partial<Omit<Label, 'id' | 'type'>>;
+
 export interface MailboxStats {
   [labelId: string]: {
     id: string;
     name: string;
+    path?: string;
+    parentId?: string | null;
+    type?: string | null;
+    color?: LabelColor | null;
     total: number;
     unread: number;
   };
@@ -139,19 +274,14 @@ class EmailAPI {
     const initPromise = (async () => {
       try {
         console.log('🔄 Initializing Rust client...');
-        const response = await fetch(`${API_BASE_URL}/auth/token`);
-        if (!response.ok) {
-          throw new Error(`Token request failed: ${response.status}`);
-        }
-        const data = await response.json();
-        if (!data.accessToken) {
-          throw new Error('accessToken missing in response');
+        // ✅ Tokeny trzyma Rust (refresh token w zaszyfrowanym pliku) - logowanie tylko gdy go brak
+        try {
+          await invoke('init_gmail_client', {});
+        } catch (error) {
+          console.log('🔐 No stored session, starting OAuth login...', error);
+          await invoke('login_rust');
+          await invoke('init_gmail_client', {});
         }
-        this.accessToken = data.accessToken;
-
-        await invoke('init_gmail_client', {
-          accessToken: data.accessToken,
-        });
 
         this.rustInitialized = true;
         console.log('✅ Rust Gmail client initialized successfully');
@@ -175,20 +305,46 @@ class EmailAPI {
     this.labelCache.clear();
     this.bodyCache.clear();
     this.pendingRequests.clear();
+    if (this.useRust) {
+      await invoke('logout_rust', {});
+    }
     const response = await fetch(`${API_BASE_URL}/auth/logout`, {
       method: 'POST',
     });
     return response.json();
   }
 
+  // ===== ACCOUNTS =====
+
+  async listAccounts(): Promise<Account[]> {
+    if (!this.useRust) return [];
+    return invoke<Account[]>('list_accounts_rust');
+  }
+
+  // ✅ Logowanie kolejnego konta Gmail + start jego synchronizacji
+  async addAccount(): Promise<Account> {
+    const account = await invoke<Account>('login_rust');
+    await invoke('init_gmail_client', { accountId: account.id });
+    this.rustInitialized = true;
+    this.labelCache.clear();
+    return account;
+  }
+
+  async removeAccount(accountId: string): Promise<void> {
+    await invoke('logout_rust', { accountId });
+    this.labelCache.clear();
+    this.bodyCache.clear();
+  }
+
   // ===== EMAIL OPERATIONS =====
 
   async getEmails(options: GetEmailsOptions = {}): Promise<EmailListResponse> {
-    const { maxResults = 20, pageToken, labelIds = 'INBOX' } = options;
+    const { maxResults = 20, pageToken, labelIds = 'INBOX', accountId } = options;
+    const cacheKey = accountId ? `${accountId}:${labelIds}` : labelIds;
     
     // ✅ Check cache first (tylko dla pierwszej strony)
     if (!pageToken) {
-      const cached = this.labelCache.get(labelIds);
+      const cached = this.labelCache.get(cacheKey);
       const now = Date.now();
       
       if (cached && (now - cached.fetchedAt) < this.cacheTTL) {
@@ -201,13 +357,13 @@ class EmailAPI {
     }
 
     // ✅ Avoid duplicate requests
-    const requestKey = `emails-${labelIds}-${pageToken || 'first'}`;
+    const requestKey = `emails-${cacheKey}-${pageToken || 'first'}`;
     if (this.pendingRequests.has(requestKey)) {
       console.log('⏳ Request already in progress:', requestKey);
       return this.pendingRequests.get(requestKey)!;
     }
 
-    const requestPromise = this._fetchEmails(labelIds, maxResults, pageToken);
+    const requestPromise = this._fetchEmails(labelIds, maxResults, pageToken, accountId);
     this.pendingRequests.set(requestKey, requestPromise);
 
     try {
@@ -215,7 +371,7 @@ class EmailAPI {
       
       // ✅ Cache result (tylko dla pierwszej strony)
       if (!pageToken) {
-        this.labelCache.set(labelIds, {
+        this.labelCache.set(cacheKey, {
           messages: result.messages,
           fetchedAt: Date.now(),
           nextPageToken: result.nextPageToken
@@ -228,7 +384,7 @@ class EmailAPI {
     }
   }
 
-  private async _fetchEmails(labelIds: string, maxResults: number, pageToken?: string): Promise<EmailListResponse> {
+  private async _fetchEmails(labelIds: string, maxResults: number, pageToken?: string, accountId?: string): Promise<EmailListResponse> {
     if (this.useRust) {
       if (!this.rustInitialized) {
         await this.initRustClient();
@@ -237,7 +393,8 @@ class EmailAPI {
         try {
           console.log('⚡ Fetching from Rust:', labelIds);
           const result = await invoke<EmailListResponse>('get_emails_rust', {
-            options: { maxResults, pageToken, labelIds }
+            options: { maxResults, pageToken, labelIds },
+            accountId
           });
           return { messages: result.messages || [], nextPageToken: result.nextPageToken };
         } catch (error) {
@@ -261,7 +418,12 @@ class EmailAPI {
     return { messages: result.messages || [], nextPageToken: result.nextPageToken };
   }
 
-  async getEmail(id: string): Promise<EmailMessage> {
+  async getEmail(id: string, accountId?: string, loadImages = false): Promise<EmailMessage> {
+    // Jednorazowe załadowanie obrazków - bez cache, żeby kolejne otwarcie znów je blokowało
+    if (loadImages) {
+      return this._fetchEmail(id, accountId, true);
+    }
+
     // ✅ Check body cache
     const cached = this.bodyCache.get(id);
     if (cached) {
@@ -276,7 +438,7 @@ class EmailAPI {
       return this.pendingRequests.get(requestKey)!;
     }
 
-    const requestPromise = this._fetchEmail(id);
+    const requestPromise = this._fetchEmail(id, accountId);
     this.pendingRequests.set(requestKey, requestPromise);
 
     try {
@@ -292,7 +454,7 @@ class EmailAPI {
     }
   }
 
-  private async _fetchEmail(id: string): Promise<EmailMessage> {
+  private async _fetchEmail(id: string, accountId?: string, loadImages = false): Promise<EmailMessage> {
     if (this.useRust) {
       if (!this.rustInitialized) {
         await this.initRustClient();
@@ -300,7 +462,7 @@ class EmailAPI {
       if (this.rustInitialized) {
         try {
           console.log('⚡ Fetching email from Rust:', id);
-          return await invoke<EmailMessage>('get_email_rust', { messageId: id });
+          return await invoke<EmailMessage>('get_email_rust', { messageId: id, accountId, loadImages });
         } catch (error) {
           console.error('Rust getEmail failed, falling back to Node.js:', error);
         }
@@ -315,9 +477,61 @@ class EmailAPI {
     return response.json();
   }
 
+  // ✅ Wyszukiwanie offline (FTS5 w cache Rust), składnia jak w Gmailu
+  async searchEmails(query: string, options: { maxResults?: number; pageToken?: string } = {}): Promise<EmailListResponse> {
+    const { maxResults = 20, pageToken } = options;
+
+    if (!this.useRust) {
+      return { messages: [] };
+    }
+    if (!this.rustInitialized) {
+      await this.initRustClient();
+    }
+
+    const result = await invoke<EmailListResponse>('search_emails_rust', {
+      options: { query, maxResults, pageToken }
+    });
+    return { messages: result.messages || [], nextPageToken: result.nextPageToken };
+  }
+
+  // Widok konwersacji - lista wątków etykiety (bez treści wiadomości)
+  async getThreads(options: GetEmailsOptions = {}): Promise<ThreadListResponse> {
+    const { maxResults = 20, pageToken, labelIds = 'INBOX', accountId } = options;
+    if (!this.rustInitialized) {
+      await this.initRustClient();
+    }
+    const result = await invoke<ThreadListResponse>('get_threads_rust', {
+      options: { labelIds, maxResults, pageToken },
+      accountId
+    });
+    return { threads: result.threads || [], nextPageToken: result.nextPageToken ?? undefined };
+  }
+
+  async getThread(threadId: string, accountId?: string, loadImages = false): Promise<EmailThread> {
+    if (!this.rustInitialized) {
+      await this.initRustClient();
+    }
+    return invoke<EmailThread>('get_thread_rust', { threadId, accountId, loadImages });
+  }
+
+  // ✅ "Zawsze ładuj obrazki" od nadawcy (zapisywane w cache konta w Rust)
+  async setSenderImages(sender: string, allowed: boolean, accountId?: string): Promise<void> {
+    await invoke('set_sender_images_rust', { sender, allowed, accountId });
+    // Treści w cache mają stare placeholdery
+    this.bodyCache.clear();
+  }
+
+  async listImageSenders(accountId?: string): Promise<string[]> {
+    return invoke<string[]>('list_image_senders_rust', { accountId });
+  }
+
   // ✅ Invalidate cache dla labela
   invalidateLabelCache(labelIds: string) {
-    this.labelCache.delete(labelIds);
+    for (const key of [...this.labelCache.keys()]) {
+      if (key === labelIds || key.endsWith(`:${labelIds}`)) {
+        this.labelCache.delete(key);
+      }
+    }
   }
 
   // ✅ Force refresh - wyczyść cache i pobierz na nowo
@@ -326,23 +540,34 @@ class EmailAPI {
     return this.getEmails({ labelIds, maxResults: 20 });
   }
 
-  async sendEmail(emailData: EmailData): Promise<{ success: boolean; id: string }> {
+  // queued = brak sieci, wiadomość czeka w kolejce i wyjdzie po jej powrocie
+  async sendEmail(emailData: EmailData): Promise<{ success: boolean; id: string; queued?: boolean }> {
     if (this.useRust) {
       if (!this.rustInitialized) {
         await this.initRustClient();
       }
       if (this.rustInitialized) {
         try {
-          const id = await invoke<string>('send_email_rust', { emailData });
+          const outcome = await invoke<SendOutcome>('send_email_rust', { emailData });
+          if (outcome.queuedOpId != null) {
+            return { success: true, id: `outbox-${outcome.queuedOpId}`, queued: true };
+          }
           // ✅ Invalidate SENT and INBOX cache
           this.invalidateLabelCache('SENT');
           this.invalidateLabelCache('INBOX');
-          return { success: true, id };
+          return { success: true, id: outcome.id ?? '' };
         } catch (error) {
+          // Backend Node nie wyśle plików z dysku
+          if (emailData.attachments?.length) {
+            throw error;
+          }
           console.error('Rust sendEmail failed, falling back to Node.js:', error);
         }
       }
     }
+    if (emailData.attachments?.length) {
+      throw new Error('Attachments need the Rust backend');
+    }
 
     const response = await fetch(`${API_BASE_URL}/api/emails/send`, {
       method: 'POST',
@@ -360,10 +585,10 @@ class EmailAPI {
     return result;
   }
 
-  async markEmail(id: string, read: boolean): Promise<{ success: boolean }> {
+  async markEmail(id: string, read: boolean, accountId?: string): Promise<{ success: boolean }> {
     if (this.useRust) {
       try {
-        await invoke('mark_email_rust', { messageId: id, read });
+        await invoke('mark_email_rust', { messageId: id, read, accountId });
         
         // ✅ Update cache
         for (const [, cache] of this.labelCache.entries()) {
@@ -404,23 +629,29 @@ class EmailAPI {
     return res.json();
   }
 
-  async deleteEmail(id: string): Promise<{ success: boolean }> {
+  // Domyślnie kosz (jak backend Node); permanent = trwałe usunięcie, tylko przez Rust
+  async deleteEmail(id: string, permanent = false, accountId?: string): Promise<{ success: boolean }> {
     if (this.useRust) {
       try {
-        await invoke('delete_email_rust', { messageId: id });
+        await invoke('delete_email_rust', { messageId: id, permanent, accountId });
         
         // ✅ Remove from all caches
-        for (const [, cache] of this.labelCache.entries()) {
-          cache.messages = cache.messages.filter(m => m.id !== id);
-        }
-        this.bodyCache.delete(id);
+        this.removeFromCaches(id);
+        this.invalidateLabelCache('TRASH');
         
         return { success: true };
       } catch (e) {
+        if (permanent) {
+          throw new Error(`Failed to delete email: ${e}`);
+        }
         console.error('Rust deleteEmail failed, falling back to Node.js:', e);
       }
     }
 
+    if (permanent) {
+      throw new Error('Permanent delete requires the Rust client');
+    }
+
     const response = await fetch(`${API_BASE_URL}/api/emails/${id}`, {
       method: 'DELETE'
     });
@@ -430,12 +661,129 @@ class EmailAPI {
     }
     
     // ✅ Remove from cache for Node.js too
+    this.removeFromCaches(id);
+    this.invalidateLabelCache('TRASH');
+    
+    return response.json();
+  }
+
+  async untrashEmail(id: string, accountId?: string): Promise<{ success: boolean }> {
+    await invoke('untrash_email_rust', { messageId: id, accountId });
+    this.removeFromCaches(id);
+    this.invalidateLabelCache('INBOX');
+    return { success: true };
+  }
+
+  // Zbiorcza zmiana etykiet (np. zaznacz wiele jako przeczytane)
+  async modifyLabels(
+    ids: string[],
+    addLabelIds: string[],
+    removeLabelIds: string[],
+    accountId?: string
+  ): Promise<{ success: boolean }> {
+    await invoke('modify_labels_rust', { messageIds: ids, addLabelIds, removeLabelIds, accountId });
+    for (const label of [...addLabelIds, ...removeLabelIds]) {
+      this.invalidateLabelCache(label);
+    }
+    if (addLabelIds.includes('UNREAD') || removeLabelIds.includes('UNREAD')) {
+      const unread = addLabelIds.includes('UNREAD');
+      const idSet = new Set(ids);
+      for (const [, cache] of this.labelCache.entries()) {
+        cache.messages = cache.messages.map(m => (idSet.has(m.id) ? { ...m, unread } : m));
+      }
+    }
+    return { success: true };
+  }
+
+  // Kolejka zmian czekających na sieć, odrzuconych przez Gmail ('failed') i porzuconych po limicie prób ('dead_letter')
+  async listPendingOps(accountId?: string): Promise<PendingOp[]> {
+    return invoke<PendingOp[]>('list_pending_ops_rust', { accountId });
+  }
+
+  async retryPendingOp(opId: number, accountId?: string): Promise<boolean> {
+    return invoke<boolean>('retry_pending_op_rust', { opId, accountId });
+  }
+
+  async discardPendingOp(opId: number, accountId?: string): Promise<boolean> {
+    const removed = await invoke<boolean>('discard_pending_op_rust', { opId, accountId });
+    // Odrzucenie oczekującej zmiany cofa ją w cache
+    this.labelCache.clear();
+    return removed;
+  }
+
+  // Autozapis z edytora - bez localId tworzy nową wersję roboczą
+  async saveDraft(localId: number | null, emailData: EmailData, threadId?: string, accountId?: string): Promise<Draft> {
+    return invoke<Draft>('save_draft_rust', { localId, emailData, threadId, accountId });
+  }
+
+  async listDrafts(accountId?: string): Promise<Draft[]> {
+    return invoke<Draft[]>('list_drafts_rust', { accountId });
+  }
+
+  async getDraft(localId: number, accountId?: string): Promise<Draft | null> {
+    return invoke<Draft | null>('get_draft_rust', { localId, accountId });
+  }
+
+  async sendDraft(localId: number, accountId?: string): Promise<{ success: boolean; id: string; queued?: boolean }> {
+    const outcome = await invoke<SendOutcome>('send_draft_rust', { localId, accountId });
+    this.invalidateLabelCache('DRAFT');
+    if (outcome.queuedOpId != null) {
+      return { success: true, id: `outbox-${outcome.queuedOpId}`, queued: true };
+    }
+    this.invalidateLabelCache('SENT');
+    return { success: true, id: outcome.id ?? '' };
+  }
+
+  // Odpowiedź / przekazanie w wątku `messageId`; emailData.body to sam nowy tekst (cytat dokłada Rust)
+  async replyEmail(
+    kind: ReplyKind,
+    messageId: string,
+    emailData: EmailData,
+    accountId?: string
+  ): Promise<{ success: boolean; id: string; queued?: boolean }> {
+    const command = {
+      reply: 'reply_email_rust',
+      replyAll: 'reply_all_rust',
+      forward: 'forward_email_rust',
+    }[kind];
+    const outcome = await invoke<SendOutcome>(command, { messageId, emailData, accountId });
+    if (outcome.queuedOpId != null) {
+      return { success: true, id: `outbox-${outcome.queuedOpId}`, queued: true };
+    }
+    this.invalidateLabelCache('SENT');
+    this.invalidateLabelCache('INBOX');
+    return { success: true, id: outcome.id ?? '' };
+  }
+
+  async deleteDraft(localId: number, accountId?: string): Promise<void> {
+    await invoke('delete_draft_rust', { localId, accountId });
+    this.invalidateLabelCache('DRAFT');
+  }
+
+  async getLabel(labelId: string, accountId?: string): Promise<Label> {
+    return invoke<Label>('get_label_rust', { labelId, accountId });
+  }
+
+  async createLabel(label: LabelUpdate & { name: string }, accountId?: string): Promise<Label> {
+    return invoke<Label>('create_label_rust', { label, accountId });
+  }
+
+  // Zmiana nazwy, koloru albo widoczności
+  async updateLabel(labelId: string, label: LabelUpdate, accountId?: string): Promise<Label> {
+    return invoke<Label>('update_label_rust', { labelId, label, accountId });
+  }
+
+  async deleteLabel(labelId: string, accountId?: string): Promise<void> {
+    await invoke('delete_label_rust', { labelId, accountId });
+    // Gmail zdejmuje etykietę ze wszystkich wiadomości
+    this.labelCache.clear();
+  }
+
+  private removeFromCaches(id: string) {
     for (const [, cache] of this.labelCache.entries()) {
       cache.messages = cache.messages.filter(m => m.id !== id);
     }
     this.bodyCache.delete(id);
-    
-    return response.json();
   }
 
   async getTodayStats(): Promise<{ totalToday: number; unreadToday: number }> {
@@ -490,7 +838,18 @@ class EmailAPI {
     return response.json();
   }
 
-  async getLabels(): Promise<Label[]> {
+  async getLabels(accountId?: string): Promise<Label[]> {
+    if (this.useRust) {
+      if (!this.rustInitialized) await this.initRustClient();
+      if (this.rustInitialized) {
+        try {
+          return await invoke<Label[]>('list_labels_rust', { accountId });
+        } catch (error) {
+          console.error('Rust getLabels failed, falling back to Node.js:', error);
+        }
+      }
+    }
+
     const response = await fetch(`${API_BASE_URL}/api/labels`);
     if (!response.ok) {
       throw new Error('Failed to get labels');
@@ -498,6 +857,28 @@ class EmailAPI {
     return response.json();
   }
 
+  // Plik lokalny z magazynu załączników (pobierany z Gmaila tylko za pierwszym razem)
+  async downloadAttachment(messageId: string, attachmentId: string, open = false, accountId?: string): Promise<AttachmentFile> {
+    return invoke<AttachmentFile>('download_attachment_rust', { messageId, attachmentId, open, accountId });
+  }
+
+  onAttachmentProgress(handler: (progress: AttachmentProgress) => void): Promise<UnlistenFn> {
+    return listen<AttachmentProgress>('attachment-download-progress', event => handler(event.payload));
+  }
+
+  // Postęp wysyłki (bajty przyjęte przez Gmaila); duże wiadomości idą uploadem wznawianym
+  onSendProgress(handler: (progress: SendProgress) => void): Promise<UnlistenFn> {
+    return listen<SendProgress>('send-progress', event => handler(event.payload));
+  }
+
+  // Zmiana z kolejki odrzucona przez Gmail albo porzucona po limicie prób - cache jest już cofnięty
+  onOutboxOpFailed(handler: (op: PendingOp) => void): Promise<UnlistenFn> {
+    return listen<PendingOp>('outbox-op-failed', event => {
+      this.labelCache.clear();
+      handler(event.payload);
+    });
+  }
+
   async getAttachment(messageId: string, attachmentId: string): Promise<{ data: string; size: number }> {
     const response = await fetch(`${API_BASE_URL}/api/emails/${messageId}/attachments/${attachmentId}`);
     if (!response.ok) {